@@ -0,0 +1,7 @@
+-- ==============================================
+-- DEPLOYMENT SECRETS: track last update
+-- ==============================================
+ALTER TABLE deployment_secrets
+ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP;
+CREATE TRIGGER set_deployment_secrets_timestamp BEFORE
+UPDATE ON deployment_secrets FOR EACH ROW EXECUTE PROCEDURE trigger_set_timestamp();
@@ -0,0 +1,14 @@
+-- ==============================================
+-- DEPLOYMENT DATA KEYS: envelope encryption
+-- ==============================================
+-- One data key per deployment encrypts its secrets, stored wrapped by the KMS
+CREATE TABLE deployment_data_keys (
+    deployment_id UUID PRIMARY KEY REFERENCES deployments(id) ON DELETE CASCADE,
+    wrapped_key BYTEA NOT NULL,
+    kms_provider VARCHAR(32) NOT NULL,
+    kms_key_id VARCHAR(255) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE TRIGGER set_deployment_data_keys_timestamp BEFORE
+UPDATE ON deployment_data_keys FOR EACH ROW EXECUTE PROCEDURE trigger_set_timestamp();
@@ -0,0 +1,13 @@
+-- ==============================================
+-- DEPLOYMENT METRICS: sampled from metrics-server
+-- ==============================================
+-- One row per deployment per minute, summed over its pods
+CREATE TABLE deployment_metrics (
+    deployment_id UUID NOT NULL REFERENCES deployments(id) ON DELETE CASCADE,
+    sampled_at TIMESTAMPTZ NOT NULL,
+    cpu_millicores DOUBLE PRECISION NOT NULL,
+    memory_mb DOUBLE PRECISION NOT NULL,
+    pod_count INTEGER NOT NULL,
+    PRIMARY KEY (deployment_id, sampled_at)
+);
+CREATE INDEX IF NOT EXISTS idx_deployment_metrics_sampled_at ON deployment_metrics(sampled_at);
@@ -0,0 +1,5 @@
+-- ==============================================
+-- DEPLOYMENT STATUS: paused
+-- ==============================================
+-- Paused deployments run zero pods; `replicas` keeps the count to resume to
+ALTER TYPE deployment_status ADD VALUE IF NOT EXISTS 'paused';
@@ -0,0 +1,18 @@
+-- ==============================================
+-- DEPLOYMENT PREVIEWS: short-lived copies of a base deployment
+-- ==============================================
+CREATE TABLE deployment_previews (
+    deployment_id UUID PRIMARY KEY REFERENCES deployments(id) ON DELETE CASCADE,
+    -- Kept after the base is deleted so the preview still expires
+    base_deployment_id UUID REFERENCES deployments(id) ON DELETE SET NULL,
+    name VARCHAR(63) NOT NULL,
+    url TEXT,
+    callback_url TEXT,
+    expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE UNIQUE INDEX IF NOT EXISTS uq_deployment_previews_base_name ON deployment_previews (base_deployment_id, name);
+CREATE INDEX IF NOT EXISTS idx_deployment_previews_expires_at ON deployment_previews (expires_at);
+CREATE TRIGGER set_deployment_previews_timestamp BEFORE
+UPDATE ON deployment_previews FOR EACH ROW EXECUTE PROCEDURE trigger_set_timestamp();
@@ -0,0 +1,12 @@
+-- ==============================================
+-- DEPLOYMENT PLACEMENT
+-- ==============================================
+-- `region` names the cluster a deployment runs on, NULL is the default
+-- cluster (deployments created before regions existed). `node_pool` is the
+-- pool it was placed on, its node selector and tolerations are copied next
+-- to it so a pool changing later doesn't move running pods.
+ALTER TABLE deployments
+ADD COLUMN IF NOT EXISTS region VARCHAR(64),
+    ADD COLUMN IF NOT EXISTS node_pool VARCHAR(64),
+    ADD COLUMN IF NOT EXISTS tolerations JSONB;
+CREATE INDEX IF NOT EXISTS idx_deployments_region ON deployments(region);
@@ -0,0 +1,41 @@
+-- ==============================================
+-- PLANS: per-user caps on what deployments may use
+-- ==============================================
+-- CPU and memory are capped on limits times replicas of running
+-- deployments, paused ones only count towards `max_deployments`
+CREATE TABLE plans (
+    name VARCHAR(32) PRIMARY KEY,
+    max_cpu_millicores INTEGER NOT NULL CHECK (max_cpu_millicores >= 0),
+    max_memory_mb INTEGER NOT NULL CHECK (max_memory_mb >= 0),
+    max_replicas INTEGER NOT NULL CHECK (max_replicas >= 0),
+    max_deployments INTEGER NOT NULL CHECK (max_deployments >= 0),
+    -- Plan of users without a `user_plans` row
+    is_default BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE UNIQUE INDEX IF NOT EXISTS uq_plans_default ON plans (is_default)
+WHERE is_default;
+CREATE TRIGGER set_plans_timestamp BEFORE
+UPDATE ON plans FOR EACH ROW EXECUTE PROCEDURE trigger_set_timestamp();
+INSERT INTO plans (
+        name,
+        max_cpu_millicores,
+        max_memory_mb,
+        max_replicas,
+        max_deployments,
+        is_default
+    )
+VALUES ('free', 2000, 2048, 4, 3, TRUE),
+    ('pro', 8000, 16384, 20, 20, FALSE),
+    ('team', 32000, 65536, 100, 100, FALSE);
+--
+--
+CREATE TABLE user_plans (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    plan_name VARCHAR(32) NOT NULL REFERENCES plans(name) ON UPDATE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE TRIGGER set_user_plans_timestamp BEFORE
+UPDATE ON user_plans FOR EACH ROW EXECUTE PROCEDURE trigger_set_timestamp();
@@ -0,0 +1,16 @@
+-- ==============================================
+-- BILLING PRICES: what an hour of resources costs
+-- ==============================================
+-- Deployments are charged on CPU and memory limits times replicas, in the
+-- currency of the user's balance
+CREATE TABLE IF NOT EXISTS billing_prices (
+    id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+    cpu_millicore_hour NUMERIC(18, 10) NOT NULL CHECK (cpu_millicore_hour >= 0),
+    memory_mb_hour NUMERIC(18, 10) NOT NULL CHECK (memory_mb_hour >= 0),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE TRIGGER set_billing_prices_timestamp BEFORE
+UPDATE ON billing_prices FOR EACH ROW EXECUTE PROCEDURE trigger_set_timestamp();
+-- $0.03 per vCPU and $0.004 per GiB of memory every hour
+INSERT INTO billing_prices (cpu_millicore_hour, memory_mb_hour)
+VALUES (0.00003, 0.000004) ON CONFLICT (id) DO NOTHING;
@@ -0,0 +1,49 @@
+-- ==============================================
+-- WEBHOOKS: outbound notifications of deployment lifecycle events
+-- ==============================================
+CREATE TYPE webhook_delivery_status AS ENUM ('pending', 'succeeded', 'failed');
+
+CREATE TABLE webhooks (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    project_id UUID NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    url TEXT NOT NULL,
+    -- Signing secret, wrapped by the KMS like deployment data keys
+    wrapped_secret BYTEA NOT NULL,
+    kms_provider VARCHAR(32) NOT NULL,
+    event_types TEXT [] NOT NULL,
+    is_active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX IF NOT EXISTS idx_webhooks_project_id ON webhooks (project_id);
+CREATE TRIGGER set_webhooks_timestamp BEFORE
+UPDATE ON webhooks FOR EACH ROW EXECUTE PROCEDURE trigger_set_timestamp();
+
+CREATE TABLE webhook_deliveries (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    webhook_id UUID NOT NULL REFERENCES webhooks(id) ON DELETE CASCADE,
+    -- No foreign key, deliveries outlive a deleted deployment
+    deployment_id UUID NOT NULL,
+    event_type VARCHAR(128) NOT NULL,
+    payload JSONB NOT NULL,
+    status webhook_delivery_status NOT NULL DEFAULT 'pending',
+    attempts INT NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    response_status INT,
+    last_error TEXT,
+    delivered_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_webhook_id ON webhook_deliveries (webhook_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_due ON webhook_deliveries (next_attempt_at)
+WHERE status = 'pending';
+CREATE TRIGGER set_webhook_deliveries_timestamp BEFORE
+UPDATE ON webhook_deliveries FOR EACH ROW EXECUTE PROCEDURE trigger_set_timestamp();
+
+-- Events every compute replica observes, e.g. a rollout becoming ready, are
+-- recorded once per key
+ALTER TABLE deployment_events
+ADD COLUMN dedupe_key VARCHAR(255);
+CREATE UNIQUE INDEX IF NOT EXISTS uq_deployment_events_dedupe_key ON deployment_events (deployment_id, dedupe_key)
+WHERE dedupe_key IS NOT NULL;
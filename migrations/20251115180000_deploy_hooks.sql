@@ -0,0 +1,19 @@
+-- ==============================================
+-- DEPLOY HOOKS: secret URLs that redeploy a deployment
+-- ==============================================
+CREATE TABLE deploy_hooks (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    deployment_id UUID NOT NULL REFERENCES deployments(id) ON DELETE CASCADE,
+    name VARCHAR(63) NOT NULL,
+    -- SHA-256 of the token, the token itself is only shown when generated
+    token_hash VARCHAR(64) NOT NULL,
+    -- Leading characters of the token, enough to tell hooks apart
+    token_prefix VARCHAR(16) NOT NULL,
+    last_triggered_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE UNIQUE INDEX IF NOT EXISTS uq_deploy_hooks_token_hash ON deploy_hooks (token_hash);
+CREATE UNIQUE INDEX IF NOT EXISTS uq_deploy_hooks_deployment_name ON deploy_hooks (deployment_id, name);
+CREATE TRIGGER set_deploy_hooks_timestamp BEFORE
+UPDATE ON deploy_hooks FOR EACH ROW EXECUTE PROCEDURE trigger_set_timestamp();
@@ -0,0 +1,61 @@
+-- ==============================================
+-- PROJECT MEMBERS: who can work on a project, and as what
+-- ==============================================
+DO $$ BEGIN CREATE TYPE project_role AS ENUM ('viewer', 'developer', 'admin', 'owner');
+EXCEPTION
+WHEN duplicate_object THEN NULL;
+END $$;
+CREATE TABLE project_members (
+    project_id UUID NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    role project_role NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (project_id, user_id)
+);
+CREATE INDEX IF NOT EXISTS idx_project_members_user_id ON project_members(user_id);
+CREATE TRIGGER set_project_members_timestamp BEFORE
+UPDATE ON project_members FOR EACH ROW EXECUTE PROCEDURE trigger_set_timestamp();
+-- Every existing project keeps its owner
+INSERT INTO project_members (project_id, user_id, role)
+SELECT id, owner_id, 'owner'
+FROM projects ON CONFLICT DO NOTHING;
+--
+--
+-- ==============================================
+-- PROJECT INVITATIONS: pending memberships, accepted by link
+-- ==============================================
+CREATE TABLE project_invitations (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    project_id UUID NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    email VARCHAR(255) NOT NULL,
+    role project_role NOT NULL,
+    -- SHA-256 of the token, the token itself only goes out in the email
+    token_hash VARCHAR(64) NOT NULL,
+    invited_by UUID REFERENCES users(id) ON DELETE
+    SET NULL,
+        expires_at TIMESTAMPTZ NOT NULL,
+        accepted_at TIMESTAMPTZ,
+        created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE UNIQUE INDEX IF NOT EXISTS uq_project_invitations_token_hash ON project_invitations (token_hash);
+CREATE UNIQUE INDEX IF NOT EXISTS uq_project_invitations_pending ON project_invitations (project_id, lower(email))
+WHERE accepted_at IS NULL;
+--
+--
+-- ==============================================
+-- AUDIT LOG: membership and ownership changes
+-- ==============================================
+-- No foreign keys, entries outlive the projects and users they mention
+CREATE TABLE audit_log (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    project_id UUID,
+    actor_id UUID,
+    target_user_id UUID,
+    action VARCHAR(64) NOT NULL,
+    details JSONB NOT NULL DEFAULT '{}'::jsonb,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX IF NOT EXISTS idx_audit_log_project_id ON audit_log(project_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_audit_log_actor_id ON audit_log(actor_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_audit_log_target_user_id ON audit_log(target_user_id, created_at DESC);
@@ -0,0 +1,99 @@
+-- ==============================================
+-- ORGANIZATIONS: teams that own projects and pay for them
+-- ==============================================
+DO $$ BEGIN CREATE TYPE organization_role AS ENUM ('member', 'admin', 'owner');
+EXCEPTION
+WHEN duplicate_object THEN NULL;
+END $$;
+CREATE TABLE organizations (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    name VARCHAR(64) NOT NULL,
+    slug VARCHAR(64) NOT NULL,
+    -- Default plan when unset, like users without a `user_plans` row
+    plan_name VARCHAR(32) REFERENCES plans(name) ON UPDATE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE UNIQUE INDEX IF NOT EXISTS uq_organizations_slug ON organizations (lower(slug));
+CREATE TRIGGER set_organizations_timestamp BEFORE
+UPDATE ON organizations FOR EACH ROW EXECUTE PROCEDURE trigger_set_timestamp();
+--
+--
+CREATE TABLE organization_members (
+    organization_id UUID NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    role organization_role NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (organization_id, user_id)
+);
+CREATE INDEX IF NOT EXISTS idx_organization_members_user_id ON organization_members(user_id);
+CREATE TRIGGER set_organization_members_timestamp BEFORE
+UPDATE ON organization_members FOR EACH ROW EXECUTE PROCEDURE trigger_set_timestamp();
+--
+--
+-- ==============================================
+-- USERS: the organization they're working in, NULL for their own account
+-- ==============================================
+ALTER TABLE users
+ADD COLUMN active_organization_id UUID REFERENCES organizations(id) ON DELETE
+SET NULL;
+--
+--
+-- ==============================================
+-- PROJECTS: owned by an organization, or by `owner_id` alone
+-- ==============================================
+-- `owner_id` stays set on organization projects, it's the member who created it
+ALTER TABLE projects
+ADD COLUMN organization_id UUID REFERENCES organizations(id) ON DELETE CASCADE;
+CREATE INDEX IF NOT EXISTS idx_projects_organization_id ON projects(organization_id);
+-- Names are unique per account, not per creator
+ALTER TABLE projects DROP CONSTRAINT IF EXISTS projects_owner_id_name_key;
+CREATE UNIQUE INDEX IF NOT EXISTS uq_projects_user_name ON projects (owner_id, name)
+WHERE organization_id IS NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS uq_projects_organization_name ON projects (organization_id, name)
+WHERE organization_id IS NOT NULL;
+--
+--
+-- ==============================================
+-- BALANCES: held by a user or by an organization
+-- ==============================================
+ALTER TABLE balances
+ALTER COLUMN user_id DROP NOT NULL;
+ALTER TABLE balances
+ADD COLUMN organization_id UUID UNIQUE REFERENCES organizations(id) ON DELETE CASCADE;
+ALTER TABLE balances
+ADD CONSTRAINT balances_single_holder CHECK (num_nonnulls(user_id, organization_id) = 1);
+-- Organizations start empty, free credit is for users only
+CREATE OR REPLACE FUNCTION on_organization_created_balance() RETURNS TRIGGER AS $$ BEGIN
+INSERT INTO balances (organization_id, amount)
+VALUES (NEW.id, 0.00);
+RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+CREATE TRIGGER after_organization_created
+AFTER
+INSERT ON organizations FOR EACH ROW EXECUTE PROCEDURE on_organization_created_balance();
+--
+--
+-- ==============================================
+-- PROJECT ACCESS: direct memberships plus those through an organization
+-- ==============================================
+-- Organization owners own its projects, admins administer them and members
+-- develop on them. A direct membership can add to that, never take away.
+CREATE OR REPLACE VIEW project_access AS
+SELECT project_id,
+    user_id,
+    role
+FROM project_members
+UNION ALL
+SELECT p.id AS project_id,
+    m.user_id,
+    CASE
+        m.role
+        WHEN 'owner' THEN 'owner'::project_role
+        WHEN 'admin' THEN 'admin'::project_role
+        ELSE 'developer'::project_role
+    END AS role
+FROM projects p
+    INNER JOIN organization_members m ON m.organization_id = p.organization_id;
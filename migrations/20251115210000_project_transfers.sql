@@ -0,0 +1,25 @@
+-- ==============================================
+-- PROJECT TRANSFERS: handing a project to another account, accepted by link
+-- ==============================================
+CREATE TABLE project_transfers (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    project_id UUID NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    -- Who the recipient has to be signed in as to accept
+    email VARCHAR(255) NOT NULL,
+    -- SHA-256 of the token, the token itself only goes out in the email
+    token_hash VARCHAR(64) NOT NULL,
+    initiated_by UUID REFERENCES users(id) ON DELETE
+    SET NULL,
+        expires_at TIMESTAMPTZ NOT NULL,
+        accepted_at TIMESTAMPTZ,
+        -- Set on acceptance, the account the project moved to
+        accepted_by UUID REFERENCES users(id) ON DELETE
+    SET NULL,
+        organization_id UUID REFERENCES organizations(id) ON DELETE
+    SET NULL,
+        created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE UNIQUE INDEX IF NOT EXISTS uq_project_transfers_token_hash ON project_transfers (token_hash);
+-- One pending transfer per project, a new one replaces it
+CREATE UNIQUE INDEX IF NOT EXISTS uq_project_transfers_pending ON project_transfers (project_id)
+WHERE accepted_at IS NULL;
@@ -1,18 +1,29 @@
-use axum::{Json, extract::State, response::IntoResponse};
+use axum::{
+    Json,
+    extract::State,
+    http::{HeaderMap, header},
+    response::IntoResponse,
+};
+use bigdecimal::BigDecimal;
 use shared::{
     schemas::ListResponse,
     services::database::Database,
-    utilities::{errors::AppError, jwt::Claims},
+    utilities::{config::Config, errors::AppError, jwt::Claims},
 };
+use sqlx::PgPool;
+use subtle::ConstantTimeEq;
 
-use crate::features::repository::BillingRepository;
+use crate::features::{
+    models::{Balance, OrganizationRole},
+    repository::BillingRepository,
+    schemas::{BalanceCheckRequest, BalanceCheckResponse},
+};
 
 pub async fn get_balance(
     claims: Claims,
     State(database): State<Database>,
 ) -> Result<impl IntoResponse, AppError> {
-    let user_id = claims.sub;
-    let balance = BillingRepository::get_user_balance(&database.pool, user_id).await?;
+    let balance = claimed_balance(&database.pool, &claims).await?;
     Ok(Json(balance))
 }
 
@@ -20,12 +31,88 @@ pub async fn get_transactions(
     claims: Claims,
     State(database): State<Database>,
 ) -> Result<impl IntoResponse, AppError> {
-    let user_id = claims.sub;
+    let balance = claimed_balance(&database.pool, &claims).await?;
 
-    let transactions = BillingRepository::get_transactions(&database.pool, user_id).await?;
+    let transactions = BillingRepository::get_transactions(&database.pool, balance.id).await?;
 
     Ok(Json(ListResponse {
-        total: i64::try_from(transactions.len()).unwrap_or_else(|_| 0),
+        total: i64::try_from(transactions.len()).unwrap_or(0),
         data: transactions,
     }))
 }
+
+/// The balance of the organization the user is working in, which only its
+/// admins may see, or the user's own
+async fn claimed_balance(pool: &PgPool, claims: &Claims) -> Result<Balance, AppError> {
+    let Some(organization_id) = claims.org else {
+        return Ok(BillingRepository::get_user_balance(pool, claims.sub).await?);
+    };
+
+    let role = BillingRepository::get_organization_role(pool, organization_id, claims.sub)
+        .await?
+        .ok_or_else(|| AppError::NotFoundError("Organization not found".to_string()))?;
+    if role < OrganizationRole::Admin {
+        return Err(AppError::ForbiddenError(
+            "Only organization admins can see its balance".to_string(),
+        ));
+    }
+
+    Ok(BillingRepository::get_organization_balance(pool, organization_id).await?)
+}
+
+// ============================================
+// INTERNAL HANDLERS
+// ============================================
+
+/// Whether a user's or organization's balance covers `MIN_BALANCE_HOURS` of
+/// their projected spend. Called by compute with `INTERNAL_API_TOKEN` as bearer
+/// token, refused for everyone while no token is configured.
+pub async fn check_balance(
+    headers: HeaderMap,
+    State(database): State<Database>,
+    State(config): State<Config>,
+    Json(req): Json<BalanceCheckRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    authorize_internal(&headers, &config)?;
+
+    let balance = match (req.user_id, req.organization_id) {
+        (Some(user_id), None) => {
+            BillingRepository::get_user_balance(&database.pool, user_id).await?
+        }
+        (None, Some(organization_id)) => {
+            BillingRepository::get_organization_balance(&database.pool, organization_id).await?
+        }
+        _ => {
+            return Err(AppError::ValidationError(
+                "Set either userId or organizationId".to_string(),
+            ));
+        }
+    };
+    let required = (&req.hourly_cost * BigDecimal::from(config.min_balance_hours)).round(6);
+
+    Ok(Json(BalanceCheckResponse {
+        allowed: balance.amount >= required,
+        balance: balance.amount,
+        required,
+        hours: config.min_balance_hours,
+        currency: balance.currency,
+    }))
+}
+
+/// Internal routes need the shared token, compared in constant time
+fn authorize_internal(headers: &HeaderMap, config: &Config) -> Result<(), AppError> {
+    let Some(token) = &config.internal_api_token else {
+        return Err(AppError::UnauthorizedError);
+    };
+
+    let provided = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    if !bool::from(provided.as_bytes().ct_eq(token.as_bytes())) {
+        return Err(AppError::UnauthorizedError);
+    }
+
+    Ok(())
+}
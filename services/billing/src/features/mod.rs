@@ -6,10 +6,15 @@ pub mod schemas;
 
 use crate::utilities::app_state::AppState;
 
-use axum::{Router, routing::get};
+use axum::{
+    Router,
+    routing::{get, post},
+};
 
 pub fn routes() -> Router<AppState> {
     Router::new()
         .route("/api/v1/balance", get(handlers::get_balance))
         .route("/api/v1/transactions", get(handlers::get_transactions))
+        // Internal
+        .route("/internal/v1/balance/check", post(handlers::check_balance))
 }
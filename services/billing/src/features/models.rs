@@ -16,6 +16,15 @@ pub enum TransactionType {
     Fund,
 }
 
+#[derive(Type, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[sqlx(type_name = "organization_role", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum OrganizationRole {
+    Member,
+    Admin,
+    Owner,
+}
+
 // ============================================
 // MODELS
 // ============================================
@@ -24,7 +33,9 @@ pub enum TransactionType {
 #[serde(rename_all = "camelCase")]
 pub struct Balance {
     pub id: Uuid,
-    pub user_id: Uuid,
+    /// Exactly one of `user_id` and `organization_id` is set
+    pub user_id: Option<Uuid>,
+    pub organization_id: Option<Uuid>,
     pub amount: BigDecimal,
     pub currency: String,
     pub created_at: DateTime<Utc>,
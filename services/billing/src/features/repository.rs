@@ -1,7 +1,7 @@
 use sqlx::{PgPool, Postgres};
 use uuid::Uuid;
 
-use crate::features::models::{Balance, Transaction};
+use crate::features::models::{Balance, OrganizationRole, Transaction};
 
 pub struct BillingRepository;
 
@@ -9,7 +9,7 @@ impl BillingRepository {
     pub async fn get_user_balance(pool: &PgPool, user_id: Uuid) -> Result<Balance, sqlx::Error> {
         sqlx::query_as::<Postgres, Balance>(
             r#"
-                SELECT id, user_id, amount, currency, created_at, updated_at
+                SELECT id, user_id, organization_id, amount, currency, created_at, updated_at
                 FROM balances
                 WHERE user_id = $1
             "#,
@@ -19,22 +19,51 @@ impl BillingRepository {
         .await
     }
 
+    pub async fn get_organization_balance(
+        pool: &PgPool,
+        organization_id: Uuid,
+    ) -> Result<Balance, sqlx::Error> {
+        sqlx::query_as::<Postgres, Balance>(
+            r#"
+                SELECT id, user_id, organization_id, amount, currency, created_at, updated_at
+                FROM balances
+                WHERE organization_id = $1
+            "#,
+        )
+        .bind(organization_id)
+        .fetch_one(pool)
+        .await
+    }
+
     pub async fn get_transactions(
         pool: &PgPool,
-        user_id: Uuid,
+        balance_id: Uuid,
     ) -> Result<Vec<Transaction>, sqlx::Error> {
         sqlx::query_as::<_, Transaction>(
             r#"
-            SELECT t.*
-            FROM transactions t
-            INNER JOIN balances b ON t.balance_id = b.id
-            WHERE b.user_id = $1
-            ORDER BY t.created_at DESC
+            SELECT *
+            FROM transactions
+            WHERE balance_id = $1
+            ORDER BY created_at DESC
             LIMIT 100
             "#,
         )
-        .bind(user_id)
+        .bind(balance_id)
         .fetch_all(pool)
         .await
     }
+
+    pub async fn get_organization_role(
+        pool: &PgPool,
+        organization_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<Option<OrganizationRole>, sqlx::Error> {
+        sqlx::query_scalar::<_, OrganizationRole>(
+            "SELECT role FROM organization_members WHERE organization_id = $1 AND user_id = $2",
+        )
+        .bind(organization_id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+    }
 }
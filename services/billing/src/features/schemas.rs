@@ -0,0 +1,25 @@
+use bigdecimal::BigDecimal;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Asked by compute before a deployment starts costing more
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BalanceCheckRequest {
+    /// Whose balance to check, exactly one of the two
+    pub user_id: Option<Uuid>,
+    pub organization_id: Option<Uuid>,
+    /// Projected hourly spend of all the account's deployments after the change
+    pub hourly_cost: BigDecimal,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BalanceCheckResponse {
+    pub allowed: bool,
+    pub balance: BigDecimal,
+    /// `hourly_cost` times `hours`
+    pub required: BigDecimal,
+    pub hours: i64,
+    pub currency: String,
+}
@@ -1,26 +1,47 @@
 use axum::{
     Json,
     extract::{Path, Query, State},
-    http::StatusCode,
-    response::IntoResponse,
+    http::{StatusCode, header},
+    response::{
+        IntoResponse,
+        sse::{KeepAlive, Sse},
+    },
 };
 use shared::{
     schemas::{ListResponse, Pagination},
-    services::database::Database,
+    services::{database::Database, redis::Redis, zepto::ZeptoMail},
     utilities::{config::Config, errors::AppError, jwt::Claims},
 };
+use tracing::warn;
 use uuid::Uuid;
 use validator::Validate;
 
 use crate::{
     features::{
+        models::{BillingAccount, ProjectRole},
         repository::{DeploymentRepository, ProjectRepository},
         schemas::{
-            CreateDeploymentRequest, CreateProjectRequest, DeploymentResponse, MessageResponse,
-            ScaleDeploymentRequest, UpdateProjectRequest,
+            AcceptInvitationRequest, AcceptTransferRequest, CloneDeploymentRequest,
+            ComposeImportQuery, ComposeImportRequest, CreateDeployHookRequest,
+            CreateDeploymentRequest, CreatePreviewRequest, CreateProjectRequest,
+            CreateSecretRequest, CreateWebhookRequest, DeploymentListQuery, DeploymentResponse,
+            DuplicateProjectRequest, EstimateChangeRequest, ExportQuery, InviteMemberRequest,
+            MessageResponse, MetricsQuery, ProjectListQuery, RegionResponse, ReplaceSecretRequest,
+            ScaleDeploymentRequest, TimelineQuery, TransferProjectRequest, TriggerDeployHookQuery,
+            UpdateMemberRequest, UpdateProjectRequest, UpdateWebhookRequest,
         },
     },
-    services::{build_kubernetes::Kubernetes, kubernetes::DeploymentService},
+    services::{
+        access::AccessService, billing::BillingClient, build_kubernetes::Kubernetes,
+        compose::ComposeImportService, deploy_hooks::DeployHookService,
+        duplicate::DuplicateService, estimate::EstimateService, event_bus::EventBus,
+        export::ExportService, kubernetes::DeploymentService, members::ProjectMemberService,
+        metrics::MetricsService, outbound::OutboundClient, preview::PreviewService,
+        project_spec::ProjectSpecService, quota::QuotaService,
+        runtime_status::RuntimeStatusService, timeline::TimelineService,
+        transfers::ProjectTransferService, webhooks::WebhookService,
+    },
+    utilities::encryption::EncryptionService,
 };
 
 // ============================================
@@ -30,12 +51,24 @@ use crate::{
 pub async fn get_projects(
     claims: Claims,
     Query(pagination): Query<Pagination>,
+    Query(query): Query<ProjectListQuery>,
     State(database): State<Database>,
 ) -> Result<impl IntoResponse, AppError> {
+    pagination.validate()?;
+    query.validate()?;
+
     let user_id: Uuid = claims.sub;
+    let organization_id =
+        AccessService::active_organization(&database.pool, claims.org, user_id).await?;
 
-    let (projects, total) =
-        ProjectRepository::get_many_by_user_id(&database.pool, user_id, pagination).await?;
+    let (projects, total) = ProjectRepository::get_many_by_user_id(
+        &database.pool,
+        user_id,
+        organization_id,
+        query.search.as_deref(),
+        pagination,
+    )
+    .await?;
 
     Ok(Json(ListResponse {
         data: projects,
@@ -50,7 +83,8 @@ pub async fn get_project(
 ) -> Result<impl IntoResponse, AppError> {
     let user_id: Uuid = claims.sub;
 
-    let project = ProjectRepository::get_one_by_id(&database.pool, project_id, user_id).await?;
+    let project =
+        AccessService::project(&database.pool, project_id, user_id, ProjectRole::Viewer).await?;
 
     Ok(Json(project))
 }
@@ -63,10 +97,14 @@ pub async fn create_project(
     req.validate()?;
 
     let user_id: Uuid = claims.sub;
+    // Created in the organization the user is working in, if any
+    let organization_id =
+        AccessService::active_organization(&database.pool, claims.org, user_id).await?;
 
     let project = ProjectRepository::create(
         &database.pool,
         user_id,
+        organization_id,
         &req.name,
         req.description.as_deref(),
     )
@@ -85,6 +123,8 @@ pub async fn update_project(
 
     let user_id: Uuid = claims.sub;
 
+    AccessService::project(&database.pool, project_id, user_id, ProjectRole::Admin).await?;
+
     let project = ProjectRepository::update(
         &database.pool,
         project_id,
@@ -104,6 +144,8 @@ pub async fn delete_project(
 ) -> Result<impl IntoResponse, AppError> {
     let user_id: Uuid = claims.sub;
 
+    AccessService::project(&database.pool, project_id, user_id, ProjectRole::Owner).await?;
+
     ProjectRepository::delete(&database.pool, project_id, user_id).await?;
 
     Ok((
@@ -112,6 +154,114 @@ pub async fn delete_project(
     ))
 }
 
+/// Status, replica and event updates of every deployment in the project
+pub async fn stream_project_updates(
+    claims: Claims,
+    Path(project_id): Path<Uuid>,
+    State(database): State<Database>,
+    State(events): State<EventBus>,
+) -> Result<impl IntoResponse, AppError> {
+    let user_id: Uuid = claims.sub;
+
+    let project =
+        AccessService::project(&database.pool, project_id, user_id, ProjectRole::Viewer).await?;
+
+    Ok(Sse::new(events.subscribe(project.id)).keep_alive(KeepAlive::default()))
+}
+
+/// Diff a YAML or JSON project spec against the project
+pub async fn plan_project_spec(
+    claims: Claims,
+    Path(project_id): Path<Uuid>,
+    State(database): State<Database>,
+    State(kubernetes): State<Kubernetes>,
+    State(config): State<Config>,
+    body: String,
+) -> Result<impl IntoResponse, AppError> {
+    let user_id: Uuid = claims.sub;
+
+    AccessService::project(&database.pool, project_id, user_id, ProjectRole::Viewer).await?;
+
+    let plan = ProjectSpecService::plan(
+        &database.pool,
+        &kubernetes,
+        &config.base_domain,
+        project_id,
+        user_id,
+        &body,
+    )
+    .await?;
+
+    Ok(Json(plan))
+}
+
+/// Apply a project spec, progress is published to the project stream
+#[allow(clippy::too_many_arguments)]
+pub async fn apply_project_spec(
+    claims: Claims,
+    Path(project_id): Path<Uuid>,
+    State(database): State<Database>,
+    State(kubernetes): State<Kubernetes>,
+    State(billing): State<BillingClient>,
+    State(events): State<EventBus>,
+    State(encryption): State<EncryptionService>,
+    State(config): State<Config>,
+    body: String,
+) -> Result<impl IntoResponse, AppError> {
+    let user_id: Uuid = claims.sub;
+
+    AccessService::project(&database.pool, project_id, user_id, ProjectRole::Developer).await?;
+
+    let applied = ProjectSpecService::apply(
+        &database.pool,
+        &kubernetes,
+        &billing,
+        &events,
+        &encryption,
+        &config.base_domain,
+        project_id,
+        user_id,
+        &body,
+    )
+    .await?;
+
+    Ok(Json(applied))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn duplicate_project(
+    claims: Claims,
+    Path(project_id): Path<Uuid>,
+    State(database): State<Database>,
+    State(kubernetes): State<Kubernetes>,
+    State(billing): State<BillingClient>,
+    State(events): State<EventBus>,
+    State(encryption): State<EncryptionService>,
+    State(config): State<Config>,
+    Json(req): Json<DuplicateProjectRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    req.validate()?;
+
+    let user_id: Uuid = claims.sub;
+
+    AccessService::project(&database.pool, project_id, user_id, ProjectRole::Developer).await?;
+
+    let duplicate = DuplicateService::duplicate_project(
+        &database.pool,
+        &kubernetes,
+        &billing,
+        &events,
+        &encryption,
+        &config.base_domain,
+        project_id,
+        user_id,
+        req,
+    )
+    .await?;
+
+    Ok((StatusCode::CREATED, Json(duplicate)))
+}
+
 // ============================================
 // DEPLOYMENT HANDLERS
 // ============================================
@@ -119,12 +269,43 @@ pub async fn delete_project(
 pub async fn get_deployments(
     claims: Claims,
     Path(project_id): Path<Uuid>,
+    Query(pagination): Query<Pagination>,
+    Query(query): Query<DeploymentListQuery>,
     State(database): State<Database>,
 ) -> Result<impl IntoResponse, AppError> {
+    pagination.validate()?;
+    query.validate()?;
+    let (labels, label_keys) = query.label_selector()?;
+
     let user_id: Uuid = claims.sub;
 
-    let deployments =
-        DeploymentRepository::get_all_by_project(&database.pool, project_id, user_id).await?;
+    AccessService::project(&database.pool, project_id, user_id, ProjectRole::Viewer).await?;
+
+    let deployments = DeploymentRepository::get_filtered(
+        &database.pool,
+        project_id,
+        user_id,
+        query.search.as_deref(),
+        query.status,
+        query.image.as_deref(),
+        labels.as_ref(),
+        &label_keys,
+        query.sort,
+        query.order,
+        &pagination,
+    )
+    .await?;
+    let total = DeploymentRepository::count_filtered(
+        &database.pool,
+        project_id,
+        user_id,
+        query.search.as_deref(),
+        query.status,
+        query.image.as_deref(),
+        labels.as_ref(),
+        &label_keys,
+    )
+    .await?;
 
     let response: Vec<DeploymentResponse> = deployments
         .into_iter()
@@ -139,6 +320,8 @@ pub async fn get_deployments(
                 replicas: d.replicas,
                 resources,
                 external_url: None,
+                region: d.region,
+                node_pool: d.node_pool,
                 created_at: d.created_at,
                 updated_at: d.updated_at,
             }
@@ -146,28 +329,50 @@ pub async fn get_deployments(
         .collect();
 
     Ok(Json(ListResponse {
-        total: i64::try_from(response.len()).unwrap_or_else(|_| 0),
         data: response,
+        total,
     }))
 }
 
 pub async fn get_deployment(
     claims: Claims,
-    Path((_, deployment_id)): Path<(Uuid, Uuid)>,
+    Path((project_id, deployment_id)): Path<(Uuid, Uuid)>,
     State(database): State<Database>,
+    State(kubernetes): State<Kubernetes>,
+    State(runtime_status): State<RuntimeStatusService>,
 ) -> Result<impl IntoResponse, AppError> {
     let user_id: Uuid = claims.sub;
 
-    let detail = DeploymentService::get_detail(&database.pool, deployment_id, user_id).await?;
+    AccessService::deployment(
+        &database.pool,
+        project_id,
+        deployment_id,
+        user_id,
+        ProjectRole::Viewer,
+    )
+    .await?;
+
+    let detail = DeploymentService::get_detail(
+        &database.pool,
+        &kubernetes,
+        &runtime_status,
+        deployment_id,
+        user_id,
+    )
+    .await?;
 
     Ok(Json(detail))
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn create_deployment(
     claims: Claims,
     Path(project_id): Path<Uuid>,
     State(database): State<Database>,
     State(kubernetes): State<Kubernetes>,
+    State(billing): State<BillingClient>,
+    State(events): State<EventBus>,
+    State(encryption): State<EncryptionService>,
     State(config): State<Config>,
     Json(req): Json<CreateDeploymentRequest>,
 ) -> Result<impl IntoResponse, AppError> {
@@ -175,17 +380,153 @@ pub async fn create_deployment(
 
     let user_id: Uuid = claims.sub;
 
-    // Verify project ownership
-    ProjectRepository::get_one_by_id(&database.pool, project_id, user_id).await?;
+    AccessService::project(&database.pool, project_id, user_id, ProjectRole::Developer).await?;
 
     let deployment = DeploymentService::create(
         &database.pool,
-        &kubernetes.client,
-        &config.k8s_encryption_key,
+        &kubernetes,
+        &billing,
+        &events,
+        &encryption,
+        user_id,
+        project_id,
+        &config.base_domain,
+        req,
+    )
+    .await?;
+
+    Ok((StatusCode::CREATED, Json(deployment)))
+}
+
+/// What creating `req` would cost, without creating anything
+pub async fn estimate_deployment(
+    claims: Claims,
+    Path(project_id): Path<Uuid>,
+    State(database): State<Database>,
+    Json(req): Json<CreateDeploymentRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    req.validate()?;
+
+    let user_id: Uuid = claims.sub;
+
+    let project =
+        AccessService::project(&database.pool, project_id, user_id, ProjectRole::Viewer).await?;
+
+    // Priced against the usage and balance of the account the deployment is billed to
+    let estimate = EstimateService::new_deployment(
+        &database.pool,
+        user_id,
+        BillingAccount::of(&project),
+        &req,
+    )
+    .await?;
+
+    Ok(Json(estimate))
+}
+
+/// What a deployment would cost after scaling or resizing it
+pub async fn estimate_deployment_change(
+    claims: Claims,
+    Path((project_id, deployment_id)): Path<(Uuid, Uuid)>,
+    State(database): State<Database>,
+    Json(req): Json<EstimateChangeRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    req.validate()?;
+
+    let user_id: Uuid = claims.sub;
+
+    AccessService::deployment(
+        &database.pool,
+        project_id,
+        deployment_id,
+        user_id,
+        ProjectRole::Viewer,
+    )
+    .await?;
+
+    let estimate = EstimateService::change(&database.pool, user_id, deployment_id, &req).await?;
+
+    Ok(Json(estimate))
+}
+
+/// Map the services of a `docker-compose.yml` onto deployments
+#[allow(clippy::too_many_arguments)]
+pub async fn import_compose(
+    claims: Claims,
+    Path(project_id): Path<Uuid>,
+    Query(query): Query<ComposeImportQuery>,
+    State(database): State<Database>,
+    State(kubernetes): State<Kubernetes>,
+    State(billing): State<BillingClient>,
+    State(events): State<EventBus>,
+    State(encryption): State<EncryptionService>,
+    State(config): State<Config>,
+    Json(req): Json<ComposeImportRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    req.validate()?;
+
+    let user_id: Uuid = claims.sub;
+
+    AccessService::project(&database.pool, project_id, user_id, ProjectRole::Developer).await?;
+
+    let import = ComposeImportService::import(
+        &database.pool,
+        &kubernetes,
+        &billing,
+        &events,
+        &encryption,
         user_id,
         project_id,
         &config.base_domain,
         req,
+        query.preview,
+    )
+    .await?;
+
+    let status = if query.preview {
+        StatusCode::OK
+    } else {
+        StatusCode::CREATED
+    };
+
+    Ok((status, Json(import)))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn clone_deployment(
+    claims: Claims,
+    Path((project_id, deployment_id)): Path<(Uuid, Uuid)>,
+    State(database): State<Database>,
+    State(kubernetes): State<Kubernetes>,
+    State(billing): State<BillingClient>,
+    State(events): State<EventBus>,
+    State(encryption): State<EncryptionService>,
+    State(config): State<Config>,
+    Json(req): Json<CloneDeploymentRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    req.validate()?;
+
+    let user_id: Uuid = claims.sub;
+
+    AccessService::deployment(
+        &database.pool,
+        project_id,
+        deployment_id,
+        user_id,
+        ProjectRole::Developer,
+    )
+    .await?;
+
+    let deployment = DuplicateService::clone_deployment(
+        &database.pool,
+        &kubernetes,
+        &billing,
+        &events,
+        &encryption,
+        &config.base_domain,
+        deployment_id,
+        user_id,
+        req,
     )
     .await?;
 
@@ -194,18 +535,31 @@ pub async fn create_deployment(
 
 pub async fn scale_deployment(
     claims: Claims,
-    Path((_, deployment_id)): Path<(Uuid, Uuid)>,
+    Path((project_id, deployment_id)): Path<(Uuid, Uuid)>,
     State(database): State<Database>,
     State(kubernetes): State<Kubernetes>,
+    State(billing): State<BillingClient>,
+    State(events): State<EventBus>,
     Json(req): Json<ScaleDeploymentRequest>,
 ) -> Result<impl IntoResponse, AppError> {
     req.validate()?;
 
     let user_id: Uuid = claims.sub;
 
+    AccessService::deployment(
+        &database.pool,
+        project_id,
+        deployment_id,
+        user_id,
+        ProjectRole::Developer,
+    )
+    .await?;
+
     let deployment = DeploymentService::scale(
         &database.pool,
-        &kubernetes.client,
+        &kubernetes,
+        &billing,
+        &events,
         deployment_id,
         user_id,
         req.replicas,
@@ -217,16 +571,2895 @@ pub async fn scale_deployment(
 
 pub async fn delete_deployment(
     claims: Claims,
-    Path((_, deployment_id)): Path<(Uuid, Uuid)>,
+    Path((project_id, deployment_id)): Path<(Uuid, Uuid)>,
     State(database): State<Database>,
     State(kubernetes): State<Kubernetes>,
+    State(events): State<EventBus>,
 ) -> Result<impl IntoResponse, AppError> {
     let user_id: Uuid = claims.sub;
 
-    DeploymentService::delete(&database.pool, &kubernetes.client, deployment_id, user_id).await?;
+    AccessService::deployment(
+        &database.pool,
+        project_id,
+        deployment_id,
+        user_id,
+        ProjectRole::Developer,
+    )
+    .await?;
+
+    DeploymentService::delete(&database.pool, &kubernetes, &events, deployment_id, user_id).await?;
 
     Ok((
         StatusCode::OK,
         Json(MessageResponse::new("Deployment deleted successfully")),
     ))
 }
+
+pub async fn restart_deployment(
+    claims: Claims,
+    Path((project_id, deployment_id)): Path<(Uuid, Uuid)>,
+    State(database): State<Database>,
+    State(kubernetes): State<Kubernetes>,
+    State(events): State<EventBus>,
+) -> Result<impl IntoResponse, AppError> {
+    let user_id: Uuid = claims.sub;
+
+    AccessService::deployment(
+        &database.pool,
+        project_id,
+        deployment_id,
+        user_id,
+        ProjectRole::Developer,
+    )
+    .await?;
+
+    let deployment =
+        DeploymentService::restart(&database.pool, &kubernetes, &events, deployment_id, user_id)
+            .await?;
+
+    Ok(Json(deployment))
+}
+
+pub async fn pause_deployment(
+    claims: Claims,
+    Path((project_id, deployment_id)): Path<(Uuid, Uuid)>,
+    State(database): State<Database>,
+    State(kubernetes): State<Kubernetes>,
+    State(events): State<EventBus>,
+) -> Result<impl IntoResponse, AppError> {
+    let user_id: Uuid = claims.sub;
+
+    AccessService::deployment(
+        &database.pool,
+        project_id,
+        deployment_id,
+        user_id,
+        ProjectRole::Developer,
+    )
+    .await?;
+
+    let deployment =
+        DeploymentService::pause(&database.pool, &kubernetes, &events, deployment_id, user_id)
+            .await?;
+
+    Ok(Json(deployment))
+}
+
+pub async fn resume_deployment(
+    claims: Claims,
+    Path((project_id, deployment_id)): Path<(Uuid, Uuid)>,
+    State(database): State<Database>,
+    State(kubernetes): State<Kubernetes>,
+    State(billing): State<BillingClient>,
+    State(events): State<EventBus>,
+) -> Result<impl IntoResponse, AppError> {
+    let user_id: Uuid = claims.sub;
+
+    AccessService::deployment(
+        &database.pool,
+        project_id,
+        deployment_id,
+        user_id,
+        ProjectRole::Developer,
+    )
+    .await?;
+
+    let deployment = DeploymentService::resume(
+        &database.pool,
+        &kubernetes,
+        &billing,
+        &events,
+        deployment_id,
+        user_id,
+    )
+    .await?;
+
+    Ok(Json(deployment))
+}
+
+// ============================================
+// DEPLOYMENT SECRET HANDLERS
+// ============================================
+
+pub async fn get_secrets(
+    claims: Claims,
+    Path((project_id, deployment_id)): Path<(Uuid, Uuid)>,
+    State(database): State<Database>,
+) -> Result<impl IntoResponse, AppError> {
+    let user_id: Uuid = claims.sub;
+
+    AccessService::deployment(
+        &database.pool,
+        project_id,
+        deployment_id,
+        user_id,
+        ProjectRole::Viewer,
+    )
+    .await?;
+
+    let secrets = DeploymentService::list_secrets(&database.pool, deployment_id, user_id).await?;
+
+    Ok(Json(ListResponse {
+        total: i64::try_from(secrets.len()).unwrap_or(0),
+        data: secrets,
+    }))
+}
+
+pub async fn create_secret(
+    claims: Claims,
+    Path((project_id, deployment_id)): Path<(Uuid, Uuid)>,
+    State(database): State<Database>,
+    State(kubernetes): State<Kubernetes>,
+    State(events): State<EventBus>,
+    State(encryption): State<EncryptionService>,
+    Json(req): Json<CreateSecretRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    req.validate()?;
+
+    let user_id: Uuid = claims.sub;
+
+    AccessService::deployment(
+        &database.pool,
+        project_id,
+        deployment_id,
+        user_id,
+        ProjectRole::Developer,
+    )
+    .await?;
+
+    let secret = DeploymentService::create_secret(
+        &database.pool,
+        &kubernetes,
+        &events,
+        &encryption,
+        deployment_id,
+        user_id,
+        req,
+    )
+    .await?;
+
+    Ok((StatusCode::CREATED, Json(secret)))
+}
+
+pub async fn replace_secret(
+    claims: Claims,
+    Path((project_id, deployment_id, key)): Path<(Uuid, Uuid, String)>,
+    State(database): State<Database>,
+    State(kubernetes): State<Kubernetes>,
+    State(events): State<EventBus>,
+    State(encryption): State<EncryptionService>,
+    Json(req): Json<ReplaceSecretRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    req.validate()?;
+
+    let user_id: Uuid = claims.sub;
+
+    AccessService::deployment(
+        &database.pool,
+        project_id,
+        deployment_id,
+        user_id,
+        ProjectRole::Developer,
+    )
+    .await?;
+
+    let secret = DeploymentService::replace_secret(
+        &database.pool,
+        &kubernetes,
+        &events,
+        &encryption,
+        deployment_id,
+        user_id,
+        &key,
+        &req.value,
+    )
+    .await?;
+
+    Ok(Json(secret))
+}
+
+pub async fn delete_secret(
+    claims: Claims,
+    Path((project_id, deployment_id, key)): Path<(Uuid, Uuid, String)>,
+    State(database): State<Database>,
+    State(kubernetes): State<Kubernetes>,
+    State(events): State<EventBus>,
+    State(encryption): State<EncryptionService>,
+) -> Result<impl IntoResponse, AppError> {
+    let user_id: Uuid = claims.sub;
+
+    AccessService::deployment(
+        &database.pool,
+        project_id,
+        deployment_id,
+        user_id,
+        ProjectRole::Developer,
+    )
+    .await?;
+
+    DeploymentService::delete_secret(
+        &database.pool,
+        &kubernetes,
+        &events,
+        &encryption,
+        deployment_id,
+        user_id,
+        &key,
+    )
+    .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(MessageResponse::new("Secret deleted successfully")),
+    ))
+}
+
+// ============================================
+// DEPLOYMENT METRICS HANDLERS
+// ============================================
+
+pub async fn get_deployment_metrics(
+    claims: Claims,
+    Path((project_id, deployment_id)): Path<(Uuid, Uuid)>,
+    Query(query): Query<MetricsQuery>,
+    State(database): State<Database>,
+) -> Result<impl IntoResponse, AppError> {
+    let user_id: Uuid = claims.sub;
+
+    AccessService::deployment(
+        &database.pool,
+        project_id,
+        deployment_id,
+        user_id,
+        ProjectRole::Viewer,
+    )
+    .await?;
+
+    let metrics = MetricsService::get_series(&database.pool, deployment_id, user_id, query).await?;
+
+    Ok(Json(metrics))
+}
+
+// ============================================
+// PREVIEW HANDLERS
+// ============================================
+
+#[allow(clippy::too_many_arguments)]
+pub async fn create_preview(
+    claims: Claims,
+    Path((project_id, deployment_id)): Path<(Uuid, Uuid)>,
+    State(database): State<Database>,
+    State(kubernetes): State<Kubernetes>,
+    State(billing): State<BillingClient>,
+    State(events): State<EventBus>,
+    State(encryption): State<EncryptionService>,
+    State(outbound): State<OutboundClient>,
+    State(config): State<Config>,
+    Json(req): Json<CreatePreviewRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    req.validate()?;
+
+    let user_id: Uuid = claims.sub;
+
+    AccessService::deployment(
+        &database.pool,
+        project_id,
+        deployment_id,
+        user_id,
+        ProjectRole::Developer,
+    )
+    .await?;
+
+    let (preview, created) = PreviewService::upsert(
+        &database.pool,
+        &kubernetes,
+        &billing,
+        &events,
+        &encryption,
+        &outbound,
+        &config.base_domain,
+        deployment_id,
+        user_id,
+        req,
+    )
+    .await?;
+
+    let status = if created {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+
+    Ok((status, Json(preview)))
+}
+
+pub async fn get_previews(
+    claims: Claims,
+    Path((project_id, deployment_id)): Path<(Uuid, Uuid)>,
+    State(database): State<Database>,
+) -> Result<impl IntoResponse, AppError> {
+    let user_id: Uuid = claims.sub;
+
+    AccessService::deployment(
+        &database.pool,
+        project_id,
+        deployment_id,
+        user_id,
+        ProjectRole::Viewer,
+    )
+    .await?;
+
+    let previews = PreviewService::list(&database.pool, deployment_id, user_id).await?;
+
+    Ok(Json(previews))
+}
+
+// ============================================
+// DEPLOYMENT TIMELINE HANDLERS
+// ============================================
+
+pub async fn get_deployment_timeline(
+    claims: Claims,
+    Path((project_id, deployment_id)): Path<(Uuid, Uuid)>,
+    Query(pagination): Query<Pagination>,
+    Query(query): Query<TimelineQuery>,
+    State(database): State<Database>,
+    State(kubernetes): State<Kubernetes>,
+) -> Result<impl IntoResponse, AppError> {
+    let user_id: Uuid = claims.sub;
+
+    AccessService::deployment(
+        &database.pool,
+        project_id,
+        deployment_id,
+        user_id,
+        ProjectRole::Viewer,
+    )
+    .await?;
+
+    let timeline = TimelineService::list(
+        &database.pool,
+        &kubernetes,
+        deployment_id,
+        user_id,
+        pagination,
+        query,
+    )
+    .await?;
+
+    Ok(Json(timeline))
+}
+
+pub async fn stream_deployment_timeline(
+    claims: Claims,
+    Path((project_id, deployment_id)): Path<(Uuid, Uuid)>,
+    Query(query): Query<TimelineQuery>,
+    State(database): State<Database>,
+    State(kubernetes): State<Kubernetes>,
+    State(events): State<EventBus>,
+) -> Result<impl IntoResponse, AppError> {
+    let user_id: Uuid = claims.sub;
+
+    AccessService::deployment(
+        &database.pool,
+        project_id,
+        deployment_id,
+        user_id,
+        ProjectRole::Viewer,
+    )
+    .await?;
+
+    let stream = TimelineService::stream(
+        database.pool,
+        kubernetes,
+        events,
+        deployment_id,
+        user_id,
+        query,
+    )
+    .await?;
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+// ============================================
+// REGION HANDLERS
+// ============================================
+
+/// Regions deployments can be placed in, with their node pools
+pub async fn get_regions(
+    _claims: Claims,
+    State(kubernetes): State<Kubernetes>,
+) -> Result<impl IntoResponse, AppError> {
+    let regions: Vec<RegionResponse> = kubernetes
+        .clusters()
+        .map(|cluster| RegionResponse {
+            region: cluster.region.clone(),
+            is_default: cluster.region == kubernetes.default_region(),
+            accepting_deployments: cluster.accepting_deployments,
+            node_pools: cluster.node_pools.keys().cloned().collect(),
+        })
+        .collect();
+
+    Ok(Json(regions))
+}
+
+// ============================================
+// QUOTA HANDLERS
+// ============================================
+
+/// What the deployments of the user, or of the organization they're working
+/// in, use next to the caps of its plan
+pub async fn get_quota(
+    claims: Claims,
+    State(database): State<Database>,
+) -> Result<impl IntoResponse, AppError> {
+    let user_id: Uuid = claims.sub;
+    let account =
+        match AccessService::active_organization(&database.pool, claims.org, user_id).await? {
+            Some(organization_id) => BillingAccount::Organization(organization_id),
+            None => BillingAccount::User(user_id),
+        };
+    let quota = QuotaService::usage(&database.pool, account).await?;
+
+    Ok(Json(quota))
+}
+
+// ============================================
+// DEPLOY HOOK HANDLERS
+// ============================================
+
+pub async fn get_deploy_hooks(
+    claims: Claims,
+    Path((project_id, deployment_id)): Path<(Uuid, Uuid)>,
+    State(database): State<Database>,
+) -> Result<impl IntoResponse, AppError> {
+    let user_id: Uuid = claims.sub;
+
+    AccessService::deployment(
+        &database.pool,
+        project_id,
+        deployment_id,
+        user_id,
+        ProjectRole::Viewer,
+    )
+    .await?;
+
+    let hooks = DeployHookService::list(&database.pool, deployment_id, user_id).await?;
+
+    Ok(Json(hooks))
+}
+
+pub async fn create_deploy_hook(
+    claims: Claims,
+    Path((project_id, deployment_id)): Path<(Uuid, Uuid)>,
+    State(database): State<Database>,
+    State(config): State<Config>,
+    Json(req): Json<CreateDeployHookRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    req.validate()?;
+
+    let user_id: Uuid = claims.sub;
+
+    AccessService::deployment(
+        &database.pool,
+        project_id,
+        deployment_id,
+        user_id,
+        ProjectRole::Developer,
+    )
+    .await?;
+
+    let hook = DeployHookService::create(
+        &database.pool,
+        &config.public_api_url,
+        deployment_id,
+        user_id,
+        req,
+    )
+    .await?;
+
+    Ok((StatusCode::CREATED, Json(hook)))
+}
+
+pub async fn regenerate_deploy_hook(
+    claims: Claims,
+    Path((project_id, deployment_id, hook_id)): Path<(Uuid, Uuid, Uuid)>,
+    State(database): State<Database>,
+    State(config): State<Config>,
+) -> Result<impl IntoResponse, AppError> {
+    let user_id: Uuid = claims.sub;
+
+    AccessService::deployment(
+        &database.pool,
+        project_id,
+        deployment_id,
+        user_id,
+        ProjectRole::Developer,
+    )
+    .await?;
+
+    let hook = DeployHookService::regenerate(
+        &database.pool,
+        &config.public_api_url,
+        deployment_id,
+        hook_id,
+        user_id,
+    )
+    .await?;
+
+    Ok(Json(hook))
+}
+
+pub async fn delete_deploy_hook(
+    claims: Claims,
+    Path((project_id, deployment_id, hook_id)): Path<(Uuid, Uuid, Uuid)>,
+    State(database): State<Database>,
+) -> Result<impl IntoResponse, AppError> {
+    let user_id: Uuid = claims.sub;
+
+    AccessService::deployment(
+        &database.pool,
+        project_id,
+        deployment_id,
+        user_id,
+        ProjectRole::Developer,
+    )
+    .await?;
+
+    DeployHookService::revoke(&database.pool, deployment_id, hook_id, user_id).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(MessageResponse::new("Deploy hook revoked successfully")),
+    ))
+}
+
+/// Called by pipelines, the token in the path is the only credential
+pub async fn trigger_deploy_hook(
+    Path(token): Path<String>,
+    Query(query): Query<TriggerDeployHookQuery>,
+    State(database): State<Database>,
+    State(kubernetes): State<Kubernetes>,
+    State(billing): State<BillingClient>,
+    State(events): State<EventBus>,
+    State(redis): State<Redis>,
+) -> Result<impl IntoResponse, AppError> {
+    query.validate()?;
+
+    let deployment = DeployHookService::trigger(
+        &database.pool,
+        &kubernetes,
+        &billing,
+        &events,
+        &redis,
+        &token,
+        query,
+    )
+    .await?;
+
+    Ok((StatusCode::ACCEPTED, Json(deployment)))
+}
+
+// ============================================
+// WEBHOOK HANDLERS
+// ============================================
+
+pub async fn get_webhooks(
+    claims: Claims,
+    Path(project_id): Path<Uuid>,
+    State(database): State<Database>,
+) -> Result<impl IntoResponse, AppError> {
+    let user_id: Uuid = claims.sub;
+
+    AccessService::project(&database.pool, project_id, user_id, ProjectRole::Admin).await?;
+
+    let webhooks = WebhookService::list(&database.pool, project_id, user_id).await?;
+
+    Ok(Json(webhooks))
+}
+
+pub async fn create_webhook(
+    claims: Claims,
+    Path(project_id): Path<Uuid>,
+    State(database): State<Database>,
+    State(encryption): State<EncryptionService>,
+    Json(req): Json<CreateWebhookRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    req.validate()?;
+
+    let user_id: Uuid = claims.sub;
+
+    AccessService::project(&database.pool, project_id, user_id, ProjectRole::Admin).await?;
+
+    let webhook =
+        WebhookService::create(&database.pool, &encryption, project_id, user_id, req).await?;
+
+    Ok((StatusCode::CREATED, Json(webhook)))
+}
+
+pub async fn update_webhook(
+    claims: Claims,
+    Path((project_id, webhook_id)): Path<(Uuid, Uuid)>,
+    State(database): State<Database>,
+    State(encryption): State<EncryptionService>,
+    Json(req): Json<UpdateWebhookRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    req.validate()?;
+
+    let user_id: Uuid = claims.sub;
+
+    AccessService::project(&database.pool, project_id, user_id, ProjectRole::Admin).await?;
+
+    let webhook = WebhookService::update(
+        &database.pool,
+        &encryption,
+        project_id,
+        webhook_id,
+        user_id,
+        req,
+    )
+    .await?;
+
+    Ok(Json(webhook))
+}
+
+pub async fn delete_webhook(
+    claims: Claims,
+    Path((project_id, webhook_id)): Path<(Uuid, Uuid)>,
+    State(database): State<Database>,
+) -> Result<impl IntoResponse, AppError> {
+    let user_id: Uuid = claims.sub;
+
+    AccessService::project(&database.pool, project_id, user_id, ProjectRole::Admin).await?;
+
+    WebhookService::delete(&database.pool, project_id, webhook_id, user_id).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(MessageResponse::new("Webhook deleted successfully")),
+    ))
+}
+
+/// Delivery log of a webhook, newest first
+pub async fn get_webhook_deliveries(
+    claims: Claims,
+    Path((project_id, webhook_id)): Path<(Uuid, Uuid)>,
+    Query(pagination): Query<Pagination>,
+    State(database): State<Database>,
+) -> Result<impl IntoResponse, AppError> {
+    pagination.validate()?;
+
+    let user_id: Uuid = claims.sub;
+
+    AccessService::project(&database.pool, project_id, user_id, ProjectRole::Admin).await?;
+
+    let (deliveries, total) =
+        WebhookService::deliveries(&database.pool, project_id, webhook_id, user_id, pagination)
+            .await?;
+
+    Ok(Json(ListResponse {
+        data: deliveries,
+        total,
+    }))
+}
+
+pub async fn redeliver_webhook(
+    claims: Claims,
+    Path((project_id, webhook_id, delivery_id)): Path<(Uuid, Uuid, Uuid)>,
+    State(database): State<Database>,
+) -> Result<impl IntoResponse, AppError> {
+    let user_id: Uuid = claims.sub;
+
+    AccessService::project(&database.pool, project_id, user_id, ProjectRole::Admin).await?;
+
+    let delivery =
+        WebhookService::redeliver(&database.pool, project_id, webhook_id, delivery_id, user_id)
+            .await?;
+
+    Ok((StatusCode::ACCEPTED, Json(delivery)))
+}
+
+// ============================================
+// PROJECT MEMBER HANDLERS
+// ============================================
+
+pub async fn get_members(
+    claims: Claims,
+    Path(project_id): Path<Uuid>,
+    State(database): State<Database>,
+) -> Result<impl IntoResponse, AppError> {
+    let user_id: Uuid = claims.sub;
+
+    AccessService::project(&database.pool, project_id, user_id, ProjectRole::Viewer).await?;
+
+    let members = ProjectMemberService::list(&database.pool, project_id).await?;
+
+    Ok(Json(members))
+}
+
+/// Invite someone by email, the link to join is only sent to them
+pub async fn invite_member(
+    claims: Claims,
+    Path(project_id): Path<Uuid>,
+    State(database): State<Database>,
+    State(config): State<Config>,
+    Json(req): Json<InviteMemberRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    req.validate()?;
+
+    let user_id: Uuid = claims.sub;
+
+    let project =
+        AccessService::project(&database.pool, project_id, user_id, ProjectRole::Admin).await?;
+
+    let (invitation, token) =
+        ProjectMemberService::invite(&database.pool, project_id, user_id, req).await?;
+
+    let link = ProjectMemberService::invitation_link(&config.frontend_endpoint, &token);
+    if let Err(e) = ZeptoMail::new()
+        .send_project_invitation(
+            invitation.email.clone(),
+            project.name,
+            invitation.role.as_str().to_string(),
+            link,
+            &config,
+        )
+        .await
+    {
+        warn!("Failed to email invitation {}: {}", invitation.id, e);
+    }
+
+    Ok((StatusCode::CREATED, Json(invitation)))
+}
+
+pub async fn get_invitations(
+    claims: Claims,
+    Path(project_id): Path<Uuid>,
+    State(database): State<Database>,
+) -> Result<impl IntoResponse, AppError> {
+    let user_id: Uuid = claims.sub;
+
+    AccessService::project(&database.pool, project_id, user_id, ProjectRole::Admin).await?;
+
+    let invitations = ProjectMemberService::invitations(&database.pool, project_id).await?;
+
+    Ok(Json(invitations))
+}
+
+pub async fn delete_invitation(
+    claims: Claims,
+    Path((project_id, invitation_id)): Path<(Uuid, Uuid)>,
+    State(database): State<Database>,
+) -> Result<impl IntoResponse, AppError> {
+    let user_id: Uuid = claims.sub;
+
+    AccessService::project(&database.pool, project_id, user_id, ProjectRole::Admin).await?;
+
+    ProjectMemberService::revoke_invitation(&database.pool, project_id, invitation_id, user_id)
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(MessageResponse::new("Invitation revoked successfully")),
+    ))
+}
+
+/// Join a project with the token from an invitation email
+pub async fn accept_invitation(
+    claims: Claims,
+    State(database): State<Database>,
+    Json(req): Json<AcceptInvitationRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    req.validate()?;
+
+    let user_id: Uuid = claims.sub;
+
+    let project = ProjectMemberService::accept(&database.pool, user_id, &req.token).await?;
+
+    Ok(Json(project))
+}
+
+pub async fn update_member(
+    claims: Claims,
+    Path((project_id, member_id)): Path<(Uuid, Uuid)>,
+    State(database): State<Database>,
+    Json(req): Json<UpdateMemberRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    req.validate()?;
+
+    let user_id: Uuid = claims.sub;
+
+    AccessService::project(&database.pool, project_id, user_id, ProjectRole::Admin).await?;
+
+    let member =
+        ProjectMemberService::update_role(&database.pool, project_id, user_id, member_id, req.role)
+            .await?;
+
+    Ok(Json(member))
+}
+
+/// Remove a member, any member can remove themselves to leave the project
+pub async fn delete_member(
+    claims: Claims,
+    Path((project_id, member_id)): Path<(Uuid, Uuid)>,
+    State(database): State<Database>,
+) -> Result<impl IntoResponse, AppError> {
+    let user_id: Uuid = claims.sub;
+
+    let required = if member_id == user_id {
+        ProjectRole::Viewer
+    } else {
+        ProjectRole::Admin
+    };
+    AccessService::project(&database.pool, project_id, user_id, required).await?;
+
+    ProjectMemberService::remove(&database.pool, project_id, user_id, member_id).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(MessageResponse::new("Member removed successfully")),
+    ))
+}
+
+/// Membership changes in the project, newest first
+pub async fn get_project_audit_log(
+    claims: Claims,
+    Path(project_id): Path<Uuid>,
+    Query(pagination): Query<Pagination>,
+    State(database): State<Database>,
+) -> Result<impl IntoResponse, AppError> {
+    pagination.validate()?;
+
+    let user_id: Uuid = claims.sub;
+
+    AccessService::project(&database.pool, project_id, user_id, ProjectRole::Admin).await?;
+
+    let (entries, total) =
+        ProjectMemberService::project_audit_log(&database.pool, project_id, pagination).await?;
+
+    Ok(Json(ListResponse {
+        data: entries,
+        total,
+    }))
+}
+
+/// Changes the user made or that concern them, across all projects
+pub async fn get_audit_log(
+    claims: Claims,
+    Query(pagination): Query<Pagination>,
+    State(database): State<Database>,
+) -> Result<impl IntoResponse, AppError> {
+    pagination.validate()?;
+
+    let user_id: Uuid = claims.sub;
+
+    let (entries, total) =
+        ProjectMemberService::user_audit_log(&database.pool, user_id, pagination).await?;
+
+    Ok(Json(ListResponse {
+        data: entries,
+        total,
+    }))
+}
+
+// ============================================
+// PROJECT TRANSFER HANDLERS
+// ============================================
+
+/// Offer the project to the account behind `req.email`
+pub async fn transfer_project(
+    claims: Claims,
+    Path(project_id): Path<Uuid>,
+    State(database): State<Database>,
+    State(config): State<Config>,
+    Json(req): Json<TransferProjectRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    req.validate()?;
+
+    let user_id: Uuid = claims.sub;
+
+    let project =
+        AccessService::project(&database.pool, project_id, user_id, ProjectRole::Owner).await?;
+
+    let (transfer, token) =
+        ProjectTransferService::initiate(&database.pool, project_id, user_id, req).await?;
+
+    let link = ProjectTransferService::transfer_link(&config.frontend_endpoint, &token);
+    if let Err(e) = ZeptoMail::new()
+        .send_project_transfer(transfer.email.clone(), project.name, link, &config)
+        .await
+    {
+        warn!("Failed to email transfer {}: {}", transfer.id, e);
+    }
+
+    Ok((StatusCode::CREATED, Json(transfer)))
+}
+
+pub async fn get_transfer(
+    claims: Claims,
+    Path(project_id): Path<Uuid>,
+    State(database): State<Database>,
+) -> Result<impl IntoResponse, AppError> {
+    let user_id: Uuid = claims.sub;
+
+    AccessService::project(&database.pool, project_id, user_id, ProjectRole::Owner).await?;
+
+    let transfer = ProjectTransferService::pending(&database.pool, project_id)
+        .await?
+        .ok_or_else(|| AppError::NotFoundError("No transfer is pending".to_string()))?;
+
+    Ok(Json(transfer))
+}
+
+pub async fn delete_transfer(
+    claims: Claims,
+    Path((project_id, transfer_id)): Path<(Uuid, Uuid)>,
+    State(database): State<Database>,
+) -> Result<impl IntoResponse, AppError> {
+    let user_id: Uuid = claims.sub;
+
+    AccessService::project(&database.pool, project_id, user_id, ProjectRole::Owner).await?;
+
+    ProjectTransferService::cancel(&database.pool, project_id, transfer_id, user_id).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(MessageResponse::new("Transfer cancelled successfully")),
+    ))
+}
+
+/// Take over a project with the token from a transfer email
+pub async fn accept_transfer(
+    claims: Claims,
+    State(database): State<Database>,
+    Json(req): Json<AcceptTransferRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    req.validate()?;
+
+    let user_id: Uuid = claims.sub;
+
+    let project =
+        ProjectTransferService::accept(&database.pool, user_id, &req.token, req.organization_id)
+            .await?;
+
+    Ok(Json(project))
+}
+
+// ============================================
+// EXPORT HANDLERS
+// ============================================
+
+pub async fn export_project(
+    claims: Claims,
+    Path(project_id): Path<Uuid>,
+    Query(query): Query<ExportQuery>,
+    State(database): State<Database>,
+    State(kubernetes): State<Kubernetes>,
+) -> Result<impl IntoResponse, AppError> {
+    let user_id: Uuid = claims.sub;
+
+    AccessService::project(&database.pool, project_id, user_id, ProjectRole::Viewer).await?;
+
+    let (name, manifests) = ExportService::project(
+        &database.pool,
+        &kubernetes,
+        project_id,
+        user_id,
+        query.secrets,
+    )
+    .await?;
+
+    Ok(yaml_attachment(&name, manifests))
+}
+
+pub async fn export_deployment(
+    claims: Claims,
+    Path((project_id, deployment_id)): Path<(Uuid, Uuid)>,
+    Query(query): Query<ExportQuery>,
+    State(database): State<Database>,
+    State(kubernetes): State<Kubernetes>,
+) -> Result<impl IntoResponse, AppError> {
+    let user_id: Uuid = claims.sub;
+
+    AccessService::deployment(
+        &database.pool,
+        project_id,
+        deployment_id,
+        user_id,
+        ProjectRole::Viewer,
+    )
+    .await?;
+
+    let (name, manifests) = ExportService::deployment(
+        &database.pool,
+        &kubernetes,
+        deployment_id,
+        user_id,
+        query.secrets,
+    )
+    .await?;
+
+    Ok(yaml_attachment(&name, manifests))
+}
+
+fn yaml_attachment(name: &str, manifests: String) -> impl IntoResponse + use<> {
+    let filename: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+
+    (
+        [
+            (header::CONTENT_TYPE, "application/yaml".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.yaml\"", filename),
+            ),
+        ],
+        manifests,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use axum::response::Response;
+    use bigdecimal::BigDecimal;
+    use futures::StreamExt;
+    use shared::utilities::jwt::TokenType;
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::features::models::{
+        Deployment, DeploymentSort, DeploymentStatus, SortOrder, WebhookDeliveryStatus,
+        WebhookEvent,
+    };
+    use crate::features::repository::{
+        BillingRepository, DeploymentEventRepository, ProjectMemberRepository,
+        WebhookDeliveryRepository,
+    };
+    use crate::features::schemas::{EventSource, ProjectUpdate};
+    use crate::services::cluster::{ClusterBackend, ClusterObject, ObjectKind, ObjectPatch};
+    use crate::services::fake_cluster::{FakeCluster, Verb};
+    use crate::services::kms::LocalKms;
+    use crate::services::kubernetes::RESTARTED_AT_ANNOTATION;
+    use crate::services::runtime_status::CACHE_TTL_SECONDS;
+    use crate::services::status_watcher::StatusWatcher;
+    use crate::utilities::encryption::Keyring;
+
+    struct TestApp {
+        cluster: Arc<FakeCluster>,
+        database: Database,
+        kubernetes: Kubernetes,
+        billing: BillingClient,
+        events: EventBus,
+        encryption: EncryptionService,
+        user_id: Uuid,
+        project_id: Uuid,
+    }
+
+    impl TestApp {
+        async fn new(pool: PgPool) -> Self {
+            let user_id: Uuid = sqlx::query_scalar(
+                "INSERT INTO users (username, email, password) VALUES ('test', 'test@example.com', '') RETURNING id",
+            )
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+            let project = ProjectRepository::create(&pool, user_id, None, "test", None)
+                .await
+                .unwrap();
+
+            let cluster = Arc::new(FakeCluster::new());
+            let keyring = Keyring::new(&Keyring::generate_key()).unwrap();
+
+            Self {
+                kubernetes: Kubernetes::with_cluster(cluster.clone()),
+                cluster,
+                billing: BillingClient::with_url(None),
+                database: Database { pool },
+                events: EventBus::local(),
+                encryption: EncryptionService::new(Arc::new(LocalKms::new(keyring)), None),
+                user_id,
+                project_id: project.id,
+            }
+        }
+
+        fn claims(&self) -> Claims {
+            Claims {
+                sub: self.user_id,
+                typ: TokenType::Access,
+                exp: i64::MAX,
+                iat: 0,
+                org: None,
+            }
+        }
+
+        async fn create_deployment(&self) -> Deployment {
+            let req: CreateDeploymentRequest = serde_json::from_value(serde_json::json!({
+                "name": "api",
+                "image": "nginx:1.27",
+                "replicas": 1,
+                "port": 8080,
+                "secrets": { "API_KEY": "secret" }
+            }))
+            .unwrap();
+
+            let created = DeploymentService::create(
+                &self.database.pool,
+                &self.kubernetes,
+                &self.billing,
+                &self.events,
+                &self.encryption,
+                self.user_id,
+                self.project_id,
+                "example.com",
+                req,
+            )
+            .await
+            .unwrap();
+
+            DeploymentRepository::get_by_id(&self.database.pool, created.id, self.user_id)
+                .await
+                .unwrap()
+        }
+
+        fn replicas(&self, deployment: &Deployment) -> Option<i32> {
+            self.cluster
+                .object(
+                    ObjectKind::Deployment,
+                    &deployment.cluster_namespace,
+                    &deployment.cluster_deployment_name,
+                )
+                .and_then(ClusterObject::into_deployment)
+                .and_then(|d| d.spec)
+                .and_then(|spec| spec.replicas)
+        }
+
+        /// When the pods were last asked to roll
+        fn restarted_at(&self, deployment: &Deployment) -> Option<String> {
+            self.cluster
+                .object(
+                    ObjectKind::Deployment,
+                    &deployment.cluster_namespace,
+                    &deployment.cluster_deployment_name,
+                )
+                .and_then(ClusterObject::into_deployment)
+                .and_then(|d| d.spec)
+                .and_then(|spec| spec.template.metadata)
+                .and_then(|metadata| metadata.annotations)
+                .and_then(|mut annotations| annotations.remove(RESTARTED_AT_ANNOTATION))
+        }
+    }
+
+    /// Billing service answering every balance check with `allowed`
+    async fn billing_stub(allowed: bool) -> BillingClient {
+        let router = axum::Router::new().route(
+            "/internal/v1/balance/check",
+            axum::routing::post(move || async move {
+                Json(serde_json::json!({
+                    "allowed": allowed,
+                    "balance": "1.000000",
+                    "required": "24.552000",
+                    "hours": 24,
+                    "currency": "USD"
+                }))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        BillingClient::with_url(Some(format!("http://{}", addr)))
+    }
+
+    fn status(response: Result<impl IntoResponse, AppError>) -> StatusCode {
+        let response: Response = response.into_response();
+        response.status()
+    }
+
+    async fn json(response: Result<impl IntoResponse, AppError>) -> serde_json::Value {
+        let response: Response = response.into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_deployment_lifecycle(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let deployment = app.create_deployment().await;
+
+        for kind in [
+            ObjectKind::Secret,
+            ObjectKind::Deployment,
+            ObjectKind::Service,
+            ObjectKind::Ingress,
+        ] {
+            assert_eq!(app.cluster.objects(kind).len(), 1, "{}", kind);
+        }
+
+        let response = scale_deployment(
+            app.claims(),
+            Path((app.project_id, deployment.id)),
+            State(app.database.clone()),
+            State(app.kubernetes.clone()),
+            State(app.billing.clone()),
+            State(app.events.clone()),
+            Json(ScaleDeploymentRequest { replicas: 3 }),
+        )
+        .await;
+        assert_eq!(status(response), StatusCode::OK);
+        assert_eq!(app.replicas(&deployment), Some(3));
+
+        let response = pause_deployment(
+            app.claims(),
+            Path((app.project_id, deployment.id)),
+            State(app.database.clone()),
+            State(app.kubernetes.clone()),
+            State(app.events.clone()),
+        )
+        .await;
+        assert_eq!(status(response), StatusCode::OK);
+        assert_eq!(app.replicas(&deployment), Some(0));
+
+        let stored =
+            DeploymentRepository::get_by_id(&app.database.pool, deployment.id, app.user_id)
+                .await
+                .unwrap();
+        assert_eq!(stored.replicas, 3);
+
+        let response = delete_deployment(
+            app.claims(),
+            Path((app.project_id, deployment.id)),
+            State(app.database.clone()),
+            State(app.kubernetes.clone()),
+            State(app.events.clone()),
+        )
+        .await;
+        assert_eq!(status(response), StatusCode::OK);
+        assert!(app.cluster.objects(ObjectKind::Deployment).is_empty());
+        assert!(app.cluster.objects(ObjectKind::Secret).is_empty());
+        assert!(
+            DeploymentRepository::find_by_id(&app.database.pool, deployment.id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_restart_pause_resume(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let deployment = app.create_deployment().await;
+        let pool = &app.database.pool;
+
+        let restart = || {
+            restart_deployment(
+                app.claims(),
+                Path((app.project_id, deployment.id)),
+                State(app.database.clone()),
+                State(app.kubernetes.clone()),
+                State(app.events.clone()),
+            )
+        };
+        let pause = || {
+            pause_deployment(
+                app.claims(),
+                Path((app.project_id, deployment.id)),
+                State(app.database.clone()),
+                State(app.kubernetes.clone()),
+                State(app.events.clone()),
+            )
+        };
+        let resume = || {
+            resume_deployment(
+                app.claims(),
+                Path((app.project_id, deployment.id)),
+                State(app.database.clone()),
+                State(app.kubernetes.clone()),
+                State(app.billing.clone()),
+                State(app.events.clone()),
+            )
+        };
+        let stored = || DeploymentRepository::get_by_id(pool, deployment.id, app.user_id);
+
+        let response = scale_deployment(
+            app.claims(),
+            Path((app.project_id, deployment.id)),
+            State(app.database.clone()),
+            State(app.kubernetes.clone()),
+            State(app.billing.clone()),
+            State(app.events.clone()),
+            Json(ScaleDeploymentRequest { replicas: 2 }),
+        )
+        .await;
+        assert_eq!(status(response), StatusCode::OK);
+
+        // Restarting only touches the pod template
+        assert!(app.restarted_at(&deployment).is_none());
+        assert_eq!(status(restart().await), StatusCode::OK);
+        assert!(app.restarted_at(&deployment).is_some());
+        assert_eq!(app.replicas(&deployment), Some(2));
+
+        let body = json(pause().await).await;
+        assert_eq!(body["status"], "Paused");
+        assert_eq!(body["replicas"], 2);
+        assert_eq!(app.replicas(&deployment), Some(0));
+        let paused = stored().await.unwrap();
+        assert_eq!(paused.status, DeploymentStatus::Paused);
+        assert_eq!(paused.replicas, 2);
+
+        assert_eq!(status(pause().await), StatusCode::CONFLICT);
+        assert_eq!(status(restart().await), StatusCode::CONFLICT);
+
+        // A resume the cluster rejects leaves the deployment paused
+        app.cluster.fail(Verb::Patch, ObjectKind::Deployment);
+        assert_eq!(status(resume().await), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(stored().await.unwrap().status, DeploymentStatus::Paused);
+        assert_eq!(app.replicas(&deployment), Some(0));
+        app.cluster.recover();
+
+        let body = json(resume().await).await;
+        assert_eq!(body["status"], "Running");
+        assert_eq!(app.replicas(&deployment), Some(2));
+        assert_eq!(stored().await.unwrap().status, DeploymentStatus::Running);
+        assert_eq!(status(resume().await), StatusCode::CONFLICT);
+
+        let events = DeploymentEventRepository::get_recent_by_deployment(pool, deployment.id, 10)
+            .await
+            .unwrap();
+        for event_type in [
+            "deployment_restarted",
+            "deployment_paused",
+            "deployment_resumed",
+        ] {
+            assert_eq!(
+                events.iter().filter(|e| e.event_type == event_type).count(),
+                1,
+                "{}",
+                event_type
+            );
+        }
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_runtime_status(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let deployment = app.create_deployment().await;
+        let runtime_status = RuntimeStatusService::local();
+
+        let pod = |name: &str| {
+            serde_json::from_value(serde_json::json!({
+                "metadata": {
+                    "name": name,
+                    "namespace": deployment.cluster_namespace,
+                    "labels": { "deployment-id": deployment.id.to_string() }
+                },
+                "status": {
+                    "phase": "Running",
+                    "containerStatuses": [{
+                        "name": "app",
+                        "image": "nginx:1.27",
+                        "imageID": "",
+                        "ready": true,
+                        "restartCount": 2
+                    }]
+                }
+            }))
+            .unwrap()
+        };
+        let get = || {
+            get_deployment(
+                app.claims(),
+                Path((app.project_id, deployment.id)),
+                State(app.database.clone()),
+                State(app.kubernetes.clone()),
+                State(runtime_status.clone()),
+            )
+        };
+
+        app.cluster.add_pod(pod("api-1"));
+        app.cluster
+            .patch(
+                ObjectKind::Deployment,
+                &deployment.cluster_namespace,
+                &deployment.cluster_deployment_name,
+                &ObjectPatch::Merge(serde_json::json!({ "status": { "readyReplicas": 1 } })),
+            )
+            .await
+            .unwrap();
+
+        let body = json(get().await).await;
+        assert_eq!(body["readyReplicas"], 1);
+        let external_url = body["externalUrl"].as_str().unwrap();
+        assert!(external_url.starts_with("https://"), "{}", external_url);
+        assert!(external_url.ends_with(".example.com"), "{}", external_url);
+        assert_eq!(body["pods"].as_array().unwrap().len(), 1);
+        assert_eq!(body["pods"][0]["ready"], true);
+        assert_eq!(body["pods"][0]["restartCount"], 2);
+
+        // Served from the cache until it expires, pods of other deployments
+        // are never counted
+        app.cluster.add_pod(pod("api-2"));
+        let mut other = pod("other-1");
+        other.metadata.labels =
+            Some([("deployment-id".to_string(), Uuid::new_v4().to_string())].into());
+        app.cluster.add_pod(other);
+        let body = json(get().await).await;
+        assert_eq!(body["pods"].as_array().unwrap().len(), 1);
+
+        tokio::time::sleep(Duration::from_secs(CACHE_TTL_SECONDS) + Duration::from_millis(200))
+            .await;
+        let body = json(get().await).await;
+        assert_eq!(body["pods"].as_array().unwrap().len(), 2);
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_failed_secret_sync_keeps_database(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let deployment = app.create_deployment().await;
+
+        app.cluster.fail(Verb::Replace, ObjectKind::Secret);
+        let response = create_secret(
+            app.claims(),
+            Path((app.project_id, deployment.id)),
+            State(app.database.clone()),
+            State(app.kubernetes.clone()),
+            State(app.events.clone()),
+            State(app.encryption.clone()),
+            Json(CreateSecretRequest {
+                key: "DB_URL".to_string(),
+                value: "postgres://".to_string(),
+            }),
+        )
+        .await;
+        assert_eq!(status(response), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let secrets =
+            DeploymentService::list_secrets(&app.database.pool, deployment.id, app.user_id)
+                .await
+                .unwrap();
+        assert_eq!(secrets.len(), 1);
+
+        app.cluster.recover();
+        let response = create_secret(
+            app.claims(),
+            Path((app.project_id, deployment.id)),
+            State(app.database.clone()),
+            State(app.kubernetes.clone()),
+            State(app.events.clone()),
+            State(app.encryption.clone()),
+            Json(CreateSecretRequest {
+                key: "DB_URL".to_string(),
+                value: "postgres://".to_string(),
+            }),
+        )
+        .await;
+        assert_eq!(status(response), StatusCode::CREATED);
+        assert!(
+            app.cluster
+                .operations()
+                .iter()
+                .any(|op| op.verb == Verb::Replace
+                    && op.kind == ObjectKind::Deployment
+                    && op.name == deployment.cluster_deployment_name)
+        );
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_plan_quota(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let deployment = app.create_deployment().await;
+
+        // The free plan allows 4 replicas and 2000 millicores of CPU
+        let response = scale_deployment(
+            app.claims(),
+            Path((app.project_id, deployment.id)),
+            State(app.database.clone()),
+            State(app.kubernetes.clone()),
+            State(app.billing.clone()),
+            State(app.events.clone()),
+            Json(ScaleDeploymentRequest { replicas: 5 }),
+        )
+        .await;
+        assert_eq!(status(response), StatusCode::PAYMENT_REQUIRED);
+        assert_eq!(app.replicas(&deployment), Some(1));
+
+        let quota = QuotaService::usage(&app.database.pool, BillingAccount::User(app.user_id))
+            .await
+            .unwrap();
+        assert_eq!(quota.plan, "free");
+        assert_eq!(quota.usage.replicas, 1);
+        assert_eq!(quota.usage.cpu_millicores, 500);
+
+        let req: CreateDeploymentRequest = serde_json::from_value(serde_json::json!({
+            "name": "worker",
+            "image": "nginx:1.27",
+            "replicas": 1,
+            "port": 8080,
+            "size": "xlarge"
+        }))
+        .unwrap();
+        let response = DeploymentService::create(
+            &app.database.pool,
+            &app.kubernetes,
+            &app.billing,
+            &app.events,
+            &app.encryption,
+            app.user_id,
+            app.project_id,
+            "example.com",
+            req,
+        )
+        .await;
+        assert!(matches!(response, Err(AppError::QuotaExceededError(_))));
+        assert_eq!(app.cluster.objects(ObjectKind::Deployment).len(), 1);
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_project_spec_apply(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let deployment = app.create_deployment().await;
+        let pool = &app.database.pool;
+
+        let apply = |document: &'static str| {
+            ProjectSpecService::apply(
+                pool,
+                &app.kubernetes,
+                &app.billing,
+                &app.events,
+                &app.encryption,
+                "example.com",
+                app.project_id,
+                app.user_id,
+                document,
+            )
+        };
+        let names = || async {
+            let mut names: Vec<String> =
+                DeploymentRepository::get_all_by_project(pool, app.project_id, app.user_id)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|d| d.name)
+                    .collect();
+            names.sort();
+            names
+        };
+
+        // Each create fits the free plan's 4 replicas alone, not together,
+        // so the plan fails before creating anything
+        let result = apply(
+            r#"
+deployments:
+  - { name: api, image: "nginx:1.27", port: 8080 }
+  - { name: web, image: "nginx:1.27", port: 8080, replicas: 2 }
+  - { name: worker, image: "nginx:1.27", port: 8080, replicas: 2 }
+"#,
+        )
+        .await;
+        assert!(matches!(result, Err(AppError::QuotaExceededError(_))));
+        assert_eq!(names().await, ["api"]);
+        assert_eq!(app.cluster.objects(ObjectKind::Deployment).len(), 1);
+
+        // The update runs after the create, which is undone when it fails
+        app.cluster.fail(Verb::Patch, ObjectKind::Deployment);
+        let document = r#"
+deployments:
+  - { name: api, image: "nginx:1.28", port: 8080 }
+  - { name: web, image: "nginx:1.27", port: 8080 }
+"#;
+        assert!(apply(document).await.is_err());
+        assert_eq!(names().await, ["api"]);
+        assert_eq!(app.cluster.objects(ObjectKind::Deployment).len(), 1);
+        let rolled_back = DeploymentRepository::get_by_id(pool, deployment.id, app.user_id)
+            .await
+            .unwrap();
+        assert_eq!(rolled_back.image, "nginx:1.27");
+        app.cluster.recover();
+
+        // One apply per project at a time
+        let mut connection = pool.acquire().await.unwrap();
+        assert!(
+            ProjectRepository::try_lock_apply(&mut connection, app.project_id)
+                .await
+                .unwrap()
+        );
+        assert!(matches!(
+            apply(document).await,
+            Err(AppError::ConflictError(_))
+        ));
+        ProjectRepository::unlock_apply(&mut connection, app.project_id)
+            .await
+            .unwrap();
+
+        let applied = apply(document).await.unwrap();
+        assert_eq!(applied.created.len(), 1);
+        assert_eq!(applied.updated.len(), 1);
+        assert_eq!(names().await, ["api", "web"]);
+        let updated = DeploymentRepository::get_by_id(pool, deployment.id, app.user_id)
+            .await
+            .unwrap();
+        assert_eq!(updated.image, "nginx:1.28");
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_insufficient_balance(pool: PgPool) {
+        let mut app = TestApp::new(pool).await;
+        let deployment = app.create_deployment().await;
+        app.billing = billing_stub(false).await;
+
+        let response = scale_deployment(
+            app.claims(),
+            Path((app.project_id, deployment.id)),
+            State(app.database.clone()),
+            State(app.kubernetes.clone()),
+            State(app.billing.clone()),
+            State(app.events.clone()),
+            Json(ScaleDeploymentRequest { replicas: 2 }),
+        )
+        .await;
+        let response: Response = response.into_response();
+        assert_eq!(response.status(), StatusCode::PAYMENT_REQUIRED);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "insufficient_balance");
+        assert_eq!(app.replicas(&deployment), Some(1));
+
+        // Nothing grows, so billing isn't asked
+        app.billing = billing_stub(true).await;
+        let response = scale_deployment(
+            app.claims(),
+            Path((app.project_id, deployment.id)),
+            State(app.database.clone()),
+            State(app.kubernetes.clone()),
+            State(app.billing.clone()),
+            State(app.events.clone()),
+            Json(ScaleDeploymentRequest { replicas: 2 }),
+        )
+        .await;
+        assert_eq!(status(response), StatusCode::OK);
+        assert_eq!(app.replicas(&deployment), Some(2));
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_webhook_delivery(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let deployment = app.create_deployment().await;
+
+        // The receiver fails its first request and records every one
+        let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let router = axum::Router::new().route(
+            "/hook",
+            axum::routing::post({
+                let received = received.clone();
+                move |headers: axum::http::HeaderMap, body: String| async move {
+                    let mut received = received.lock().unwrap();
+                    received.push((headers, body));
+                    if received.len() == 1 {
+                        StatusCode::INTERNAL_SERVER_ERROR
+                    } else {
+                        StatusCode::NO_CONTENT
+                    }
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        let secret = "whsec_0123456789abcdef";
+        let create = |url: String| {
+            WebhookService::create(
+                &app.database.pool,
+                &app.encryption,
+                app.project_id,
+                app.user_id,
+                CreateWebhookRequest {
+                    url,
+                    secret: Some(secret.to_string()),
+                    event_types: vec![WebhookEvent::Scaled, WebhookEvent::Deleted],
+                },
+            )
+        };
+        assert!(matches!(
+            create("gopher://example.com/hook".to_string()).await,
+            Err(AppError::ValidationError(_))
+        ));
+        let webhook = create(format!("http://{}/hook", addr)).await.unwrap();
+        assert!(webhook.secret.is_none());
+
+        let response = scale_deployment(
+            app.claims(),
+            Path((app.project_id, deployment.id)),
+            State(app.database.clone()),
+            State(app.kubernetes.clone()),
+            State(app.billing.clone()),
+            State(app.events.clone()),
+            Json(ScaleDeploymentRequest { replicas: 2 }),
+        )
+        .await;
+        assert_eq!(status(response), StatusCode::OK);
+
+        let outbound = OutboundClient::permissive();
+        let dispatch = || WebhookService::dispatch(&app.database.pool, &app.encryption, &outbound);
+        assert_eq!(dispatch().await.unwrap(), 1);
+
+        let (deliveries, total) = WebhookService::deliveries(
+            &app.database.pool,
+            app.project_id,
+            webhook.id,
+            app.user_id,
+            Pagination {
+                offset: 0,
+                limit: 20,
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(total, 1);
+        let failed = &deliveries[0];
+        assert_eq!(failed.event_type, "deployment.scaled");
+        assert_eq!(failed.status, WebhookDeliveryStatus::Pending);
+        assert_eq!(failed.attempts, 1);
+        assert_eq!(failed.response_status, Some(500));
+        assert!(failed.next_attempt_at > chrono::Utc::now());
+        for (offset, limit) in [(-1, 20), (0, -1), (0, 1000)] {
+            let response = get_webhook_deliveries(
+                app.claims(),
+                Path((app.project_id, webhook.id)),
+                Query(Pagination { offset, limit }),
+                State(app.database.clone()),
+            )
+            .await;
+            assert_eq!(status(response), StatusCode::UNPROCESSABLE_ENTITY);
+        }
+
+        // Backing off, so only the redelivery is due
+        let redelivery = WebhookService::redeliver(
+            &app.database.pool,
+            app.project_id,
+            webhook.id,
+            failed.id,
+            app.user_id,
+        )
+        .await
+        .unwrap();
+        assert_eq!(dispatch().await.unwrap(), 1);
+        let delivered =
+            WebhookDeliveryRepository::get_by_id(&app.database.pool, redelivery.id, webhook.id)
+                .await
+                .unwrap();
+        assert_eq!(delivered.status, WebhookDeliveryStatus::Succeeded);
+        assert_eq!(delivered.response_status, Some(204));
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        let (headers, body) = &received[1];
+        let header = |name: &str| headers.get(name).unwrap().to_str().unwrap().to_string();
+        assert_eq!(header("x-webhook-event"), "deployment.scaled");
+        assert_eq!(header("x-webhook-delivery"), redelivery.id.to_string());
+        let timestamp: i64 = header("x-webhook-timestamp").parse().unwrap();
+        assert_eq!(
+            header("x-webhook-signature"),
+            format!(
+                "sha256={}",
+                WebhookService::signature(secret.as_bytes(), timestamp, body).unwrap()
+            )
+        );
+
+        let payload: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(payload["type"], "deployment.scaled");
+        assert_eq!(payload["data"]["deploymentId"], deployment.id.to_string());
+        assert_eq!(payload["data"]["replicas"], 2);
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_timeline(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let deployment = app.create_deployment().await;
+        let namespace = &deployment.cluster_namespace;
+        let name = &deployment.cluster_deployment_name;
+
+        let owned = |kind: &str, object_name: String| {
+            serde_json::json!({
+                "kind": kind,
+                "metadata": {
+                    "name": object_name,
+                    "namespace": namespace,
+                    "labels": { "deployment-id": deployment.id.to_string() }
+                }
+            })
+        };
+        app.cluster.add_replica_set(
+            serde_json::from_value(owned("ReplicaSet", format!("{}-5d4", name))).unwrap(),
+        );
+        app.cluster
+            .add_pod(serde_json::from_value(owned("Pod", format!("{}-5d4-x2x9k", name))).unwrap());
+
+        let event = |uid: &str, kind: &str, object_name: String, reason: &str, minutes: i64| {
+            serde_json::from_value(serde_json::json!({
+                "metadata": { "name": uid, "namespace": namespace, "uid": uid },
+                "involvedObject": { "kind": kind, "name": object_name },
+                "reason": reason,
+                "type": "Normal",
+                "count": 1,
+                "lastTimestamp": deployment.created_at + chrono::Duration::minutes(minutes)
+            }))
+            .unwrap()
+        };
+        app.cluster.add_event(event(
+            "e1",
+            "Deployment",
+            name.clone(),
+            "ScalingReplicaSet",
+            1,
+        ));
+        let mut back_off = event("e2", "Pod", format!("{}-5d4-x2x9k", name), "BackOff", 2);
+        back_off.type_ = Some("Warning".to_string());
+        back_off.count = Some(3);
+        app.cluster.add_event(back_off);
+        // A pod that is gone, and an object that only shares the prefix
+        app.cluster.add_event(event(
+            "e3",
+            "Pod",
+            format!("{}-0ld-aaaaa", name),
+            "Pulled",
+            3,
+        ));
+        app.cluster.add_event(event(
+            "e4",
+            "Deployment",
+            format!("{}-5d4", name),
+            "ScalingReplicaSet",
+            4,
+        ));
+
+        let timeline = |query: TimelineQuery| {
+            get_deployment_timeline(
+                app.claims(),
+                Path((app.project_id, deployment.id)),
+                Query(Pagination {
+                    offset: 0,
+                    limit: 20,
+                }),
+                Query(query),
+                State(app.database.clone()),
+                State(app.kubernetes.clone()),
+            )
+        };
+
+        let body = json(timeline(TimelineQuery::default()).await).await;
+        assert_eq!(body["total"], 3);
+        let types: Vec<&str> = body["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["eventType"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            types,
+            ["BackOff", "ScalingReplicaSet", "deployment_created"]
+        );
+        let back_off = &body["data"][0];
+        assert_eq!(back_off["source"], "kubernetes");
+        assert_eq!(back_off["severity"], "Warning");
+        assert_eq!(back_off["count"], 3);
+        assert_eq!(
+            back_off["object"].as_str().unwrap(),
+            format!("Pod/{}-5d4-x2x9k", name)
+        );
+
+        let body = json(
+            timeline(TimelineQuery {
+                source: Some(EventSource::Platform),
+                ..Default::default()
+            })
+            .await,
+        )
+        .await;
+        assert_eq!(body["total"], 1);
+        let body = json(
+            timeline(TimelineQuery {
+                event_type: Some("BackOff".to_string()),
+                ..Default::default()
+            })
+            .await,
+        )
+        .await;
+        assert_eq!(body["total"], 1);
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_timeline_stream(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let deployment = app.create_deployment().await;
+        let pool = &app.database.pool;
+
+        app.cluster.add_event(
+            serde_json::from_value(serde_json::json!({
+                "metadata": { "name": "e1", "namespace": deployment.cluster_namespace, "uid": "e1" },
+                "involvedObject": { "kind": "Deployment", "name": deployment.cluster_deployment_name },
+                "reason": "ScalingReplicaSet",
+                "lastTimestamp": deployment.created_at + chrono::Duration::seconds(1)
+            }))
+            .unwrap(),
+        );
+
+        let query = TimelineQuery {
+            from: Some(deployment.created_at - chrono::Duration::minutes(1)),
+            ..Default::default()
+        };
+        let stream = TimelineService::stream(
+            pool.clone(),
+            app.kubernetes.clone(),
+            app.events.clone(),
+            deployment.id,
+            app.user_id,
+            query,
+        )
+        .await
+        .unwrap();
+        let mut stream = Box::pin(stream);
+        async fn next<S, E>(stream: &mut S) -> String
+        where
+            S: futures::Stream<Item = Result<E, std::convert::Infallible>> + Unpin,
+            E: std::fmt::Debug,
+        {
+            let event = tokio::time::timeout(Duration::from_secs(5), stream.next())
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            format!("{:?}", event)
+        }
+
+        // Stored events first, then what is recorded while connected, once
+        assert!(next(&mut stream).await.contains("deployment_created"));
+        assert!(next(&mut stream).await.contains("ScalingReplicaSet"));
+        app.events
+            .record(pool, &deployment, "deployment_scaled", Some("Scaled to 2"))
+            .await
+            .unwrap();
+        let live = next(&mut stream).await;
+        assert!(live.contains("deployment_scaled"));
+        assert!(!live.contains("deployment_created"));
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_status_updates(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let deployment = app.create_deployment().await;
+        let pool = &app.database.pool;
+
+        let mut updates = Box::pin(app.events.updates(app.project_id));
+        let mut other_project = Box::pin(app.events.updates(Uuid::new_v4()));
+        async fn next<S>(updates: &mut S) -> ProjectUpdate
+        where
+            S: futures::Stream<Item = ProjectUpdate> + Unpin,
+        {
+            tokio::time::timeout(Duration::from_secs(5), updates.next())
+                .await
+                .unwrap()
+                .unwrap()
+        }
+        let watched = || {
+            app.cluster
+                .object(
+                    ObjectKind::Deployment,
+                    &deployment.cluster_namespace,
+                    &deployment.cluster_deployment_name,
+                )
+                .and_then(ClusterObject::into_deployment)
+                .unwrap()
+        };
+
+        // The watcher sees the rollout finish
+        app.cluster
+            .patch(
+                ObjectKind::Deployment,
+                &deployment.cluster_namespace,
+                &deployment.cluster_deployment_name,
+                &ObjectPatch::Merge(serde_json::json!({
+                    "status": {
+                        "replicas": 1,
+                        "readyReplicas": 1,
+                        "availableReplicas": 1,
+                        "updatedReplicas": 1
+                    }
+                })),
+            )
+            .await
+            .unwrap();
+        StatusWatcher::publish(pool, &app.events, &watched())
+            .await
+            .unwrap();
+        assert!(matches!(
+            next(&mut updates).await,
+            ProjectUpdate::Status { deployment_id, ready_replicas: Some(1), .. }
+                if deployment_id == deployment.id
+        ));
+        assert!(matches!(
+            next(&mut updates).await,
+            ProjectUpdate::Event { event, .. } if event.event_type == "deployment_ready"
+        ));
+
+        // Watching the same rollout again doesn't record it twice, and
+        // Deployments we don't manage are skipped
+        StatusWatcher::publish(pool, &app.events, &watched())
+            .await
+            .unwrap();
+        assert!(matches!(
+            next(&mut updates).await,
+            ProjectUpdate::Status { .. }
+        ));
+        let mut unmanaged = watched();
+        unmanaged.metadata.labels = None;
+        StatusWatcher::publish(pool, &app.events, &unmanaged)
+            .await
+            .unwrap();
+
+        // Changes made through the API are pushed as they happen
+        let response = pause_deployment(
+            app.claims(),
+            Path((app.project_id, deployment.id)),
+            State(app.database.clone()),
+            State(app.kubernetes.clone()),
+            State(app.events.clone()),
+        )
+        .await;
+        assert_eq!(status(response), StatusCode::OK);
+        assert!(matches!(
+            next(&mut updates).await,
+            ProjectUpdate::Event { event, .. } if event.event_type == "deployment_paused"
+        ));
+        assert!(matches!(
+            next(&mut updates).await,
+            ProjectUpdate::Status {
+                status: DeploymentStatus::Paused,
+                replicas: 1,
+                ready_replicas: None,
+                ..
+            }
+        ));
+
+        let events = DeploymentEventRepository::get_recent_by_deployment(pool, deployment.id, 10)
+            .await
+            .unwrap();
+        assert_eq!(
+            events
+                .iter()
+                .filter(|e| e.event_type == "deployment_ready")
+                .count(),
+            1
+        );
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), other_project.next())
+                .await
+                .is_err()
+        );
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_deploy_hook(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let deployment = app.create_deployment().await;
+        let pool = &app.database.pool;
+
+        let create = |name: &str| {
+            DeployHookService::create(
+                pool,
+                "https://api.example.com/",
+                deployment.id,
+                app.user_id,
+                CreateDeployHookRequest {
+                    name: name.to_string(),
+                },
+            )
+        };
+        let hook = create("ci").await.unwrap();
+        let url = hook.url.unwrap();
+        let token = url
+            .strip_prefix("https://api.example.com/api/v1/deploy-hooks/")
+            .unwrap();
+        assert!(token.starts_with(&hook.token_prefix));
+        assert!(matches!(
+            create("ci").await,
+            Err(AppError::ConflictError(_))
+        ));
+
+        // Only the prefix can be read back
+        let hooks = DeployHookService::list(pool, deployment.id, app.user_id)
+            .await
+            .unwrap();
+        assert_eq!(hooks.len(), 1);
+        assert!(hooks[0].url.is_none());
+
+        let stored = DeployHookService::authenticate(pool, token).await.unwrap();
+        DeployHookService::deploy(
+            pool,
+            &app.kubernetes,
+            &app.billing,
+            &app.events,
+            &stored,
+            Some("v2".to_string()),
+        )
+        .await
+        .unwrap();
+
+        let updated = DeploymentRepository::get_by_id(pool, deployment.id, app.user_id)
+            .await
+            .unwrap();
+        assert_eq!(updated.image, "nginx:v2");
+        let image = app
+            .cluster
+            .object(
+                ObjectKind::Deployment,
+                &deployment.cluster_namespace,
+                &deployment.cluster_deployment_name,
+            )
+            .and_then(ClusterObject::into_deployment)
+            .and_then(|d| d.spec)
+            .and_then(|spec| spec.template.spec)
+            .and_then(|spec| spec.containers[0].image.clone());
+        assert_eq!(image.as_deref(), Some("nginx:v2"));
+
+        let events = DeploymentEventRepository::get_recent_by_deployment(pool, deployment.id, 10)
+            .await
+            .unwrap();
+        assert!(
+            events
+                .iter()
+                .any(|e| e.event_type == "deploy_hook_triggered"
+                    && e.message.as_deref() == Some("Deploy hook 'ci' deploying nginx:v2"))
+        );
+
+        // A regenerated hook no longer answers to its old URL
+        let regenerated = DeployHookService::regenerate(
+            pool,
+            "https://api.example.com",
+            deployment.id,
+            hook.id,
+            app.user_id,
+        )
+        .await
+        .unwrap();
+        assert!(matches!(
+            DeployHookService::authenticate(pool, token).await,
+            Err(AppError::NotFoundError(_))
+        ));
+        let new_token = regenerated
+            .url
+            .unwrap()
+            .rsplit('/')
+            .next()
+            .unwrap()
+            .to_string();
+        assert!(
+            DeployHookService::authenticate(pool, &new_token)
+                .await
+                .is_ok()
+        );
+
+        DeployHookService::revoke(pool, deployment.id, hook.id, app.user_id)
+            .await
+            .unwrap();
+        assert!(matches!(
+            DeployHookService::authenticate(pool, &new_token).await,
+            Err(AppError::NotFoundError(_))
+        ));
+
+        // The hook keeps restarting after its creator lost access to the project
+        let hook = create("release").await.unwrap();
+        let token = hook.url.unwrap().rsplit('/').next().unwrap().to_string();
+        sqlx::query("DELETE FROM project_members WHERE project_id = $1 AND user_id = $2")
+            .bind(app.project_id)
+            .bind(app.user_id)
+            .execute(pool)
+            .await
+            .unwrap();
+        let stored = DeployHookService::authenticate(pool, &token).await.unwrap();
+        DeployHookService::deploy(
+            pool,
+            &app.kubernetes,
+            &app.billing,
+            &app.events,
+            &stored,
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert!(app.restarted_at(&deployment).is_some());
+        let events = DeploymentEventRepository::get_recent_by_deployment(pool, deployment.id, 10)
+            .await
+            .unwrap();
+        assert!(
+            events
+                .iter()
+                .any(|e| e.event_type == "deploy_hook_triggered"
+                    && e.message.as_deref() == Some("Deploy hook 'release' requested a restart"))
+        );
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_preview_sweep(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let base = app.create_deployment().await;
+        let pool = &app.database.pool;
+        let outbound = OutboundClient::permissive();
+
+        let request = |image_tag: &str| CreatePreviewRequest {
+            name: "pr-42".to_string(),
+            image: None,
+            image_tag: Some(image_tag.to_string()),
+            ttl_hours: None,
+            callback_url: None,
+        };
+        assert!(request("v2; rm -rf /").validate().is_err());
+
+        let (preview, created) = PreviewService::upsert(
+            pool,
+            &app.kubernetes,
+            &app.billing,
+            &app.events,
+            &app.encryption,
+            &outbound,
+            "example.com",
+            base.id,
+            app.user_id,
+            request("v2"),
+        )
+        .await
+        .unwrap();
+        assert!(created);
+        let deployment = DeploymentRepository::find_by_id(pool, preview.deployment_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(deployment.image, "nginx:v2");
+
+        // Expired previews go even after their creator lost access to the project
+        sqlx::query("DELETE FROM project_members WHERE project_id = $1 AND user_id = $2")
+            .bind(app.project_id)
+            .bind(app.user_id)
+            .execute(pool)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE deployment_previews SET expires_at = NOW() - INTERVAL '1 minute'")
+            .execute(pool)
+            .await
+            .unwrap();
+        PreviewService::sweep(pool, &app.kubernetes, &app.events, &outbound)
+            .await
+            .unwrap();
+
+        assert!(
+            DeploymentRepository::find_by_id(pool, deployment.id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            app.cluster
+                .object(
+                    ObjectKind::Deployment,
+                    &deployment.cluster_namespace,
+                    &deployment.cluster_deployment_name,
+                )
+                .is_none()
+        );
+        // The base is no preview and stays
+        assert!(
+            DeploymentRepository::find_by_id(pool, base.id)
+                .await
+                .unwrap()
+                .is_some()
+        );
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_project_members(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let pool = &app.database.pool;
+        let deployment = app.create_deployment().await;
+
+        let member_id: Uuid = sqlx::query_scalar(
+            "INSERT INTO users (username, email, password) VALUES ('member', 'member@example.com', '') RETURNING id",
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        let member = || Claims {
+            sub: member_id,
+            typ: TokenType::Access,
+            exp: i64::MAX,
+            iat: 0,
+            org: None,
+        };
+
+        // Not a member yet, the project doesn't exist for them
+        let response =
+            get_project(member(), Path(app.project_id), State(app.database.clone())).await;
+        assert_eq!(status(response), StatusCode::NOT_FOUND);
+
+        let (_, token) = ProjectMemberService::invite(
+            pool,
+            app.project_id,
+            app.user_id,
+            InviteMemberRequest {
+                email: "Member@example.com".to_string(),
+                role: ProjectRole::Viewer,
+            },
+        )
+        .await
+        .unwrap();
+        assert!(matches!(
+            ProjectMemberService::accept(pool, app.user_id, &token).await,
+            Err(AppError::ForbiddenError(_))
+        ));
+        let project = ProjectMemberService::accept(pool, member_id, &token)
+            .await
+            .unwrap();
+        assert_eq!(project.id, app.project_id);
+        assert!(matches!(
+            ProjectMemberService::accept(pool, member_id, &token).await,
+            Err(AppError::NotFoundError(_))
+        ));
+
+        // Viewers can read but not change deployments
+        let response = get_deployments(
+            member(),
+            Path(app.project_id),
+            Query(Pagination {
+                offset: 0,
+                limit: 20,
+            }),
+            Query(DeploymentListQuery::default()),
+            State(app.database.clone()),
+        )
+        .await;
+        assert_eq!(status(response), StatusCode::OK);
+        let scale = |claims: Claims| {
+            scale_deployment(
+                claims,
+                Path((app.project_id, deployment.id)),
+                State(app.database.clone()),
+                State(app.kubernetes.clone()),
+                State(app.billing.clone()),
+                State(app.events.clone()),
+                Json(ScaleDeploymentRequest { replicas: 2 }),
+            )
+        };
+        assert_eq!(status(scale(member()).await), StatusCode::FORBIDDEN);
+
+        ProjectMemberService::update_role(
+            pool,
+            app.project_id,
+            app.user_id,
+            member_id,
+            ProjectRole::Developer,
+        )
+        .await
+        .unwrap();
+        assert_eq!(status(scale(member()).await), StatusCode::OK);
+        assert_eq!(app.replicas(&deployment), Some(2));
+
+        // Still billed to the owner, whoever scaled it
+        let stored = DeploymentRepository::find_by_id(pool, deployment.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.user_id, app.user_id);
+
+        assert!(matches!(
+            ProjectMemberService::remove(pool, app.project_id, member_id, app.user_id).await,
+            Err(AppError::ForbiddenError(_))
+        ));
+        ProjectMemberService::remove(pool, app.project_id, app.user_id, member_id)
+            .await
+            .unwrap();
+        assert_eq!(status(scale(member()).await), StatusCode::NOT_FOUND);
+
+        let (entries, total) = ProjectMemberService::project_audit_log(
+            pool,
+            app.project_id,
+            Pagination {
+                offset: 0,
+                limit: 20,
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(total, 4);
+        let actions: Vec<&str> = entries.iter().map(|e| e.action.as_str()).collect();
+        assert_eq!(
+            actions,
+            [
+                "member_removed",
+                "member_role_changed",
+                "member_joined",
+                "member_invited"
+            ]
+        );
+
+        let invalid = || {
+            Query(Pagination {
+                offset: -1,
+                limit: 20,
+            })
+        };
+        let response = get_project_audit_log(
+            app.claims(),
+            Path(app.project_id),
+            invalid(),
+            State(app.database.clone()),
+        )
+        .await;
+        assert_eq!(status(response), StatusCode::UNPROCESSABLE_ENTITY);
+        let response = get_audit_log(app.claims(), invalid(), State(app.database.clone())).await;
+        assert_eq!(status(response), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_organization_projects(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let pool = &app.database.pool;
+
+        let organization_id: Uuid = sqlx::query_scalar(
+            "INSERT INTO organizations (name, slug) VALUES ('Acme', 'acme') RETURNING id",
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        let member_id: Uuid = sqlx::query_scalar(
+            "INSERT INTO users (username, email, password) VALUES ('member', 'member@example.com', '') RETURNING id",
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO organization_members (organization_id, user_id, role) VALUES ($1, $2, 'owner'), ($1, $3, 'member')",
+        )
+        .bind(organization_id)
+        .bind(app.user_id)
+        .bind(member_id)
+        .execute(pool)
+        .await
+        .unwrap();
+        let member = |org: Option<Uuid>| Claims {
+            sub: member_id,
+            typ: TokenType::Access,
+            exp: i64::MAX,
+            iat: 0,
+            org,
+        };
+
+        // Same name as the owner's own project, names are unique per account
+        let project =
+            ProjectRepository::create(pool, app.user_id, Some(organization_id), "test", None)
+                .await
+                .unwrap();
+
+        // Members develop on every project of the organization
+        let role = ProjectMemberRepository::get_access_role(pool, project.id, member_id)
+            .await
+            .unwrap();
+        assert_eq!(role, Some(ProjectRole::Developer));
+        let response = get_project(
+            member(None),
+            Path(app.project_id),
+            State(app.database.clone()),
+        )
+        .await;
+        assert_eq!(status(response), StatusCode::NOT_FOUND);
+
+        let list = |org: Option<Uuid>| {
+            ProjectRepository::get_many_by_user_id(
+                pool,
+                member_id,
+                org,
+                None,
+                Pagination {
+                    offset: 0,
+                    limit: 20,
+                },
+            )
+        };
+        let (projects, _) = list(Some(organization_id)).await.unwrap();
+        assert_eq!(
+            projects.iter().map(|p| p.id).collect::<Vec<_>>(),
+            [project.id]
+        );
+        let (projects, _) = list(None).await.unwrap();
+        assert!(projects.is_empty());
+
+        // A token for an organization they left is refused
+        let outsider = Claims {
+            sub: member_id,
+            typ: TokenType::Access,
+            exp: i64::MAX,
+            iat: 0,
+            org: Some(Uuid::new_v4()),
+        };
+        let response = get_quota(outsider, State(app.database.clone())).await;
+        assert_eq!(status(response), StatusCode::NOT_FOUND);
+
+        // A member's deployment belongs to the project's creator, and counts
+        // against the organization rather than either of them
+        let req: CreateDeploymentRequest = serde_json::from_value(serde_json::json!({
+            "name": "api",
+            "image": "nginx:1.27",
+            "replicas": 1,
+            "port": 8080
+        }))
+        .unwrap();
+        let created = DeploymentService::create(
+            pool,
+            &app.kubernetes,
+            &app.billing,
+            &app.events,
+            &app.encryption,
+            member_id,
+            project.id,
+            "example.com",
+            req,
+        )
+        .await
+        .unwrap();
+        let stored = DeploymentRepository::find_by_id(pool, created.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.user_id, app.user_id);
+
+        let account = BillingAccount::Organization(organization_id);
+        let quota = QuotaService::usage(pool, account).await.unwrap();
+        assert_eq!(quota.usage.deployments, 1);
+        let quota = QuotaService::usage(pool, BillingAccount::User(app.user_id))
+            .await
+            .unwrap();
+        assert_eq!(quota.usage.deployments, 0);
+
+        // Organizations start without free credit
+        let balance = BillingRepository::get_balance(pool, account).await.unwrap();
+        assert_eq!(balance.amount, BigDecimal::from(0));
+
+        // Only admins see the organization's balance in estimates
+        let estimate = |claims: Claims| {
+            let req: CreateDeploymentRequest = serde_json::from_value(serde_json::json!({
+                "name": "worker",
+                "image": "nginx:1.27",
+                "replicas": 1,
+                "port": 8080
+            }))
+            .unwrap();
+            estimate_deployment(
+                claims,
+                Path(project.id),
+                State(app.database.clone()),
+                Json(req),
+            )
+        };
+        let body = json(estimate(member(Some(organization_id))).await).await;
+        assert!(body["hourly"].is_string());
+        assert!(body.get("balance").is_none());
+        assert!(body.get("runwayDays").is_none());
+        let body = json(estimate(app.claims()).await).await;
+        assert!(body.get("balance").is_some());
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_duplicate_outside_organization(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let pool = &app.database.pool;
+
+        let organization_id: Uuid = sqlx::query_scalar(
+            "INSERT INTO organizations (name, slug) VALUES ('Acme', 'acme') RETURNING id",
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        let collaborator_id: Uuid = sqlx::query_scalar(
+            "INSERT INTO users (username, email, password) VALUES ('collaborator', 'collaborator@example.com', '') RETURNING id",
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO organization_members (organization_id, user_id, role) VALUES ($1, $2, 'owner')",
+        )
+        .bind(organization_id)
+        .bind(app.user_id)
+        .execute(pool)
+        .await
+        .unwrap();
+        let project =
+            ProjectRepository::create(pool, app.user_id, Some(organization_id), "shared", None)
+                .await
+                .unwrap();
+
+        // Invited onto the project alone, not into the organization
+        sqlx::query(
+            "INSERT INTO project_members (project_id, user_id, role) VALUES ($1, $2, 'developer')",
+        )
+        .bind(project.id)
+        .bind(collaborator_id)
+        .execute(pool)
+        .await
+        .unwrap();
+
+        let duplicate = |user_id: Uuid, name: &str| {
+            DuplicateService::duplicate_project(
+                pool,
+                &app.kubernetes,
+                &app.billing,
+                &app.events,
+                &app.encryption,
+                "example.com",
+                project.id,
+                user_id,
+                DuplicateProjectRequest {
+                    name: name.to_string(),
+                    description: None,
+                    replicas: None,
+                },
+            )
+        };
+
+        // Their copy lands in their own account, not the organization's
+        let copy = duplicate(collaborator_id, "mine").await.unwrap();
+        assert_eq!(copy.project.organization_id, None);
+        assert_eq!(
+            ProjectRepository::get_billing_account(pool, copy.project.id)
+                .await
+                .unwrap(),
+            BillingAccount::User(collaborator_id)
+        );
+
+        // Members still copy into the organization
+        let copy = duplicate(app.user_id, "ours").await.unwrap();
+        assert_eq!(copy.project.organization_id, Some(organization_id));
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_project_transfer(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let pool = &app.database.pool;
+        let deployment = app.create_deployment().await;
+
+        let recipient_id: Uuid = sqlx::query_scalar(
+            "INSERT INTO users (username, email, password) VALUES ('client', 'client@example.com', '') RETURNING id",
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap();
+
+        let (_, token) = ProjectTransferService::initiate(
+            pool,
+            app.project_id,
+            app.user_id,
+            TransferProjectRequest {
+                email: "client@example.com".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        assert!(matches!(
+            ProjectTransferService::accept(pool, app.user_id, &token, None).await,
+            Err(AppError::ForbiddenError(_))
+        ));
+
+        let project = ProjectTransferService::accept(pool, recipient_id, &token, None)
+            .await
+            .unwrap();
+        assert_eq!(project.owner_id, recipient_id);
+        assert!(matches!(
+            ProjectTransferService::accept(pool, recipient_id, &token, None).await,
+            Err(AppError::NotFoundError(_))
+        ));
+
+        // The deployment moved along and kept running where it was
+        let stored = DeploymentRepository::find_by_id(pool, deployment.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.user_id, recipient_id);
+        assert_eq!(app.replicas(&deployment), Some(1));
+        let quota = QuotaService::usage(pool, BillingAccount::User(recipient_id))
+            .await
+            .unwrap();
+        assert_eq!(quota.usage.deployments, 1);
+
+        // The previous owner is out, the recipient owns it
+        let response = get_project(
+            app.claims(),
+            Path(app.project_id),
+            State(app.database.clone()),
+        )
+        .await;
+        assert_eq!(status(response), StatusCode::NOT_FOUND);
+        let role = ProjectMemberRepository::get_role(pool, app.project_id, recipient_id)
+            .await
+            .unwrap();
+        assert_eq!(role, Some(ProjectRole::Owner));
+
+        let pagination = || Pagination {
+            offset: 0,
+            limit: 20,
+        };
+        for user_id in [app.user_id, recipient_id] {
+            let (entries, _) = ProjectMemberService::user_audit_log(pool, user_id, pagination())
+                .await
+                .unwrap();
+            assert_eq!(entries[0].action, "project_transferred");
+        }
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn test_deployment_list(pool: PgPool) {
+        let app = TestApp::new(pool).await;
+        let pool = &app.database.pool;
+
+        for (name, image, labels) in [
+            (
+                "api",
+                "nginx:1.27",
+                serde_json::json!({ "tier": "web", "env": "prod" }),
+            ),
+            (
+                "worker",
+                "ghcr.io/acme/worker:2",
+                serde_json::json!({ "env": "prod" }),
+            ),
+            (
+                "admin",
+                "nginx:1.25",
+                serde_json::json!({ "env": "staging" }),
+            ),
+        ] {
+            let req: CreateDeploymentRequest = serde_json::from_value(serde_json::json!({
+                "name": name,
+                "image": image,
+                "replicas": 1,
+                "port": 8080,
+                "labels": labels
+            }))
+            .unwrap();
+            DeploymentService::create(
+                pool,
+                &app.kubernetes,
+                &app.billing,
+                &app.events,
+                &app.encryption,
+                app.user_id,
+                app.project_id,
+                "example.com",
+                req,
+            )
+            .await
+            .unwrap();
+        }
+
+        let app = &app;
+        let list = |query: DeploymentListQuery, limit: i64| async move {
+            let response = get_deployments(
+                app.claims(),
+                Path(app.project_id),
+                Query(Pagination { offset: 0, limit }),
+                Query(query),
+                State(app.database.clone()),
+            )
+            .await;
+            let body = json(response).await;
+            let names: Vec<String> = body["data"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|d| d["name"].as_str().unwrap().to_string())
+                .collect();
+            (names, body["total"].as_i64().unwrap())
+        };
+
+        let by_name = DeploymentListQuery {
+            sort: DeploymentSort::Name,
+            order: SortOrder::Asc,
+            ..Default::default()
+        };
+        assert_eq!(
+            list(by_name, 2).await,
+            (vec!["admin".into(), "api".into()], 3)
+        );
+
+        let nginx = DeploymentListQuery {
+            image: Some("nginx".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            list(nginx, 20).await,
+            (vec!["admin".into(), "api".into()], 2)
+        );
+
+        let prod = DeploymentListQuery {
+            labels: Some("env=prod, tier".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(list(prod, 20).await, (vec!["api".into()], 1));
+
+        // Statuses sort by name, so paused comes before pending
+        let worker = DeploymentRepository::get_all_by_project(pool, app.project_id, app.user_id)
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|d| d.name == "worker")
+            .unwrap();
+        DeploymentService::pause(pool, &app.kubernetes, &app.events, worker.id, app.user_id)
+            .await
+            .unwrap();
+        let by_status = |order: SortOrder| DeploymentListQuery {
+            sort: DeploymentSort::Status,
+            order,
+            ..Default::default()
+        };
+        let (names, _) = list(by_status(SortOrder::Asc), 20).await;
+        assert_eq!(names[0], "worker");
+        let (names, _) = list(by_status(SortOrder::Desc), 20).await;
+        assert_eq!(names[2], "worker");
+
+        let invalid = DeploymentListQuery {
+            labels: Some("=prod".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            invalid.label_selector(),
+            Err(AppError::ValidationError(_))
+        ));
+
+        let response = get_deployments(
+            app.claims(),
+            Path(app.project_id),
+            Query(Pagination {
+                offset: 0,
+                limit: 0,
+            }),
+            Query(DeploymentListQuery::default()),
+            State(app.database.clone()),
+        )
+        .await;
+        assert_eq!(status(response), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
@@ -7,7 +7,10 @@ pub mod websocket;
 
 use crate::utilities::app_state::AppState;
 
-use axum::{Router, routing::get};
+use axum::{
+    Router,
+    routing::{delete, get, patch, post, put},
+};
 
 pub fn routes() -> Router<AppState> {
     Router::new()
@@ -22,15 +25,173 @@ pub fn routes() -> Router<AppState> {
                 .patch(handlers::update_project)
                 .delete(handlers::delete_project),
         )
+        .route(
+            "/api/v1/projects/{project_id}/stream",
+            get(handlers::stream_project_updates),
+        )
+        .route(
+            "/api/v1/projects/{project_id}/export",
+            get(handlers::export_project),
+        )
+        .route(
+            "/api/v1/projects/{project_id}/duplicate",
+            post(handlers::duplicate_project),
+        )
+        // Project spec
+        .route(
+            "/api/v1/projects/{project_id}/spec/plan",
+            post(handlers::plan_project_spec),
+        )
+        .route(
+            "/api/v1/projects/{project_id}/spec/apply",
+            post(handlers::apply_project_spec),
+        )
+        // Webhooks
+        .route(
+            "/api/v1/projects/{project_id}/webhooks",
+            get(handlers::get_webhooks).post(handlers::create_webhook),
+        )
+        .route(
+            "/api/v1/projects/{project_id}/webhooks/{webhook_id}",
+            patch(handlers::update_webhook).delete(handlers::delete_webhook),
+        )
+        .route(
+            "/api/v1/projects/{project_id}/webhooks/{webhook_id}/deliveries",
+            get(handlers::get_webhook_deliveries),
+        )
+        .route(
+            "/api/v1/projects/{project_id}/webhooks/{webhook_id}/deliveries/{delivery_id}/redeliver",
+            post(handlers::redeliver_webhook),
+        )
+        // Members
+        .route(
+            "/api/v1/projects/{project_id}/members",
+            get(handlers::get_members),
+        )
+        .route(
+            "/api/v1/projects/{project_id}/members/{member_id}",
+            patch(handlers::update_member).delete(handlers::delete_member),
+        )
+        .route(
+            "/api/v1/projects/{project_id}/invitations",
+            get(handlers::get_invitations).post(handlers::invite_member),
+        )
+        .route(
+            "/api/v1/projects/{project_id}/invitations/{invitation_id}",
+            delete(handlers::delete_invitation),
+        )
+        .route(
+            "/api/v1/invitations/accept",
+            post(handlers::accept_invitation),
+        )
+        // Transfers
+        .route(
+            "/api/v1/projects/{project_id}/transfer",
+            get(handlers::get_transfer).post(handlers::transfer_project),
+        )
+        .route(
+            "/api/v1/projects/{project_id}/transfer/{transfer_id}",
+            delete(handlers::delete_transfer),
+        )
+        .route("/api/v1/transfers/accept", post(handlers::accept_transfer))
+        // Audit log
+        .route(
+            "/api/v1/projects/{project_id}/audit-log",
+            get(handlers::get_project_audit_log),
+        )
+        .route("/api/v1/audit-log", get(handlers::get_audit_log))
+        // Regions
+        .route("/api/v1/regions", get(handlers::get_regions))
+        // Quota
+        .route("/api/v1/quota", get(handlers::get_quota))
         // Deployments
         .route(
             "/api/v1/projects/{project_id}/deployments",
             get(handlers::get_deployments).post(handlers::create_deployment),
         )
+        .route(
+            "/api/v1/projects/{project_id}/deployments/estimate",
+            post(handlers::estimate_deployment),
+        )
+        .route(
+            "/api/v1/projects/{project_id}/import/compose",
+            post(handlers::import_compose),
+        )
         .route(
             "/api/v1/projects/{project_id}/deployments/{deployment_id}",
             get(handlers::get_deployment)
                 .patch(handlers::scale_deployment)
                 .delete(handlers::delete_deployment),
         )
+        // Deployment actions
+        .route(
+            "/api/v1/projects/{project_id}/deployments/{deployment_id}/estimate",
+            post(handlers::estimate_deployment_change),
+        )
+        .route(
+            "/api/v1/projects/{project_id}/deployments/{deployment_id}/restart",
+            post(handlers::restart_deployment),
+        )
+        .route(
+            "/api/v1/projects/{project_id}/deployments/{deployment_id}/pause",
+            post(handlers::pause_deployment),
+        )
+        .route(
+            "/api/v1/projects/{project_id}/deployments/{deployment_id}/resume",
+            post(handlers::resume_deployment),
+        )
+        .route(
+            "/api/v1/projects/{project_id}/deployments/{deployment_id}/clone",
+            post(handlers::clone_deployment),
+        )
+        // Deployment previews
+        .route(
+            "/api/v1/projects/{project_id}/deployments/{deployment_id}/previews",
+            get(handlers::get_previews).post(handlers::create_preview),
+        )
+        // Deployment timeline
+        .route(
+            "/api/v1/projects/{project_id}/deployments/{deployment_id}/timeline",
+            get(handlers::get_deployment_timeline),
+        )
+        .route(
+            "/api/v1/projects/{project_id}/deployments/{deployment_id}/timeline/stream",
+            get(handlers::stream_deployment_timeline),
+        )
+        // Deployment secrets
+        .route(
+            "/api/v1/projects/{project_id}/deployments/{deployment_id}/secrets",
+            get(handlers::get_secrets).post(handlers::create_secret),
+        )
+        .route(
+            "/api/v1/projects/{project_id}/deployments/{deployment_id}/secrets/{key}",
+            put(handlers::replace_secret).delete(handlers::delete_secret),
+        )
+        // Deploy hooks
+        .route(
+            "/api/v1/projects/{project_id}/deployments/{deployment_id}/hooks",
+            get(handlers::get_deploy_hooks).post(handlers::create_deploy_hook),
+        )
+        .route(
+            "/api/v1/projects/{project_id}/deployments/{deployment_id}/hooks/{hook_id}",
+            delete(handlers::delete_deploy_hook),
+        )
+        .route(
+            "/api/v1/projects/{project_id}/deployments/{deployment_id}/hooks/{hook_id}/regenerate",
+            post(handlers::regenerate_deploy_hook),
+        )
+        .route(
+            "/api/v1/deploy-hooks/{token}",
+            post(handlers::trigger_deploy_hook),
+        )
+        // Deployment metrics
+        .route(
+            "/api/v1/projects/{project_id}/deployments/{deployment_id}/metrics",
+            get(handlers::get_deployment_metrics),
+        )
+        // Deployment export
+        .route(
+            "/api/v1/projects/{project_id}/deployments/{deployment_id}/export",
+            get(handlers::export_deployment),
+        )
 }
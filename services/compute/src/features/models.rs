@@ -1,3 +1,4 @@
+use bigdecimal::BigDecimal;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::{FromRow, Type};
@@ -15,6 +16,213 @@ pub enum DeploymentStatus {
     Succeeded,
     Failed,
     Terminated,
+    Paused,
+}
+
+#[derive(Type, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[sqlx(type_name = "webhook_delivery_status", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookDeliveryStatus {
+    Pending,
+    Succeeded,
+    Failed,
+}
+
+/// What a member may do in a project, each role can do everything the ones
+/// before it can
+#[derive(Type, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[sqlx(type_name = "project_role", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum ProjectRole {
+    Viewer,
+    Developer,
+    Admin,
+    Owner,
+}
+
+impl ProjectRole {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ProjectRole::Viewer => "viewer",
+            ProjectRole::Developer => "developer",
+            ProjectRole::Admin => "admin",
+            ProjectRole::Owner => "owner",
+        }
+    }
+}
+
+#[derive(Type, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[sqlx(type_name = "organization_role", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum OrganizationRole {
+    Member,
+    Admin,
+    Owner,
+}
+
+impl OrganizationRole {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OrganizationRole::Member => "member",
+            OrganizationRole::Admin => "admin",
+            OrganizationRole::Owner => "owner",
+        }
+    }
+}
+
+/// Who a project's deployments are billed to and counted against: the
+/// project's organization, or its owner for projects outside of one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BillingAccount {
+    User(Uuid),
+    Organization(Uuid),
+}
+
+impl BillingAccount {
+    pub fn of(project: &Project) -> Self {
+        match project.organization_id {
+            Some(organization_id) => BillingAccount::Organization(organization_id),
+            None => BillingAccount::User(project.owner_id),
+        }
+    }
+
+    pub fn user_id(self) -> Option<Uuid> {
+        match self {
+            BillingAccount::User(user_id) => Some(user_id),
+            BillingAccount::Organization(_) => None,
+        }
+    }
+
+    pub fn organization_id(self) -> Option<Uuid> {
+        match self {
+            BillingAccount::User(_) => None,
+            BillingAccount::Organization(organization_id) => Some(organization_id),
+        }
+    }
+}
+
+/// Deployment events a webhook can subscribe to, named as receivers see them
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookEvent {
+    #[serde(rename = "deployment.created")]
+    Created,
+    #[serde(rename = "deployment.updated")]
+    Updated,
+    #[serde(rename = "deployment.scaled")]
+    Scaled,
+    #[serde(rename = "deployment.restarted")]
+    Restarted,
+    #[serde(rename = "deployment.paused")]
+    Paused,
+    #[serde(rename = "deployment.resumed")]
+    Resumed,
+    #[serde(rename = "deployment.ready")]
+    Ready,
+    #[serde(rename = "deployment.failed")]
+    Failed,
+    #[serde(rename = "deployment.deleted")]
+    Deleted,
+}
+
+impl WebhookEvent {
+    /// The public event of a `deployment_events` type, secret changes have none
+    pub fn from_event_type(event_type: &str) -> Option<Self> {
+        match event_type {
+            "deployment_created" => Some(WebhookEvent::Created),
+            "deployment_updated" => Some(WebhookEvent::Updated),
+            "deployment_scaled" => Some(WebhookEvent::Scaled),
+            "deployment_restarted" => Some(WebhookEvent::Restarted),
+            "deployment_paused" => Some(WebhookEvent::Paused),
+            "deployment_resumed" => Some(WebhookEvent::Resumed),
+            "deployment_ready" => Some(WebhookEvent::Ready),
+            "deployment_failed" => Some(WebhookEvent::Failed),
+            "deployment_deleted" => Some(WebhookEvent::Deleted),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WebhookEvent::Created => "deployment.created",
+            WebhookEvent::Updated => "deployment.updated",
+            WebhookEvent::Scaled => "deployment.scaled",
+            WebhookEvent::Restarted => "deployment.restarted",
+            WebhookEvent::Paused => "deployment.paused",
+            WebhookEvent::Resumed => "deployment.resumed",
+            WebhookEvent::Ready => "deployment.ready",
+            WebhookEvent::Failed => "deployment.failed",
+            WebhookEvent::Deleted => "deployment.deleted",
+        }
+    }
+}
+
+/// What the deployment list can be sorted by
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DeploymentSort {
+    Name,
+    #[default]
+    CreatedAt,
+    Status,
+}
+
+impl DeploymentSort {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DeploymentSort::Name => "name",
+            DeploymentSort::CreatedAt => "created_at",
+            DeploymentSort::Status => "status",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl SortOrder {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SortOrder::Asc => "asc",
+            SortOrder::Desc => "desc",
+        }
+    }
+}
+
+/// Named sizes a deployment can ask for instead of exact `resources`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ResourcePreset {
+    Nano,
+    Micro,
+    Small,
+    Medium,
+    Large,
+    Xlarge,
+}
+
+impl ResourcePreset {
+    pub fn resources(self) -> ResourceSpec {
+        let (cpu_request, cpu_limit, memory_request, memory_limit) = match self {
+            ResourcePreset::Nano => (50, 100, 64, 128),
+            ResourcePreset::Micro => (100, 250, 128, 256),
+            ResourcePreset::Small => (250, 500, 256, 512),
+            ResourcePreset::Medium => (500, 1000, 512, 1024),
+            ResourcePreset::Large => (1000, 2000, 1024, 2048),
+            ResourcePreset::Xlarge => (2000, 4000, 2048, 4096),
+        };
+
+        ResourceSpec {
+            cpu_request_millicores: cpu_request,
+            cpu_limit_millicores: cpu_limit,
+            memory_request_mb: memory_request,
+            memory_limit_mb: memory_limit,
+        }
+    }
 }
 
 // ============================================
@@ -26,12 +234,66 @@ pub enum DeploymentStatus {
 pub struct Project {
     pub id: Uuid,
     pub owner_id: Uuid,
+    pub organization_id: Option<Uuid>,
     pub name: String,
     pub description: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+#[derive(FromRow, Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectMember {
+    pub project_id: Uuid,
+    pub user_id: Uuid,
+    pub username: String,
+    pub email: String,
+    pub role: ProjectRole,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Pending membership, only the token's hash is stored
+#[derive(FromRow, Debug, Clone)]
+pub struct ProjectInvitation {
+    pub id: Uuid,
+    pub project_id: Uuid,
+    pub email: String,
+    pub role: ProjectRole,
+    pub token_hash: String,
+    pub invited_by: Option<Uuid>,
+    pub expires_at: DateTime<Utc>,
+    pub accepted_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Pending handover of a project, only the token's hash is stored
+#[derive(FromRow, Debug, Clone)]
+pub struct ProjectTransfer {
+    pub id: Uuid,
+    pub project_id: Uuid,
+    pub email: String,
+    pub token_hash: String,
+    pub initiated_by: Option<Uuid>,
+    pub expires_at: DateTime<Utc>,
+    pub accepted_at: Option<DateTime<Utc>>,
+    pub accepted_by: Option<Uuid>,
+    pub organization_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(FromRow, Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditLogEntry {
+    pub id: Uuid,
+    pub project_id: Option<Uuid>,
+    pub actor_id: Option<Uuid>,
+    pub target_user_id: Option<Uuid>,
+    pub action: String,
+    pub details: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
 #[derive(FromRow, Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Deployment {
@@ -48,6 +310,9 @@ pub struct Deployment {
     pub cluster_namespace: String,
     pub cluster_deployment_name: String,
     pub node_selector: Option<serde_json::Value>,
+    pub region: Option<String>,
+    pub node_pool: Option<String>,
+    pub tolerations: Option<serde_json::Value>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -60,6 +325,142 @@ pub struct DeploymentSecret {
     pub key: String,
     pub value: Vec<u8>,
     pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Per-deployment data key, wrapped by the KMS that encrypts it
+#[derive(FromRow, Debug, Clone)]
+pub struct DeploymentDataKey {
+    pub deployment_id: Uuid,
+    pub wrapped_key: Vec<u8>,
+    pub kms_provider: String,
+    pub kms_key_id: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(FromRow, Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DeploymentPreview {
+    pub deployment_id: Uuid,
+    pub base_deployment_id: Option<Uuid>,
+    pub name: String,
+    pub url: Option<String>,
+    pub callback_url: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Usage of a deployment aggregated over one time bucket
+#[derive(FromRow, Debug, Clone)]
+pub struct DeploymentMetricRollup {
+    pub bucket: DateTime<Utc>,
+    pub cpu_millicores_avg: f64,
+    pub cpu_millicores_max: f64,
+    pub memory_mb_avg: f64,
+    pub memory_mb_max: f64,
+    pub pod_count: i32,
+}
+
+/// Caps on the deployments of every user on the plan
+#[derive(FromRow, Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Plan {
+    pub name: String,
+    pub max_cpu_millicores: i32,
+    pub max_memory_mb: i32,
+    pub max_replicas: i32,
+    pub max_deployments: i32,
+    pub is_default: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// What a user's deployments take out of their plan. CPU and memory are
+/// limits times replicas, paused deployments only count as a deployment.
+#[derive(FromRow, Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceUsage {
+    pub deployments: i64,
+    pub replicas: i64,
+    pub cpu_millicores: i64,
+    pub memory_mb: i64,
+}
+
+impl std::iter::Sum for ResourceUsage {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), |total, usage| Self {
+            deployments: total.deployments + usage.deployments,
+            replicas: total.replicas + usage.replicas,
+            cpu_millicores: total.cpu_millicores + usage.cpu_millicores,
+            memory_mb: total.memory_mb + usage.memory_mb,
+        })
+    }
+}
+
+/// Hourly price of resources, shared with the billing service
+#[derive(FromRow, Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BillingPrice {
+    pub cpu_millicore_hour: BigDecimal,
+    pub memory_mb_hour: BigDecimal,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// What is left on a user's or organization's balance, owned by the billing
+/// service
+#[derive(FromRow, Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Balance {
+    pub amount: BigDecimal,
+    pub currency: String,
+}
+
+/// Project subscription to deployment events, its signing secret is wrapped
+/// by the KMS
+#[derive(FromRow, Debug, Clone)]
+pub struct Webhook {
+    pub id: Uuid,
+    pub project_id: Uuid,
+    pub url: String,
+    pub wrapped_secret: Vec<u8>,
+    pub kms_provider: String,
+    pub event_types: Vec<String>,
+    pub is_active: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(FromRow, Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    pub webhook_id: Uuid,
+    pub deployment_id: Uuid,
+    pub event_type: String,
+    pub payload: serde_json::Value,
+    pub status: WebhookDeliveryStatus,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub response_status: Option<i32>,
+    pub last_error: Option<String>,
+    pub delivered_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Secret URL that redeploys a deployment, only the token's hash is stored
+#[derive(FromRow, Debug, Clone)]
+pub struct DeployHook {
+    pub id: Uuid,
+    pub deployment_id: Uuid,
+    pub name: String,
+    pub token_hash: String,
+    pub token_prefix: String,
+    pub last_triggered_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
 }
 
 #[derive(FromRow, Serialize, Deserialize, Debug, Clone)]
@@ -77,7 +478,7 @@ pub struct DeploymentEvent {
 // ============================================
 
 /// Resource specification stored in the `resources` JSONB field
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct ResourceSpec {
     pub cpu_request_millicores: i32,
@@ -1,30 +1,47 @@
+use chrono::{DateTime, Utc};
 use shared::schemas::Pagination;
-use sqlx::{PgPool, Postgres, Transaction};
+use sqlx::{PgConnection, PgExecutor, PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
 use crate::features::models::{
-    Deployment, DeploymentEvent, DeploymentSecret, DeploymentStatus, Project,
+    AuditLogEntry, Balance, BillingAccount, BillingPrice, DeployHook, Deployment,
+    DeploymentDataKey, DeploymentEvent, DeploymentMetricRollup, DeploymentPreview,
+    DeploymentSecret, DeploymentSort, DeploymentStatus, OrganizationRole, Plan, Project,
+    ProjectInvitation, ProjectMember, ProjectRole, ProjectTransfer, ResourceUsage, SortOrder,
+    Webhook, WebhookDelivery,
 };
 
 pub struct ProjectRepository;
 
 impl ProjectRepository {
+    /// Projects of the user's own account, or of the organization they're
+    /// working in, optionally only those with `search` in their name
     pub async fn get_many_by_user_id(
         pool: &PgPool,
         user_id: Uuid,
+        organization_id: Option<Uuid>,
+        search: Option<&str>,
         pagination: Pagination,
     ) -> Result<(Vec<Project>, i64), sqlx::Error> {
         let projects = sqlx::query_as::<_, Project>(
             r#"
-                SELECT id, owner_id, name, description, created_at, updated_at
+                SELECT id, owner_id, organization_id, name, description, created_at, updated_at
                 FROM projects
-                WHERE owner_id = $1
+                WHERE CASE
+                    WHEN $2::UUID IS NULL
+                        THEN id IN (SELECT project_id FROM project_members WHERE user_id = $1)
+                    ELSE organization_id = $2
+                        AND id IN (SELECT project_id FROM project_access WHERE user_id = $1)
+                END
+                  AND ($3::TEXT IS NULL OR strpos(lower(name), lower($3)) > 0)
                 ORDER BY created_at DESC
-                LIMIT $2
-                OFFSET $3
+                LIMIT $4
+                OFFSET $5
             "#,
         )
         .bind(user_id)
+        .bind(organization_id)
+        .bind(search)
         .bind(pagination.limit)
         .bind(pagination.offset)
         .fetch_all(pool)
@@ -33,10 +50,18 @@ impl ProjectRepository {
         let row = sqlx::query!(
             r#"
                 SELECT COUNT(*) as count
-                FROM projects d
-                WHERE owner_id = $1
+                FROM projects
+                WHERE CASE
+                    WHEN $2::UUID IS NULL
+                        THEN id IN (SELECT project_id FROM project_members WHERE user_id = $1)
+                    ELSE organization_id = $2
+                        AND id IN (SELECT project_id FROM project_access WHERE user_id = $1)
+                END
+                  AND ($3::TEXT IS NULL OR strpos(lower(name), lower($3)) > 0)
             "#,
-            user_id
+            user_id,
+            organization_id,
+            search
         )
         .fetch_one(pool)
         .await?;
@@ -53,9 +78,10 @@ impl ProjectRepository {
     ) -> Result<Project, sqlx::Error> {
         sqlx::query_as::<_, Project>(
             r#"
-                SELECT id, owner_id, name, description, created_at, updated_at
+                SELECT id, owner_id, organization_id, name, description, created_at, updated_at
                 FROM projects
-                WHERE id = $1 AND owner_id = $2
+                WHERE id = $1
+                  AND id IN (SELECT project_id FROM project_access WHERE user_id = $2)
             "#,
         )
         .bind(project_id)
@@ -64,20 +90,31 @@ impl ProjectRepository {
         .await
     }
 
+    /// Outside of an organization the creator becomes the project's owner
+    /// member, inside one the organization's roles apply
     pub async fn create(
         pool: &PgPool,
         user_id: Uuid,
+        organization_id: Option<Uuid>,
         name: &str,
         description: Option<&str>,
     ) -> Result<Project, sqlx::Error> {
         sqlx::query_as::<_, Project>(
             r#"
-                INSERT INTO projects (owner_id, name, description)
-                VALUES ($1, $2, $3)
-                RETURNING id, owner_id, name, description, created_at, updated_at
+                WITH project AS (
+                    INSERT INTO projects (owner_id, organization_id, name, description)
+                    VALUES ($1, $2, $3, $4)
+                    RETURNING id, owner_id, organization_id, name, description,
+                              created_at, updated_at
+                ), owner AS (
+                    INSERT INTO project_members (project_id, user_id, role)
+                    SELECT id, owner_id, 'owner' FROM project WHERE organization_id IS NULL
+                )
+                SELECT * FROM project
             "#,
         )
         .bind(user_id)
+        .bind(organization_id)
         .bind(name)
         .bind(description)
         .fetch_one(pool)
@@ -96,8 +133,9 @@ impl ProjectRepository {
                 UPDATE projects
                 SET name = COALESCE($3, name),
                     description = COALESCE($4, description)
-                WHERE id = $1 AND owner_id = $2
-                RETURNING id, owner_id, name, description, created_at, updated_at
+                WHERE id = $1
+                  AND id IN (SELECT project_id FROM project_access WHERE user_id = $2)
+                RETURNING id, owner_id, organization_id, name, description, created_at, updated_at
             "#,
         )
         .bind(project_id)
@@ -112,7 +150,10 @@ impl ProjectRepository {
         sqlx::query(
             r#"
                 DELETE FROM projects
-                WHERE id = $1 AND owner_id = $2
+                WHERE id = $1
+                  AND id IN (
+                      SELECT project_id FROM project_access WHERE user_id = $2 AND role = 'owner'
+                  )
             "#,
         )
         .bind(project_id)
@@ -122,240 +163,1914 @@ impl ProjectRepository {
 
         Ok(())
     }
-}
 
-pub struct DeploymentRepository;
+    /// Hand the project and its deployments to `owner_id`, or to the
+    /// organization with `owner_id` as its creator
+    pub async fn transfer(
+        tx: &mut Transaction<'_, Postgres>,
+        project_id: Uuid,
+        owner_id: Uuid,
+        organization_id: Option<Uuid>,
+    ) -> Result<Project, sqlx::Error> {
+        let project = sqlx::query_as::<_, Project>(
+            r#"
+                UPDATE projects
+                SET owner_id = $2, organization_id = $3
+                WHERE id = $1
+                RETURNING id, owner_id, organization_id, name, description, created_at, updated_at
+            "#,
+        )
+        .bind(project_id)
+        .bind(owner_id)
+        .bind(organization_id)
+        .fetch_one(&mut **tx)
+        .await?;
 
-impl DeploymentRepository {
-    pub async fn get_all_by_project(
+        sqlx::query("UPDATE deployments SET user_id = $2 WHERE project_id = $1")
+            .bind(project_id)
+            .bind(owner_id)
+            .execute(&mut **tx)
+            .await?;
+
+        Ok(project)
+    }
+
+    /// Lookup without a membership check, callers check access themselves
+    pub async fn find_by_id(
         pool: &PgPool,
         project_id: Uuid,
-        user_id: Uuid,
-    ) -> Result<Vec<Deployment>, sqlx::Error> {
-        sqlx::query_as::<_, Deployment>(
+    ) -> Result<Option<Project>, sqlx::Error> {
+        sqlx::query_as::<_, Project>(
             r#"
-                SELECT d.*
-                FROM deployments d
-                INNER JOIN projects p ON d.project_id = p.id
-                WHERE d.project_id = $1 AND p.owner_id = $2
-                ORDER BY d.created_at DESC
+                SELECT id, owner_id, organization_id, name, description, created_at, updated_at
+                FROM projects
+                WHERE id = $1
             "#,
         )
         .bind(project_id)
-        .bind(user_id)
-        .fetch_all(pool)
+        .fetch_optional(pool)
         .await
     }
 
-    pub async fn get_by_id(
+    /// Take the project's apply lock for the session of `connection`, `false`
+    /// when another apply holds it. A connection that closes releases it.
+    pub async fn try_lock_apply(
+        connection: &mut PgConnection,
+        project_id: Uuid,
+    ) -> Result<bool, sqlx::Error> {
+        sqlx::query_scalar("SELECT pg_try_advisory_lock(hashtextextended($1::TEXT, 1))")
+            .bind(project_id)
+            .fetch_one(connection)
+            .await
+    }
+
+    pub async fn unlock_apply(
+        connection: &mut PgConnection,
+        project_id: Uuid,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("SELECT pg_advisory_unlock(hashtextextended($1::TEXT, 1))")
+            .bind(project_id)
+            .execute(connection)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Who the project's deployments are billed to
+    pub async fn get_billing_account(
         pool: &PgPool,
-        deployment_id: Uuid,
+        project_id: Uuid,
+    ) -> Result<BillingAccount, sqlx::Error> {
+        let (owner_id, organization_id) = sqlx::query_as::<_, (Uuid, Option<Uuid>)>(
+            "SELECT owner_id, organization_id FROM projects WHERE id = $1",
+        )
+        .bind(project_id)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(match organization_id {
+            Some(organization_id) => BillingAccount::Organization(organization_id),
+            None => BillingAccount::User(owner_id),
+        })
+    }
+}
+
+pub struct ProjectMemberRepository;
+
+impl ProjectMemberRepository {
+    /// The user's role on the project, the highest of their own membership
+    /// and what their organization role grants
+    pub async fn get_access_role(
+        pool: &PgPool,
+        project_id: Uuid,
         user_id: Uuid,
-    ) -> Result<Deployment, sqlx::Error> {
-        sqlx::query_as::<_, Deployment>(
-            r#"
-                SELECT d.*
-                FROM deployments d
-                INNER JOIN projects p ON d.project_id = p.id
-                WHERE d.id = $1 AND p.owner_id = $2
-            "#,
+    ) -> Result<Option<ProjectRole>, sqlx::Error> {
+        sqlx::query_scalar::<_, Option<ProjectRole>>(
+            "SELECT MAX(role) FROM project_access WHERE project_id = $1 AND user_id = $2",
         )
-        .bind(deployment_id)
+        .bind(project_id)
         .bind(user_id)
         .fetch_one(pool)
         .await
     }
 
-    pub async fn create(
-        tx: &mut Transaction<'_, Postgres>,
+    /// Role of the user's own membership
+    pub async fn get_role(
+        pool: &PgPool,
+        project_id: Uuid,
         user_id: Uuid,
+    ) -> Result<Option<ProjectRole>, sqlx::Error> {
+        sqlx::query_scalar::<_, ProjectRole>(
+            "SELECT role FROM project_members WHERE project_id = $1 AND user_id = $2",
+        )
+        .bind(project_id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+    }
+
+    pub async fn get_all_by_project(
+        pool: &PgPool,
         project_id: Uuid,
-        name: &str,
-        image: &str,
-        env_vars: serde_json::Value,
-        replicas: i32,
-        resources: serde_json::Value,
-        labels: Option<serde_json::Value>,
-        cluster_namespace: &str,
-        cluster_deployment_name: &str,
-    ) -> Result<Deployment, sqlx::Error> {
-        sqlx::query_as::<_, Deployment>(
+    ) -> Result<Vec<ProjectMember>, sqlx::Error> {
+        sqlx::query_as::<_, ProjectMember>(
             r#"
-                INSERT INTO deployments (
-                    user_id, project_id, name, image, env_vars, replicas,
-                    resources, labels, cluster_namespace, cluster_deployment_name
-                )
-                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
-                RETURNING *
+                SELECT m.project_id, m.user_id, u.username, u.email, m.role,
+                       m.created_at, m.updated_at
+                FROM project_members m
+                INNER JOIN users u ON u.id = m.user_id
+                WHERE m.project_id = $1
+                ORDER BY m.role DESC, m.created_at
             "#,
         )
-        .bind(user_id)
         .bind(project_id)
-        .bind(name)
-        .bind(image)
-        .bind(env_vars)
-        .bind(replicas)
-        .bind(resources)
-        .bind(labels)
-        .bind(cluster_namespace)
-        .bind(cluster_deployment_name)
-        .fetch_one(&mut **tx)
+        .fetch_all(pool)
         .await
     }
 
-    pub async fn update_status(
+    /// Whether someone with this email already belongs to the project
+    pub async fn exists_by_email(
         pool: &PgPool,
-        deployment_id: Uuid,
-        status: DeploymentStatus,
+        project_id: Uuid,
+        email: &str,
+    ) -> Result<bool, sqlx::Error> {
+        sqlx::query_scalar::<_, bool>(
+            r#"
+                SELECT EXISTS (
+                    SELECT 1
+                    FROM project_members m
+                    INNER JOIN users u ON u.id = m.user_id
+                    WHERE m.project_id = $1 AND lower(u.email) = lower($2)
+                )
+            "#,
+        )
+        .bind(project_id)
+        .bind(email)
+        .fetch_one(pool)
+        .await
+    }
+
+    pub async fn create<'e>(
+        executor: impl PgExecutor<'e>,
+        project_id: Uuid,
+        user_id: Uuid,
+        role: ProjectRole,
     ) -> Result<(), sqlx::Error> {
         sqlx::query(
             r#"
-                UPDATE deployments
-                SET status = $2
-                WHERE id = $1
+                INSERT INTO project_members (project_id, user_id, role)
+                VALUES ($1, $2, $3)
             "#,
         )
-        .bind(deployment_id)
-        .bind(status)
-        .execute(pool)
+        .bind(project_id)
+        .bind(user_id)
+        .bind(role)
+        .execute(executor)
         .await?;
 
         Ok(())
     }
 
-    pub async fn update_replicas(
-        pool: &PgPool,
-        deployment_id: Uuid,
+    /// Change a member's role, the owner's role can't be changed this way
+    pub async fn update_role<'e>(
+        executor: impl PgExecutor<'e>,
+        project_id: Uuid,
         user_id: Uuid,
-        replicas: i32,
-    ) -> Result<Deployment, sqlx::Error> {
-        sqlx::query_as::<_, Deployment>(
+        role: ProjectRole,
+    ) -> Result<Option<ProjectMember>, sqlx::Error> {
+        sqlx::query_as::<_, ProjectMember>(
             r#"
-                UPDATE deployments d
-                SET replicas = $3
-                FROM projects p
-                WHERE d.id = $1 AND d.project_id = p.id AND p.owner_id = $2
-                RETURNING d.*
+                WITH member AS (
+                    UPDATE project_members
+                    SET role = $3
+                    WHERE project_id = $1 AND user_id = $2 AND role <> 'owner'
+                    RETURNING *
+                )
+                SELECT m.project_id, m.user_id, u.username, u.email, m.role,
+                       m.created_at, m.updated_at
+                FROM member m
+                INNER JOIN users u ON u.id = m.user_id
             "#,
         )
-        .bind(deployment_id)
+        .bind(project_id)
         .bind(user_id)
-        .bind(replicas)
-        .fetch_one(pool)
+        .bind(role)
+        .fetch_optional(executor)
         .await
     }
 
-    pub async fn delete(
-        pool: &PgPool,
-        deployment_id: Uuid,
+    /// Remove a member, the owner can't be removed. Returns the role they had
+    pub async fn delete<'e>(
+        executor: impl PgExecutor<'e>,
+        project_id: Uuid,
         user_id: Uuid,
+    ) -> Result<Option<ProjectRole>, sqlx::Error> {
+        sqlx::query_scalar::<_, ProjectRole>(
+            r#"
+                DELETE FROM project_members
+                WHERE project_id = $1 AND user_id = $2 AND role <> 'owner'
+                RETURNING role
+            "#,
+        )
+        .bind(project_id)
+        .bind(user_id)
+        .fetch_optional(executor)
+        .await
+    }
+
+    /// Make `owner_id` the project's only owner member, replacing any role
+    /// they had. `None` leaves it without one, for organization projects.
+    pub async fn replace_owner(
+        tx: &mut Transaction<'_, Postgres>,
+        project_id: Uuid,
+        owner_id: Option<Uuid>,
     ) -> Result<(), sqlx::Error> {
         sqlx::query(
             r#"
-                DELETE FROM deployments d
-                USING projects p
-                WHERE d.id = $1 AND d.project_id = p.id AND p.owner_id = $2
+                DELETE FROM project_members
+                WHERE project_id = $1 AND (role = 'owner' OR user_id = $2)
             "#,
         )
-        .bind(deployment_id)
-        .bind(user_id)
-        .execute(pool)
+        .bind(project_id)
+        .bind(owner_id)
+        .execute(&mut **tx)
         .await?;
 
+        if let Some(owner_id) = owner_id {
+            Self::create(&mut **tx, project_id, owner_id, ProjectRole::Owner).await?;
+        }
+
         Ok(())
     }
 }
 
-pub struct DeploymentSecretRepository;
+pub struct ProjectInvitationRepository;
 
-impl DeploymentSecretRepository {
-    pub async fn create(
-        tx: &mut Transaction<'_, Postgres>,
-        deployment_id: Uuid,
-        key: &str,
-        encrypted_value: Vec<u8>,
-    ) -> Result<DeploymentSecret, sqlx::Error> {
-        sqlx::query_as::<_, DeploymentSecret>(
+impl ProjectInvitationRepository {
+    /// Inviting an email that already has a pending invitation replaces it
+    #[allow(clippy::too_many_arguments)]
+    pub async fn upsert(
+        pool: &PgPool,
+        project_id: Uuid,
+        email: &str,
+        role: ProjectRole,
+        token_hash: &str,
+        invited_by: Uuid,
+        expires_at: DateTime<Utc>,
+    ) -> Result<ProjectInvitation, sqlx::Error> {
+        sqlx::query_as::<_, ProjectInvitation>(
             r#"
-                INSERT INTO deployment_secrets (deployment_id, key, value)
-                VALUES ($1, $2, $3)
+                INSERT INTO project_invitations
+                    (project_id, email, role, token_hash, invited_by, expires_at)
+                VALUES ($1, $2, $3, $4, $5, $6)
+                ON CONFLICT (project_id, lower(email)) WHERE accepted_at IS NULL
+                DO UPDATE SET role = EXCLUDED.role,
+                              token_hash = EXCLUDED.token_hash,
+                              invited_by = EXCLUDED.invited_by,
+                              expires_at = EXCLUDED.expires_at,
+                              created_at = NOW()
                 RETURNING *
             "#,
         )
-        .bind(deployment_id)
-        .bind(key)
-        .bind(encrypted_value)
-        .fetch_one(&mut **tx)
+        .bind(project_id)
+        .bind(email)
+        .bind(role)
+        .bind(token_hash)
+        .bind(invited_by)
+        .bind(expires_at)
+        .fetch_one(pool)
         .await
     }
 
-    pub async fn get_all_by_deployment(
+    pub async fn get_pending_by_project(
         pool: &PgPool,
-        deployment_id: Uuid,
-    ) -> Result<Vec<DeploymentSecret>, sqlx::Error> {
-        sqlx::query_as::<_, DeploymentSecret>(
+        project_id: Uuid,
+    ) -> Result<Vec<ProjectInvitation>, sqlx::Error> {
+        sqlx::query_as::<_, ProjectInvitation>(
             r#"
-                SELECT * FROM deployment_secrets
-                WHERE deployment_id = $1
-                ORDER BY created_at ASC
+                SELECT *
+                FROM project_invitations
+                WHERE project_id = $1 AND accepted_at IS NULL AND expires_at > NOW()
+                ORDER BY created_at DESC
             "#,
         )
-        .bind(deployment_id)
+        .bind(project_id)
         .fetch_all(pool)
         .await
     }
 
-    pub async fn delete_by_deployment(
+    /// Lock a pending invitation for acceptance
+    pub async fn find_pending_by_token_hash(
         tx: &mut Transaction<'_, Postgres>,
-        deployment_id: Uuid,
+        token_hash: &str,
+    ) -> Result<Option<ProjectInvitation>, sqlx::Error> {
+        sqlx::query_as::<_, ProjectInvitation>(
+            r#"
+                SELECT *
+                FROM project_invitations
+                WHERE token_hash = $1 AND accepted_at IS NULL AND expires_at > NOW()
+                FOR UPDATE
+            "#,
+        )
+        .bind(token_hash)
+        .fetch_optional(&mut **tx)
+        .await
+    }
+
+    pub async fn mark_accepted(
+        tx: &mut Transaction<'_, Postgres>,
+        invitation_id: Uuid,
     ) -> Result<(), sqlx::Error> {
-        sqlx::query("DELETE FROM deployment_secrets WHERE deployment_id = $1")
-            .bind(deployment_id)
+        sqlx::query("UPDATE project_invitations SET accepted_at = NOW() WHERE id = $1")
+            .bind(invitation_id)
             .execute(&mut **tx)
             .await?;
+
         Ok(())
     }
+
+    /// Revoke a pending invitation, its link stops working
+    pub async fn delete(
+        pool: &PgPool,
+        invitation_id: Uuid,
+        project_id: Uuid,
+    ) -> Result<ProjectInvitation, sqlx::Error> {
+        sqlx::query_as::<_, ProjectInvitation>(
+            r#"
+                DELETE FROM project_invitations
+                WHERE id = $1 AND project_id = $2 AND accepted_at IS NULL
+                RETURNING *
+            "#,
+        )
+        .bind(invitation_id)
+        .bind(project_id)
+        .fetch_one(pool)
+        .await
+    }
 }
 
-pub struct DeploymentEventRepository;
+pub struct ProjectTransferRepository;
 
-impl DeploymentEventRepository {
-    pub async fn create(
+impl ProjectTransferRepository {
+    /// Starting a transfer while one is pending replaces it
+    pub async fn upsert(
         pool: &PgPool,
-        deployment_id: Uuid,
-        event_type: &str,
-        message: Option<&str>,
-    ) -> Result<DeploymentEvent, sqlx::Error> {
-        sqlx::query_as::<_, DeploymentEvent>(
+        project_id: Uuid,
+        email: &str,
+        token_hash: &str,
+        initiated_by: Uuid,
+        expires_at: DateTime<Utc>,
+    ) -> Result<ProjectTransfer, sqlx::Error> {
+        sqlx::query_as::<_, ProjectTransfer>(
             r#"
-                INSERT INTO deployment_events (deployment_id, event_type, message)
-                VALUES ($1, $2, $3)
+                INSERT INTO project_transfers
+                    (project_id, email, token_hash, initiated_by, expires_at)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (project_id) WHERE accepted_at IS NULL
+                DO UPDATE SET email = EXCLUDED.email,
+                              token_hash = EXCLUDED.token_hash,
+                              initiated_by = EXCLUDED.initiated_by,
+                              expires_at = EXCLUDED.expires_at,
+                              created_at = NOW()
                 RETURNING *
             "#,
         )
-        .bind(deployment_id)
-        .bind(event_type)
-        .bind(message)
+        .bind(project_id)
+        .bind(email)
+        .bind(token_hash)
+        .bind(initiated_by)
+        .bind(expires_at)
         .fetch_one(pool)
         .await
     }
 
-    pub async fn get_recent_by_deployment(
+    pub async fn get_pending_by_project(
         pool: &PgPool,
-        deployment_id: Uuid,
-        limit: i64,
-    ) -> Result<Vec<DeploymentEvent>, sqlx::Error> {
-        sqlx::query_as::<_, DeploymentEvent>(
+        project_id: Uuid,
+    ) -> Result<Option<ProjectTransfer>, sqlx::Error> {
+        sqlx::query_as::<_, ProjectTransfer>(
             r#"
-                SELECT * FROM deployment_events
-                WHERE deployment_id = $1
-                ORDER BY created_at DESC
-                LIMIT $2
+                SELECT *
+                FROM project_transfers
+                WHERE project_id = $1 AND accepted_at IS NULL AND expires_at > NOW()
             "#,
         )
-        .bind(deployment_id)
-        .bind(limit)
-        .fetch_all(pool)
+        .bind(project_id)
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// Lock a pending transfer for acceptance
+    pub async fn find_pending_by_token_hash(
+        tx: &mut Transaction<'_, Postgres>,
+        token_hash: &str,
+    ) -> Result<Option<ProjectTransfer>, sqlx::Error> {
+        sqlx::query_as::<_, ProjectTransfer>(
+            r#"
+                SELECT *
+                FROM project_transfers
+                WHERE token_hash = $1 AND accepted_at IS NULL AND expires_at > NOW()
+                FOR UPDATE
+            "#,
+        )
+        .bind(token_hash)
+        .fetch_optional(&mut **tx)
         .await
     }
+
+    pub async fn mark_accepted(
+        tx: &mut Transaction<'_, Postgres>,
+        transfer_id: Uuid,
+        accepted_by: Uuid,
+        organization_id: Option<Uuid>,
+    ) -> Result<ProjectTransfer, sqlx::Error> {
+        sqlx::query_as::<_, ProjectTransfer>(
+            r#"
+                UPDATE project_transfers
+                SET accepted_at = NOW(), accepted_by = $2, organization_id = $3
+                WHERE id = $1
+                RETURNING *
+            "#,
+        )
+        .bind(transfer_id)
+        .bind(accepted_by)
+        .bind(organization_id)
+        .fetch_one(&mut **tx)
+        .await
+    }
+
+    /// Cancel a pending transfer, its link stops working
+    pub async fn delete(
+        pool: &PgPool,
+        transfer_id: Uuid,
+        project_id: Uuid,
+    ) -> Result<ProjectTransfer, sqlx::Error> {
+        sqlx::query_as::<_, ProjectTransfer>(
+            r#"
+                DELETE FROM project_transfers
+                WHERE id = $1 AND project_id = $2 AND accepted_at IS NULL
+                RETURNING *
+            "#,
+        )
+        .bind(transfer_id)
+        .bind(project_id)
+        .fetch_one(pool)
+        .await
+    }
+}
+
+pub struct AuditLogRepository;
+
+impl AuditLogRepository {
+    pub async fn create<'e>(
+        executor: impl PgExecutor<'e>,
+        project_id: Option<Uuid>,
+        actor_id: Option<Uuid>,
+        target_user_id: Option<Uuid>,
+        action: &str,
+        details: serde_json::Value,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+                INSERT INTO audit_log (project_id, actor_id, target_user_id, action, details)
+                VALUES ($1, $2, $3, $4, $5)
+            "#,
+        )
+        .bind(project_id)
+        .bind(actor_id)
+        .bind(target_user_id)
+        .bind(action)
+        .bind(details)
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_many_by_project(
+        pool: &PgPool,
+        project_id: Uuid,
+        pagination: Pagination,
+    ) -> Result<(Vec<AuditLogEntry>, i64), sqlx::Error> {
+        let entries = sqlx::query_as::<_, AuditLogEntry>(
+            r#"
+                SELECT *
+                FROM audit_log
+                WHERE project_id = $1
+                ORDER BY created_at DESC
+                LIMIT $2
+                OFFSET $3
+            "#,
+        )
+        .bind(project_id)
+        .bind(pagination.limit)
+        .bind(pagination.offset)
+        .fetch_all(pool)
+        .await?;
+
+        let total =
+            sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM audit_log WHERE project_id = $1")
+                .bind(project_id)
+                .fetch_one(pool)
+                .await?;
+
+        Ok((entries, total))
+    }
+
+    /// Entries the user made or that were about them, across projects
+    pub async fn get_many_by_user(
+        pool: &PgPool,
+        user_id: Uuid,
+        pagination: Pagination,
+    ) -> Result<(Vec<AuditLogEntry>, i64), sqlx::Error> {
+        let entries = sqlx::query_as::<_, AuditLogEntry>(
+            r#"
+                SELECT *
+                FROM audit_log
+                WHERE actor_id = $1 OR target_user_id = $1
+                ORDER BY created_at DESC
+                LIMIT $2
+                OFFSET $3
+            "#,
+        )
+        .bind(user_id)
+        .bind(pagination.limit)
+        .bind(pagination.offset)
+        .fetch_all(pool)
+        .await?;
+
+        let total = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM audit_log WHERE actor_id = $1 OR target_user_id = $1",
+        )
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+
+        Ok((entries, total))
+    }
+}
+
+pub struct UserRepository;
+
+impl UserRepository {
+    pub async fn get_email(pool: &PgPool, user_id: Uuid) -> Result<String, sqlx::Error> {
+        sqlx::query_scalar::<_, String>("SELECT email FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_one(pool)
+            .await
+    }
+}
+
+pub struct OrganizationRepository;
+
+impl OrganizationRepository {
+    pub async fn get_role(
+        pool: &PgPool,
+        organization_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<Option<OrganizationRole>, sqlx::Error> {
+        sqlx::query_scalar::<_, OrganizationRole>(
+            "SELECT role FROM organization_members WHERE organization_id = $1 AND user_id = $2",
+        )
+        .bind(organization_id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+    }
+}
+
+pub struct DeploymentRepository;
+
+impl DeploymentRepository {
+    /// A page of the project's deployments, every filter is optional.
+    /// `labels` must be contained in a deployment's labels, `label_keys` all
+    /// present in them.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn get_filtered(
+        pool: &PgPool,
+        project_id: Uuid,
+        user_id: Uuid,
+        search: Option<&str>,
+        status: Option<DeploymentStatus>,
+        image: Option<&str>,
+        labels: Option<&serde_json::Value>,
+        label_keys: &[String],
+        sort: DeploymentSort,
+        order: SortOrder,
+        pagination: &Pagination,
+    ) -> Result<Vec<Deployment>, sqlx::Error> {
+        sqlx::query_as::<_, Deployment>(
+            r#"
+                SELECT d.*
+                FROM deployments d
+                WHERE d.project_id = $1
+                  AND d.project_id IN (SELECT project_id FROM project_access WHERE user_id = $2)
+                  AND ($3::TEXT IS NULL OR strpos(lower(d.name), lower($3)) > 0)
+                  AND ($4::deployment_status IS NULL OR d.status = $4)
+                  AND ($5::TEXT IS NULL OR strpos(d.image, $5) > 0)
+                  AND ($6::JSONB IS NULL OR d.labels @> $6)
+                  AND (cardinality($7::TEXT[]) = 0 OR COALESCE(d.labels ?& $7, FALSE))
+                ORDER BY
+                    CASE WHEN $8 = 'name' AND $9 = 'asc' THEN d.name END ASC,
+                    CASE WHEN $8 = 'name' AND $9 = 'desc' THEN d.name END DESC,
+                    CASE WHEN $8 = 'status' AND $9 = 'asc' THEN d.status::TEXT END ASC,
+                    CASE WHEN $8 = 'status' AND $9 = 'desc' THEN d.status::TEXT END DESC,
+                    CASE WHEN $9 = 'asc' THEN d.created_at END ASC,
+                    d.created_at DESC,
+                    d.id
+                LIMIT $10
+                OFFSET $11
+            "#,
+        )
+        .bind(project_id)
+        .bind(user_id)
+        .bind(search)
+        .bind(status)
+        .bind(image)
+        .bind(labels)
+        .bind(label_keys)
+        .bind(sort.as_str())
+        .bind(order.as_str())
+        .bind(pagination.limit)
+        .bind(pagination.offset)
+        .fetch_all(pool)
+        .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn count_filtered(
+        pool: &PgPool,
+        project_id: Uuid,
+        user_id: Uuid,
+        search: Option<&str>,
+        status: Option<DeploymentStatus>,
+        image: Option<&str>,
+        labels: Option<&serde_json::Value>,
+        label_keys: &[String],
+    ) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar::<_, i64>(
+            r#"
+                SELECT COUNT(*)
+                FROM deployments d
+                WHERE d.project_id = $1
+                  AND d.project_id IN (SELECT project_id FROM project_access WHERE user_id = $2)
+                  AND ($3::TEXT IS NULL OR strpos(lower(d.name), lower($3)) > 0)
+                  AND ($4::deployment_status IS NULL OR d.status = $4)
+                  AND ($5::TEXT IS NULL OR strpos(d.image, $5) > 0)
+                  AND ($6::JSONB IS NULL OR d.labels @> $6)
+                  AND (cardinality($7::TEXT[]) = 0 OR COALESCE(d.labels ?& $7, FALSE))
+            "#,
+        )
+        .bind(project_id)
+        .bind(user_id)
+        .bind(search)
+        .bind(status)
+        .bind(image)
+        .bind(labels)
+        .bind(label_keys)
+        .fetch_one(pool)
+        .await
+    }
+
+    pub async fn get_all_by_project(
+        pool: &PgPool,
+        project_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<Vec<Deployment>, sqlx::Error> {
+        sqlx::query_as::<_, Deployment>(
+            r#"
+                SELECT d.*
+                FROM deployments d
+                WHERE d.project_id = $1
+                  AND d.project_id IN (SELECT project_id FROM project_access WHERE user_id = $2)
+                ORDER BY d.created_at DESC
+            "#,
+        )
+        .bind(project_id)
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn get_by_id(
+        pool: &PgPool,
+        deployment_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<Deployment, sqlx::Error> {
+        sqlx::query_as::<_, Deployment>(
+            r#"
+                SELECT d.*
+                FROM deployments d
+                WHERE d.id = $1
+                  AND d.project_id IN (SELECT project_id FROM project_access WHERE user_id = $2)
+            "#,
+        )
+        .bind(deployment_id)
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn create(
+        tx: &mut Transaction<'_, Postgres>,
+        user_id: Uuid,
+        project_id: Uuid,
+        name: &str,
+        image: &str,
+        env_vars: serde_json::Value,
+        replicas: i32,
+        resources: serde_json::Value,
+        labels: Option<serde_json::Value>,
+        cluster_namespace: &str,
+        cluster_deployment_name: &str,
+        region: &str,
+        node_pool: Option<&str>,
+        node_selector: Option<serde_json::Value>,
+        tolerations: Option<serde_json::Value>,
+    ) -> Result<Deployment, sqlx::Error> {
+        sqlx::query_as::<_, Deployment>(
+            r#"
+                INSERT INTO deployments (
+                    user_id, project_id, name, image, env_vars, replicas,
+                    resources, labels, cluster_namespace, cluster_deployment_name,
+                    region, node_pool, node_selector, tolerations
+                )
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+                RETURNING *
+            "#,
+        )
+        .bind(user_id)
+        .bind(project_id)
+        .bind(name)
+        .bind(image)
+        .bind(env_vars)
+        .bind(replicas)
+        .bind(resources)
+        .bind(labels)
+        .bind(cluster_namespace)
+        .bind(cluster_deployment_name)
+        .bind(region)
+        .bind(node_pool)
+        .bind(node_selector)
+        .bind(tolerations)
+        .fetch_one(&mut **tx)
+        .await
+    }
+
+    /// Lookup without an ownership check, for background jobs only
+    pub async fn find_by_id(
+        pool: &PgPool,
+        deployment_id: Uuid,
+    ) -> Result<Option<Deployment>, sqlx::Error> {
+        sqlx::query_as::<_, Deployment>("SELECT * FROM deployments WHERE id = $1")
+            .bind(deployment_id)
+            .fetch_optional(pool)
+            .await
+    }
+
+    /// Every deployment in the given status, across all users
+    pub async fn get_all_by_status(
+        pool: &PgPool,
+        status: DeploymentStatus,
+    ) -> Result<Vec<Deployment>, sqlx::Error> {
+        sqlx::query_as::<_, Deployment>("SELECT * FROM deployments WHERE status = $1")
+            .bind(status)
+            .fetch_all(pool)
+            .await
+    }
+
+    pub async fn update_status<'e>(
+        executor: impl PgExecutor<'e>,
+        deployment_id: Uuid,
+        status: DeploymentStatus,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+                UPDATE deployments
+                SET status = $2
+                WHERE id = $1
+            "#,
+        )
+        .bind(deployment_id)
+        .bind(status)
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn update_replicas<'e>(
+        executor: impl PgExecutor<'e>,
+        deployment_id: Uuid,
+        user_id: Uuid,
+        replicas: i32,
+    ) -> Result<Deployment, sqlx::Error> {
+        sqlx::query_as::<_, Deployment>(
+            r#"
+                UPDATE deployments d
+                SET replicas = $3
+                WHERE d.id = $1
+                  AND d.project_id IN (SELECT project_id FROM project_access WHERE user_id = $2)
+                RETURNING d.*
+            "#,
+        )
+        .bind(deployment_id)
+        .bind(user_id)
+        .bind(replicas)
+        .fetch_one(executor)
+        .await
+    }
+
+    pub async fn update_spec<'e>(
+        executor: impl PgExecutor<'e>,
+        deployment_id: Uuid,
+        image: &str,
+        env_vars: serde_json::Value,
+        replicas: i32,
+        resources: serde_json::Value,
+    ) -> Result<Deployment, sqlx::Error> {
+        sqlx::query_as::<_, Deployment>(
+            r#"
+                UPDATE deployments
+                SET image = $2, env_vars = $3, replicas = $4, resources = $5
+                WHERE id = $1
+                RETURNING *
+            "#,
+        )
+        .bind(deployment_id)
+        .bind(image)
+        .bind(env_vars)
+        .bind(replicas)
+        .bind(resources)
+        .fetch_one(executor)
+        .await
+    }
+
+    /// Usage of every deployment billed to the account, optionally leaving
+    /// one out
+    pub async fn usage_by_account<'e>(
+        executor: impl PgExecutor<'e>,
+        account: BillingAccount,
+        excluding: &[Uuid],
+    ) -> Result<ResourceUsage, sqlx::Error> {
+        sqlx::query_as::<_, ResourceUsage>(
+            r#"
+                SELECT
+                    COUNT(*) AS deployments,
+                    COALESCE(SUM(d.replicas) FILTER (WHERE d.status <> 'paused'), 0)::BIGINT
+                        AS replicas,
+                    COALESCE(SUM(d.replicas * (d.resources->>'cpuLimitMillicores')::BIGINT)
+                        FILTER (WHERE d.status <> 'paused'), 0)::BIGINT AS cpu_millicores,
+                    COALESCE(SUM(d.replicas * (d.resources->>'memoryLimitMb')::BIGINT)
+                        FILTER (WHERE d.status <> 'paused'), 0)::BIGINT AS memory_mb
+                FROM deployments d
+                INNER JOIN projects p ON d.project_id = p.id
+                WHERE CASE
+                    WHEN $2::UUID IS NULL THEN p.owner_id = $1 AND p.organization_id IS NULL
+                    ELSE p.organization_id = $2
+                END
+                  AND d.id <> ALL($3)
+            "#,
+        )
+        .bind(account.user_id())
+        .bind(account.organization_id())
+        .bind(excluding)
+        .fetch_one(executor)
+        .await
+    }
+
+    /// Delete without a membership check, callers check access themselves
+    pub async fn delete(pool: &PgPool, deployment_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+                DELETE FROM deployments
+                WHERE id = $1
+            "#,
+        )
+        .bind(deployment_id)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+pub struct PlanRepository;
+
+impl PlanRepository {
+    /// The account's plan, the default plan when it has none
+    pub async fn get_for_account<'e>(
+        executor: impl PgExecutor<'e>,
+        account: BillingAccount,
+    ) -> Result<Option<Plan>, sqlx::Error> {
+        sqlx::query_as::<_, Plan>(
+            r#"
+                SELECT pl.*
+                FROM plans pl
+                LEFT JOIN user_plans up ON up.plan_name = pl.name AND up.user_id = $1
+                LEFT JOIN organizations o ON o.plan_name = pl.name AND o.id = $2
+                WHERE up.user_id IS NOT NULL OR o.id IS NOT NULL OR pl.is_default
+                ORDER BY up.user_id IS NULL AND o.id IS NULL
+                LIMIT 1
+            "#,
+        )
+        .bind(account.user_id())
+        .bind(account.organization_id())
+        .fetch_optional(executor)
+        .await
+    }
+
+    /// Serialize quota checks of one account until the transaction ends
+    pub async fn lock_account(
+        tx: &mut Transaction<'_, Postgres>,
+        account: BillingAccount,
+    ) -> Result<(), sqlx::Error> {
+        let id = match account {
+            BillingAccount::User(id) | BillingAccount::Organization(id) => id,
+        };
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtextextended($1::TEXT, 0))")
+            .bind(id)
+            .execute(&mut **tx)
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Reads of the tables the billing service owns
+pub struct BillingRepository;
+
+impl BillingRepository {
+    pub async fn get_prices(pool: &PgPool) -> Result<Option<BillingPrice>, sqlx::Error> {
+        sqlx::query_as::<_, BillingPrice>(
+            "SELECT cpu_millicore_hour, memory_mb_hour, updated_at FROM billing_prices",
+        )
+        .fetch_optional(pool)
+        .await
+    }
+
+    pub async fn get_balance(
+        pool: &PgPool,
+        account: BillingAccount,
+    ) -> Result<Balance, sqlx::Error> {
+        sqlx::query_as::<_, Balance>(
+            r#"
+                SELECT amount, currency
+                FROM balances
+                WHERE user_id = $1 OR organization_id = $2
+            "#,
+        )
+        .bind(account.user_id())
+        .bind(account.organization_id())
+        .fetch_one(pool)
+        .await
+    }
+}
+
+pub struct DeploymentSecretRepository;
+
+impl DeploymentSecretRepository {
+    pub async fn create(
+        tx: &mut Transaction<'_, Postgres>,
+        deployment_id: Uuid,
+        key: &str,
+        encrypted_value: Vec<u8>,
+    ) -> Result<DeploymentSecret, sqlx::Error> {
+        sqlx::query_as::<_, DeploymentSecret>(
+            r#"
+                INSERT INTO deployment_secrets (deployment_id, key, value)
+                VALUES ($1, $2, $3)
+                RETURNING *
+            "#,
+        )
+        .bind(deployment_id)
+        .bind(key)
+        .bind(encrypted_value)
+        .fetch_one(&mut **tx)
+        .await
+    }
+
+    pub async fn get_all_by_deployment<'e>(
+        executor: impl PgExecutor<'e>,
+        deployment_id: Uuid,
+    ) -> Result<Vec<DeploymentSecret>, sqlx::Error> {
+        sqlx::query_as::<_, DeploymentSecret>(
+            r#"
+                SELECT * FROM deployment_secrets
+                WHERE deployment_id = $1
+                ORDER BY created_at ASC
+            "#,
+        )
+        .bind(deployment_id)
+        .fetch_all(executor)
+        .await
+    }
+
+    pub async fn update_value(
+        tx: &mut Transaction<'_, Postgres>,
+        deployment_id: Uuid,
+        key: &str,
+        encrypted_value: Vec<u8>,
+    ) -> Result<Option<DeploymentSecret>, sqlx::Error> {
+        sqlx::query_as::<_, DeploymentSecret>(
+            r#"
+                UPDATE deployment_secrets
+                SET value = $3
+                WHERE deployment_id = $1 AND key = $2
+                RETURNING *
+            "#,
+        )
+        .bind(deployment_id)
+        .bind(key)
+        .bind(encrypted_value)
+        .fetch_optional(&mut **tx)
+        .await
+    }
+
+    pub async fn delete_by_key(
+        tx: &mut Transaction<'_, Postgres>,
+        deployment_id: Uuid,
+        key: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let result =
+            sqlx::query("DELETE FROM deployment_secrets WHERE deployment_id = $1 AND key = $2")
+                .bind(deployment_id)
+                .bind(key)
+                .execute(&mut **tx)
+                .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Keyset-paginated scan over every secret, used by background jobs
+    pub async fn get_batch_after(
+        pool: &PgPool,
+        after_id: Option<Uuid>,
+        limit: i64,
+    ) -> Result<Vec<DeploymentSecret>, sqlx::Error> {
+        sqlx::query_as::<_, DeploymentSecret>(
+            r#"
+                SELECT * FROM deployment_secrets
+                WHERE $1::uuid IS NULL OR id > $1
+                ORDER BY id ASC
+                LIMIT $2
+            "#,
+        )
+        .bind(after_id)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn count_all(pool: &PgPool) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM deployment_secrets")
+            .fetch_one(pool)
+            .await
+    }
+
+    /// Swap the ciphertext only if nobody changed it since it was read
+    pub async fn swap_value(
+        pool: &PgPool,
+        secret_id: Uuid,
+        current_value: &[u8],
+        new_value: Vec<u8>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+                UPDATE deployment_secrets
+                SET value = $3
+                WHERE id = $1 AND value = $2
+            "#,
+        )
+        .bind(secret_id)
+        .bind(current_value)
+        .bind(new_value)
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn delete_by_deployment(
+        tx: &mut Transaction<'_, Postgres>,
+        deployment_id: Uuid,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM deployment_secrets WHERE deployment_id = $1")
+            .bind(deployment_id)
+            .execute(&mut **tx)
+            .await?;
+        Ok(())
+    }
+}
+
+pub struct DeploymentDataKeyRepository;
+
+impl DeploymentDataKeyRepository {
+    pub async fn get<'e>(
+        executor: impl PgExecutor<'e>,
+        deployment_id: Uuid,
+    ) -> Result<Option<DeploymentDataKey>, sqlx::Error> {
+        sqlx::query_as::<_, DeploymentDataKey>(
+            "SELECT * FROM deployment_data_keys WHERE deployment_id = $1",
+        )
+        .bind(deployment_id)
+        .fetch_optional(executor)
+        .await
+    }
+
+    /// Insert the key unless the deployment already has one, returning `None` in that case
+    pub async fn create_if_absent<'e>(
+        executor: impl PgExecutor<'e>,
+        deployment_id: Uuid,
+        wrapped_key: Vec<u8>,
+        kms_provider: &str,
+        kms_key_id: &str,
+    ) -> Result<Option<DeploymentDataKey>, sqlx::Error> {
+        sqlx::query_as::<_, DeploymentDataKey>(
+            r#"
+                INSERT INTO deployment_data_keys (deployment_id, wrapped_key, kms_provider, kms_key_id)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (deployment_id) DO NOTHING
+                RETURNING *
+            "#,
+        )
+        .bind(deployment_id)
+        .bind(wrapped_key)
+        .bind(kms_provider)
+        .bind(kms_key_id)
+        .fetch_optional(executor)
+        .await
+    }
+
+    /// Keys not wrapped by the given KMS key, used by the rewrap job
+    pub async fn get_stale(
+        pool: &PgPool,
+        kms_provider: &str,
+        kms_key_id: &str,
+    ) -> Result<Vec<DeploymentDataKey>, sqlx::Error> {
+        sqlx::query_as::<_, DeploymentDataKey>(
+            r#"
+                SELECT * FROM deployment_data_keys
+                WHERE kms_provider <> $1 OR kms_key_id <> $2
+                ORDER BY deployment_id ASC
+            "#,
+        )
+        .bind(kms_provider)
+        .bind(kms_key_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Replace the wrapped key only if nobody rewrapped it since it was read
+    pub async fn swap_wrapped_key(
+        pool: &PgPool,
+        deployment_id: Uuid,
+        current_wrapped_key: &[u8],
+        wrapped_key: Vec<u8>,
+        kms_provider: &str,
+        kms_key_id: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+                UPDATE deployment_data_keys
+                SET wrapped_key = $3, kms_provider = $4, kms_key_id = $5
+                WHERE deployment_id = $1 AND wrapped_key = $2
+            "#,
+        )
+        .bind(deployment_id)
+        .bind(current_wrapped_key)
+        .bind(wrapped_key)
+        .bind(kms_provider)
+        .bind(kms_key_id)
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+pub struct DeploymentMetricRepository;
+
+impl DeploymentMetricRepository {
+    /// Samples are keyed by minute, so replicas sampling the same minute don't double count
+    pub async fn create(
+        pool: &PgPool,
+        deployment_id: Uuid,
+        sampled_at: DateTime<Utc>,
+        cpu_millicores: f64,
+        memory_mb: f64,
+        pod_count: i32,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+                INSERT INTO deployment_metrics (deployment_id, sampled_at, cpu_millicores, memory_mb, pod_count)
+                VALUES ($1, date_trunc('minute', $2), $3, $4, $5)
+                ON CONFLICT (deployment_id, sampled_at) DO NOTHING
+            "#,
+        )
+        .bind(deployment_id)
+        .bind(sampled_at)
+        .bind(cpu_millicores)
+        .bind(memory_mb)
+        .bind(pod_count)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Average and peak usage per `step_seconds` bucket in `[from, to)`
+    pub async fn get_rollup(
+        pool: &PgPool,
+        deployment_id: Uuid,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        step_seconds: i64,
+    ) -> Result<Vec<DeploymentMetricRollup>, sqlx::Error> {
+        sqlx::query_as::<_, DeploymentMetricRollup>(
+            r#"
+                SELECT
+                    date_bin(make_interval(secs => $4), sampled_at, $2) AS bucket,
+                    AVG(cpu_millicores) AS cpu_millicores_avg,
+                    MAX(cpu_millicores) AS cpu_millicores_max,
+                    AVG(memory_mb) AS memory_mb_avg,
+                    MAX(memory_mb) AS memory_mb_max,
+                    MAX(pod_count) AS pod_count
+                FROM deployment_metrics
+                WHERE deployment_id = $1 AND sampled_at >= $2 AND sampled_at < $3
+                GROUP BY bucket
+                ORDER BY bucket ASC
+            "#,
+        )
+        .bind(deployment_id)
+        .bind(from)
+        .bind(to)
+        .bind(step_seconds as f64)
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn delete_older_than(
+        pool: &PgPool,
+        cutoff: DateTime<Utc>,
+    ) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM deployment_metrics WHERE sampled_at < $1")
+            .bind(cutoff)
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+pub struct DeploymentEventRepository;
+
+impl DeploymentEventRepository {
+    pub async fn create(
+        pool: &PgPool,
+        deployment_id: Uuid,
+        event_type: &str,
+        message: Option<&str>,
+    ) -> Result<DeploymentEvent, sqlx::Error> {
+        sqlx::query_as::<_, DeploymentEvent>(
+            r#"
+                INSERT INTO deployment_events (deployment_id, event_type, message)
+                VALUES ($1, $2, $3)
+                RETURNING *
+            "#,
+        )
+        .bind(deployment_id)
+        .bind(event_type)
+        .bind(message)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Insert the event unless one with the same `dedupe_key` exists, returning
+    /// `None` in that case
+    pub async fn create_once(
+        pool: &PgPool,
+        deployment_id: Uuid,
+        event_type: &str,
+        message: Option<&str>,
+        dedupe_key: &str,
+    ) -> Result<Option<DeploymentEvent>, sqlx::Error> {
+        sqlx::query_as::<_, DeploymentEvent>(
+            r#"
+                INSERT INTO deployment_events (deployment_id, event_type, message, dedupe_key)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (deployment_id, dedupe_key) WHERE dedupe_key IS NOT NULL DO NOTHING
+                RETURNING *
+            "#,
+        )
+        .bind(deployment_id)
+        .bind(event_type)
+        .bind(message)
+        .bind(dedupe_key)
+        .fetch_optional(pool)
+        .await
+    }
+
+    pub async fn get_recent_by_deployment(
+        pool: &PgPool,
+        deployment_id: Uuid,
+        limit: i64,
+    ) -> Result<Vec<DeploymentEvent>, sqlx::Error> {
+        sqlx::query_as::<_, DeploymentEvent>(
+            r#"
+                SELECT * FROM deployment_events
+                WHERE deployment_id = $1
+                ORDER BY created_at DESC
+                LIMIT $2
+            "#,
+        )
+        .bind(deployment_id)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Newest first, every filter is optional
+    pub async fn get_filtered(
+        pool: &PgPool,
+        deployment_id: Uuid,
+        event_types: Option<&[String]>,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> Result<Vec<DeploymentEvent>, sqlx::Error> {
+        sqlx::query_as::<_, DeploymentEvent>(
+            r#"
+                SELECT * FROM deployment_events
+                WHERE deployment_id = $1
+                    AND ($2::text[] IS NULL OR event_type = ANY($2))
+                    AND ($3::timestamptz IS NULL OR created_at >= $3)
+                    AND ($4::timestamptz IS NULL OR created_at <= $4)
+                ORDER BY created_at DESC
+                LIMIT $5
+            "#,
+        )
+        .bind(deployment_id)
+        .bind(event_types)
+        .bind(from)
+        .bind(to)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn count_filtered(
+        pool: &PgPool,
+        deployment_id: Uuid,
+        event_types: Option<&[String]>,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar::<_, i64>(
+            r#"
+                SELECT COUNT(*) FROM deployment_events
+                WHERE deployment_id = $1
+                    AND ($2::text[] IS NULL OR event_type = ANY($2))
+                    AND ($3::timestamptz IS NULL OR created_at >= $3)
+                    AND ($4::timestamptz IS NULL OR created_at <= $4)
+            "#,
+        )
+        .bind(deployment_id)
+        .bind(event_types)
+        .bind(from)
+        .bind(to)
+        .fetch_one(pool)
+        .await
+    }
+}
+
+pub struct DeploymentPreviewRepository;
+
+impl DeploymentPreviewRepository {
+    pub async fn create(
+        pool: &PgPool,
+        deployment_id: Uuid,
+        base_deployment_id: Uuid,
+        name: &str,
+        url: Option<&str>,
+        callback_url: Option<&str>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<DeploymentPreview, sqlx::Error> {
+        sqlx::query_as::<_, DeploymentPreview>(
+            r#"
+                INSERT INTO deployment_previews
+                    (deployment_id, base_deployment_id, name, url, callback_url, expires_at)
+                VALUES ($1, $2, $3, $4, $5, $6)
+                RETURNING *
+            "#,
+        )
+        .bind(deployment_id)
+        .bind(base_deployment_id)
+        .bind(name)
+        .bind(url)
+        .bind(callback_url)
+        .bind(expires_at)
+        .fetch_one(pool)
+        .await
+    }
+
+    pub async fn get_by_name(
+        pool: &PgPool,
+        base_deployment_id: Uuid,
+        name: &str,
+    ) -> Result<Option<DeploymentPreview>, sqlx::Error> {
+        sqlx::query_as::<_, DeploymentPreview>(
+            "SELECT * FROM deployment_previews WHERE base_deployment_id = $1 AND name = $2",
+        )
+        .bind(base_deployment_id)
+        .bind(name)
+        .fetch_optional(pool)
+        .await
+    }
+
+    pub async fn get_all_by_base(
+        pool: &PgPool,
+        base_deployment_id: Uuid,
+    ) -> Result<Vec<DeploymentPreview>, sqlx::Error> {
+        sqlx::query_as::<_, DeploymentPreview>(
+            r#"
+                SELECT * FROM deployment_previews
+                WHERE base_deployment_id = $1
+                ORDER BY created_at DESC
+            "#,
+        )
+        .bind(base_deployment_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Push the expiry out, keeping the callback when none is given
+    pub async fn extend(
+        pool: &PgPool,
+        deployment_id: Uuid,
+        callback_url: Option<&str>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<DeploymentPreview, sqlx::Error> {
+        sqlx::query_as::<_, DeploymentPreview>(
+            r#"
+                UPDATE deployment_previews
+                SET callback_url = COALESCE($2, callback_url), expires_at = $3
+                WHERE deployment_id = $1
+                RETURNING *
+            "#,
+        )
+        .bind(deployment_id)
+        .bind(callback_url)
+        .bind(expires_at)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Lease expired previews to one sweeper: their expiry moves `lease_seconds`
+    /// ahead, so a failed teardown is retried once the lease runs out
+    pub async fn claim_expired(
+        pool: &PgPool,
+        lease_seconds: i64,
+        limit: i64,
+    ) -> Result<Vec<DeploymentPreview>, sqlx::Error> {
+        sqlx::query_as::<_, DeploymentPreview>(
+            r#"
+                UPDATE deployment_previews
+                SET expires_at = NOW() + make_interval(secs => $1)
+                WHERE deployment_id IN (
+                    SELECT deployment_id FROM deployment_previews
+                    WHERE expires_at <= NOW()
+                    ORDER BY expires_at
+                    LIMIT $2
+                    FOR UPDATE SKIP LOCKED
+                )
+                RETURNING *
+            "#,
+        )
+        .bind(lease_seconds as f64)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+    }
+}
+
+pub struct WebhookRepository;
+
+impl WebhookRepository {
+    pub async fn create(
+        pool: &PgPool,
+        project_id: Uuid,
+        url: &str,
+        wrapped_secret: Vec<u8>,
+        kms_provider: &str,
+        event_types: &[String],
+    ) -> Result<Webhook, sqlx::Error> {
+        sqlx::query_as::<_, Webhook>(
+            r#"
+                INSERT INTO webhooks (project_id, url, wrapped_secret, kms_provider, event_types)
+                VALUES ($1, $2, $3, $4, $5)
+                RETURNING *
+            "#,
+        )
+        .bind(project_id)
+        .bind(url)
+        .bind(wrapped_secret)
+        .bind(kms_provider)
+        .bind(event_types)
+        .fetch_one(pool)
+        .await
+    }
+
+    pub async fn get_many_by_project(
+        pool: &PgPool,
+        project_id: Uuid,
+    ) -> Result<Vec<Webhook>, sqlx::Error> {
+        sqlx::query_as::<_, Webhook>(
+            r#"
+                SELECT * FROM webhooks
+                WHERE project_id = $1
+                ORDER BY created_at ASC
+            "#,
+        )
+        .bind(project_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn get_by_id(
+        pool: &PgPool,
+        webhook_id: Uuid,
+        project_id: Uuid,
+    ) -> Result<Webhook, sqlx::Error> {
+        sqlx::query_as::<_, Webhook>("SELECT * FROM webhooks WHERE id = $1 AND project_id = $2")
+            .bind(webhook_id)
+            .bind(project_id)
+            .fetch_one(pool)
+            .await
+    }
+
+    /// Used by the dispatcher, which acts for no particular project
+    pub async fn find_by_id(
+        pool: &PgPool,
+        webhook_id: Uuid,
+    ) -> Result<Option<Webhook>, sqlx::Error> {
+        sqlx::query_as::<_, Webhook>("SELECT * FROM webhooks WHERE id = $1")
+            .bind(webhook_id)
+            .fetch_optional(pool)
+            .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn update(
+        pool: &PgPool,
+        webhook_id: Uuid,
+        project_id: Uuid,
+        url: Option<&str>,
+        wrapped_secret: Option<Vec<u8>>,
+        kms_provider: Option<&str>,
+        event_types: Option<&[String]>,
+        is_active: Option<bool>,
+    ) -> Result<Webhook, sqlx::Error> {
+        sqlx::query_as::<_, Webhook>(
+            r#"
+                UPDATE webhooks
+                SET url = COALESCE($3, url),
+                    wrapped_secret = COALESCE($4, wrapped_secret),
+                    kms_provider = COALESCE($5, kms_provider),
+                    event_types = COALESCE($6, event_types),
+                    is_active = COALESCE($7, is_active)
+                WHERE id = $1 AND project_id = $2
+                RETURNING *
+            "#,
+        )
+        .bind(webhook_id)
+        .bind(project_id)
+        .bind(url)
+        .bind(wrapped_secret)
+        .bind(kms_provider)
+        .bind(event_types)
+        .bind(is_active)
+        .fetch_one(pool)
+        .await
+    }
+
+    pub async fn delete(
+        pool: &PgPool,
+        webhook_id: Uuid,
+        project_id: Uuid,
+    ) -> Result<(), sqlx::Error> {
+        let result = sqlx::query("DELETE FROM webhooks WHERE id = $1 AND project_id = $2")
+            .bind(webhook_id)
+            .bind(project_id)
+            .execute(pool)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(sqlx::Error::RowNotFound);
+        }
+
+        Ok(())
+    }
+}
+
+pub struct WebhookDeliveryRepository;
+
+impl WebhookDeliveryRepository {
+    /// Queue a delivery for every active webhook of the project subscribed to
+    /// `event_type`, returning how many were queued
+    pub async fn enqueue(
+        pool: &PgPool,
+        project_id: Uuid,
+        deployment_id: Uuid,
+        event_type: &str,
+        payload: &serde_json::Value,
+    ) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+                INSERT INTO webhook_deliveries (webhook_id, deployment_id, event_type, payload)
+                SELECT id, $2, $3, $4
+                FROM webhooks
+                WHERE project_id = $1 AND is_active AND $3 = ANY(event_types)
+            "#,
+        )
+        .bind(project_id)
+        .bind(deployment_id)
+        .bind(event_type)
+        .bind(payload)
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    pub async fn create(
+        pool: &PgPool,
+        webhook_id: Uuid,
+        deployment_id: Uuid,
+        event_type: &str,
+        payload: &serde_json::Value,
+    ) -> Result<WebhookDelivery, sqlx::Error> {
+        sqlx::query_as::<_, WebhookDelivery>(
+            r#"
+                INSERT INTO webhook_deliveries (webhook_id, deployment_id, event_type, payload)
+                VALUES ($1, $2, $3, $4)
+                RETURNING *
+            "#,
+        )
+        .bind(webhook_id)
+        .bind(deployment_id)
+        .bind(event_type)
+        .bind(payload)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Newest first
+    pub async fn get_many_by_webhook(
+        pool: &PgPool,
+        webhook_id: Uuid,
+        pagination: Pagination,
+    ) -> Result<(Vec<WebhookDelivery>, i64), sqlx::Error> {
+        let deliveries = sqlx::query_as::<_, WebhookDelivery>(
+            r#"
+                SELECT * FROM webhook_deliveries
+                WHERE webhook_id = $1
+                ORDER BY created_at DESC
+                LIMIT $2
+                OFFSET $3
+            "#,
+        )
+        .bind(webhook_id)
+        .bind(pagination.limit)
+        .bind(pagination.offset)
+        .fetch_all(pool)
+        .await?;
+
+        let total = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM webhook_deliveries WHERE webhook_id = $1",
+        )
+        .bind(webhook_id)
+        .fetch_one(pool)
+        .await?;
+
+        Ok((deliveries, total))
+    }
+
+    pub async fn get_by_id(
+        pool: &PgPool,
+        delivery_id: Uuid,
+        webhook_id: Uuid,
+    ) -> Result<WebhookDelivery, sqlx::Error> {
+        sqlx::query_as::<_, WebhookDelivery>(
+            "SELECT * FROM webhook_deliveries WHERE id = $1 AND webhook_id = $2",
+        )
+        .bind(delivery_id)
+        .bind(webhook_id)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Lease due deliveries of active webhooks by pushing their next attempt
+    /// back, so concurrent dispatchers don't send the same delivery twice
+    pub async fn claim_due(
+        pool: &PgPool,
+        lease_seconds: i64,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, sqlx::Error> {
+        sqlx::query_as::<_, WebhookDelivery>(
+            r#"
+                UPDATE webhook_deliveries
+                SET next_attempt_at = NOW() + make_interval(secs => $1)
+                WHERE id IN (
+                    SELECT d.id FROM webhook_deliveries d
+                    JOIN webhooks w ON w.id = d.webhook_id
+                    WHERE d.status = 'pending' AND d.next_attempt_at <= NOW() AND w.is_active
+                    ORDER BY d.next_attempt_at
+                    LIMIT $2
+                    FOR UPDATE OF d SKIP LOCKED
+                )
+                RETURNING *
+            "#,
+        )
+        .bind(lease_seconds as f64)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn mark_succeeded(
+        pool: &PgPool,
+        delivery_id: Uuid,
+        response_status: i32,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+                UPDATE webhook_deliveries
+                SET status = 'succeeded',
+                    attempts = attempts + 1,
+                    response_status = $2,
+                    last_error = NULL,
+                    delivered_at = NOW()
+                WHERE id = $1
+            "#,
+        )
+        .bind(delivery_id)
+        .bind(response_status)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Record a failed attempt, retried after `retry_in_seconds` or given up on
+    /// without it
+    pub async fn mark_attempt_failed(
+        pool: &PgPool,
+        delivery_id: Uuid,
+        response_status: Option<i32>,
+        error: &str,
+        retry_in_seconds: Option<i64>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+                UPDATE webhook_deliveries
+                SET status = CASE WHEN $4::FLOAT8 IS NULL THEN 'failed' ELSE 'pending' END::webhook_delivery_status,
+                    attempts = attempts + 1,
+                    response_status = $2,
+                    last_error = $3,
+                    next_attempt_at = NOW() + make_interval(secs => COALESCE($4, 0))
+                WHERE id = $1
+            "#,
+        )
+        .bind(delivery_id)
+        .bind(response_status)
+        .bind(error)
+        .bind(retry_in_seconds.map(|seconds| seconds as f64))
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+pub struct DeployHookRepository;
+
+impl DeployHookRepository {
+    pub async fn create(
+        pool: &PgPool,
+        deployment_id: Uuid,
+        name: &str,
+        token_hash: &str,
+        token_prefix: &str,
+    ) -> Result<DeployHook, sqlx::Error> {
+        sqlx::query_as::<_, DeployHook>(
+            r#"
+                INSERT INTO deploy_hooks (deployment_id, name, token_hash, token_prefix)
+                VALUES ($1, $2, $3, $4)
+                RETURNING *
+            "#,
+        )
+        .bind(deployment_id)
+        .bind(name)
+        .bind(token_hash)
+        .bind(token_prefix)
+        .fetch_one(pool)
+        .await
+    }
+
+    pub async fn get_all_by_deployment(
+        pool: &PgPool,
+        deployment_id: Uuid,
+    ) -> Result<Vec<DeployHook>, sqlx::Error> {
+        sqlx::query_as::<_, DeployHook>(
+            r#"
+                SELECT * FROM deploy_hooks
+                WHERE deployment_id = $1
+                ORDER BY created_at ASC
+            "#,
+        )
+        .bind(deployment_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn find_by_token_hash(
+        pool: &PgPool,
+        token_hash: &str,
+    ) -> Result<Option<DeployHook>, sqlx::Error> {
+        sqlx::query_as::<_, DeployHook>("SELECT * FROM deploy_hooks WHERE token_hash = $1")
+            .bind(token_hash)
+            .fetch_optional(pool)
+            .await
+    }
+
+    /// Swap in a new token, the old one stops working at once
+    pub async fn regenerate(
+        pool: &PgPool,
+        hook_id: Uuid,
+        deployment_id: Uuid,
+        token_hash: &str,
+        token_prefix: &str,
+    ) -> Result<DeployHook, sqlx::Error> {
+        sqlx::query_as::<_, DeployHook>(
+            r#"
+                UPDATE deploy_hooks
+                SET token_hash = $3, token_prefix = $4
+                WHERE id = $1 AND deployment_id = $2
+                RETURNING *
+            "#,
+        )
+        .bind(hook_id)
+        .bind(deployment_id)
+        .bind(token_hash)
+        .bind(token_prefix)
+        .fetch_one(pool)
+        .await
+    }
+
+    pub async fn touch(pool: &PgPool, hook_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE deploy_hooks SET last_triggered_at = NOW() WHERE id = $1")
+            .bind(hook_id)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete(
+        pool: &PgPool,
+        hook_id: Uuid,
+        deployment_id: Uuid,
+    ) -> Result<(), sqlx::Error> {
+        let result = sqlx::query("DELETE FROM deploy_hooks WHERE id = $1 AND deployment_id = $2")
+            .bind(hook_id)
+            .bind(deployment_id)
+            .execute(pool)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(sqlx::Error::RowNotFound);
+        }
+
+        Ok(())
+    }
 }
@@ -1,12 +1,17 @@
+use bigdecimal::BigDecimal;
 use chrono::{DateTime, Utc};
 use once_cell::sync::Lazy;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use shared::utilities::errors::AppError;
 use std::collections::HashMap;
 use uuid::Uuid;
 use validator::Validate;
 
-use crate::features::models::{DeploymentStatus, ResourceSpec};
+use crate::features::models::{
+    DeploymentSort, DeploymentStatus, Project, ProjectRole, ResourcePreset, ResourceSpec,
+    ResourceUsage, SortOrder, WebhookEvent,
+};
 
 // ============================================
 // PROJECT SCHEMAS
@@ -30,6 +35,34 @@ pub struct UpdateProjectRequest {
     pub description: Option<String>,
 }
 
+/// Copies every deployment of the project into a new one
+#[derive(Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateProjectRequest {
+    #[validate(length(min = 1, max = 20))]
+    pub name: String,
+    #[validate(length(max = 180))]
+    pub description: Option<String>,
+    /// Replica count of every copied deployment, defaults to the source's
+    #[validate(range(min = 1, max = 10))]
+    pub replicas: Option<i32>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateProjectResponse {
+    pub project: Project,
+    pub deployments: Vec<DeploymentResponse>,
+}
+
+/// Narrows the project list, `search` matches anywhere in the name
+#[derive(Deserialize, Validate, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectListQuery {
+    #[validate(length(min = 1, max = 64))]
+    pub search: Option<String>,
+}
+
 // ============================================
 // DEPLOYMENT SCHEMAS
 // ============================================
@@ -59,6 +92,9 @@ pub struct CreateDeploymentRequest {
     /// Resource limits
     pub resources: Option<ResourceSpec>,
 
+    /// Named size instead of `resources`
+    pub size: Option<ResourcePreset>,
+
     /// Custom labels for the deployment
     pub labels: Option<HashMap<String, String>>,
 
@@ -66,6 +102,14 @@ pub struct CreateDeploymentRequest {
     #[validate(length(min = 3, max = 63))]
     #[validate(regex(path = *SUBDOMAIN))]
     pub subdomain: Option<String>,
+
+    /// Cluster region to run in, the default region when omitted
+    #[validate(length(min = 1, max = 64))]
+    pub region: Option<String>,
+
+    /// Node pool of the region, e.g. `high-memory`
+    #[validate(length(min = 1, max = 64))]
+    pub node_pool: Option<String>,
 }
 
 static SUBDOMAIN: Lazy<Regex> =
@@ -80,6 +124,7 @@ pub struct UpdateDeploymentRequest {
     pub env_vars: Option<HashMap<String, String>>,
     pub secrets: Option<HashMap<String, String>>,
     pub resources: Option<ResourceSpec>,
+    pub size: Option<ResourcePreset>,
 }
 
 #[derive(Deserialize, Validate, Debug)]
@@ -89,6 +134,36 @@ pub struct ScaleDeploymentRequest {
     pub replicas: i32,
 }
 
+/// Full desired state of an existing deployment, secrets are managed separately
+#[derive(Debug, Clone)]
+pub struct DeploymentUpdate {
+    pub image: String,
+    pub replicas: i32,
+    pub port: i32,
+    pub env_vars: HashMap<String, String>,
+    pub resources: ResourceSpec,
+    pub external_url: Option<String>,
+}
+
+/// Every field falls back to the source deployment
+#[derive(Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CloneDeploymentRequest {
+    /// Project to clone into, defaults to the source's project
+    pub project_id: Option<Uuid>,
+
+    /// Defaults to the source name, with `-copy` when cloning into the same project
+    #[validate(length(min = 1, max = 128))]
+    pub name: Option<String>,
+
+    #[validate(length(min = 3, max = 63))]
+    #[validate(regex(path = *SUBDOMAIN))]
+    pub subdomain: Option<String>,
+
+    #[validate(range(min = 1, max = 10))]
+    pub replicas: Option<i32>,
+}
+
 #[derive(Serialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct DeploymentResponse {
@@ -100,6 +175,8 @@ pub struct DeploymentResponse {
     pub replicas: i32,
     pub resources: ResourceSpec,
     pub external_url: Option<String>,
+    pub region: Option<String>,
+    pub node_pool: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -114,22 +191,703 @@ pub struct DeploymentDetailResponse {
     pub status: DeploymentStatus,
     pub replicas: i32,
     pub ready_replicas: Option<i32>,
+    pub available_replicas: Option<i32>,
+    pub updated_replicas: Option<i32>,
+    pub pods: Vec<PodStatusResponse>,
     pub resources: ResourceSpec,
     pub env_vars: HashMap<String, String>,
     pub secret_keys: Vec<String>, // Only return keys, not values
     pub labels: Option<HashMap<String, String>>,
     pub external_url: Option<String>,
+    pub region: Option<String>,
+    pub node_pool: Option<String>,
     pub cluster_namespace: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+/// Live cluster state of a deployment, cached briefly in Redis
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DeploymentRuntimeStatus {
+    pub ready_replicas: i32,
+    pub available_replicas: i32,
+    pub updated_replicas: i32,
+    pub external_url: Option<String>,
+    pub pods: Vec<PodStatusResponse>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PodStatusResponse {
+    pub name: String,
+    pub phase: Option<String>,
+    pub ready: bool,
+    pub restart_count: i32,
+    pub waiting_reason: Option<String>, // e.g. CrashLoopBackOff, ImagePullBackOff
+    pub last_termination_reason: Option<String>, // e.g. OOMKilled, Error
+    pub node_name: Option<String>,
+    pub started_at: Option<DateTime<Utc>>,
+}
+
+// ============================================
+// PREVIEW SCHEMAS
+// ============================================
+
+/// A preview is a copy of the base deployment running another image.
+/// Creating an existing preview again updates its image and expiry.
+#[derive(Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatePreviewRequest {
+    /// Short key such as `pr-42`, prefixed to the base's name and subdomain
+    #[validate(length(min = 1, max = 20))]
+    #[validate(regex(path = *SUBDOMAIN))]
+    pub name: String,
+
+    /// Full image to run, takes precedence over `imageTag`
+    #[validate(length(min = 1, max = 500))]
+    pub image: Option<String>,
+
+    /// Replaces the tag of the base image
+    #[validate(regex(path = *IMAGE_TAG))]
+    pub image_tag: Option<String>,
+
+    /// Defaults to 72 hours
+    #[validate(range(min = 1, max = 720))]
+    pub ttl_hours: Option<i64>,
+
+    /// Receives a POST when the preview is ready and when it expires
+    #[validate(url)]
+    pub callback_url: Option<String>,
+}
+
 #[derive(Serialize, Debug)]
 #[serde(rename_all = "camelCase")]
+pub struct PreviewResponse {
+    pub deployment_id: Uuid,
+    pub base_deployment_id: Option<Uuid>,
+    pub name: String,
+    pub url: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+// ============================================
+// DEPLOYMENT TIMELINE SCHEMAS
+// ============================================
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventSource {
+    Platform,   // `deployment_events` rows
+    Kubernetes, // Events of the deployment, its ReplicaSets and pods
+}
+
+/// Filters and order of the deployment list. `labels` is a comma separated
+/// selector of `key=value` pairs and bare keys a deployment must have.
+/// Statuses sort alphabetically by name, ties are newest first.
+#[derive(Deserialize, Validate, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DeploymentListQuery {
+    #[validate(length(min = 1, max = 64))]
+    pub search: Option<String>,
+    pub status: Option<DeploymentStatus>,
+    /// Matches anywhere in the image reference
+    #[validate(length(min = 1, max = 255))]
+    pub image: Option<String>,
+    #[validate(length(min = 1, max = 512))]
+    pub labels: Option<String>,
+    #[serde(default)]
+    pub sort: DeploymentSort,
+    #[serde(default)]
+    pub order: SortOrder,
+}
+
+impl DeploymentListQuery {
+    /// The label pairs to match as one JSON object, and the keys that only
+    /// have to be present
+    pub fn label_selector(&self) -> Result<(Option<serde_json::Value>, Vec<String>), AppError> {
+        let Some(selector) = &self.labels else {
+            return Ok((None, vec![]));
+        };
+
+        let mut pairs = serde_json::Map::new();
+        let mut keys = vec![];
+        for term in selector.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            match term.split_once('=') {
+                Some((key, value)) if !key.trim().is_empty() => {
+                    pairs.insert(key.trim().to_string(), value.trim().into());
+                }
+                None => keys.push(term.to_string()),
+                _ => {
+                    return Err(AppError::ValidationError(format!(
+                        "Invalid label selector '{}'",
+                        term
+                    )));
+                }
+            }
+        }
+
+        Ok(((!pairs.is_empty()).then(|| pairs.into()), keys))
+    }
+}
+
+/// Filters for the timeline, `eventType` is a comma separated list
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TimelineQuery {
+    pub event_type: Option<String>,
+    pub source: Option<EventSource>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl TimelineQuery {
+    pub fn event_types(&self) -> Option<Vec<String>> {
+        self.event_type.as_ref().map(|types| {
+            types
+                .split(',')
+                .map(str::trim)
+                .filter(|t| !t.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct DeploymentEventResponse {
-    pub id: Uuid,
-    pub event_type: String,
+    pub id: String,
+    pub source: EventSource,
+    pub event_type: String, // Our event type, or the Kubernetes reason (BackOff, Pulled)
+    pub severity: Option<String>, // Normal or Warning, Kubernetes only
+    pub object: Option<String>, // e.g. Pod/web-7d9f8c6b5-x2x9k
     pub message: Option<String>,
+    pub count: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+// ============================================
+// PROJECT UPDATE SCHEMAS
+// ============================================
+
+/// Pushed to the project stream of every compute replica through Redis pub/sub
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(
+    tag = "type",
+    rename_all = "snake_case",
+    rename_all_fields = "camelCase"
+)]
+pub enum ProjectUpdate {
+    Status {
+        project_id: Uuid,
+        deployment_id: Uuid,
+        status: DeploymentStatus,
+        replicas: i32,
+        ready_replicas: Option<i32>,
+        available_replicas: Option<i32>,
+        updated_replicas: Option<i32>,
+    },
+    Event {
+        project_id: Uuid,
+        deployment_id: Uuid,
+        event: DeploymentEventResponse,
+    },
+    Deleted {
+        project_id: Uuid,
+        deployment_id: Uuid,
+    },
+    Apply {
+        project_id: Uuid,
+        step: usize,
+        total: usize,
+        action: SpecAction,
+        name: String,
+        state: ApplyState,
+    },
+}
+
+impl ProjectUpdate {
+    pub fn project_id(&self) -> Uuid {
+        match self {
+            Self::Status { project_id, .. }
+            | Self::Event { project_id, .. }
+            | Self::Deleted { project_id, .. }
+            | Self::Apply { project_id, .. } => *project_id,
+        }
+    }
+
+    /// SSE event name
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Status { .. } => "status",
+            Self::Event { .. } => "event",
+            Self::Deleted { .. } => "deleted",
+            Self::Apply { .. } => "apply",
+        }
+    }
+}
+
+// ============================================
+// DEPLOYMENT SECRET SCHEMAS
+// ============================================
+
+#[derive(Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateSecretRequest {
+    #[validate(length(min = 1, max = 253))]
+    #[validate(regex(path = *SECRET_KEY))]
+    pub key: String,
+
+    #[validate(length(max = 65536))]
+    pub value: String,
+}
+
+#[derive(Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplaceSecretRequest {
+    #[validate(length(max = 65536))]
+    pub value: String,
+}
+
+/// Secret keys must be valid environment variable names
+pub static SECRET_KEY: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$").unwrap());
+
+/// Only metadata is ever returned, never the value
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DeploymentSecretResponse {
+    pub key: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+// ============================================
+// METRICS SCHEMAS
+// ============================================
+
+/// `from`/`to` default to the last hour, `step` is in seconds
+#[derive(Deserialize, Debug)]
+pub struct MetricsQuery {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub step: Option<i64>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DeploymentMetricsResponse {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub step: i64,
+    pub resources: ResourceSpec, // Per pod
+    pub points: Vec<MetricPointResponse>,
+}
+
+/// Usage summed over pods, next to the requests and limits of that many pods
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MetricPointResponse {
+    pub timestamp: DateTime<Utc>,
+    pub pod_count: i32,
+    pub cpu_millicores_avg: f64,
+    pub cpu_millicores_max: f64,
+    pub cpu_request_millicores: i64,
+    pub cpu_limit_millicores: i64,
+    pub memory_mb_avg: f64,
+    pub memory_mb_max: f64,
+    pub memory_request_mb: i64,
+    pub memory_limit_mb: i64,
+}
+
+// ============================================
+// EXPORT SCHEMAS
+// ============================================
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SecretExportMode {
+    #[default]
+    Placeholder, // Secret manifest with `${KEY}` values to fill in
+    Redacted, // No Secret manifest, env vars still reference it
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub secrets: SecretExportMode,
+}
+
+// ============================================
+// COMPOSE IMPORT SCHEMAS
+// ============================================
+
+#[derive(Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ComposeImportRequest {
+    /// Contents of the `docker-compose.yml`
+    #[validate(length(min = 1, max = 262144))]
+    pub compose: String,
+
+    /// Contents of the files referenced by `env_file`, keyed by the path
+    /// used in the compose file. Their variables become secrets.
+    #[serde(default)]
+    pub env_files: HashMap<String, String>,
+}
+
+/// `preview=true` returns the plan without creating anything
+#[derive(Deserialize, Debug, Default)]
+pub struct ComposeImportQuery {
+    #[serde(default)]
+    pub preview: bool,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ComposeImportResponse {
+    pub preview: bool,
+    /// Deployments in creation order
+    pub plan: Vec<PlannedDeploymentResponse>,
+    pub warnings: Vec<String>,
+    pub created: Vec<DeploymentResponse>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PlannedDeploymentResponse {
+    pub service: String,
+    pub name: String,
+    pub image: String,
+    pub replicas: i32,
+    pub port: i32,
+    pub env_vars: HashMap<String, String>,
+    pub secret_keys: Vec<String>,
+    pub resources: ResourceSpec,
+    pub depends_on: Vec<String>,
+}
+
+// ============================================
+// PROJECT SPEC SCHEMAS
+// ============================================
+
+/// Whole-project document managed from a repository, YAML or JSON
+#[derive(Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ProjectSpec {
+    #[validate(nested)]
+    pub project: Option<UpdateProjectRequest>,
+
+    /// Named sets of env vars deployments can pull in
+    #[serde(default)]
+    pub env_groups: HashMap<String, HashMap<String, String>>,
+
+    #[serde(default)]
+    #[validate(nested)]
+    pub deployments: Vec<DeploymentSpec>,
+}
+
+#[derive(Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct DeploymentSpec {
+    #[validate(length(min = 1, max = 128))]
+    pub name: String,
+
+    #[validate(length(min = 1, max = 500))]
+    pub image: String,
+
+    #[serde(default = "default_spec_replicas")]
+    #[validate(range(min = 0, max = 10))]
+    pub replicas: i32,
+
+    #[validate(range(min = 1, max = 65535))]
+    pub port: i32,
+
+    /// Applied in order, `env` overrides them
+    #[serde(default)]
+    pub env_groups: Vec<String>,
+
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+
+    /// Defaults apply when omitted
+    pub resources: Option<ResourceSpec>,
+
+    /// Named size instead of `resources`
+    pub size: Option<ResourcePreset>,
+
+    /// Kept as is on existing deployments when omitted
+    #[validate(length(min = 3, max = 63))]
+    #[validate(regex(path = *SUBDOMAIN))]
+    pub subdomain: Option<String>,
+
+    /// Placement of new deployments, existing ones can't move
+    pub region: Option<String>,
+    pub node_pool: Option<String>,
+}
+
+fn default_spec_replicas() -> i32 {
+    1
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SpecAction {
+    Create,
+    Update,
+    Delete,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ApplyState {
+    Started,
+    Done,
+    Failed,
+    RolledBack,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PlannedChange {
+    pub action: SpecAction,
+    pub name: String,
+    pub deployment_id: Option<Uuid>,
+    /// Human readable field changes, e.g. `replicas: 1 -> 3`
+    pub changes: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SpecPlanResponse {
+    /// Changes to the project's own name and description
+    pub project: Vec<String>,
+    pub creates: Vec<PlannedChange>,
+    pub updates: Vec<PlannedChange>,
+    pub deletes: Vec<PlannedChange>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SpecApplyResponse {
+    pub plan: SpecPlanResponse,
+    pub created: Vec<DeploymentResponse>,
+    pub updated: Vec<DeploymentResponse>,
+    pub deleted: Vec<Uuid>,
+}
+
+// ============================================
+// REGION SCHEMAS
+// ============================================
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RegionResponse {
+    pub region: String,
+    pub is_default: bool,
+    pub accepting_deployments: bool,
+    pub node_pools: Vec<String>,
+}
+
+/// What the user's deployments use next to what their plan allows
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct QuotaResponse {
+    pub plan: String,
+    pub usage: ResourceUsage,
+    pub limits: ResourceUsage,
+}
+
+/// Proposed change of an existing deployment, omitted fields stay as they are
+#[derive(Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct EstimateChangeRequest {
+    #[validate(range(min = 0, max = 10))]
+    pub replicas: Option<i32>,
+    pub resources: Option<ResourceSpec>,
+    pub size: Option<ResourcePreset>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CostEstimateResponse {
+    pub currency: String,
+    pub hourly: BigDecimal,
+    pub daily: BigDecimal,
+    pub monthly: BigDecimal,
+    /// Hourly cost of the deployment as it runs now, when estimating a change
+    pub current_hourly: Option<BigDecimal>,
+    /// Only for users who may see the account's balance, its own user or
+    /// the organization's admins
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub balance: Option<BigDecimal>,
+    /// Days the balance lasts with every deployment running as projected,
+    /// left out when nothing would be running or the balance is hidden
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub runway_days: Option<BigDecimal>,
+}
+
+// ============================================
+// WEBHOOK SCHEMAS
+// ============================================
+
+#[derive(Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateWebhookRequest {
+    #[validate(url)]
+    #[validate(length(max = 2048))]
+    pub url: String,
+
+    /// Generated and returned once when omitted
+    #[validate(length(min = 16, max = 255))]
+    pub secret: Option<String>,
+
+    #[validate(length(min = 1))]
+    pub event_types: Vec<WebhookEvent>,
+}
+
+/// Omitted fields stay as they are
+#[derive(Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateWebhookRequest {
+    #[validate(url)]
+    #[validate(length(max = 2048))]
+    pub url: Option<String>,
+
+    #[validate(length(min = 16, max = 255))]
+    pub secret: Option<String>,
+
+    #[validate(length(min = 1))]
+    pub event_types: Option<Vec<WebhookEvent>>,
+
+    pub is_active: Option<bool>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookResponse {
+    pub id: Uuid,
+    pub project_id: Uuid,
+    pub url: String,
+    pub event_types: Vec<String>,
+    pub is_active: bool,
+    /// Only returned when the secret was generated for the caller
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+// ============================================
+// DEPLOY HOOK SCHEMAS
+// ============================================
+
+/// Docker tag grammar
+static IMAGE_TAG: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^[A-Za-z0-9_][A-Za-z0-9_.-]{0,127}$").unwrap());
+
+#[derive(Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateDeployHookRequest {
+    /// Tells hooks apart, e.g. the pipeline that calls it
+    #[validate(length(min = 1, max = 63))]
+    pub name: String,
+}
+
+#[derive(Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TriggerDeployHookQuery {
+    /// Replaces the tag of the current image, a rolling restart without it
+    #[validate(regex(path = *IMAGE_TAG))]
+    pub tag: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DeployHookResponse {
+    pub id: Uuid,
+    pub deployment_id: Uuid,
+    pub name: String,
+    pub token_prefix: String,
+    /// Only returned when the token is generated, it can't be read back later
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    pub last_triggered_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+// ============================================
+// PROJECT MEMBER SCHEMAS
+// ============================================
+
+#[derive(Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct InviteMemberRequest {
+    #[validate(email, length(max = 255))]
+    pub email: String,
+    /// Any role but owner, ownership moves by transfer only
+    pub role: ProjectRole,
+}
+
+#[derive(Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateMemberRequest {
+    pub role: ProjectRole,
+}
+
+#[derive(Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AcceptInvitationRequest {
+    #[validate(length(min = 1, max = 255))]
+    pub token: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectInvitationResponse {
+    pub id: Uuid,
+    pub project_id: Uuid,
+    pub email: String,
+    pub role: ProjectRole,
+    pub invited_by: Option<Uuid>,
+    pub expires_at: DateTime<Utc>,
+    pub accepted_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+// ============================================
+// PROJECT TRANSFER SCHEMAS
+// ============================================
+
+#[derive(Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferProjectRequest {
+    /// Account of the recipient, who accepts from the emailed link
+    #[validate(email, length(max = 255))]
+    pub email: String,
+}
+
+#[derive(Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AcceptTransferRequest {
+    #[validate(length(min = 1, max = 255))]
+    pub token: String,
+    /// Organization to move the project into, the recipient's own account
+    /// when omitted
+    pub organization_id: Option<Uuid>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectTransferResponse {
+    pub id: Uuid,
+    pub project_id: Uuid,
+    pub email: String,
+    pub initiated_by: Option<Uuid>,
+    pub expires_at: DateTime<Utc>,
+    pub accepted_at: Option<DateTime<Utc>>,
+    pub accepted_by: Option<Uuid>,
+    pub organization_id: Option<Uuid>,
     pub created_at: DateTime<Utc>,
 }
 
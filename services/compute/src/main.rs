@@ -25,7 +25,18 @@ use tracing_subscriber::{
     EnvFilter, fmt::time::LocalTime, layer::SubscriberExt, util::SubscriberInitExt,
 };
 
-use crate::{services::build_kubernetes::Kubernetes, utilities::app_state::AppState};
+use crate::{
+    services::{
+        billing::BillingClient, build_kubernetes::Kubernetes, event_bus::EventBus, kms::build_kms,
+        metrics::MetricsService, outbound::OutboundClient, preview::PreviewService,
+        runtime_status::RuntimeStatusService, secret_rotation::SecretReencryptionJob,
+        status_watcher::StatusWatcher, webhooks::WebhookService,
+    },
+    utilities::{
+        app_state::AppState,
+        encryption::{EncryptionService, Keyring},
+    },
+};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -66,23 +77,64 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // let rustls_config = build_rustls_config(&config)?;
     let database = Database::new(&config).await?;
+    let http_client = reqwest::ClientBuilder::new()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?;
+    // The env keyring only reads secrets written before envelope encryption
+    let encryption = EncryptionService::new(
+        build_kms(&config, &http_client)?,
+        Keyring::from_config(&config)?,
+    );
+
+    // `compute reencrypt-secrets` rewraps data keys, moves stored secrets onto them and exits
+    if std::env::args().nth(1).as_deref() == Some("reencrypt-secrets") {
+        let report = SecretReencryptionJob::run(&database.pool, &encryption, 500).await?;
+        info!(
+            "✅ Re-encryption finished: {} re-encrypted, {} already current, {} data keys rewrapped, {} failed",
+            report.reencrypted, report.skipped, report.rewrapped_keys, report.failed
+        );
+        return Ok(());
+    }
+
     let redis = Redis::new(&config).await?;
     let kubernetes = Kubernetes::new(&config).await?;
+    let events = EventBus::new(redis.clone());
+    let runtime_status = RuntimeStatusService::new(redis.clone());
+    let billing = BillingClient::new(&config, http_client.clone());
+    MetricsService::spawn_sampler(database.pool.clone(), kubernetes.clone());
+    for cluster in kubernetes.clusters() {
+        StatusWatcher::spawn(
+            database.pool.clone(),
+            cluster.client.clone(),
+            events.clone(),
+        );
+    }
+    // User-configured URLs, kept off private networks
+    let outbound = OutboundClient::new()?;
+    PreviewService::spawn_sweeper(
+        database.pool.clone(),
+        kubernetes.clone(),
+        events.clone(),
+        outbound.clone(),
+    );
+    WebhookService::spawn_dispatcher(database.pool.clone(), encryption.clone(), outbound.clone());
     let amqp = Amqp::new(&config).await?;
     let kafka = Kafka::new(&config, "compute-service-group")?;
-    let http_client = reqwest::ClientBuilder::new()
-        .redirect(reqwest::redirect::Policy::none())
-        .build()?;
 
     let app_state = AppState {
         rustls_config: None,
         database,
         redis,
         kubernetes,
+        billing,
+        encryption,
+        events,
         amqp,
         kafka,
         config: config.clone(),
         http_client,
+        outbound,
+        runtime_status,
     };
 
     let cors = CorsLayer::new()
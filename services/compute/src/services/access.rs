@@ -0,0 +1,134 @@
+use shared::utilities::errors::AppError;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::features::models::{BillingAccount, Deployment, OrganizationRole, Project, ProjectRole};
+use crate::features::repository::{
+    DeploymentRepository, OrganizationRepository, ProjectMemberRepository, ProjectRepository,
+};
+
+/// Role checks every handler runs before touching a project. Projects the
+/// user isn't a member of don't exist as far as they can tell, members
+/// whose role is too low are told so.
+pub struct AccessService;
+
+impl AccessService {
+    /// The project, when `user_id` has at least `required` on it, directly or
+    /// through the project's organization
+    pub async fn project(
+        pool: &PgPool,
+        project_id: Uuid,
+        user_id: Uuid,
+        required: ProjectRole,
+    ) -> Result<Project, AppError> {
+        let role = ProjectMemberRepository::get_access_role(pool, project_id, user_id).await?;
+        let project = ProjectRepository::find_by_id(pool, project_id).await?;
+
+        let (Some(role), Some(project)) = (role, project) else {
+            return Err(AppError::NotFoundError("Project not found".to_string()));
+        };
+        Self::require(role, required)?;
+
+        Ok(project)
+    }
+
+    /// The deployment, when it belongs to `project_id` and `user_id` is a
+    /// member of that project with at least `required`
+    pub async fn deployment(
+        pool: &PgPool,
+        project_id: Uuid,
+        deployment_id: Uuid,
+        user_id: Uuid,
+        required: ProjectRole,
+    ) -> Result<Deployment, AppError> {
+        Self::project(pool, project_id, user_id, required).await?;
+
+        DeploymentRepository::find_by_id(pool, deployment_id)
+            .await?
+            .filter(|deployment| deployment.project_id == project_id)
+            .ok_or_else(|| AppError::NotFoundError("Deployment not found".to_string()))
+    }
+
+    /// Check `user_id` belongs to the organization with at least `required`
+    pub async fn organization(
+        pool: &PgPool,
+        organization_id: Uuid,
+        user_id: Uuid,
+        required: OrganizationRole,
+    ) -> Result<OrganizationRole, AppError> {
+        let role = OrganizationRepository::get_role(pool, organization_id, user_id)
+            .await?
+            .ok_or_else(|| AppError::NotFoundError("Organization not found".to_string()))?;
+
+        if role < required {
+            return Err(AppError::ForbiddenError(format!(
+                "This requires the {} role on the organization, you are a {}",
+                required.as_str(),
+                role.as_str()
+            )));
+        }
+
+        Ok(role)
+    }
+
+    /// The organization the user is working in, checking they still belong
+    /// to the one their token names
+    pub async fn active_organization(
+        pool: &PgPool,
+        organization_id: Option<Uuid>,
+        user_id: Uuid,
+    ) -> Result<Option<Uuid>, AppError> {
+        if let Some(organization_id) = organization_id {
+            Self::organization(pool, organization_id, user_id, OrganizationRole::Member).await?;
+        }
+
+        Ok(organization_id)
+    }
+
+    /// Whether `user_id` may see the balance of `account`: their own, or an
+    /// organization's they administer, as the billing service allows
+    pub async fn balance(
+        pool: &PgPool,
+        account: BillingAccount,
+        user_id: Uuid,
+    ) -> Result<bool, AppError> {
+        match account {
+            BillingAccount::User(owner_id) => Ok(owner_id == user_id),
+            BillingAccount::Organization(organization_id) => {
+                let role = OrganizationRepository::get_role(pool, organization_id, user_id).await?;
+                Ok(role.is_some_and(|role| role >= OrganizationRole::Admin))
+            }
+        }
+    }
+
+    pub fn require(role: ProjectRole, required: ProjectRole) -> Result<(), AppError> {
+        if role < required {
+            return Err(AppError::ForbiddenError(format!(
+                "This requires the {} role on the project, you are a {}",
+                required.as_str(),
+                role.as_str()
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_require() {
+        assert!(AccessService::require(ProjectRole::Owner, ProjectRole::Admin).is_ok());
+        assert!(AccessService::require(ProjectRole::Developer, ProjectRole::Developer).is_ok());
+        assert!(matches!(
+            AccessService::require(ProjectRole::Viewer, ProjectRole::Developer),
+            Err(AppError::ForbiddenError(_))
+        ));
+        assert!(matches!(
+            AccessService::require(ProjectRole::Admin, ProjectRole::Owner),
+            Err(AppError::ForbiddenError(_))
+        ));
+    }
+}
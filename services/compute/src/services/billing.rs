@@ -0,0 +1,143 @@
+use bigdecimal::BigDecimal;
+use serde::{Deserialize, Serialize};
+use shared::utilities::{config::Config, errors::AppError};
+use sqlx::PgPool;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::features::models::{BillingAccount, Deployment, ResourceUsage};
+use crate::features::repository::{BillingRepository, DeploymentRepository};
+use crate::services::estimate::EstimateService;
+use crate::services::quota::QuotaService;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BalanceCheckRequest<'a> {
+    user_id: Option<Uuid>,
+    organization_id: Option<Uuid>,
+    hourly_cost: &'a BigDecimal,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BalanceCheckResponse {
+    allowed: bool,
+    balance: BigDecimal,
+    required: BigDecimal,
+    hours: i64,
+    currency: String,
+}
+
+/// Pre-flight balance checks against the billing service
+#[derive(Clone)]
+pub struct BillingClient {
+    http_client: reqwest::Client,
+    /// Checks are skipped without one, e.g. in development
+    base_url: Option<String>,
+    token: Option<String>,
+}
+
+impl BillingClient {
+    pub fn new(config: &Config, http_client: reqwest::Client) -> Self {
+        if config.billing_service_url.is_none() {
+            warn!("BILLING_SERVICE_URL is not set, deployments are created without balance checks");
+        } else if config.internal_api_token.is_none() {
+            warn!("INTERNAL_API_TOKEN is not set, billing refuses every balance check");
+        }
+
+        Self {
+            http_client,
+            base_url: config
+                .billing_service_url
+                .as_ref()
+                .map(|url| url.trim_end_matches('/').to_string()),
+            token: config.internal_api_token.clone(),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn with_url(base_url: Option<String>) -> Self {
+        Self {
+            http_client: reqwest::Client::new(),
+            base_url,
+            token: None,
+        }
+    }
+
+    /// Refuse `demand` when it costs more than what `previous` costs now and
+    /// the balance can't cover the account's projected spend long enough
+    pub async fn check(
+        &self,
+        pool: &PgPool,
+        account: BillingAccount,
+        previous: Option<&Deployment>,
+        demand: ResourceUsage,
+    ) -> Result<(), AppError> {
+        self.check_replacing(pool, account, previous.as_slice(), demand)
+            .await
+    }
+
+    /// `check` for a change that replaces several deployments at once, with
+    /// `demand` what all of their replacements use together
+    pub async fn check_replacing(
+        &self,
+        pool: &PgPool,
+        account: BillingAccount,
+        replaced: &[&Deployment],
+        demand: ResourceUsage,
+    ) -> Result<(), AppError> {
+        let Some(base_url) = &self.base_url else {
+            return Ok(());
+        };
+
+        let current = replaced
+            .iter()
+            .map(|d| QuotaService::footprint_of(d))
+            .sum::<Result<ResourceUsage, AppError>>()?;
+        if demand.cpu_millicores <= current.cpu_millicores && demand.memory_mb <= current.memory_mb
+        {
+            return Ok(());
+        }
+
+        let prices = BillingRepository::get_prices(pool).await?.ok_or_else(|| {
+            AppError::InternalError("No billing prices are configured".to_string())
+        })?;
+        let excluding: Vec<Uuid> = replaced.iter().map(|d| d.id).collect();
+        let others = DeploymentRepository::usage_by_account(pool, account, &excluding).await?;
+        let hourly_cost = EstimateService::hourly_cost(&prices, &others)
+            + EstimateService::hourly_cost(&prices, &demand);
+
+        let mut request = self
+            .http_client
+            .post(format!("{}/internal/v1/balance/check", base_url))
+            .json(&BalanceCheckRequest {
+                user_id: account.user_id(),
+                organization_id: account.organization_id(),
+                hourly_cost: &hourly_cost,
+            });
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+
+        let response: BalanceCheckResponse = request
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| AppError::InternalError(format!("Balance check failed: {}", e)))?
+            .json()
+            .await?;
+
+        if !response.allowed {
+            return Err(AppError::InsufficientBalanceError(format!(
+                "Your balance of {} {} doesn't cover {} hours of projected spend ({} {}), add funds to continue",
+                response.balance.round(2),
+                response.currency,
+                response.hours,
+                response.required.round(2),
+                response.currency
+            )));
+        }
+
+        Ok(())
+    }
+}
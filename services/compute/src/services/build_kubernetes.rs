@@ -1,26 +1,175 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use k8s_openapi::api::core::v1::Toleration;
 use kube::{
     Client, Config as KubeConfig,
     config::{KubeConfigOptions, Kubeconfig},
 };
+use serde::Deserialize;
 use shared::utilities::{config::Config, errors::AppError};
 use tracing::info;
 
+use crate::features::models::Deployment;
+use crate::services::cluster::{ClusterBackend, KubeBackend};
+
+/// Region of the only cluster when `K8S_CLUSTERS` is not set
+pub const DEFAULT_REGION: &str = "default";
+
+/// `K8S_CLUSTERS` points at a YAML file listing every cluster:
+///
+/// ```yaml
+/// default_region: eu-central
+/// clusters:
+///   - region: eu-central
+///     kubeconfig: /etc/compute/eu-central.yaml  # in-cluster when omitted
+///     context: prod
+///     node_pools:
+///       high-memory:
+///         node_selector: { pool: high-memory }
+///         tolerations:
+///           - { key: dedicated, value: high-memory, effect: NoSchedule }
+///   - region: us-east
+///     kubeconfig: /etc/compute/us-east.yaml
+///     accepting_deployments: false
+/// ```
+#[derive(Deserialize)]
+struct ClustersFile {
+    default_region: Option<String>,
+    clusters: Vec<ClusterEntry>,
+}
+
+#[derive(Deserialize)]
+struct ClusterEntry {
+    region: String,
+    kubeconfig: Option<String>,
+    context: Option<String>,
+    #[serde(default = "accepting_by_default")]
+    accepting_deployments: bool,
+    #[serde(default)]
+    node_pools: BTreeMap<String, NodePool>,
+}
+
+fn accepting_by_default() -> bool {
+    true
+}
+
+/// Nodes a deployment can ask for by name, e.g. high-memory machines
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct NodePool {
+    #[serde(default)]
+    pub node_selector: BTreeMap<String, String>,
+    #[serde(default)]
+    pub tolerations: Vec<Toleration>,
+}
+
 #[derive(Clone)]
-pub struct Kubernetes {
+pub struct Cluster {
+    pub region: String,
     pub client: Client,
+    /// What deployments are created, changed and deleted through
+    pub backend: Arc<dyn ClusterBackend>,
+    /// Existing deployments keep running when this is off, new ones go elsewhere
+    pub accepting_deployments: bool,
+    pub node_pools: BTreeMap<String, NodePool>,
+}
+
+/// Where a new deployment runs, as stored on its row
+pub struct Placement {
+    pub region: String,
+    pub node_pool: Option<String>,
+    pub node_selector: Option<serde_json::Value>,
+    pub tolerations: Option<serde_json::Value>,
+}
+
+/// Every cluster deployments can run on, by region
+#[derive(Clone)]
+pub struct Kubernetes {
+    clusters: Arc<BTreeMap<String, Cluster>>,
+    default_region: String,
 }
 
 impl Kubernetes {
     pub async fn new(config: &Config) -> Result<Self, AppError> {
+        let Some(path) = &config.k8s_clusters_path else {
+            let client = Self::connect(
+                config.k8s_in_cluster,
+                config.k8s_config_path.as_deref(),
+                None,
+            )
+            .await?;
+            return Ok(Self::single(DEFAULT_REGION, client, None));
+        };
+
+        let contents = tokio::fs::read_to_string(path)
+            .await
+            .map_err(|e| AppError::FileReadError(format!("{}: {}", path, e)))?;
+        let file: ClustersFile = serde_yaml::from_str(&contents)
+            .map_err(|e| AppError::InternalError(format!("Invalid K8S_CLUSTERS file: {}", e)))?;
+
+        let default_region = match file.default_region {
+            Some(region) => region,
+            None => file
+                .clusters
+                .first()
+                .map(|c| c.region.clone())
+                .ok_or_else(|| {
+                    AppError::InternalError("K8S_CLUSTERS lists no clusters".to_string())
+                })?,
+        };
+
+        let mut clusters = BTreeMap::new();
+        for entry in file.clusters {
+            let client = Self::connect(
+                entry.kubeconfig.is_none(),
+                entry.kubeconfig.as_deref(),
+                entry.context,
+            )
+            .await?;
+            info!("✅ Connected to cluster of region {}", entry.region);
+
+            clusters.insert(
+                entry.region.clone(),
+                Cluster {
+                    region: entry.region,
+                    backend: Arc::new(KubeBackend::new(client.clone())),
+                    client,
+                    accepting_deployments: entry.accepting_deployments,
+                    node_pools: entry.node_pools,
+                },
+            );
+        }
+
+        if !clusters.contains_key(&default_region) {
+            return Err(AppError::InternalError(format!(
+                "K8S_CLUSTERS has no cluster for the default region {}",
+                default_region
+            )));
+        }
+
+        Ok(Self {
+            clusters: Arc::new(clusters),
+            default_region,
+        })
+    }
+
+    async fn connect(
+        in_cluster: bool,
+        path: Option<&str>,
+        context: Option<String>,
+    ) -> Result<Client, AppError> {
         // let client = kube::Client::try_default().await?;
-        let client = if config.k8s_in_cluster {
+        let client = if in_cluster {
             let kube_config = KubeConfig::incluster()?;
             info!("Connected from incluster environment!");
             Client::try_from(kube_config)?
         } else {
-            let kube_config = if let Some(path) = &config.k8s_config_path {
+            let kube_config = if let Some(path) = path {
                 let kubeconfig = Kubeconfig::read_from(path)?;
-                let options = KubeConfigOptions::default();
+                let options = KubeConfigOptions {
+                    context,
+                    ..Default::default()
+                };
                 KubeConfig::from_custom_kubeconfig(kubeconfig, &options).await?
             } else {
                 KubeConfig::infer().await?
@@ -30,6 +179,142 @@ impl Kubernetes {
             Client::try_from(kube_config)?
         };
 
-        Ok(Self { client })
+        Ok(client)
+    }
+
+    fn single(region: &str, client: Client, backend: Option<Arc<dyn ClusterBackend>>) -> Self {
+        let cluster = Cluster {
+            region: region.to_string(),
+            backend: backend.unwrap_or_else(|| Arc::new(KubeBackend::new(client.clone()))),
+            client,
+            accepting_deployments: true,
+            node_pools: BTreeMap::new(),
+        };
+
+        Self {
+            clusters: Arc::new(BTreeMap::from([(region.to_string(), cluster)])),
+            default_region: region.to_string(),
+        }
+    }
+
+    /// One region whose writes go to `backend`, the client points nowhere
+    #[cfg(test)]
+    pub fn with_cluster(backend: Arc<dyn ClusterBackend>) -> Self {
+        // Tests don't run `main`, which installs the provider otherwise
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        let config = KubeConfig::new("http://127.0.0.1:9".parse().unwrap());
+        Self::single(
+            DEFAULT_REGION,
+            Client::try_from(config).unwrap(),
+            Some(backend),
+        )
+    }
+
+    pub fn clusters(&self) -> impl Iterator<Item = &Cluster> {
+        self.clusters.values()
+    }
+
+    pub fn default_region(&self) -> &str {
+        &self.default_region
+    }
+
+    /// Cluster of an existing deployment
+    pub fn cluster(&self, deployment: &Deployment) -> Result<&Cluster, AppError> {
+        let region = deployment.region.as_deref().unwrap_or(&self.default_region);
+
+        self.clusters.get(region).ok_or_else(|| {
+            AppError::InternalError(format!(
+                "Deployment {} runs in region {}, which is not configured",
+                deployment.id, region
+            ))
+        })
+    }
+
+    pub fn backend(&self, deployment: &Deployment) -> Result<&dyn ClusterBackend, AppError> {
+        Ok(self.cluster(deployment)?.backend.as_ref())
+    }
+
+    /// Check a requested region and node pool, `None` picks the default region
+    /// and any node
+    pub fn placement(
+        &self,
+        region: Option<&str>,
+        node_pool: Option<&str>,
+    ) -> Result<Placement, AppError> {
+        let region = region.unwrap_or(&self.default_region);
+        let cluster = self
+            .clusters
+            .get(region)
+            .ok_or_else(|| AppError::ValidationError(format!("Unknown region '{}'", region)))?;
+
+        if !cluster.accepting_deployments {
+            return Err(AppError::ConflictError(format!(
+                "Region '{}' is not accepting new deployments",
+                region
+            )));
+        }
+
+        let pool = node_pool
+            .map(|name| {
+                cluster.node_pools.get(name).ok_or_else(|| {
+                    AppError::ValidationError(format!(
+                        "Region '{}' has no node pool '{}'",
+                        region, name
+                    ))
+                })
+            })
+            .transpose()?;
+
+        Ok(Placement {
+            region: region.to_string(),
+            node_pool: node_pool.map(str::to_string),
+            node_selector: pool
+                .filter(|p| !p.node_selector.is_empty())
+                .map(|p| serde_json::to_value(&p.node_selector))
+                .transpose()?,
+            tolerations: pool
+                .filter(|p| !p.tolerations.is_empty())
+                .map(|p| serde_json::to_value(&p.tolerations))
+                .transpose()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::fake_cluster::FakeCluster;
+
+    #[tokio::test]
+    async fn test_placement() {
+        let mut kubernetes = Kubernetes::with_cluster(Arc::new(FakeCluster::new()));
+        let cluster = Arc::get_mut(&mut kubernetes.clusters)
+            .unwrap()
+            .get_mut(DEFAULT_REGION)
+            .unwrap();
+        cluster.node_pools.insert(
+            "high-memory".to_string(),
+            NodePool {
+                node_selector: BTreeMap::from([("pool".to_string(), "high-memory".to_string())]),
+                tolerations: vec![],
+            },
+        );
+
+        let placement = kubernetes.placement(None, Some("high-memory")).unwrap();
+        assert_eq!(placement.region, DEFAULT_REGION);
+        assert_eq!(
+            placement.node_selector,
+            Some(serde_json::json!({ "pool": "high-memory" }))
+        );
+        assert_eq!(placement.tolerations, None);
+
+        assert!(matches!(
+            kubernetes.placement(Some("mars"), None),
+            Err(AppError::ValidationError(_))
+        ));
+        assert!(matches!(
+            kubernetes.placement(None, Some("gpu")),
+            Err(AppError::ValidationError(_))
+        ));
     }
 }
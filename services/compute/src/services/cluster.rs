@@ -0,0 +1,344 @@
+use std::fmt;
+
+use async_trait::async_trait;
+use k8s_openapi::NamespaceResourceScope;
+use k8s_openapi::api::apps::v1::{Deployment as K8sDeployment, ReplicaSet};
+use k8s_openapi::api::core::v1::{Event as K8sEvent, Pod, Secret as K8sSecret, Service};
+use k8s_openapi::api::networking::v1::Ingress;
+use kube::api::{DeleteParams, ListParams, ObjectMeta, Patch, PatchParams, PostParams};
+use kube::{Api, Client, Resource};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use shared::utilities::errors::AppError;
+
+/// Kinds of objects a deployment is made of
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ObjectKind {
+    Deployment,
+    Service,
+    Ingress,
+    Secret,
+}
+
+impl fmt::Display for ObjectKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ObjectKind::Deployment => "k8s deployment",
+            ObjectKind::Service => "service",
+            ObjectKind::Ingress => "ingress",
+            ObjectKind::Secret => "secret",
+        })
+    }
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone)]
+pub enum ClusterObject {
+    Deployment(K8sDeployment),
+    Service(Service),
+    Ingress(Ingress),
+    Secret(K8sSecret),
+}
+
+impl ClusterObject {
+    pub fn kind(&self) -> ObjectKind {
+        match self {
+            ClusterObject::Deployment(_) => ObjectKind::Deployment,
+            ClusterObject::Service(_) => ObjectKind::Service,
+            ClusterObject::Ingress(_) => ObjectKind::Ingress,
+            ClusterObject::Secret(_) => ObjectKind::Secret,
+        }
+    }
+
+    pub fn metadata(&self) -> &ObjectMeta {
+        match self {
+            ClusterObject::Deployment(object) => &object.metadata,
+            ClusterObject::Service(object) => &object.metadata,
+            ClusterObject::Ingress(object) => &object.metadata,
+            ClusterObject::Secret(object) => &object.metadata,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        self.metadata().name.as_deref().unwrap_or_default()
+    }
+
+    pub fn namespace(&self) -> &str {
+        self.metadata().namespace.as_deref().unwrap_or("default")
+    }
+
+    pub fn into_deployment(self) -> Option<K8sDeployment> {
+        match self {
+            ClusterObject::Deployment(object) => Some(object),
+            _ => None,
+        }
+    }
+
+    pub fn into_ingress(self) -> Option<Ingress> {
+        match self {
+            ClusterObject::Ingress(object) => Some(object),
+            _ => None,
+        }
+    }
+}
+
+/// JSON patch bodies, `Merge` replaces lists whole while `Strategic` merges
+/// them by key
+#[derive(Debug, Clone)]
+pub enum ObjectPatch {
+    Merge(serde_json::Value),
+    Strategic(serde_json::Value),
+}
+
+/// Writes and reads of the objects `DeploymentService` manages, plus the
+/// pods, ReplicaSets and Events behind a deployment's runtime status and
+/// timeline.
+///
+/// Metrics and watches still go through the `kube::Client` directly.
+#[async_trait]
+pub trait ClusterBackend: Send + Sync {
+    async fn create(&self, object: &ClusterObject) -> Result<(), AppError>;
+
+    /// Overwrite an existing object, its `resourceVersion` must be current
+    async fn replace(&self, object: &ClusterObject) -> Result<(), AppError>;
+
+    async fn get(
+        &self,
+        kind: ObjectKind,
+        namespace: &str,
+        name: &str,
+    ) -> Result<Option<ClusterObject>, AppError>;
+
+    async fn patch(
+        &self,
+        kind: ObjectKind,
+        namespace: &str,
+        name: &str,
+        patch: &ObjectPatch,
+    ) -> Result<(), AppError>;
+
+    /// `false` when there was nothing to delete
+    async fn delete(&self, kind: ObjectKind, namespace: &str, name: &str)
+    -> Result<bool, AppError>;
+
+    /// Pods in `namespace` matching the `key=value` label selector
+    async fn pods(&self, namespace: &str, labels: &str) -> Result<Vec<Pod>, AppError>;
+
+    /// Names of the ReplicaSets and pods in `namespace` matching the
+    /// `key=value` label selector
+    async fn owned_objects(&self, namespace: &str, labels: &str) -> Result<Vec<String>, AppError>;
+
+    /// Up to `limit` Events about the object named `object_name`
+    async fn events(
+        &self,
+        namespace: &str,
+        object_name: &str,
+        limit: u32,
+    ) -> Result<Vec<K8sEvent>, AppError>;
+}
+
+/// Error of a failed cluster call, worded the same for every backend
+pub fn cluster_error(verb: &str, kind: ObjectKind, error: impl fmt::Display) -> AppError {
+    AppError::InternalError(format!("Failed to {} {}: {}", verb, kind, error))
+}
+
+// ==============================================
+// KUBERNETES API
+// ==============================================
+
+/// Talks to the cluster through `kube::Api`
+pub struct KubeBackend {
+    client: Client,
+}
+
+impl KubeBackend {
+    pub fn new(client: Client) -> Self {
+        Self { client }
+    }
+
+    fn api<K>(&self, namespace: &str) -> Api<K>
+    where
+        K: Resource<Scope = NamespaceResourceScope>,
+        K::DynamicType: Default,
+    {
+        Api::namespaced(self.client.clone(), namespace)
+    }
+}
+
+async fn create_object<K>(api: Api<K>, object: &K) -> Result<(), kube::Error>
+where
+    K: Clone + DeserializeOwned + Serialize + fmt::Debug,
+{
+    api.create(&PostParams::default(), object).await.map(|_| ())
+}
+
+async fn replace_object<K>(api: Api<K>, name: &str, object: &K) -> Result<(), kube::Error>
+where
+    K: Clone + DeserializeOwned + Serialize + fmt::Debug,
+{
+    api.replace(name, &PostParams::default(), object)
+        .await
+        .map(|_| ())
+}
+
+async fn patch_object<K>(api: Api<K>, name: &str, patch: &ObjectPatch) -> Result<(), kube::Error>
+where
+    K: Clone + DeserializeOwned + fmt::Debug,
+{
+    let patch = match patch {
+        ObjectPatch::Merge(body) => Patch::Merge(body),
+        ObjectPatch::Strategic(body) => Patch::Strategic(body),
+    };
+    api.patch(name, &PatchParams::default(), &patch)
+        .await
+        .map(|_| ())
+}
+
+async fn delete_object<K>(api: Api<K>, name: &str) -> Result<bool, kube::Error>
+where
+    K: Clone + DeserializeOwned + fmt::Debug,
+{
+    match api.delete(name, &DeleteParams::default()).await {
+        Ok(_) => Ok(true),
+        Err(kube::Error::Api(response)) if response.code == 404 => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+#[async_trait]
+impl ClusterBackend for KubeBackend {
+    async fn create(&self, object: &ClusterObject) -> Result<(), AppError> {
+        let namespace = object.namespace();
+        match object {
+            ClusterObject::Deployment(o) => create_object(self.api(namespace), o).await,
+            ClusterObject::Service(o) => create_object(self.api(namespace), o).await,
+            ClusterObject::Ingress(o) => create_object(self.api(namespace), o).await,
+            ClusterObject::Secret(o) => create_object(self.api(namespace), o).await,
+        }
+        .map_err(|e| cluster_error("create", object.kind(), e))
+    }
+
+    async fn replace(&self, object: &ClusterObject) -> Result<(), AppError> {
+        let (namespace, name) = (object.namespace(), object.name());
+        match object {
+            ClusterObject::Deployment(o) => replace_object(self.api(namespace), name, o).await,
+            ClusterObject::Service(o) => replace_object(self.api(namespace), name, o).await,
+            ClusterObject::Ingress(o) => replace_object(self.api(namespace), name, o).await,
+            ClusterObject::Secret(o) => replace_object(self.api(namespace), name, o).await,
+        }
+        .map_err(|e| cluster_error("update", object.kind(), e))
+    }
+
+    async fn get(
+        &self,
+        kind: ObjectKind,
+        namespace: &str,
+        name: &str,
+    ) -> Result<Option<ClusterObject>, AppError> {
+        let object = match kind {
+            ObjectKind::Deployment => self
+                .api(namespace)
+                .get_opt(name)
+                .await
+                .map(|o| o.map(ClusterObject::Deployment)),
+            ObjectKind::Service => self
+                .api(namespace)
+                .get_opt(name)
+                .await
+                .map(|o| o.map(ClusterObject::Service)),
+            ObjectKind::Ingress => self
+                .api(namespace)
+                .get_opt(name)
+                .await
+                .map(|o| o.map(ClusterObject::Ingress)),
+            ObjectKind::Secret => self
+                .api(namespace)
+                .get_opt(name)
+                .await
+                .map(|o| o.map(ClusterObject::Secret)),
+        };
+        object.map_err(|e| cluster_error("get", kind, e))
+    }
+
+    async fn patch(
+        &self,
+        kind: ObjectKind,
+        namespace: &str,
+        name: &str,
+        patch: &ObjectPatch,
+    ) -> Result<(), AppError> {
+        match kind {
+            ObjectKind::Deployment => {
+                patch_object::<K8sDeployment>(self.api(namespace), name, patch).await
+            }
+            ObjectKind::Service => patch_object::<Service>(self.api(namespace), name, patch).await,
+            ObjectKind::Ingress => patch_object::<Ingress>(self.api(namespace), name, patch).await,
+            ObjectKind::Secret => patch_object::<K8sSecret>(self.api(namespace), name, patch).await,
+        }
+        .map_err(|e| cluster_error("update", kind, e))
+    }
+
+    async fn delete(
+        &self,
+        kind: ObjectKind,
+        namespace: &str,
+        name: &str,
+    ) -> Result<bool, AppError> {
+        match kind {
+            ObjectKind::Deployment => {
+                delete_object::<K8sDeployment>(self.api(namespace), name).await
+            }
+            ObjectKind::Service => delete_object::<Service>(self.api(namespace), name).await,
+            ObjectKind::Ingress => delete_object::<Ingress>(self.api(namespace), name).await,
+            ObjectKind::Secret => delete_object::<K8sSecret>(self.api(namespace), name).await,
+        }
+        .map_err(|e| cluster_error("delete", kind, e))
+    }
+
+    async fn pods(&self, namespace: &str, labels: &str) -> Result<Vec<Pod>, AppError> {
+        self.api::<Pod>(namespace)
+            .list(&ListParams::default().labels(labels))
+            .await
+            .map(|list| list.items)
+            .map_err(|e| AppError::InternalError(format!("Failed to list pods: {}", e)))
+    }
+
+    async fn owned_objects(&self, namespace: &str, labels: &str) -> Result<Vec<String>, AppError> {
+        let params = ListParams::default().labels(labels);
+        let list_error = |e| AppError::InternalError(format!("Failed to list objects: {}", e));
+        let replica_sets = self
+            .api::<ReplicaSet>(namespace)
+            .list_metadata(&params)
+            .await
+            .map_err(list_error)?;
+        let pods = self
+            .api::<Pod>(namespace)
+            .list_metadata(&params)
+            .await
+            .map_err(list_error)?;
+
+        let mut names: Vec<String> = replica_sets
+            .items
+            .into_iter()
+            .filter_map(|r| r.metadata.name)
+            .collect();
+        names.extend(pods.items.into_iter().filter_map(|p| p.metadata.name));
+        Ok(names)
+    }
+
+    async fn events(
+        &self,
+        namespace: &str,
+        object_name: &str,
+        limit: u32,
+    ) -> Result<Vec<K8sEvent>, AppError> {
+        let params = ListParams::default()
+            .fields(&format!("involvedObject.name={}", object_name))
+            .limit(limit);
+        self.api::<K8sEvent>(namespace)
+            .list(&params)
+            .await
+            .map(|list| list.items)
+            .map_err(|e| AppError::InternalError(format!("Failed to list events: {}", e)))
+    }
+}
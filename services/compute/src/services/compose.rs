@@ -0,0 +1,663 @@
+use std::collections::{HashMap, HashSet};
+
+use serde_yaml::{Mapping, Value};
+use shared::utilities::errors::AppError;
+use sqlx::PgPool;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::features::models::ResourceSpec;
+use crate::features::schemas::{
+    ComposeImportRequest, ComposeImportResponse, CreateDeploymentRequest, PlannedDeploymentResponse,
+};
+use crate::services::billing::BillingClient;
+use crate::services::build_kubernetes::Kubernetes;
+use crate::services::event_bus::EventBus;
+use crate::services::kubernetes::DeploymentService;
+use crate::utilities::encryption::EncryptionService;
+
+/// Keys that have no deployment equivalent and are ignored without a warning
+const IGNORED_TOP_LEVEL_KEYS: [&str; 2] = ["version", "name"];
+
+/// One compose service mapped onto a deployment
+#[derive(Debug)]
+pub struct PlannedDeployment {
+    pub service: String,
+    pub depends_on: Vec<String>,
+    pub request: CreateDeploymentRequest,
+}
+
+/// Deployments in `depends_on` order plus everything that didn't map cleanly
+#[derive(Debug)]
+pub struct ComposePlan {
+    pub deployments: Vec<PlannedDeployment>,
+    pub warnings: Vec<String>,
+}
+
+/// Maps `docker-compose.yml` services onto deployments
+pub struct ComposeImportService;
+
+impl ComposeImportService {
+    /// Plan the import and, unless previewing, create the deployments one by
+    /// one in dependency order. A failure stops the import, deployments
+    /// created before it are kept.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn import(
+        pool: &PgPool,
+        kubernetes: &Kubernetes,
+        billing: &BillingClient,
+        events: &EventBus,
+        encryption_service: &EncryptionService,
+        user_id: Uuid,
+        project_id: Uuid,
+        base_domain: &str,
+        req: ComposeImportRequest,
+        preview: bool,
+    ) -> Result<ComposeImportResponse, AppError> {
+        let plan = Self::plan(&req.compose, &req.env_files)?;
+
+        // Reject the whole file before anything is created
+        for deployment in &plan.deployments {
+            deployment.request.validate().map_err(|e| {
+                AppError::ValidationError(format!("Service {}: {}", deployment.service, e))
+            })?;
+        }
+
+        let planned = plan
+            .deployments
+            .iter()
+            .map(Self::planned_response)
+            .collect();
+
+        let mut created = vec![];
+        if !preview {
+            for deployment in plan.deployments {
+                created.push(
+                    DeploymentService::create(
+                        pool,
+                        kubernetes,
+                        billing,
+                        events,
+                        encryption_service,
+                        user_id,
+                        project_id,
+                        base_domain,
+                        deployment.request,
+                    )
+                    .await?,
+                );
+            }
+        }
+
+        Ok(ComposeImportResponse {
+            preview,
+            plan: planned,
+            warnings: plan.warnings,
+            created,
+        })
+    }
+
+    /// Parse a compose file into deployment requests, without touching anything
+    pub fn plan(
+        compose: &str,
+        env_files: &HashMap<String, String>,
+    ) -> Result<ComposePlan, AppError> {
+        let document: Value = serde_yaml::from_str(compose)
+            .map_err(|e| AppError::ValidationError(format!("Invalid compose file: {}", e)))?;
+        let root = document.as_mapping().ok_or_else(|| {
+            AppError::ValidationError("Compose file must be a mapping".to_string())
+        })?;
+
+        let mut warnings = vec![];
+        for key in root.keys().filter_map(Value::as_str) {
+            if key != "services" && !IGNORED_TOP_LEVEL_KEYS.contains(&key) {
+                warnings.push(format!(
+                    "Top-level `{}` is not supported and was ignored",
+                    key
+                ));
+            }
+        }
+
+        let services = root
+            .get("services")
+            .and_then(Value::as_mapping)
+            .filter(|services| !services.is_empty())
+            .ok_or_else(|| {
+                AppError::ValidationError("Compose file defines no services".to_string())
+            })?;
+
+        let mut deployments = vec![];
+        for (name, service) in services {
+            let name = name.as_str().ok_or_else(|| {
+                AppError::ValidationError("Service names must be strings".to_string())
+            })?;
+            let service = service.as_mapping().ok_or_else(|| {
+                AppError::ValidationError(format!("Service {} must be a mapping", name))
+            })?;
+            deployments.push(Self::plan_service(name, service, env_files, &mut warnings)?);
+        }
+
+        Ok(ComposePlan {
+            deployments: Self::order_by_dependencies(deployments)?,
+            warnings,
+        })
+    }
+
+    fn plan_service(
+        name: &str,
+        service: &Mapping,
+        env_files: &HashMap<String, String>,
+        warnings: &mut Vec<String>,
+    ) -> Result<PlannedDeployment, AppError> {
+        let mut replicas = 1;
+        let mut resources = None;
+        let mut env_vars = HashMap::new();
+        let mut secrets = HashMap::new();
+        let mut depends_on = vec![];
+
+        for (key, value) in service {
+            match key.as_str().unwrap_or_default() {
+                "image" | "ports" | "expose" => {} // Read below, they decide the port together
+                "environment" => env_vars = Self::environment(name, value, warnings)?,
+                "env_file" => secrets = Self::env_file_secrets(name, value, env_files, warnings),
+                "depends_on" => depends_on = Self::depends_on(name, value)?,
+                "build" => warnings.push(format!(
+                    "Service {}: `build` is not supported, the `image` is deployed as is",
+                    name
+                )),
+                "deploy" => {
+                    for (key, value) in value.as_mapping().into_iter().flatten() {
+                        match key.as_str().unwrap_or_default() {
+                            "replicas" => {
+                                replicas = value.as_i64().ok_or_else(|| {
+                                    AppError::ValidationError(format!(
+                                        "Service {}: deploy.replicas must be a number",
+                                        name
+                                    ))
+                                })? as i32
+                            }
+                            "resources" => resources = Some(Self::resources(name, value)?),
+                            other => warnings.push(format!(
+                                "Service {}: `deploy.{}` is not supported and was ignored",
+                                name, other
+                            )),
+                        }
+                    }
+                }
+                other => warnings.push(format!(
+                    "Service {}: `{}` is not supported and was ignored",
+                    name, other
+                )),
+            }
+        }
+
+        let image = service
+            .get("image")
+            .and_then(Value::as_str)
+            .ok_or_else(|| {
+                AppError::ValidationError(format!(
+                    "Service {} has no `image`, only prebuilt images can be deployed",
+                    name
+                ))
+            })?
+            .to_string();
+
+        // `environment` wins over `env_file`, like in compose
+        secrets.retain(|key, _| !env_vars.contains_key(key));
+
+        Ok(PlannedDeployment {
+            service: name.to_string(),
+            depends_on,
+            request: CreateDeploymentRequest {
+                name: name.to_string(),
+                image,
+                replicas,
+                port: Self::port(name, service, warnings)?,
+                env_vars: (!env_vars.is_empty()).then_some(env_vars),
+                secrets: (!secrets.is_empty()).then_some(secrets),
+                resources,
+                size: None,
+                labels: None,
+                subdomain: None,
+                region: None,
+                node_pool: None,
+            },
+        })
+    }
+
+    /// Container side of the first published port, falling back to `expose`
+    fn port(name: &str, service: &Mapping, warnings: &mut Vec<String>) -> Result<i32, AppError> {
+        let ports = ["ports", "expose"]
+            .iter()
+            .find_map(|key| service.get(*key).and_then(Value::as_sequence))
+            .filter(|ports| !ports.is_empty())
+            .ok_or_else(|| {
+                AppError::ValidationError(format!(
+                    "Service {} publishes no port, deployments need one",
+                    name
+                ))
+            })?;
+
+        if ports.len() > 1 {
+            warnings.push(format!(
+                "Service {}: only the first port is routed, the others were ignored",
+                name
+            ));
+        }
+
+        let port = match &ports[0] {
+            Value::Number(port) => port.as_i64(),
+            Value::String(port) => Self::parse_port(port),
+            Value::Mapping(port) => port.get("target").and_then(Value::as_i64),
+            _ => None,
+        };
+
+        port.map(|port| port as i32).ok_or_else(|| {
+            AppError::ValidationError(format!("Service {}: unsupported port syntax", name))
+        })
+    }
+
+    /// Container port of a short port syntax, e.g. `127.0.0.1:8080:80/tcp` is 80
+    fn parse_port(port: &str) -> Option<i64> {
+        let port = port.split('/').next()?;
+        let container = port.rsplit(':').next()?;
+        // A range maps to its first port
+        container.split('-').next()?.trim().parse().ok()
+    }
+
+    fn environment(
+        name: &str,
+        value: &Value,
+        warnings: &mut Vec<String>,
+    ) -> Result<HashMap<String, String>, AppError> {
+        let mut pairs: Vec<(String, Option<String>)> = vec![];
+
+        match value {
+            Value::Sequence(entries) => {
+                for entry in entries.iter().filter_map(Value::as_str) {
+                    match entry.split_once('=') {
+                        Some((key, value)) => {
+                            pairs.push((key.to_string(), Some(value.to_string())))
+                        }
+                        None => pairs.push((entry.to_string(), None)),
+                    }
+                }
+            }
+            Value::Mapping(entries) => {
+                for (key, value) in entries {
+                    let key = key.as_str().unwrap_or_default().to_string();
+                    pairs.push((key, Self::scalar(value)));
+                }
+            }
+            _ => {
+                return Err(AppError::ValidationError(format!(
+                    "Service {}: `environment` must be a list or a mapping",
+                    name
+                )));
+            }
+        }
+
+        let mut env_vars = HashMap::new();
+        for (key, value) in pairs {
+            match value {
+                Some(value) => {
+                    if value.contains("${") {
+                        warnings.push(format!(
+                            "Service {}: {} uses interpolation, it was imported literally",
+                            name, key
+                        ));
+                    }
+                    env_vars.insert(key, value);
+                }
+                None => warnings.push(format!(
+                    "Service {}: {} has no value, add it as a secret after the import",
+                    name, key
+                )),
+            }
+        }
+
+        Ok(env_vars)
+    }
+
+    /// Variables of the provided `env_file`s, they are imported as secrets
+    fn env_file_secrets(
+        name: &str,
+        value: &Value,
+        env_files: &HashMap<String, String>,
+        warnings: &mut Vec<String>,
+    ) -> HashMap<String, String> {
+        let paths: Vec<&str> = match value {
+            Value::String(path) => vec![path.as_str()],
+            Value::Sequence(entries) => entries
+                .iter()
+                .filter_map(|entry| match entry {
+                    Value::String(path) => Some(path.as_str()),
+                    Value::Mapping(entry) => entry.get("path").and_then(Value::as_str),
+                    _ => None,
+                })
+                .collect(),
+            _ => vec![],
+        };
+
+        let mut secrets = HashMap::new();
+        for path in paths {
+            match env_files.get(path) {
+                // Later files override earlier ones
+                Some(contents) => secrets.extend(parse_env_file(contents)),
+                None => warnings.push(format!(
+                    "Service {}: env_file {} was not provided, its variables are missing",
+                    name, path
+                )),
+            }
+        }
+        secrets
+    }
+
+    fn depends_on(name: &str, value: &Value) -> Result<Vec<String>, AppError> {
+        let dependencies: Vec<&Value> = match value {
+            Value::Sequence(entries) => entries.iter().collect(),
+            Value::Mapping(entries) => entries.keys().collect(),
+            _ => vec![],
+        };
+
+        dependencies
+            .into_iter()
+            .map(|dependency| {
+                dependency.as_str().map(str::to_string).ok_or_else(|| {
+                    AppError::ValidationError(format!(
+                        "Service {}: `depends_on` entries must be service names",
+                        name
+                    ))
+                })
+            })
+            .collect()
+    }
+
+    /// `deploy.resources` limits and reservations. A side that isn't set keeps
+    /// our default, clamped so requests never exceed limits.
+    fn resources(name: &str, value: &Value) -> Result<ResourceSpec, AppError> {
+        let read = |side: &str| -> Result<(Option<i32>, Option<i32>), AppError> {
+            let Some(side) = value.get(side) else {
+                return Ok((None, None));
+            };
+
+            let cpu = side
+                .get("cpus")
+                .map(|cpus| {
+                    Self::scalar(cpus)
+                        .and_then(|cpus| cpus.parse::<f64>().ok())
+                        .map(|cpus| (cpus * 1000.0).round() as i32)
+                        .ok_or_else(|| {
+                            AppError::ValidationError(format!("Service {}: invalid cpus", name))
+                        })
+                })
+                .transpose()?;
+            let memory = side
+                .get("memory")
+                .map(|memory| {
+                    Self::scalar(memory)
+                        .and_then(|memory| parse_memory_mb(&memory))
+                        .ok_or_else(|| {
+                            AppError::ValidationError(format!("Service {}: invalid memory", name))
+                        })
+                })
+                .transpose()?;
+
+            Ok((cpu, memory))
+        };
+
+        let (cpu_limit, memory_limit) = read("limits")?;
+        let (cpu_request, memory_request) = read("reservations")?;
+        let default = ResourceSpec::default();
+
+        let (cpu_request_millicores, cpu_limit_millicores) = Self::request_and_limit(
+            cpu_request,
+            cpu_limit,
+            default.cpu_request_millicores,
+            default.cpu_limit_millicores,
+        );
+        let (memory_request_mb, memory_limit_mb) = Self::request_and_limit(
+            memory_request,
+            memory_limit,
+            default.memory_request_mb,
+            default.memory_limit_mb,
+        );
+
+        Ok(ResourceSpec {
+            cpu_request_millicores,
+            cpu_limit_millicores,
+            memory_request_mb,
+            memory_limit_mb,
+        })
+    }
+
+    fn request_and_limit(
+        request: Option<i32>,
+        limit: Option<i32>,
+        default_request: i32,
+        default_limit: i32,
+    ) -> (i32, i32) {
+        match (request, limit) {
+            (Some(request), Some(limit)) => (request, limit),
+            (Some(request), None) => (request, default_limit.max(request)),
+            (None, Some(limit)) => (default_request.min(limit), limit),
+            (None, None) => (default_request, default_limit),
+        }
+    }
+
+    fn scalar(value: &Value) -> Option<String> {
+        match value {
+            Value::String(value) => Some(value.clone()),
+            Value::Number(value) => Some(value.to_string()),
+            Value::Bool(value) => Some(value.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Order services so every one comes after what it depends on, keeping
+    /// the file order otherwise
+    fn order_by_dependencies(
+        mut pending: Vec<PlannedDeployment>,
+    ) -> Result<Vec<PlannedDeployment>, AppError> {
+        let names: HashSet<String> = pending.iter().map(|d| d.service.clone()).collect();
+        for deployment in &pending {
+            if let Some(unknown) = deployment.depends_on.iter().find(|d| !names.contains(*d)) {
+                return Err(AppError::ValidationError(format!(
+                    "Service {} depends on unknown service {}",
+                    deployment.service, unknown
+                )));
+            }
+        }
+
+        let mut placed = HashSet::new();
+        let mut ordered = vec![];
+        while !pending.is_empty() {
+            let ready = pending
+                .iter()
+                .position(|d| d.depends_on.iter().all(|d| placed.contains(d)))
+                .ok_or_else(|| {
+                    let services: Vec<&str> = pending.iter().map(|d| d.service.as_str()).collect();
+                    AppError::ValidationError(format!(
+                        "Circular depends_on between {}",
+                        services.join(", ")
+                    ))
+                })?;
+
+            let deployment = pending.remove(ready);
+            placed.insert(deployment.service.clone());
+            ordered.push(deployment);
+        }
+
+        Ok(ordered)
+    }
+
+    fn planned_response(deployment: &PlannedDeployment) -> PlannedDeploymentResponse {
+        let request = &deployment.request;
+        let mut secret_keys: Vec<String> = request
+            .secrets
+            .as_ref()
+            .map(|secrets| secrets.keys().cloned().collect())
+            .unwrap_or_default();
+        secret_keys.sort();
+
+        PlannedDeploymentResponse {
+            service: deployment.service.clone(),
+            name: request.name.clone(),
+            image: request.image.clone(),
+            replicas: request.replicas,
+            port: request.port,
+            env_vars: request.env_vars.clone().unwrap_or_default(),
+            secret_keys,
+            resources: request.resources.clone().unwrap_or_default(),
+            depends_on: deployment.depends_on.clone(),
+        }
+    }
+}
+
+/// Parse dotenv contents (`KEY=value`, `export KEY=value`, `# comments`)
+fn parse_env_file(contents: &str) -> HashMap<String, String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let (key, value) = line.split_once('=')?;
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+                .unwrap_or(value);
+            Some((key.trim().to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+/// Parse a compose byte value ("512m", "1g", "1.5gb", "268435456") into MiB
+fn parse_memory_mb(value: &str) -> Option<i32> {
+    let value = value.trim().to_lowercase();
+    let value = value.strip_suffix('b').unwrap_or(&value);
+
+    let (number, factor) = match value.chars().last()? {
+        'k' => (&value[..value.len() - 1], 1.0 / 1024.0),
+        'm' => (&value[..value.len() - 1], 1.0),
+        'g' => (&value[..value.len() - 1], 1024.0),
+        _ => (value, 1.0 / (1024.0 * 1024.0)),
+    };
+
+    number
+        .parse::<f64>()
+        .ok()
+        .map(|n| (n * factor).round() as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMPOSE: &str = r#"
+version: "3.9"
+services:
+  web:
+    image: ghcr.io/acme/web:1.4
+    ports:
+      - "8080:3000"
+    environment:
+      NODE_ENV: production
+      API_URL: http://api
+    env_file: .env.web
+    depends_on:
+      api:
+        condition: service_healthy
+    deploy:
+      replicas: 2
+      resources:
+        limits:
+          cpus: "0.5"
+          memory: 128M
+  api:
+    image: ghcr.io/acme/api:2.0
+    expose:
+      - 8000
+    environment:
+      - LOG_LEVEL=info
+      - STRIPE_KEY
+    volumes:
+      - data:/var/lib/api
+volumes:
+  data: {}
+"#;
+
+    #[test]
+    fn test_plan_compose() {
+        let env_files = HashMap::from([(
+            ".env.web".to_string(),
+            "# web secrets\nSESSION_SECRET=\"s3cr3t\"\nNODE_ENV=development\n".to_string(),
+        )]);
+        let plan = ComposeImportService::plan(COMPOSE, &env_files).unwrap();
+
+        let services: Vec<&str> = plan
+            .deployments
+            .iter()
+            .map(|d| d.service.as_str())
+            .collect();
+        assert_eq!(services, ["api", "web"]);
+
+        let web = &plan.deployments[1].request;
+        assert_eq!(web.port, 3000);
+        assert_eq!(web.replicas, 2);
+        let resources = web.resources.as_ref().unwrap();
+        assert_eq!(resources.cpu_limit_millicores, 500);
+        assert_eq!(resources.cpu_request_millicores, 250);
+        assert_eq!(resources.memory_limit_mb, 128);
+        assert_eq!(resources.memory_request_mb, 128);
+        assert_eq!(
+            web.secrets,
+            Some(HashMap::from([(
+                "SESSION_SECRET".to_string(),
+                "s3cr3t".to_string()
+            )]))
+        );
+
+        let api = &plan.deployments[0].request;
+        assert_eq!(api.port, 8000);
+        assert_eq!(api.env_vars.as_ref().unwrap()["LOG_LEVEL"], "info");
+
+        assert!(plan.warnings.iter().any(|w| w.contains("`volumes`")));
+        assert!(
+            plan.warnings
+                .iter()
+                .any(|w| w.contains("Top-level `volumes`"))
+        );
+        assert!(plan.warnings.iter().any(|w| w.contains("STRIPE_KEY")));
+    }
+
+    #[test]
+    fn test_plan_rejects_dependency_cycles() {
+        let compose = r#"
+services:
+  a:
+    image: a
+    ports: ["80"]
+    depends_on: [b]
+  b:
+    image: b
+    ports: ["80"]
+    depends_on: [a]
+"#;
+        assert!(ComposeImportService::plan(compose, &HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_parse_ports_and_memory() {
+        assert_eq!(
+            ComposeImportService::parse_port("127.0.0.1:8080:80/tcp"),
+            Some(80)
+        );
+        assert_eq!(ComposeImportService::parse_port("3000-3005"), Some(3000));
+        assert_eq!(parse_memory_mb("1g"), Some(1024));
+        assert_eq!(parse_memory_mb("512MB"), Some(512));
+        assert_eq!(parse_memory_mb("268435456"), Some(256));
+    }
+}
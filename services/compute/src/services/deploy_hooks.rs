@@ -0,0 +1,240 @@
+use chrono::Utc;
+use shared::services::redis::Redis;
+use shared::utilities::errors::AppError;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::features::models::DeployHook;
+use crate::features::repository::{DeployHookRepository, DeploymentRepository};
+use crate::features::schemas::{
+    CreateDeployHookRequest, DeployHookResponse, DeploymentResponse, TriggerDeployHookQuery,
+};
+use crate::services::billing::BillingClient;
+use crate::services::build_kubernetes::Kubernetes;
+use crate::services::event_bus::EventBus;
+use crate::services::kubernetes::{DeploymentService, with_tag};
+use crate::utilities::tokens;
+
+const TOKEN_PREFIX: &str = "dh_";
+const TOKEN_LEN: usize = 32;
+/// Characters of a token kept in the clear, the prefix plus a few of its own
+const TOKEN_DISPLAY_LEN: usize = 10;
+/// Triggers allowed per hook in every window
+const RATE_LIMIT: i64 = 10;
+const RATE_LIMIT_WINDOW_SECONDS: i64 = 60;
+
+/// Secret URLs that redeploy a deployment, for pipelines that only push an
+/// image. Whoever holds the URL can trigger it, so tokens are stored hashed
+/// and can be regenerated or revoked.
+pub struct DeployHookService;
+
+impl DeployHookService {
+    pub async fn list(
+        pool: &PgPool,
+        deployment_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<Vec<DeployHookResponse>, AppError> {
+        let deployment = DeploymentRepository::get_by_id(pool, deployment_id, user_id).await?;
+        let hooks = DeployHookRepository::get_all_by_deployment(pool, deployment.id).await?;
+
+        Ok(hooks
+            .into_iter()
+            .map(|hook| Self::hook_response(hook, None))
+            .collect())
+    }
+
+    /// The response carries the hook's URL, it is only shown this once
+    pub async fn create(
+        pool: &PgPool,
+        public_api_url: &str,
+        deployment_id: Uuid,
+        user_id: Uuid,
+        req: CreateDeployHookRequest,
+    ) -> Result<DeployHookResponse, AppError> {
+        let deployment = DeploymentRepository::get_by_id(pool, deployment_id, user_id).await?;
+
+        let token = tokens::generate(TOKEN_PREFIX, TOKEN_LEN);
+        let hook = DeployHookRepository::create(
+            pool,
+            deployment.id,
+            &req.name,
+            &tokens::hash(&token),
+            &token[..TOKEN_DISPLAY_LEN],
+        )
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(db) if db.is_unique_violation() => {
+                AppError::ConflictError(format!("Deploy hook '{}' already exists", req.name))
+            }
+            e => AppError::from(e),
+        })?;
+
+        Ok(Self::hook_response(
+            hook,
+            Some(Self::url(public_api_url, &token)),
+        ))
+    }
+
+    /// Replace the hook's token, the old URL stops working at once
+    pub async fn regenerate(
+        pool: &PgPool,
+        public_api_url: &str,
+        deployment_id: Uuid,
+        hook_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<DeployHookResponse, AppError> {
+        let deployment = DeploymentRepository::get_by_id(pool, deployment_id, user_id).await?;
+
+        let token = tokens::generate(TOKEN_PREFIX, TOKEN_LEN);
+        let hook = DeployHookRepository::regenerate(
+            pool,
+            hook_id,
+            deployment.id,
+            &tokens::hash(&token),
+            &token[..TOKEN_DISPLAY_LEN],
+        )
+        .await?;
+
+        Ok(Self::hook_response(
+            hook,
+            Some(Self::url(public_api_url, &token)),
+        ))
+    }
+
+    pub async fn revoke(
+        pool: &PgPool,
+        deployment_id: Uuid,
+        hook_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<(), AppError> {
+        let deployment = DeploymentRepository::get_by_id(pool, deployment_id, user_id).await?;
+        DeployHookRepository::delete(pool, hook_id, deployment.id).await?;
+
+        Ok(())
+    }
+
+    /// Redeploy through the hook `token` belongs to, a request without a
+    /// token of ours gets the same answer as one for a revoked hook
+    #[allow(clippy::too_many_arguments)]
+    pub async fn trigger(
+        pool: &PgPool,
+        kubernetes: &Kubernetes,
+        billing: &BillingClient,
+        events: &EventBus,
+        redis: &Redis,
+        token: &str,
+        query: TriggerDeployHookQuery,
+    ) -> Result<DeploymentResponse, AppError> {
+        let hook = Self::authenticate(pool, token).await?;
+        Self::rate_limit(redis, &hook).await?;
+
+        Self::deploy(pool, kubernetes, billing, events, &hook, query.tag).await
+    }
+
+    pub async fn authenticate(pool: &PgPool, token: &str) -> Result<DeployHook, AppError> {
+        if !token.starts_with(TOKEN_PREFIX) {
+            return Err(Self::not_found());
+        }
+
+        DeployHookRepository::find_by_token_hash(pool, &tokens::hash(token))
+            .await?
+            .ok_or_else(Self::not_found)
+    }
+
+    /// Roll the deployment onto `tag` of its image, or restart it on the
+    /// image it runs
+    pub async fn deploy(
+        pool: &PgPool,
+        kubernetes: &Kubernetes,
+        billing: &BillingClient,
+        events: &EventBus,
+        hook: &DeployHook,
+        tag: Option<String>,
+    ) -> Result<DeploymentResponse, AppError> {
+        let deployment = DeploymentRepository::find_by_id(pool, hook.deployment_id)
+            .await?
+            .ok_or_else(Self::not_found)?;
+
+        let image = tag.map(|tag| with_tag(&deployment.image, &tag));
+        let message = match &image {
+            Some(image) => format!("Deploy hook '{}' deploying {}", hook.name, image),
+            None => format!("Deploy hook '{}' requested a restart", hook.name),
+        };
+
+        // Straight from the row, the hook keeps working after whoever created
+        // the deployment lost access to the project
+        let response = match image {
+            Some(image) => {
+                DeploymentService::update_image(
+                    pool,
+                    kubernetes,
+                    billing,
+                    events,
+                    &deployment,
+                    image,
+                )
+                .await?
+            }
+            None => {
+                DeploymentService::restart_deployment(pool, kubernetes, events, deployment.clone())
+                    .await?
+            }
+        };
+
+        // Only a rollout that went through counts as a trigger
+        DeployHookRepository::touch(pool, hook.id).await?;
+        events
+            .record(pool, &deployment, "deploy_hook_triggered", Some(&message))
+            .await?;
+
+        Ok(response)
+    }
+
+    /// Fixed window per hook, shared by every compute replica
+    async fn rate_limit(redis: &Redis, hook: &DeployHook) -> Result<(), AppError> {
+        let window = Utc::now().timestamp() / RATE_LIMIT_WINDOW_SECONDS;
+        let key = format!("compute:deploy_hook:{}:{}", hook.id, window);
+
+        let mut connection = redis.connection.clone();
+        let (count,): (i64,) = redis::pipe()
+            .atomic()
+            .incr(&key, 1)
+            .expire(&key, RATE_LIMIT_WINDOW_SECONDS)
+            .ignore()
+            .query_async(&mut connection)
+            .await?;
+
+        if count > RATE_LIMIT {
+            return Err(AppError::RateLimitedError(format!(
+                "Deploy hook '{}' allows {} triggers per {} seconds, try again later",
+                hook.name, RATE_LIMIT, RATE_LIMIT_WINDOW_SECONDS
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn url(public_api_url: &str, token: &str) -> String {
+        format!(
+            "{}/api/v1/deploy-hooks/{}",
+            public_api_url.trim_end_matches('/'),
+            token
+        )
+    }
+
+    fn not_found() -> AppError {
+        AppError::NotFoundError("Deploy hook not found".to_string())
+    }
+
+    fn hook_response(hook: DeployHook, url: Option<String>) -> DeployHookResponse {
+        DeployHookResponse {
+            id: hook.id,
+            deployment_id: hook.deployment_id,
+            name: hook.name,
+            token_prefix: hook.token_prefix,
+            url,
+            last_triggered_at: hook.last_triggered_at,
+            created_at: hook.created_at,
+        }
+    }
+}
@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+
+use shared::utilities::errors::AppError;
+use sqlx::PgPool;
+use tracing::warn;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::features::models::{Deployment, OrganizationRole, ProjectRole};
+use crate::features::repository::{
+    DeploymentRepository, DeploymentSecretRepository, ProjectRepository,
+};
+use crate::features::schemas::{
+    CloneDeploymentRequest, CreateDeploymentRequest, DeploymentResponse, DuplicateProjectRequest,
+    DuplicateProjectResponse,
+};
+use crate::services::access::AccessService;
+use crate::services::billing::BillingClient;
+use crate::services::build_kubernetes::Kubernetes;
+use crate::services::event_bus::EventBus;
+use crate::services::export::ExportService;
+use crate::services::kubernetes::DeploymentService;
+use crate::utilities::encryption::EncryptionService;
+use crate::utilities::naming;
+
+/// Copies deployments, secrets included, through the regular create path so
+/// the copies get their own names, data keys and Kubernetes objects
+pub struct DuplicateService;
+
+impl DuplicateService {
+    /// Clone a deployment into its own or another of the user's projects
+    #[allow(clippy::too_many_arguments)]
+    pub async fn clone_deployment(
+        pool: &PgPool,
+        kubernetes: &Kubernetes,
+        billing: &BillingClient,
+        events: &EventBus,
+        encryption_service: &EncryptionService,
+        base_domain: &str,
+        deployment_id: Uuid,
+        user_id: Uuid,
+        req: CloneDeploymentRequest,
+    ) -> Result<DeploymentResponse, AppError> {
+        let source = DeploymentRepository::get_by_id(pool, deployment_id, user_id).await?;
+
+        let project_id = req.project_id.unwrap_or(source.project_id);
+        AccessService::project(pool, project_id, user_id, ProjectRole::Developer).await?;
+
+        let name = req.name.unwrap_or_else(|| {
+            if project_id == source.project_id {
+                format!("{}-copy", source.name)
+            } else {
+                source.name.clone()
+            }
+        });
+        let subdomain = match req.subdomain {
+            Some(subdomain) => subdomain,
+            None => Self::default_subdomain(&name, project_id)?,
+        };
+
+        let create = Self::create_request(
+            pool,
+            kubernetes,
+            encryption_service,
+            &source,
+            name,
+            subdomain,
+            req.replicas,
+        )
+        .await?;
+
+        DeploymentService::create(
+            pool,
+            kubernetes,
+            billing,
+            events,
+            encryption_service,
+            user_id,
+            project_id,
+            base_domain,
+            create,
+        )
+        .await
+    }
+
+    /// Create a new project holding a copy of every deployment. If a copy
+    /// fails, the new project and the copies made so far are removed.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn duplicate_project(
+        pool: &PgPool,
+        kubernetes: &Kubernetes,
+        billing: &BillingClient,
+        events: &EventBus,
+        encryption_service: &EncryptionService,
+        base_domain: &str,
+        project_id: Uuid,
+        user_id: Uuid,
+        req: DuplicateProjectRequest,
+    ) -> Result<DuplicateProjectResponse, AppError> {
+        let source = ProjectRepository::get_one_by_id(pool, project_id, user_id).await?;
+        let mut deployments =
+            DeploymentRepository::get_all_by_project(pool, source.id, user_id).await?;
+        // Oldest first, in the order they were originally created
+        deployments.reverse();
+
+        // The copy stays in the source's organization when the user belongs
+        // to it, a collaborator invited onto just the project gets their own
+        let organization_id = match source.organization_id {
+            Some(organization_id) => {
+                match AccessService::organization(
+                    pool,
+                    organization_id,
+                    user_id,
+                    OrganizationRole::Member,
+                )
+                .await
+                {
+                    Ok(_) => Some(organization_id),
+                    Err(AppError::NotFoundError(_)) => None,
+                    Err(error) => return Err(error),
+                }
+            }
+            None => None,
+        };
+        let description = req.description.or(source.description);
+        let project = ProjectRepository::create(
+            pool,
+            user_id,
+            organization_id,
+            &req.name,
+            description.as_deref(),
+        )
+        .await?;
+
+        let mut copies = vec![];
+        for deployment in &deployments {
+            let copy = async {
+                let create = Self::create_request(
+                    pool,
+                    kubernetes,
+                    encryption_service,
+                    deployment,
+                    deployment.name.clone(),
+                    Self::default_subdomain(&deployment.name, project.id)?,
+                    req.replicas,
+                )
+                .await?;
+
+                DeploymentService::create(
+                    pool,
+                    kubernetes,
+                    billing,
+                    events,
+                    encryption_service,
+                    user_id,
+                    project.id,
+                    base_domain,
+                    create,
+                )
+                .await
+            }
+            .await;
+
+            match copy {
+                Ok(copy) => copies.push(copy),
+                Err(e) => {
+                    Self::discard(pool, kubernetes, events, user_id, project.id, &copies).await;
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(DuplicateProjectResponse {
+            project,
+            deployments: copies,
+        })
+    }
+
+    /// The source's spec with decrypted secrets, `create` encrypts them
+    /// again under the copy's own data key
+    pub(crate) async fn create_request(
+        pool: &PgPool,
+        kubernetes: &Kubernetes,
+        encryption_service: &EncryptionService,
+        source: &Deployment,
+        name: String,
+        subdomain: String,
+        replicas: Option<i32>,
+    ) -> Result<CreateDeploymentRequest, AppError> {
+        let live = ExportService::live_spec(kubernetes, source)
+            .await?
+            .ok_or_else(|| {
+                AppError::ConflictError(format!(
+                    "Deployment {} has no Kubernetes objects to clone",
+                    source.name
+                ))
+            })?;
+
+        let mut connection = pool.acquire().await?;
+        let data_key = encryption_service
+            .data_key(&mut connection, source.id)
+            .await?;
+        let secrets =
+            DeploymentSecretRepository::get_all_by_deployment(&mut *connection, source.id)
+                .await?
+                .into_iter()
+                .map(|s| Ok((s.key, data_key.decrypt(&s.value)?)))
+                .collect::<Result<HashMap<String, String>, AppError>>()?;
+
+        let env_vars: HashMap<String, String> = serde_json::from_value(source.env_vars.clone())?;
+        let labels: Option<HashMap<String, String>> = source
+            .labels
+            .clone()
+            .map(serde_json::from_value)
+            .transpose()?;
+
+        let req = CreateDeploymentRequest {
+            name,
+            image: source.image.clone(),
+            replicas: replicas.unwrap_or(source.replicas),
+            port: live.container_port,
+            env_vars: (!env_vars.is_empty()).then_some(env_vars),
+            secrets: (!secrets.is_empty()).then_some(secrets),
+            resources: Some(serde_json::from_value(source.resources.clone())?),
+            size: None,
+            labels,
+            subdomain: Some(subdomain),
+            region: source.region.clone(),
+            node_pool: source.node_pool.clone(),
+        };
+        req.validate()?;
+
+        Ok(req)
+    }
+
+    /// The generated subdomain only depends on name and user, so copies
+    /// would collide with their source. Key it on the target project instead.
+    fn default_subdomain(name: &str, project_id: Uuid) -> Result<String, AppError> {
+        naming::generated_subdomain(&project_id.to_string(), name)
+    }
+
+    async fn discard(
+        pool: &PgPool,
+        kubernetes: &Kubernetes,
+        events: &EventBus,
+        user_id: Uuid,
+        project_id: Uuid,
+        copies: &[DeploymentResponse],
+    ) {
+        for copy in copies {
+            if let Err(e) =
+                DeploymentService::delete(pool, kubernetes, events, copy.id, user_id).await
+            {
+                warn!("Failed to remove copied deployment {}: {}", copy.id, e);
+            }
+        }
+
+        if let Err(e) = ProjectRepository::delete(pool, project_id, user_id).await {
+            warn!("Failed to remove duplicated project {}: {}", project_id, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_subdomain_is_per_project() {
+        let a = DuplicateService::default_subdomain("My_Api", Uuid::from_u128(1)).unwrap();
+        let b = DuplicateService::default_subdomain("My_Api", Uuid::from_u128(2)).unwrap();
+
+        assert!(a.starts_with("my-api-"));
+        assert_ne!(a, b);
+    }
+}
@@ -0,0 +1,138 @@
+use bigdecimal::{BigDecimal, Zero};
+use shared::utilities::errors::AppError;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::features::models::{BillingAccount, BillingPrice, ResourceUsage};
+use crate::features::repository::{BillingRepository, DeploymentRepository, ProjectRepository};
+use crate::features::schemas::{
+    CostEstimateResponse, CreateDeploymentRequest, EstimateChangeRequest,
+};
+use crate::services::access::AccessService;
+use crate::services::quota::QuotaService;
+
+/// Hours in an average month, 365 * 24 / 12
+const HOURS_PER_MONTH: i64 = 730;
+
+/// Balances are kept to 6 decimals
+const COST_SCALE: i64 = 6;
+
+pub struct EstimateService;
+
+impl EstimateService {
+    /// Cost of creating the deployment `req` describes, billed to `account`
+    pub async fn new_deployment(
+        pool: &PgPool,
+        user_id: Uuid,
+        account: BillingAccount,
+        req: &CreateDeploymentRequest,
+    ) -> Result<CostEstimateResponse, AppError> {
+        let resources = QuotaService::resources(req.size, req.resources.clone())?;
+        let proposed = QuotaService::footprint(req.replicas, &resources, false);
+        let others = DeploymentRepository::usage_by_account(pool, account, &[]).await?;
+
+        Self::estimate(pool, user_id, account, proposed, None, others).await
+    }
+
+    /// Cost of an existing deployment after scaling or resizing it, as if it
+    /// were running even when it is paused now
+    pub async fn change(
+        pool: &PgPool,
+        user_id: Uuid,
+        deployment_id: Uuid,
+        req: &EstimateChangeRequest,
+    ) -> Result<CostEstimateResponse, AppError> {
+        let deployment = DeploymentRepository::get_by_id(pool, deployment_id, user_id).await?;
+        let resources = if req.size.is_some() || req.resources.is_some() {
+            QuotaService::resources(req.size, req.resources.clone())?
+        } else {
+            serde_json::from_value(deployment.resources.clone())?
+        };
+        let proposed = QuotaService::footprint(
+            req.replicas.unwrap_or(deployment.replicas),
+            &resources,
+            false,
+        );
+        let current = QuotaService::footprint_of(&deployment)?;
+        let account = ProjectRepository::get_billing_account(pool, deployment.project_id).await?;
+        let others =
+            DeploymentRepository::usage_by_account(pool, account, &[deployment.id]).await?;
+
+        Self::estimate(pool, user_id, account, proposed, Some(current), others).await
+    }
+
+    /// The balance and runway are left out for users who may not see the
+    /// account's balance
+    async fn estimate(
+        pool: &PgPool,
+        user_id: Uuid,
+        account: BillingAccount,
+        proposed: ResourceUsage,
+        current: Option<ResourceUsage>,
+        others: ResourceUsage,
+    ) -> Result<CostEstimateResponse, AppError> {
+        let prices = BillingRepository::get_prices(pool).await?.ok_or_else(|| {
+            AppError::InternalError("No billing prices are configured".to_string())
+        })?;
+        let balance = BillingRepository::get_balance(pool, account).await?;
+
+        let hourly = Self::hourly_cost(&prices, &proposed);
+        let (balance_amount, runway_days) =
+            if AccessService::balance(pool, account, user_id).await? {
+                let spend_per_day =
+                    (Self::hourly_cost(&prices, &others) + &hourly) * BigDecimal::from(24);
+                let runway_days =
+                    (!spend_per_day.is_zero()).then(|| (&balance.amount / spend_per_day).round(1));
+                (Some(balance.amount), runway_days)
+            } else {
+                (None, None)
+            };
+
+        Ok(CostEstimateResponse {
+            currency: balance.currency,
+            daily: (&hourly * BigDecimal::from(24)).round(COST_SCALE),
+            monthly: (&hourly * BigDecimal::from(HOURS_PER_MONTH)).round(COST_SCALE),
+            hourly: hourly.round(COST_SCALE),
+            current_hourly: current
+                .map(|usage| Self::hourly_cost(&prices, &usage).round(COST_SCALE)),
+            balance: balance_amount,
+            runway_days,
+        })
+    }
+
+    pub fn hourly_cost(prices: &BillingPrice, usage: &ResourceUsage) -> BigDecimal {
+        &prices.cpu_millicore_hour * BigDecimal::from(usage.cpu_millicores)
+            + &prices.memory_mb_hour * BigDecimal::from(usage.memory_mb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::features::models::ResourceSpec;
+
+    #[test]
+    fn test_hourly_cost() {
+        let prices = BillingPrice {
+            cpu_millicore_hour: BigDecimal::from_str("0.00003").unwrap(),
+            memory_mb_hour: BigDecimal::from_str("0.000004").unwrap(),
+            updated_at: chrono::Utc::now(),
+        };
+        let usage = QuotaService::footprint(2, &ResourceSpec::default(), false);
+
+        // 1000m of CPU and 1024Mi of memory
+        assert_eq!(
+            EstimateService::hourly_cost(&prices, &usage),
+            BigDecimal::from_str("0.034096").unwrap()
+        );
+        assert!(
+            EstimateService::hourly_cost(
+                &prices,
+                &QuotaService::footprint(2, &ResourceSpec::default(), true)
+            )
+            .is_zero()
+        );
+    }
+}
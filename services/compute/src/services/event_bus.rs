@@ -0,0 +1,246 @@
+use std::convert::Infallible;
+use std::time::Duration;
+
+use axum::response::sse::Event as SseEvent;
+use futures::{Stream, StreamExt, stream};
+use redis::AsyncCommands;
+use shared::services::redis::Redis;
+use shared::utilities::errors::AppError;
+use sqlx::PgPool;
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::features::models::{Deployment, DeploymentEvent};
+use crate::features::repository::DeploymentEventRepository;
+use crate::features::schemas::ProjectUpdate;
+use crate::services::timeline::TimelineService;
+use crate::services::webhooks::WebhookService;
+
+const CHANNEL_PATTERN: &str = "compute:project:*:updates";
+const LOCAL_BUFFER: usize = 1024;
+const DEDUPE_TTL_SECONDS: u64 = 300;
+
+/// Records deployment events and fans project updates out to every compute
+/// replica: updates are published to Redis, and each replica's subscriber
+/// forwards them to its own connected clients.
+#[derive(Clone)]
+pub struct EventBus {
+    /// `None` keeps updates on this replica
+    redis: Option<Redis>,
+    sender: broadcast::Sender<ProjectUpdate>,
+}
+
+impl EventBus {
+    /// Start the Redis subscriber, it reconnects for the lifetime of the process
+    pub fn new(redis: Redis) -> Self {
+        let (sender, _) = broadcast::channel(LOCAL_BUFFER);
+
+        let bus = Self {
+            redis: Some(redis),
+            sender,
+        };
+        tokio::spawn(bus.clone().subscribe_loop());
+        bus
+    }
+
+    /// Bus without Redis, updates go straight to local subscribers
+    #[cfg(test)]
+    pub fn local() -> Self {
+        let (sender, _) = broadcast::channel(LOCAL_BUFFER);
+        Self {
+            redis: None,
+            sender,
+        }
+    }
+
+    /// Write a `deployment_events` row, push it to the project's subscribers
+    /// and queue the project's webhooks
+    pub async fn record(
+        &self,
+        pool: &PgPool,
+        deployment: &Deployment,
+        event_type: &str,
+        message: Option<&str>,
+    ) -> Result<DeploymentEvent, AppError> {
+        let event =
+            DeploymentEventRepository::create(pool, deployment.id, event_type, message).await?;
+        self.fan_out(pool, deployment, &event).await;
+
+        Ok(event)
+    }
+
+    /// Like `record`, but only the first call with the same `dedupe_key` for
+    /// the deployment writes and publishes the event
+    pub async fn record_once(
+        &self,
+        pool: &PgPool,
+        deployment: &Deployment,
+        event_type: &str,
+        message: Option<&str>,
+        dedupe_key: &str,
+    ) -> Result<Option<DeploymentEvent>, AppError> {
+        let event = DeploymentEventRepository::create_once(
+            pool,
+            deployment.id,
+            event_type,
+            message,
+            dedupe_key,
+        )
+        .await?;
+        if let Some(event) = &event {
+            self.fan_out(pool, deployment, event).await;
+        }
+
+        Ok(event)
+    }
+
+    async fn fan_out(&self, pool: &PgPool, deployment: &Deployment, event: &DeploymentEvent) {
+        self.publish(&ProjectUpdate::Event {
+            project_id: deployment.project_id,
+            deployment_id: deployment.id,
+            event: TimelineService::platform_event(event.clone()),
+        })
+        .await;
+        WebhookService::enqueue(pool, deployment, event).await;
+    }
+
+    /// Push the stored status and replica count of a deployment
+    pub async fn publish_status(&self, deployment: &Deployment) {
+        self.publish(&ProjectUpdate::Status {
+            project_id: deployment.project_id,
+            deployment_id: deployment.id,
+            status: deployment.status,
+            replicas: deployment.replicas,
+            ready_replicas: None,
+            available_replicas: None,
+            updated_replicas: None,
+        })
+        .await;
+    }
+
+    /// Best effort, a missed update must never fail the request that caused it
+    pub async fn publish(&self, update: &ProjectUpdate) {
+        let payload = match serde_json::to_string(update) {
+            Ok(payload) => payload,
+            Err(e) => {
+                warn!("Failed to serialize project update: {}", e);
+                return;
+            }
+        };
+
+        let Some(redis) = &self.redis else {
+            let _ = self.sender.send(update.clone());
+            return;
+        };
+
+        let mut connection = redis.connection.clone();
+        if let Err(e) = connection
+            .publish::<_, _, ()>(Self::channel(update.project_id()), payload)
+            .await
+        {
+            warn!("Failed to publish project update: {}", e);
+        }
+    }
+
+    /// Publish from only one replica when every replica observes the same
+    /// change, `dedupe_key` identifies the change
+    pub async fn publish_once(&self, dedupe_key: &str, update: &ProjectUpdate) {
+        let Some(redis) = &self.redis else {
+            return self.publish(update).await;
+        };
+
+        let mut connection = redis.connection.clone();
+        let claimed = redis::cmd("SET")
+            .arg(format!("compute:published:{}", dedupe_key))
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(DEDUPE_TTL_SECONDS)
+            .query_async::<Option<String>>(&mut connection)
+            .await;
+
+        match claimed {
+            Ok(Some(_)) => self.publish(update).await,
+            Ok(None) => {}
+            Err(e) => warn!("Failed to claim project update: {}", e),
+        }
+    }
+
+    /// Updates of one project as Server-Sent Events
+    pub fn subscribe(
+        &self,
+        project_id: Uuid,
+    ) -> impl Stream<Item = Result<SseEvent, Infallible>> + use<> {
+        self.updates(project_id).filter_map(|update| async move {
+            SseEvent::default()
+                .event(update.name())
+                .json_data(&update)
+                .ok()
+                .map(Ok)
+        })
+    }
+
+    /// Updates of one project from the moment this is called
+    pub fn updates(&self, project_id: Uuid) -> impl Stream<Item = ProjectUpdate> + use<> {
+        let receiver = self.sender.subscribe();
+
+        stream::unfold(receiver, |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(update) => return Some((update, receiver)),
+                    // A slow client skips what it missed rather than stalling the others
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("Project stream lagged, skipped {} updates", skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+        .filter(move |update| std::future::ready(update.project_id() == project_id))
+    }
+
+    fn channel(project_id: Uuid) -> String {
+        format!("compute:project:{}:updates", project_id)
+    }
+
+    async fn subscribe_loop(self) {
+        loop {
+            if let Err(e) = self.forward_updates().await {
+                warn!("Project update subscriber disconnected: {}", e);
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    async fn forward_updates(&self) -> Result<(), AppError> {
+        let Some(redis) = &self.redis else {
+            return Ok(());
+        };
+
+        let mut pubsub = redis.client.get_async_pubsub().await?;
+        pubsub.psubscribe(CHANNEL_PATTERN).await?;
+        info!("📡 Subscribed to {}", CHANNEL_PATTERN);
+
+        let mut messages = pubsub.on_message();
+        while let Some(message) = messages.next().await {
+            let payload: String = match message.get_payload() {
+                Ok(payload) => payload,
+                Err(e) => {
+                    warn!("Invalid project update payload: {}", e);
+                    continue;
+                }
+            };
+
+            match serde_json::from_str::<ProjectUpdate>(&payload) {
+                // No receivers just means no client is connected to this replica
+                Ok(update) => {
+                    let _ = self.sender.send(update);
+                }
+                Err(e) => warn!("Invalid project update payload: {}", e),
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,265 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Serialize;
+use shared::utilities::errors::AppError;
+use sqlx::PgPool;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::features::models::Deployment;
+use crate::features::repository::{
+    DeploymentRepository, DeploymentSecretRepository, ProjectRepository,
+};
+use crate::features::schemas::SecretExportMode;
+use crate::services::build_kubernetes::Kubernetes;
+use crate::services::cluster::{ClusterObject, ObjectKind};
+use crate::services::kubernetes::DeploymentService;
+
+/// Values chosen at creation time that only the cluster remembers
+pub(crate) struct LiveSpec {
+    pub container_port: i32,
+    pub external_url: Option<String>,
+}
+
+/// Renders deployments as the manifests `DeploymentService` applies, so they
+/// can be applied to a cluster we don't manage
+pub struct ExportService;
+
+impl ExportService {
+    /// Multi-document YAML of one deployment
+    pub async fn deployment(
+        pool: &PgPool,
+        kubernetes: &Kubernetes,
+        deployment_id: Uuid,
+        user_id: Uuid,
+        mode: SecretExportMode,
+    ) -> Result<(String, String), AppError> {
+        let deployment = DeploymentRepository::get_by_id(pool, deployment_id, user_id).await?;
+
+        let live = Self::live_spec(kubernetes, &deployment)
+            .await?
+            .ok_or_else(|| {
+                AppError::NotFoundError(format!(
+                    "Deployment {} has no Kubernetes objects to export",
+                    deployment.name
+                ))
+            })?;
+        let manifests = Self::manifests(pool, &deployment, &live, mode).await?;
+
+        Ok((deployment.name, manifests))
+    }
+
+    /// Multi-document YAML of every deployment in a project. Deployments
+    /// missing from the cluster are skipped with a comment.
+    pub async fn project(
+        pool: &PgPool,
+        kubernetes: &Kubernetes,
+        project_id: Uuid,
+        user_id: Uuid,
+        mode: SecretExportMode,
+    ) -> Result<(String, String), AppError> {
+        let project = ProjectRepository::get_one_by_id(pool, project_id, user_id).await?;
+        let deployments =
+            DeploymentRepository::get_all_by_project(pool, project.id, user_id).await?;
+
+        let mut documents = vec![];
+        for deployment in deployments {
+            match Self::live_spec(kubernetes, &deployment).await? {
+                Some(live) => {
+                    documents.push(Self::manifests(pool, &deployment, &live, mode).await?)
+                }
+                None => {
+                    warn!(
+                        "Skipping export of deployment {}, it has no Kubernetes objects",
+                        deployment.id
+                    );
+                    documents.push(format!(
+                        "# {}: skipped, it has no Kubernetes objects\n",
+                        deployment.name
+                    ));
+                }
+            }
+        }
+
+        Ok((project.name, documents.join("---\n")))
+    }
+
+    async fn manifests(
+        pool: &PgPool,
+        deployment: &Deployment,
+        live: &LiveSpec,
+        mode: SecretExportMode,
+    ) -> Result<String, AppError> {
+        let env_vars: HashMap<String, String> =
+            serde_json::from_value(deployment.env_vars.clone())?;
+        let secret_keys: Vec<String> =
+            DeploymentSecretRepository::get_all_by_deployment(pool, deployment.id)
+                .await?
+                .into_iter()
+                .map(|s| s.key)
+                .collect();
+
+        Self::render(deployment, live, &env_vars, &secret_keys, mode)
+    }
+
+    /// `None` when the Kubernetes Deployment is gone, e.g. after a failed create
+    pub(crate) async fn live_spec(
+        kubernetes: &Kubernetes,
+        deployment: &Deployment,
+    ) -> Result<Option<LiveSpec>, AppError> {
+        let namespace = &deployment.cluster_namespace;
+        let name = &deployment.cluster_deployment_name;
+
+        let cluster = kubernetes.backend(deployment)?;
+        let container_port = cluster
+            .get(ObjectKind::Deployment, namespace, name)
+            .await?
+            .and_then(ClusterObject::into_deployment)
+            .and_then(|d| d.spec)
+            .and_then(|spec| spec.template.spec)
+            .and_then(|spec| spec.containers.into_iter().next())
+            .and_then(|container| container.ports)
+            .and_then(|ports| ports.into_iter().next())
+            .map(|port| port.container_port);
+        let Some(container_port) = container_port else {
+            return Ok(None);
+        };
+
+        let external_url = cluster
+            .get(ObjectKind::Ingress, namespace, name)
+            .await?
+            .and_then(ClusterObject::into_ingress)
+            .and_then(|i| i.spec)
+            .and_then(|spec| spec.rules)
+            .and_then(|rules| rules.into_iter().find_map(|rule| rule.host));
+
+        Ok(Some(LiveSpec {
+            container_port,
+            external_url,
+        }))
+    }
+
+    fn render(
+        deployment: &Deployment,
+        live: &LiveSpec,
+        env_vars: &HashMap<String, String>,
+        secret_keys: &[String],
+        mode: SecretExportMode,
+    ) -> Result<String, AppError> {
+        let mut documents = vec![];
+
+        if mode == SecretExportMode::Placeholder && !secret_keys.is_empty() {
+            let mut secret = DeploymentService::build_secret(deployment, &HashMap::new());
+            secret.data = None;
+            secret.string_data = Some(
+                secret_keys
+                    .iter()
+                    .map(|key| (key.clone(), format!("${{{}}}", key)))
+                    .collect::<BTreeMap<_, _>>(),
+            );
+            documents.push(Self::to_yaml(&secret)?);
+        }
+
+        documents.push(Self::to_yaml(&DeploymentService::build_deployment(
+            deployment,
+            live.container_port,
+            env_vars,
+            secret_keys,
+        )?)?);
+        documents.push(Self::to_yaml(&DeploymentService::build_service(
+            deployment,
+            live.container_port,
+        ))?);
+
+        if let Some(external_url) = &live.external_url {
+            documents.push(Self::to_yaml(&DeploymentService::build_ingress(
+                deployment,
+                external_url,
+            ))?);
+        }
+
+        Ok(documents.join("---\n"))
+    }
+
+    fn to_yaml(manifest: &impl Serialize) -> Result<String, AppError> {
+        serde_yaml::to_string(manifest)
+            .map_err(|e| AppError::InternalError(format!("Failed to render manifest: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+    use crate::features::models::DeploymentStatus;
+
+    fn deployment() -> Deployment {
+        Deployment {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            project_id: Uuid::new_v4(),
+            name: "api".to_string(),
+            image: "nginx:1.27".to_string(),
+            env_vars: json!({ "MODE": "production" }),
+            replicas: 2,
+            resources: json!({
+                "cpuRequestMillicores": 100,
+                "cpuLimitMillicores": 500,
+                "memoryRequestMb": 128,
+                "memoryLimitMb": 512
+            }),
+            labels: None,
+            status: DeploymentStatus::Running,
+            cluster_namespace: "default".to_string(),
+            cluster_deployment_name: "project-api".to_string(),
+            node_selector: None,
+            region: None,
+            node_pool: None,
+            tolerations: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_render_secret_modes() {
+        let deployment = deployment();
+        let env_vars = serde_json::from_value(deployment.env_vars.clone()).unwrap();
+        let live = LiveSpec {
+            container_port: 8080,
+            external_url: Some("api.example.com".to_string()),
+        };
+        let secret_keys = vec!["DATABASE_URL".to_string()];
+
+        let placeholder = ExportService::render(
+            &deployment,
+            &live,
+            &env_vars,
+            &secret_keys,
+            SecretExportMode::Placeholder,
+        )
+        .unwrap();
+        let kinds: Vec<String> = serde_yaml::Deserializer::from_str(&placeholder)
+            .map(|document| {
+                let value = serde_yaml::Value::deserialize(document).unwrap();
+                value["kind"].as_str().unwrap().to_string()
+            })
+            .collect();
+        assert_eq!(kinds, ["Secret", "Deployment", "Service", "Ingress"]);
+        assert!(placeholder.contains("DATABASE_URL: ${DATABASE_URL}"));
+
+        let redacted = ExportService::render(
+            &deployment,
+            &live,
+            &env_vars,
+            &secret_keys,
+            SecretExportMode::Redacted,
+        )
+        .unwrap();
+        assert!(!redacted.contains("kind: Secret"));
+        assert!(redacted.contains("name: project-api-secrets"));
+    }
+}
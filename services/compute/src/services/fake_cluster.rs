@@ -0,0 +1,328 @@
+//! In-memory `ClusterBackend` for tests. Objects are kept as the API server
+//! would return them, every write is recorded, and failures can be injected
+//! per verb and kind.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use k8s_openapi::api::apps::v1::ReplicaSet;
+use k8s_openapi::api::core::v1::{Event as K8sEvent, Pod};
+use kube::api::ObjectMeta;
+use shared::utilities::errors::AppError;
+
+use crate::services::cluster::{
+    ClusterBackend, ClusterObject, ObjectKind, ObjectPatch, cluster_error,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verb {
+    Create,
+    Replace,
+    Patch,
+    Delete,
+}
+
+impl Verb {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Verb::Create => "create",
+            Verb::Replace | Verb::Patch => "update",
+            Verb::Delete => "delete",
+        }
+    }
+}
+
+/// A successful write
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Operation {
+    pub verb: Verb,
+    pub kind: ObjectKind,
+    pub name: String,
+}
+
+type ObjectKey = (ObjectKind, String, String);
+
+#[derive(Default)]
+struct State {
+    objects: BTreeMap<ObjectKey, ClusterObject>,
+    /// Pods, ReplicaSets and Events aren't written by `DeploymentService`,
+    /// tests add them
+    pods: Vec<Pod>,
+    replica_sets: Vec<ReplicaSet>,
+    events: Vec<K8sEvent>,
+    operations: Vec<Operation>,
+    failures: Vec<(Verb, ObjectKind)>,
+    resource_version: u64,
+}
+
+#[derive(Default)]
+pub struct FakeCluster {
+    state: Mutex<State>,
+}
+
+impl FakeCluster {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make every following `verb` on `kind` fail until `recover`
+    pub fn fail(&self, verb: Verb, kind: ObjectKind) {
+        self.state.lock().unwrap().failures.push((verb, kind));
+    }
+
+    pub fn recover(&self) {
+        self.state.lock().unwrap().failures.clear();
+    }
+
+    pub fn operations(&self) -> Vec<Operation> {
+        self.state.lock().unwrap().operations.clone()
+    }
+
+    pub fn object(&self, kind: ObjectKind, namespace: &str, name: &str) -> Option<ClusterObject> {
+        let key = (kind, namespace.to_string(), name.to_string());
+        self.state.lock().unwrap().objects.get(&key).cloned()
+    }
+
+    pub fn objects(&self, kind: ObjectKind) -> Vec<ClusterObject> {
+        let state = self.state.lock().unwrap();
+        state
+            .objects
+            .iter()
+            .filter(|((k, _, _), _)| *k == kind)
+            .map(|(_, object)| object.clone())
+            .collect()
+    }
+
+    pub fn add_pod(&self, pod: Pod) {
+        self.state.lock().unwrap().pods.push(pod);
+    }
+
+    pub fn add_replica_set(&self, replica_set: ReplicaSet) {
+        self.state.lock().unwrap().replica_sets.push(replica_set);
+    }
+
+    /// Add an Event, or replace the one with the same uid
+    pub fn add_event(&self, event: K8sEvent) {
+        let mut state = self.state.lock().unwrap();
+        state
+            .events
+            .retain(|e| e.metadata.uid.is_none() || e.metadata.uid != event.metadata.uid);
+        state.events.push(event);
+    }
+
+    /// Run `write` against the state unless a failure is injected, and
+    /// record it when it succeeds
+    fn write(
+        &self,
+        verb: Verb,
+        kind: ObjectKind,
+        name: &str,
+        write: impl FnOnce(&mut State) -> Result<(), String>,
+    ) -> Result<(), AppError> {
+        let mut state = self.state.lock().unwrap();
+        if state.failures.contains(&(verb, kind)) {
+            return Err(cluster_error(verb.as_str(), kind, "injected failure"));
+        }
+
+        write(&mut state).map_err(|e| cluster_error(verb.as_str(), kind, e))?;
+        state.operations.push(Operation {
+            verb,
+            kind,
+            name: name.to_string(),
+        });
+        Ok(())
+    }
+}
+
+fn key(object: &ClusterObject) -> ObjectKey {
+    (
+        object.kind(),
+        object.namespace().to_string(),
+        object.name().to_string(),
+    )
+}
+
+/// Store `object` with the next `resourceVersion`
+fn store(state: &mut State, mut object: ClusterObject) {
+    state.resource_version += 1;
+    let version = Some(state.resource_version.to_string());
+    match &mut object {
+        ClusterObject::Deployment(o) => o.metadata.resource_version = version,
+        ClusterObject::Service(o) => o.metadata.resource_version = version,
+        ClusterObject::Ingress(o) => o.metadata.resource_version = version,
+        ClusterObject::Secret(o) => o.metadata.resource_version = version,
+    }
+    state.objects.insert(key(&object), object);
+}
+
+fn to_json(object: &ClusterObject) -> Result<serde_json::Value, serde_json::Error> {
+    match object {
+        ClusterObject::Deployment(o) => serde_json::to_value(o),
+        ClusterObject::Service(o) => serde_json::to_value(o),
+        ClusterObject::Ingress(o) => serde_json::to_value(o),
+        ClusterObject::Secret(o) => serde_json::to_value(o),
+    }
+}
+
+fn from_json(
+    kind: ObjectKind,
+    value: serde_json::Value,
+) -> Result<ClusterObject, serde_json::Error> {
+    Ok(match kind {
+        ObjectKind::Deployment => ClusterObject::Deployment(serde_json::from_value(value)?),
+        ObjectKind::Service => ClusterObject::Service(serde_json::from_value(value)?),
+        ObjectKind::Ingress => ClusterObject::Ingress(serde_json::from_value(value)?),
+        ObjectKind::Secret => ClusterObject::Secret(serde_json::from_value(value)?),
+    })
+}
+
+/// RFC 7386 merge patch. Strategic patches get the same treatment, which is
+/// exact for the maps and scalars `DeploymentService` patches.
+fn merge(target: &mut serde_json::Value, patch: &serde_json::Value) {
+    let serde_json::Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+
+    if !target.is_object() {
+        *target = serde_json::Value::Object(Default::default());
+    }
+    let target = target.as_object_mut().unwrap();
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            merge(
+                target.entry(key.clone()).or_insert(serde_json::Value::Null),
+                value,
+            );
+        }
+    }
+}
+
+#[async_trait]
+impl ClusterBackend for FakeCluster {
+    async fn create(&self, object: &ClusterObject) -> Result<(), AppError> {
+        self.write(Verb::Create, object.kind(), object.name(), |state| {
+            if state.objects.contains_key(&key(object)) {
+                return Err(format!("{} already exists", object.name()));
+            }
+            store(state, object.clone());
+            Ok(())
+        })
+    }
+
+    async fn replace(&self, object: &ClusterObject) -> Result<(), AppError> {
+        self.write(Verb::Replace, object.kind(), object.name(), |state| {
+            let existing = state
+                .objects
+                .get(&key(object))
+                .ok_or_else(|| format!("{} not found", object.name()))?;
+            // Without a `resourceVersion` the API server overwrites unconditionally
+            let version = &object.metadata().resource_version;
+            if version.is_some() && existing.metadata().resource_version != *version {
+                return Err("the object has been modified".to_string());
+            }
+            store(state, object.clone());
+            Ok(())
+        })
+    }
+
+    async fn get(
+        &self,
+        kind: ObjectKind,
+        namespace: &str,
+        name: &str,
+    ) -> Result<Option<ClusterObject>, AppError> {
+        Ok(self.object(kind, namespace, name))
+    }
+
+    async fn patch(
+        &self,
+        kind: ObjectKind,
+        namespace: &str,
+        name: &str,
+        patch: &ObjectPatch,
+    ) -> Result<(), AppError> {
+        self.write(Verb::Patch, kind, name, |state| {
+            let key = (kind, namespace.to_string(), name.to_string());
+            let existing = state
+                .objects
+                .get(&key)
+                .ok_or_else(|| format!("{} not found", name))?;
+
+            let mut value = to_json(existing).map_err(|e| e.to_string())?;
+            let (ObjectPatch::Merge(body) | ObjectPatch::Strategic(body)) = patch;
+            merge(&mut value, body);
+
+            let patched = from_json(kind, value).map_err(|e| e.to_string())?;
+            store(state, patched);
+            Ok(())
+        })
+    }
+
+    async fn delete(
+        &self,
+        kind: ObjectKind,
+        namespace: &str,
+        name: &str,
+    ) -> Result<bool, AppError> {
+        let mut deleted = false;
+        self.write(Verb::Delete, kind, name, |state| {
+            let key = (kind, namespace.to_string(), name.to_string());
+            deleted = state.objects.remove(&key).is_some();
+            Ok(())
+        })?;
+        Ok(deleted)
+    }
+
+    async fn pods(&self, namespace: &str, labels: &str) -> Result<Vec<Pod>, AppError> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .pods
+            .iter()
+            .filter(|pod| selects(&pod.metadata, namespace, labels))
+            .cloned()
+            .collect())
+    }
+
+    async fn owned_objects(&self, namespace: &str, labels: &str) -> Result<Vec<String>, AppError> {
+        let state = self.state.lock().unwrap();
+        let replica_sets = state.replica_sets.iter().map(|r| &r.metadata);
+        let pods = state.pods.iter().map(|p| &p.metadata);
+        Ok(replica_sets
+            .chain(pods)
+            .filter(|metadata| selects(metadata, namespace, labels))
+            .filter_map(|metadata| metadata.name.clone())
+            .collect())
+    }
+
+    async fn events(
+        &self,
+        namespace: &str,
+        object_name: &str,
+        limit: u32,
+    ) -> Result<Vec<K8sEvent>, AppError> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .events
+            .iter()
+            .filter(|e| e.metadata.namespace.as_deref() == Some(namespace))
+            .filter(|e| e.involved_object.name.as_deref() == Some(object_name))
+            .take(limit as usize)
+            .cloned()
+            .collect())
+    }
+}
+
+/// Whether the object is in `namespace` and matches the `key=value` selector
+fn selects(metadata: &ObjectMeta, namespace: &str, labels: &str) -> bool {
+    let (label, value) = labels.split_once('=').unwrap_or((labels, ""));
+    metadata.namespace.as_deref() == Some(namespace)
+        && metadata
+            .labels
+            .as_ref()
+            .and_then(|labels| labels.get(label))
+            .is_some_and(|v| v == value)
+}
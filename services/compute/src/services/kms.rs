@@ -0,0 +1,278 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::json;
+use shared::utilities::{config::Config, errors::AppError};
+use tracing::warn;
+
+use crate::utilities::encryption::Keyring;
+
+/// Wraps and unwraps deployment data keys with a master key it never hands out
+#[async_trait]
+pub trait KmsProvider: Send + Sync {
+    /// Stored next to every wrapped key so a provider switch is detectable
+    fn name(&self) -> &'static str;
+
+    /// Master key new data keys are wrapped with
+    fn key_id(&self) -> &str;
+
+    async fn wrap_key(&self, data_key: &[u8]) -> Result<Vec<u8>, AppError>;
+
+    async fn unwrap_key(&self, wrapped_key: &[u8]) -> Result<Vec<u8>, AppError>;
+}
+
+/// Build the provider selected by `KMS_PROVIDER`
+pub fn build_kms(config: &Config, http_client: &Client) -> Result<Arc<dyn KmsProvider>, AppError> {
+    match config.kms_provider.as_str() {
+        "local" => Ok(Arc::new(LocalKms::from_config(config)?)),
+        "vault" => Ok(Arc::new(VaultTransitKms::from_config(config, http_client)?)),
+        other => Err(AppError::KmsError(format!(
+            "Unknown KMS provider '{}', expected 'local' or 'vault'",
+            other
+        ))),
+    }
+}
+
+// ==============================================
+// LOCAL (FILE-BASED) KMS
+// ==============================================
+
+/// Master keys read from a keyring file (`id:base64` per line)
+pub struct LocalKms {
+    keyring: Keyring,
+}
+
+impl LocalKms {
+    pub fn new(keyring: Keyring) -> Self {
+        Self { keyring }
+    }
+
+    pub fn from_config(config: &Config) -> Result<Self, AppError> {
+        if let Some(entries) = &config.kms_local_keyring {
+            let keyring = Keyring::parse(entries, config.kms_local_active_key_id.as_deref(), None)?;
+            return Ok(Self::new(keyring));
+        }
+
+        warn!("KMS_LOCAL_KEYRING is not set, wrapping data keys with K8S_ENCRYPTION_KEYS");
+        Keyring::from_config(config)?.map(Self::new).ok_or_else(|| {
+            AppError::EnvironmentVariableNotSetError("KMS_LOCAL_KEYRING".to_string())
+        })
+    }
+}
+
+#[async_trait]
+impl KmsProvider for LocalKms {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn key_id(&self) -> &str {
+        self.keyring.active_key_id()
+    }
+
+    async fn wrap_key(&self, data_key: &[u8]) -> Result<Vec<u8>, AppError> {
+        self.keyring.encrypt(data_key)
+    }
+
+    async fn unwrap_key(&self, wrapped_key: &[u8]) -> Result<Vec<u8>, AppError> {
+        self.keyring.decrypt(wrapped_key)
+    }
+}
+
+// ==============================================
+// HASHICORP VAULT TRANSIT
+// ==============================================
+
+#[derive(Deserialize)]
+struct TransitResponse {
+    data: TransitData,
+}
+
+#[derive(Deserialize)]
+struct TransitData {
+    ciphertext: Option<String>,
+    plaintext: Option<String>,
+}
+
+/// Wraps data keys with a Vault Transit key, the wrapped form is Vault's
+/// `vault:v<version>:...` ciphertext
+pub struct VaultTransitKms {
+    http_client: Client,
+    addr: String,
+    token: String,
+    mount: String,
+    key_id: String,
+}
+
+impl VaultTransitKms {
+    pub fn new(http_client: Client, addr: &str, token: &str, mount: &str, key_name: &str) -> Self {
+        Self {
+            http_client,
+            addr: addr.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+            mount: mount.trim_matches('/').to_string(),
+            key_id: key_name.to_string(),
+        }
+    }
+
+    pub fn from_config(config: &Config, http_client: &Client) -> Result<Self, AppError> {
+        let addr = config
+            .vault_addr
+            .as_deref()
+            .ok_or_else(|| AppError::EnvironmentVariableNotSetError("VAULT_ADDR".to_string()))?;
+        let token = config
+            .vault_token
+            .as_deref()
+            .ok_or_else(|| AppError::EnvironmentVariableNotSetError("VAULT_TOKEN".to_string()))?;
+
+        Ok(Self::new(
+            http_client.clone(),
+            addr,
+            token,
+            &config.vault_transit_mount,
+            &config.vault_transit_key,
+        ))
+    }
+
+    async fn call(
+        &self,
+        operation: &str,
+        body: serde_json::Value,
+    ) -> Result<TransitData, AppError> {
+        let url = format!(
+            "{}/v1/{}/{}/{}",
+            self.addr, self.mount, operation, self.key_id
+        );
+
+        let response = self
+            .http_client
+            .post(&url)
+            .header("X-Vault-Token", &self.token)
+            .json(&body)
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(AppError::KmsError(format!(
+                "Vault transit {} failed with {}: {}",
+                operation, status, body
+            )));
+        }
+
+        Ok(response.json::<TransitResponse>().await?.data)
+    }
+}
+
+#[async_trait]
+impl KmsProvider for VaultTransitKms {
+    fn name(&self) -> &'static str {
+        "vault"
+    }
+
+    fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    async fn wrap_key(&self, data_key: &[u8]) -> Result<Vec<u8>, AppError> {
+        let data = self
+            .call("encrypt", json!({ "plaintext": BASE64.encode(data_key) }))
+            .await?;
+
+        data.ciphertext
+            .map(String::into_bytes)
+            .ok_or_else(|| AppError::KmsError("Vault returned no ciphertext".to_string()))
+    }
+
+    async fn unwrap_key(&self, wrapped_key: &[u8]) -> Result<Vec<u8>, AppError> {
+        let ciphertext = std::str::from_utf8(wrapped_key)
+            .map_err(|_| AppError::KmsError("Wrapped key is not a Vault ciphertext".to_string()))?;
+
+        let plaintext = self
+            .call("decrypt", json!({ "ciphertext": ciphertext }))
+            .await?
+            .plaintext
+            .ok_or_else(|| AppError::KmsError("Vault returned no plaintext".to_string()))?;
+
+        BASE64
+            .decode(plaintext)
+            .map_err(|e| AppError::KmsError(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Json, Router,
+        extract::Path,
+        http::{HeaderMap, StatusCode},
+        routing::post,
+    };
+    use serde_json::Value;
+
+    use super::*;
+
+    /// Minimal Transit stand-in: "encrypts" by prefixing the base64 plaintext
+    fn transit_stub() -> Router {
+        async fn encrypt(
+            headers: HeaderMap,
+            Path((_, key)): Path<(String, String)>,
+            Json(body): Json<Value>,
+        ) -> Result<Json<Value>, StatusCode> {
+            if headers.get("X-Vault-Token").is_none_or(|t| t != "root") {
+                return Err(StatusCode::FORBIDDEN);
+            }
+            let plaintext = body["plaintext"].as_str().ok_or(StatusCode::BAD_REQUEST)?;
+            Ok(Json(
+                json!({ "data": { "ciphertext": format!("vault:v1:{key}:{plaintext}") } }),
+            ))
+        }
+
+        async fn decrypt(
+            Path((_, key)): Path<(String, String)>,
+            Json(body): Json<Value>,
+        ) -> Result<Json<Value>, StatusCode> {
+            let ciphertext = body["ciphertext"].as_str().ok_or(StatusCode::BAD_REQUEST)?;
+            let plaintext = ciphertext
+                .strip_prefix(&format!("vault:v1:{key}:"))
+                .ok_or(StatusCode::BAD_REQUEST)?;
+            Ok(Json(json!({ "data": { "plaintext": plaintext } })))
+        }
+
+        Router::new()
+            .route("/v1/{mount}/encrypt/{key}", post(encrypt))
+            .route("/v1/{mount}/decrypt/{key}", post(decrypt))
+    }
+
+    async fn serve_stub() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, transit_stub()).await });
+        format!("http://{addr}")
+    }
+
+    #[tokio::test]
+    async fn test_vault_transit_roundtrip() {
+        let addr = serve_stub().await;
+        let kms = VaultTransitKms::new(Client::new(), &addr, "root", "transit", "compute-secrets");
+
+        let wrapped = kms.wrap_key(b"data-key").await.unwrap();
+        assert!(wrapped.starts_with(b"vault:v1:compute-secrets:"));
+        assert_eq!(kms.unwrap_key(&wrapped).await.unwrap(), b"data-key");
+    }
+
+    #[tokio::test]
+    async fn test_vault_transit_surfaces_errors() {
+        let addr = serve_stub().await;
+        let kms = VaultTransitKms::new(Client::new(), &addr, "wrong", "transit", "compute-secrets");
+
+        assert!(matches!(
+            kms.wrap_key(b"data-key").await,
+            Err(AppError::KmsError(_))
+        ));
+    }
+}
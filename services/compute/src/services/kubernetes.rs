@@ -14,46 +14,66 @@ use k8s_openapi::api::networking::v1::{
 use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector;
 use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
-use kube::api::{DeleteParams, ObjectMeta, Patch, PatchParams, PostParams};
-use kube::{Api, Client};
+use kube::api::ObjectMeta;
 use shared::utilities::errors::AppError;
-use sqlx::PgPool;
+use sqlx::{PgPool, Postgres, Transaction};
+use tracing::warn;
 use uuid::Uuid;
 
-use crate::features::models::{Deployment, DeploymentStatus, ResourceSpec};
+use crate::features::models::{BillingAccount, Deployment, DeploymentStatus, ResourceSpec};
 use crate::features::repository::{
-    DeploymentEventRepository, DeploymentRepository, DeploymentSecretRepository,
+    DeploymentRepository, DeploymentSecretRepository, ProjectRepository,
 };
 use crate::features::schemas::{
-    CreateDeploymentRequest, DeploymentDetailResponse, DeploymentResponse,
+    CreateDeploymentRequest, CreateSecretRequest, DeploymentDetailResponse, DeploymentResponse,
+    DeploymentSecretResponse, DeploymentUpdate, ProjectUpdate,
 };
+use crate::services::billing::BillingClient;
+use crate::services::build_kubernetes::Kubernetes;
+use crate::services::cluster::{ClusterBackend, ClusterObject, ObjectKind, ObjectPatch};
+use crate::services::event_bus::EventBus;
+use crate::services::export::ExportService;
+use crate::services::quota::QuotaService;
+use crate::services::runtime_status::RuntimeStatusService;
 use crate::utilities::encryption::EncryptionService;
+use crate::utilities::naming;
+
+/// Pod template annotation bumped to force a rolling restart
+pub(crate) const RESTARTED_AT_ANNOTATION: &str = "kubectl.kubernetes.io/restartedAt";
 
 pub struct DeploymentService;
 
 impl DeploymentService {
     /// Create a new deployment with Kubernetes resources
+    #[allow(clippy::too_many_arguments)]
     pub async fn create(
         pool: &PgPool,
-        k8s_client: &Client,
-        encryption_key: &str,
+        kubernetes: &Kubernetes,
+        billing: &BillingClient,
+        events: &EventBus,
+        encryption_service: &EncryptionService,
         user_id: Uuid,
         project_id: Uuid,
         base_domain: &str,
         req: CreateDeploymentRequest,
     ) -> Result<DeploymentResponse, AppError> {
-        let encryption_service = EncryptionService::new(encryption_key)?;
+        let placement = kubernetes.placement(req.region.as_deref(), req.node_pool.as_deref())?;
+
+        // Deployments belong to the project's owner and are billed to its
+        // account, whichever member creates them
+        let project = ProjectRepository::get_one_by_id(pool, project_id, user_id).await?;
+        let owner_id = project.owner_id;
+        let account = BillingAccount::of(&project);
 
         // Generate cluster resource names
         let cluster_namespace = "default"; // Or use user-specific namespace
-        let cluster_deployment_name = format!("{}-{}", project_id, req.name)
-            .to_lowercase()
-            .replace("_", "-");
+        let cluster_deployment_name = naming::cluster_deployment_name(project_id, &req.name)?;
 
         // Determine subdomain
-        let subdomain = req.subdomain.unwrap_or_else(|| {
-            format!("{}-{}", req.name, &user_id.to_string()[..8]).to_lowercase()
-        });
+        let subdomain = match req.subdomain {
+            Some(subdomain) => subdomain,
+            None => naming::generated_subdomain(&owner_id.to_string(), &req.name)?,
+        };
 
         let external_url = format!("{}.{}", subdomain, base_domain);
 
@@ -61,7 +81,10 @@ impl DeploymentService {
         let env_vars_json = serde_json::to_value(req.env_vars.clone().unwrap_or_default())?;
 
         // Prepare resources JSON
-        let resources_json = serde_json::to_value(req.resources.unwrap_or_default())?;
+        let resources = QuotaService::resources(req.size, req.resources)?;
+        let resources_json = serde_json::to_value(&resources)?;
+        let demand = QuotaService::footprint(req.replicas, &resources, false);
+        billing.check(pool, account, None, demand).await?;
 
         // Prepare labels
         let labels_json = req.labels.map(|l| serde_json::to_value(l).unwrap());
@@ -69,10 +92,12 @@ impl DeploymentService {
         // Start transaction
         let mut tx = pool.begin().await?;
 
+        QuotaService::check(&mut tx, account, None, demand).await?;
+
         // Create deployment record
         let deployment = DeploymentRepository::create(
             &mut tx,
-            user_id,
+            owner_id,
             project_id,
             &req.name,
             &req.image,
@@ -82,14 +107,19 @@ impl DeploymentService {
             labels_json,
             cluster_namespace,
             &cluster_deployment_name,
+            &placement.region,
+            placement.node_pool.as_deref(),
+            placement.node_selector,
+            placement.tolerations,
         )
         .await?;
 
         // Store encrypted secrets
         if let Some(secrets) = &req.secrets {
+            let data_key = encryption_service.data_key(&mut tx, deployment.id).await?;
             for (key, value) in secrets {
-                let encrypted_value = encryption_service.encrypt(&value)?;
-                DeploymentSecretRepository::create(&mut tx, deployment.id, &key, encrypted_value)
+                let encrypted_value = data_key.encrypt(value)?;
+                DeploymentSecretRepository::create(&mut tx, deployment.id, key, encrypted_value)
                     .await?;
             }
         }
@@ -98,37 +128,65 @@ impl DeploymentService {
         tx.commit().await?;
 
         // Create Kubernetes resources
-        Self::create_k8s_resources(
-            k8s_client,
-            &deployment,
-            req.port,
-            &external_url,
-            req.env_vars.unwrap_or_default(),
-            req.secrets.unwrap_or_default(),
-        )
-        .await?;
+        let created = async {
+            Self::create_k8s_resources(
+                kubernetes.backend(&deployment)?,
+                &deployment,
+                req.port,
+                &external_url,
+                req.env_vars.unwrap_or_default(),
+                req.secrets.unwrap_or_default(),
+            )
+            .await
+        };
+        if let Err(e) = created.await {
+            DeploymentRepository::update_status(pool, deployment.id, DeploymentStatus::Failed)
+                .await?;
+            let deployment = Deployment {
+                status: DeploymentStatus::Failed,
+                ..deployment
+            };
+            events
+                .record(
+                    pool,
+                    &deployment,
+                    "deployment_failed",
+                    Some(&format!("Creating Kubernetes resources failed: {}", e)),
+                )
+                .await?;
+            events.publish_status(&deployment).await;
+            return Err(e);
+        }
 
         // Log event
-        DeploymentEventRepository::create(
-            pool,
-            deployment.id,
-            "deployment_created",
-            Some("Deployment created successfully"),
-        )
-        .await?;
+        events
+            .record(
+                pool,
+                &deployment,
+                "deployment_created",
+                Some("Deployment created successfully"),
+            )
+            .await?;
 
         // Update status to running
         DeploymentRepository::update_status(pool, deployment.id, DeploymentStatus::Running).await?;
+        let deployment = Deployment {
+            status: DeploymentStatus::Running,
+            ..deployment
+        };
+        events.publish_status(&deployment).await;
 
         Ok(DeploymentResponse {
             id: deployment.id,
             project_id: deployment.project_id,
             name: deployment.name,
             image: deployment.image,
-            status: DeploymentStatus::Running,
+            status: deployment.status,
             replicas: deployment.replicas,
-            resources: serde_json::from_value(resources_json)?,
+            resources,
             external_url: Some(external_url),
+            region: deployment.region,
+            node_pool: deployment.node_pool,
             created_at: deployment.created_at,
             updated_at: deployment.updated_at,
         })
@@ -136,83 +194,70 @@ impl DeploymentService {
 
     /// Create Kubernetes Deployment, Service, Secret, and Ingress
     async fn create_k8s_resources(
-        client: &Client,
+        cluster: &dyn ClusterBackend,
         deployment: &Deployment,
         container_port: i32,
         external_url: &str,
         env_vars: HashMap<String, String>,
         secrets: HashMap<String, String>,
     ) -> Result<(), AppError> {
-        let namespace = &deployment.cluster_namespace;
-        let name = &deployment.cluster_deployment_name;
+        // 1. Create Kubernetes Secret if there are secrets
+        if !secrets.is_empty() {
+            let secret = Self::build_secret(deployment, &secrets);
+            cluster.create(&ClusterObject::Secret(secret)).await?;
+        }
 
-        // Parse resources
-        let resources: ResourceSpec = serde_json::from_value(deployment.resources.clone())?;
+        // 2. Create Kubernetes Deployment
+        let k8s_deployment =
+            Self::build_deployment(deployment, container_port, &env_vars, secrets.keys())?;
+        cluster
+            .create(&ClusterObject::Deployment(k8s_deployment))
+            .await?;
 
-        // Create labels
-        let mut labels = BTreeMap::new();
-        labels.insert("app".to_string(), name.clone());
-        labels.insert("deployment-id".to_string(), deployment.id.to_string());
+        // 3. Create Kubernetes Service
+        let service = Self::build_service(deployment, container_port);
+        cluster.create(&ClusterObject::Service(service)).await?;
 
-        // 1. Create Kubernetes Secret if there are secrets
-        if !secrets.is_empty() {
-            let secret_name = format!("{}-secrets", name);
-            let mut secret_data = BTreeMap::new();
+        // 4. Create Ingress with Traefik annotations
+        let ingress = Self::build_ingress(deployment, external_url);
+        cluster.create(&ClusterObject::Ingress(ingress)).await?;
 
-            for (key, value) in &secrets {
-                secret_data.insert(key.clone(), ByteString(value.clone().into_bytes()));
-            }
+        Ok(())
+    }
 
-            let secret = K8sSecret {
-                metadata: ObjectMeta {
-                    name: Some(secret_name.clone()),
-                    namespace: Some(namespace.clone()),
-                    labels: Some(labels.clone()),
-                    ..Default::default()
-                },
-                data: Some(secret_data),
-                ..Default::default()
-            };
+    /// Build the Kubernetes Deployment running the deployment's container.
+    /// Env vars are sorted by name so the same input always renders the same pod spec.
+    pub(crate) fn build_deployment<'a>(
+        deployment: &Deployment,
+        container_port: i32,
+        env_vars: &HashMap<String, String>,
+        secret_keys: impl IntoIterator<Item = &'a String>,
+    ) -> Result<K8sDeployment, AppError> {
+        let namespace = &deployment.cluster_namespace;
+        let name = &deployment.cluster_deployment_name;
 
-            let secrets_api: Api<K8sSecret> = Api::namespaced(client.clone(), namespace);
-            secrets_api
-                .create(&PostParams::default(), &secret)
-                .await
-                .map_err(|e| AppError::InternalError(format!("Failed to create secret: {}", e)))?;
-        }
+        // Parse resources
+        let resources: ResourceSpec = serde_json::from_value(deployment.resources.clone())?;
 
-        // 2. Build container environment variables
-        let mut container_env = vec![];
+        // Create labels
+        let labels = Self::resource_labels(deployment);
 
         // Regular env vars
-        for (key, value) in env_vars {
-            container_env.push(EnvVar {
-                name: key,
-                value: Some(value),
+        let mut container_env: Vec<EnvVar> = env_vars
+            .iter()
+            .map(|(key, value)| EnvVar {
+                name: key.clone(),
+                value: Some(value.clone()),
                 ..Default::default()
-            });
-        }
+            })
+            .collect();
+        container_env.sort_by(|a, b| a.name.cmp(&b.name));
 
         // Secret env vars
-        if !secrets.is_empty() {
-            let secret_name = format!("{}-secrets", name);
-            for key in secrets.keys() {
-                container_env.push(EnvVar {
-                    name: key.clone(),
-                    value_from: Some(EnvVarSource {
-                        secret_key_ref: Some(SecretKeySelector {
-                            name: secret_name.clone(),
-                            key: key.clone(),
-                            ..Default::default()
-                        }),
-                        ..Default::default()
-                    }),
-                    ..Default::default()
-                });
-            }
-        }
+        let mut secret_env = Self::secret_env_vars(name, secret_keys);
+        secret_env.sort_by(|a, b| a.name.cmp(&b.name));
+        container_env.extend(secret_env);
 
-        // 3. Create Kubernetes Deployment
         let mut resource_requirements = BTreeMap::new();
         resource_requirements.insert(
             "cpu".to_string(),
@@ -233,7 +278,7 @@ impl DeploymentService {
             Quantity(format!("{}Mi", resources.memory_limit_mb)),
         );
 
-        let k8s_deployment = K8sDeployment {
+        Ok(K8sDeployment {
             metadata: ObjectMeta {
                 name: Some(name.clone()),
                 namespace: Some(namespace.clone()),
@@ -271,32 +316,38 @@ impl DeploymentService {
                             }),
                             ..Default::default()
                         }],
+                        node_selector: deployment
+                            .node_selector
+                            .clone()
+                            .map(serde_json::from_value)
+                            .transpose()?,
+                        tolerations: deployment
+                            .tolerations
+                            .clone()
+                            .map(serde_json::from_value)
+                            .transpose()?,
                         ..Default::default()
                     }),
                 },
                 ..Default::default()
             }),
             ..Default::default()
-        };
+        })
+    }
 
-        let deployments_api: Api<K8sDeployment> = Api::namespaced(client.clone(), namespace);
-        deployments_api
-            .create(&PostParams::default(), &k8s_deployment)
-            .await
-            .map_err(|e| {
-                AppError::InternalError(format!("Failed to create k8s deployment: {}", e))
-            })?;
+    /// Build the ClusterIP Service exposing the container on port 80
+    pub(crate) fn build_service(deployment: &Deployment, container_port: i32) -> Service {
+        let labels = Self::resource_labels(deployment);
 
-        // 4. Create Kubernetes Service
-        let service = Service {
+        Service {
             metadata: ObjectMeta {
-                name: Some(name.clone()),
-                namespace: Some(namespace.clone()),
+                name: Some(deployment.cluster_deployment_name.clone()),
+                namespace: Some(deployment.cluster_namespace.clone()),
                 labels: Some(labels.clone()),
                 ..Default::default()
             },
             spec: Some(ServiceSpec {
-                selector: Some(labels.clone()),
+                selector: Some(labels),
                 ports: Some(vec![ServicePort {
                     port: 80,
                     target_port: Some(IntOrString::Int(container_port)),
@@ -306,15 +357,13 @@ impl DeploymentService {
                 ..Default::default()
             }),
             ..Default::default()
-        };
+        }
+    }
 
-        let services_api: Api<Service> = Api::namespaced(client.clone(), namespace);
-        services_api
-            .create(&PostParams::default(), &service)
-            .await
-            .map_err(|e| AppError::InternalError(format!("Failed to create service: {}", e)))?;
+    /// Build the Traefik Ingress routing `external_url` to the Service
+    pub(crate) fn build_ingress(deployment: &Deployment, external_url: &str) -> Ingress {
+        let name = &deployment.cluster_deployment_name;
 
-        // 5. Create Ingress with Traefik annotations
         let mut annotations = BTreeMap::new();
         annotations.insert(
             "kubernetes.io/ingress.class".to_string(),
@@ -329,11 +378,11 @@ impl DeploymentService {
             "letsencrypt-prod".to_string(), // Assuming cert-manager is installed
         );
 
-        let ingress = Ingress {
+        Ingress {
             metadata: ObjectMeta {
                 name: Some(name.clone()),
-                namespace: Some(namespace.clone()),
-                labels: Some(labels.clone()),
+                namespace: Some(deployment.cluster_namespace.clone()),
+                labels: Some(Self::resource_labels(deployment)),
                 annotations: Some(annotations),
                 ..Default::default()
             },
@@ -364,58 +413,440 @@ impl DeploymentService {
                 ..Default::default()
             }),
             ..Default::default()
-        };
+        }
+    }
 
-        let ingress_api: Api<Ingress> = Api::namespaced(client.clone(), namespace);
-        ingress_api
-            .create(&PostParams::default(), &ingress)
-            .await
-            .map_err(|e| AppError::InternalError(format!("Failed to create ingress: {}", e)))?;
+    /// Labels shared by every Kubernetes object belonging to a deployment
+    fn resource_labels(deployment: &Deployment) -> BTreeMap<String, String> {
+        let mut labels = BTreeMap::new();
+        labels.insert(
+            "app".to_string(),
+            deployment.cluster_deployment_name.clone(),
+        );
+        labels.insert("deployment-id".to_string(), deployment.id.to_string());
+        labels
+    }
 
-        Ok(())
+    /// Name of the Kubernetes Secret holding a deployment's secret env vars
+    fn secret_name(cluster_deployment_name: &str) -> String {
+        format!("{}-secrets", cluster_deployment_name)
+    }
+
+    /// Build the `<name>-secrets` Secret from plaintext values
+    pub(crate) fn build_secret(
+        deployment: &Deployment,
+        secrets: &HashMap<String, String>,
+    ) -> K8sSecret {
+        let secret_data: BTreeMap<String, ByteString> = secrets
+            .iter()
+            .map(|(key, value)| (key.clone(), ByteString(value.clone().into_bytes())))
+            .collect();
+
+        K8sSecret {
+            metadata: ObjectMeta {
+                name: Some(Self::secret_name(&deployment.cluster_deployment_name)),
+                namespace: Some(deployment.cluster_namespace.clone()),
+                labels: Some(Self::resource_labels(deployment)),
+                ..Default::default()
+            },
+            data: Some(secret_data),
+            ..Default::default()
+        }
+    }
+
+    /// Env vars that reference keys of the `<name>-secrets` Secret
+    fn secret_env_vars<'a>(
+        cluster_deployment_name: &str,
+        keys: impl IntoIterator<Item = &'a String>,
+    ) -> Vec<EnvVar> {
+        let secret_name = Self::secret_name(cluster_deployment_name);
+        keys.into_iter()
+            .map(|key| EnvVar {
+                name: key.clone(),
+                value_from: Some(EnvVarSource {
+                    secret_key_ref: Some(SecretKeySelector {
+                        name: secret_name.clone(),
+                        key: key.clone(),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .collect()
     }
 
     /// Scale deployment
     pub async fn scale(
         pool: &PgPool,
-        k8s_client: &Client,
+        kubernetes: &Kubernetes,
+        billing: &BillingClient,
+        events: &EventBus,
         deployment_id: Uuid,
         user_id: Uuid,
         new_replicas: i32,
     ) -> Result<DeploymentResponse, AppError> {
+        let current = DeploymentRepository::get_by_id(pool, deployment_id, user_id).await?;
+        let resources: ResourceSpec = serde_json::from_value(current.resources.clone())?;
+        let demand = QuotaService::footprint(
+            new_replicas,
+            &resources,
+            current.status == DeploymentStatus::Paused,
+        );
+        let account = ProjectRepository::get_billing_account(pool, current.project_id).await?;
+        billing.check(pool, account, Some(&current), demand).await?;
+
+        let mut tx = pool.begin().await?;
+        QuotaService::check(&mut tx, account, Some(&current), demand).await?;
+
         // Update database
         let deployment =
-            DeploymentRepository::update_replicas(pool, deployment_id, user_id, new_replicas)
+            DeploymentRepository::update_replicas(&mut *tx, deployment_id, user_id, new_replicas)
                 .await?;
 
-        // Update Kubernetes deployment
-        let deployments_api: Api<K8sDeployment> =
-            Api::namespaced(k8s_client.clone(), &deployment.cluster_namespace);
+        // A paused deployment keeps running zero pods, the new count applies on resume
+        if deployment.status != DeploymentStatus::Paused {
+            Self::patch_replicas(kubernetes.backend(&deployment)?, &deployment, new_replicas)
+                .await?;
+        }
+        tx.commit().await?;
+
+        // Log event
+        events
+            .record(
+                pool,
+                &deployment,
+                "deployment_scaled",
+                Some(&format!("Scaled to {} replicas", new_replicas)),
+            )
+            .await?;
+        events.publish_status(&deployment).await;
+
+        Self::deployment_response(deployment)
+    }
+
+    /// Roll all pods of a deployment without changing its spec
+    pub async fn restart(
+        pool: &PgPool,
+        kubernetes: &Kubernetes,
+        events: &EventBus,
+        deployment_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<DeploymentResponse, AppError> {
+        let deployment = DeploymentRepository::get_by_id(pool, deployment_id, user_id).await?;
+
+        Self::restart_deployment(pool, kubernetes, events, deployment).await
+    }
+
+    /// `restart` for callers that already hold the row and checked access
+    /// their own way, such as deploy hooks that outlive their creator's access
+    pub async fn restart_deployment(
+        pool: &PgPool,
+        kubernetes: &Kubernetes,
+        events: &EventBus,
+        deployment: Deployment,
+    ) -> Result<DeploymentResponse, AppError> {
+        if deployment.status == DeploymentStatus::Paused {
+            return Err(AppError::ConflictError(
+                "Paused deployments have no pods to restart".to_string(),
+            ));
+        }
 
         let patch = serde_json::json!({
             "spec": {
-                "replicas": new_replicas
+                "template": {
+                    "metadata": {
+                        "annotations": {
+                            RESTARTED_AT_ANNOTATION: chrono::Utc::now().to_rfc3339()
+                        }
+                    }
+                }
             }
         });
 
-        deployments_api
+        kubernetes
+            .backend(&deployment)?
             .patch(
+                ObjectKind::Deployment,
+                &deployment.cluster_namespace,
                 &deployment.cluster_deployment_name,
-                &PatchParams::default(),
-                &Patch::Strategic(patch),
+                &ObjectPatch::Strategic(patch),
             )
-            .await
-            .map_err(|e| AppError::InternalError(format!("Failed to scale deployment: {}", e)))?;
+            .await?;
 
-        // Log event
-        DeploymentEventRepository::create(
-            pool,
+        events
+            .record(
+                pool,
+                &deployment,
+                "deployment_restarted",
+                Some("Rolling restart requested"),
+            )
+            .await?;
+
+        Self::deployment_response(deployment)
+    }
+
+    /// Scale to zero pods. `replicas` keeps the count to resume to, and only
+    /// running deployments are billed.
+    pub async fn pause(
+        pool: &PgPool,
+        kubernetes: &Kubernetes,
+        events: &EventBus,
+        deployment_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<DeploymentResponse, AppError> {
+        let mut deployment = DeploymentRepository::get_by_id(pool, deployment_id, user_id).await?;
+
+        if deployment.status == DeploymentStatus::Paused {
+            return Err(AppError::ConflictError(
+                "Deployment is already paused".to_string(),
+            ));
+        }
+
+        let mut tx = pool.begin().await?;
+        DeploymentRepository::update_status(&mut *tx, deployment.id, DeploymentStatus::Paused)
+            .await?;
+        Self::patch_replicas(kubernetes.backend(&deployment)?, &deployment, 0).await?;
+        tx.commit().await?;
+        deployment.status = DeploymentStatus::Paused;
+
+        events
+            .record(
+                pool,
+                &deployment,
+                "deployment_paused",
+                Some(&format!(
+                    "Paused, {} replicas will be restored on resume",
+                    deployment.replicas
+                )),
+            )
+            .await?;
+        events.publish_status(&deployment).await;
+
+        Self::deployment_response(deployment)
+    }
+
+    /// Restore the replica count a deployment had when it was paused
+    pub async fn resume(
+        pool: &PgPool,
+        kubernetes: &Kubernetes,
+        billing: &BillingClient,
+        events: &EventBus,
+        deployment_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<DeploymentResponse, AppError> {
+        let mut deployment = DeploymentRepository::get_by_id(pool, deployment_id, user_id).await?;
+
+        if deployment.status != DeploymentStatus::Paused {
+            return Err(AppError::ConflictError(
+                "Deployment is not paused".to_string(),
+            ));
+        }
+
+        let resources: ResourceSpec = serde_json::from_value(deployment.resources.clone())?;
+        let demand = QuotaService::footprint(deployment.replicas, &resources, false);
+        let account = ProjectRepository::get_billing_account(pool, deployment.project_id).await?;
+        billing
+            .check(pool, account, Some(&deployment), demand)
+            .await?;
+
+        let mut tx = pool.begin().await?;
+        QuotaService::check(&mut tx, account, Some(&deployment), demand).await?;
+        DeploymentRepository::update_status(&mut *tx, deployment.id, DeploymentStatus::Running)
+            .await?;
+        Self::patch_replicas(
+            kubernetes.backend(&deployment)?,
+            &deployment,
+            deployment.replicas,
+        )
+        .await?;
+        tx.commit().await?;
+        deployment.status = DeploymentStatus::Running;
+
+        events
+            .record(
+                pool,
+                &deployment,
+                "deployment_resumed",
+                Some(&format!("Resumed with {} replicas", deployment.replicas)),
+            )
+            .await?;
+        events.publish_status(&deployment).await;
+
+        Self::deployment_response(deployment)
+    }
+
+    /// Bring an existing deployment to `update`, re-rendering its Deployment,
+    /// Service and Ingress with the same builders `create` uses
+    pub async fn update(
+        pool: &PgPool,
+        kubernetes: &Kubernetes,
+        billing: &BillingClient,
+        events: &EventBus,
+        deployment: &Deployment,
+        update: &DeploymentUpdate,
+    ) -> Result<DeploymentResponse, AppError> {
+        QuotaService::validate(&update.resources)?;
+        let demand = QuotaService::footprint(
+            update.replicas,
+            &update.resources,
+            deployment.status == DeploymentStatus::Paused,
+        );
+        let account = ProjectRepository::get_billing_account(pool, deployment.project_id).await?;
+        billing
+            .check(pool, account, Some(deployment), demand)
+            .await?;
+
+        let mut tx = pool.begin().await?;
+        QuotaService::check(&mut tx, account, Some(deployment), demand).await?;
+
+        let deployment = DeploymentRepository::update_spec(
+            &mut *tx,
             deployment.id,
-            "deployment_scaled",
-            Some(&format!("Scaled to {} replicas", new_replicas)),
+            &update.image,
+            serde_json::to_value(&update.env_vars)?,
+            update.replicas,
+            serde_json::to_value(&update.resources)?,
         )
         .await?;
+        tx.commit().await?;
+
+        let secret_keys: Vec<String> =
+            DeploymentSecretRepository::get_all_by_deployment(pool, deployment.id)
+                .await?
+                .into_iter()
+                .map(|s| s.key)
+                .collect();
+
+        Self::update_k8s_resources(
+            kubernetes.backend(&deployment)?,
+            &deployment,
+            update,
+            &secret_keys,
+        )
+        .await?;
+
+        events
+            .record(
+                pool,
+                &deployment,
+                "deployment_updated",
+                Some("Deployment spec updated"),
+            )
+            .await?;
+        events.publish_status(&deployment).await;
+
+        Self::deployment_response(deployment)
+    }
+
+    /// Roll the deployment onto another image, keeping the rest of its spec
+    pub async fn update_image(
+        pool: &PgPool,
+        kubernetes: &Kubernetes,
+        billing: &BillingClient,
+        events: &EventBus,
+        deployment: &Deployment,
+        image: String,
+    ) -> Result<DeploymentResponse, AppError> {
+        let live = ExportService::live_spec(kubernetes, deployment)
+            .await?
+            .ok_or_else(|| {
+                AppError::ConflictError(format!(
+                    "Deployment {} has no Kubernetes objects, delete it and create it again",
+                    deployment.name
+                ))
+            })?;
+
+        let env_vars: HashMap<String, String> =
+            serde_json::from_value(deployment.env_vars.clone())?;
+        let update = DeploymentUpdate {
+            image,
+            replicas: deployment.replicas,
+            port: live.container_port,
+            env_vars,
+            resources: serde_json::from_value(deployment.resources.clone())?,
+            external_url: live.external_url,
+        };
+
+        Self::update(pool, kubernetes, billing, events, deployment, &update).await
+    }
+
+    /// Merge-patch the live objects with freshly built ones. Lists such as
+    /// `env` and `ports` are replaced whole, so removed entries disappear.
+    async fn update_k8s_resources(
+        cluster: &dyn ClusterBackend,
+        deployment: &Deployment,
+        update: &DeploymentUpdate,
+        secret_keys: &[String],
+    ) -> Result<(), AppError> {
+        let namespace = &deployment.cluster_namespace;
+        let name = &deployment.cluster_deployment_name;
+
+        let mut k8s_deployment =
+            Self::build_deployment(deployment, update.port, &update.env_vars, secret_keys)?;
+        // A paused deployment keeps running zero pods
+        if deployment.status == DeploymentStatus::Paused
+            && let Some(spec) = k8s_deployment.spec.as_mut()
+        {
+            spec.replicas = Some(0);
+        }
+
+        cluster
+            .patch(
+                ObjectKind::Deployment,
+                namespace,
+                name,
+                &ObjectPatch::Merge(serde_json::json!({ "spec": k8s_deployment.spec })),
+            )
+            .await?;
+
+        let service = Self::build_service(deployment, update.port);
+        cluster
+            .patch(
+                ObjectKind::Service,
+                namespace,
+                name,
+                &ObjectPatch::Merge(serde_json::json!({ "spec": service.spec })),
+            )
+            .await?;
+
+        if let Some(external_url) = &update.external_url {
+            let ingress = Self::build_ingress(deployment, external_url);
+            cluster
+                .patch(
+                    ObjectKind::Ingress,
+                    namespace,
+                    name,
+                    &ObjectPatch::Merge(serde_json::json!({ "spec": ingress.spec })),
+                )
+                .await?;
+        }
 
+        Ok(())
+    }
+
+    async fn patch_replicas(
+        cluster: &dyn ClusterBackend,
+        deployment: &Deployment,
+        replicas: i32,
+    ) -> Result<(), AppError> {
+        let patch = serde_json::json!({
+            "spec": {
+                "replicas": replicas
+            }
+        });
+
+        cluster
+            .patch(
+                ObjectKind::Deployment,
+                &deployment.cluster_namespace,
+                &deployment.cluster_deployment_name,
+                &ObjectPatch::Strategic(patch),
+            )
+            .await
+    }
+
+    fn deployment_response(deployment: Deployment) -> Result<DeploymentResponse, AppError> {
         let resources: ResourceSpec = serde_json::from_value(deployment.resources.clone())?;
 
         Ok(DeploymentResponse {
@@ -427,6 +858,8 @@ impl DeploymentService {
             replicas: deployment.replicas,
             resources,
             external_url: None, // You'd need to query this from Ingress
+            region: deployment.region,
+            node_pool: deployment.node_pool,
             created_at: deployment.created_at,
             updated_at: deployment.updated_at,
         })
@@ -435,38 +868,63 @@ impl DeploymentService {
     /// Delete deployment and cleanup Kubernetes resources
     pub async fn delete(
         pool: &PgPool,
-        k8s_client: &Client,
+        kubernetes: &Kubernetes,
+        events: &EventBus,
         deployment_id: Uuid,
         user_id: Uuid,
     ) -> Result<(), AppError> {
-        // Get deployment info
         let deployment = DeploymentRepository::get_by_id(pool, deployment_id, user_id).await?;
 
+        Self::delete_deployment(pool, kubernetes, events, deployment).await
+    }
+
+    /// `delete` without an access check, for system jobs such as the preview
+    /// sweeper that act after the creator may have left the project
+    pub async fn delete_deployment(
+        pool: &PgPool,
+        kubernetes: &Kubernetes,
+        events: &EventBus,
+        deployment: Deployment,
+    ) -> Result<(), AppError> {
         let namespace = &deployment.cluster_namespace;
         let name = &deployment.cluster_deployment_name;
 
         // Delete Kubernetes resources
-        let delete_params = DeleteParams::default();
-
-        // Delete Ingress
-        let ingress_api: Api<Ingress> = Api::namespaced(k8s_client.clone(), namespace);
-        let _ = ingress_api.delete(name, &delete_params).await;
+        let cluster = kubernetes.backend(&deployment)?;
+        let _ = cluster.delete(ObjectKind::Ingress, namespace, name).await;
+        let _ = cluster.delete(ObjectKind::Service, namespace, name).await;
+        let _ = cluster
+            .delete(ObjectKind::Deployment, namespace, name)
+            .await;
 
-        // Delete Service
-        let service_api: Api<Service> = Api::namespaced(k8s_client.clone(), namespace);
-        let _ = service_api.delete(name, &delete_params).await;
+        let secret_name = Self::secret_name(name);
+        let _ = cluster
+            .delete(ObjectKind::Secret, namespace, &secret_name)
+            .await;
 
-        // Delete Deployment
-        let deployment_api: Api<K8sDeployment> = Api::namespaced(k8s_client.clone(), namespace);
-        let _ = deployment_api.delete(name, &delete_params).await;
-
-        // Delete Secret
-        let secret_name = format!("{}-secrets", name);
-        let secret_api: Api<K8sSecret> = Api::namespaced(k8s_client.clone(), namespace);
-        let _ = secret_api.delete(&secret_name, &delete_params).await;
+        // Recorded first so webhooks hear of it, the row goes with the deployment
+        let deleted = Deployment {
+            status: DeploymentStatus::Terminated,
+            ..deployment.clone()
+        };
+        events
+            .record(
+                pool,
+                &deleted,
+                "deployment_deleted",
+                Some("Deployment deleted"),
+            )
+            .await?;
 
         // Delete from database (cascades to secrets and events)
-        DeploymentRepository::delete(pool, deployment_id, user_id).await?;
+        DeploymentRepository::delete(pool, deployment.id).await?;
+
+        events
+            .publish(&ProjectUpdate::Deleted {
+                project_id: deployment.project_id,
+                deployment_id: deployment.id,
+            })
+            .await;
 
         Ok(())
     }
@@ -474,11 +932,29 @@ impl DeploymentService {
     /// Get deployment details with decrypted secret keys (but not values)
     pub async fn get_detail(
         pool: &PgPool,
+        kubernetes: &Kubernetes,
+        runtime_status: &RuntimeStatusService,
         deployment_id: Uuid,
         user_id: Uuid,
     ) -> Result<DeploymentDetailResponse, AppError> {
         let deployment = DeploymentRepository::get_by_id(pool, deployment_id, user_id).await?;
 
+        // The stored record is still useful while the cluster is unreachable
+        let runtime = async {
+            let cluster = kubernetes.backend(&deployment)?;
+            runtime_status.get(cluster, &deployment).await
+        };
+        let runtime = match runtime.await {
+            Ok(runtime) => Some(runtime),
+            Err(e) => {
+                warn!(
+                    "Failed to read runtime status of deployment {}: {}",
+                    deployment.id, e
+                );
+                None
+            }
+        };
+
         let secrets =
             DeploymentSecretRepository::get_all_by_deployment(pool, deployment_id).await?;
         let secret_keys: Vec<String> = secrets.into_iter().map(|s| s.key).collect();
@@ -498,15 +974,302 @@ impl DeploymentService {
             image: deployment.image,
             status: deployment.status,
             replicas: deployment.replicas,
-            ready_replicas: None, // Would need to query from K8s
+            ready_replicas: runtime.as_ref().map(|r| r.ready_replicas),
+            available_replicas: runtime.as_ref().map(|r| r.available_replicas),
+            updated_replicas: runtime.as_ref().map(|r| r.updated_replicas),
             resources,
             env_vars,
             secret_keys,
             labels,
-            external_url: None, // Would need to query from Ingress
+            external_url: runtime.as_ref().and_then(|r| r.external_url.clone()),
+            pods: runtime.map(|r| r.pods).unwrap_or_default(),
+            region: deployment.region,
+            node_pool: deployment.node_pool,
             cluster_namespace: deployment.cluster_namespace,
             created_at: deployment.created_at,
             updated_at: deployment.updated_at,
         })
     }
+
+    /// List secret keys of a deployment with their timestamps (never the values)
+    pub async fn list_secrets(
+        pool: &PgPool,
+        deployment_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<Vec<DeploymentSecretResponse>, AppError> {
+        let deployment = DeploymentRepository::get_by_id(pool, deployment_id, user_id).await?;
+
+        let secrets =
+            DeploymentSecretRepository::get_all_by_deployment(pool, deployment.id).await?;
+
+        Ok(secrets
+            .into_iter()
+            .map(|s| DeploymentSecretResponse {
+                key: s.key,
+                created_at: s.created_at,
+                updated_at: s.updated_at,
+            })
+            .collect())
+    }
+
+    /// Add a new secret key, sync the Kubernetes Secret and restart pods
+    pub async fn create_secret(
+        pool: &PgPool,
+        kubernetes: &Kubernetes,
+        events: &EventBus,
+        encryption_service: &EncryptionService,
+        deployment_id: Uuid,
+        user_id: Uuid,
+        req: CreateSecretRequest,
+    ) -> Result<DeploymentSecretResponse, AppError> {
+        let deployment = DeploymentRepository::get_by_id(pool, deployment_id, user_id).await?;
+        let mut tx = pool.begin().await?;
+
+        let data_key = encryption_service.data_key(&mut tx, deployment.id).await?;
+        let encrypted_value = data_key.encrypt(&req.value)?;
+        let secret =
+            DeploymentSecretRepository::create(&mut tx, deployment.id, &req.key, encrypted_value)
+                .await
+                .map_err(|e| match e {
+                    sqlx::Error::Database(db) if db.is_unique_violation() => {
+                        AppError::ConflictError(format!("Secret '{}' already exists", req.key))
+                    }
+                    e => AppError::from(e),
+                })?;
+
+        Self::apply_secrets(&mut tx, kubernetes, encryption_service, &deployment).await?;
+
+        tx.commit().await?;
+
+        events
+            .record(
+                pool,
+                &deployment,
+                "secret_created",
+                Some(&format!("Secret '{}' created", secret.key)),
+            )
+            .await?;
+
+        Ok(DeploymentSecretResponse {
+            key: secret.key,
+            created_at: secret.created_at,
+            updated_at: secret.updated_at,
+        })
+    }
+
+    /// Replace the value of an existing secret key, sync the Kubernetes Secret and restart pods
+    #[allow(clippy::too_many_arguments)]
+    pub async fn replace_secret(
+        pool: &PgPool,
+        kubernetes: &Kubernetes,
+        events: &EventBus,
+        encryption_service: &EncryptionService,
+        deployment_id: Uuid,
+        user_id: Uuid,
+        key: &str,
+        value: &str,
+    ) -> Result<DeploymentSecretResponse, AppError> {
+        let deployment = DeploymentRepository::get_by_id(pool, deployment_id, user_id).await?;
+        let mut tx = pool.begin().await?;
+
+        let data_key = encryption_service.data_key(&mut tx, deployment.id).await?;
+        let encrypted_value = data_key.encrypt(value)?;
+        let secret =
+            DeploymentSecretRepository::update_value(&mut tx, deployment.id, key, encrypted_value)
+                .await?
+                .ok_or_else(|| AppError::NotFoundError(format!("Secret '{}' not found", key)))?;
+
+        Self::apply_secrets(&mut tx, kubernetes, encryption_service, &deployment).await?;
+
+        tx.commit().await?;
+
+        events
+            .record(
+                pool,
+                &deployment,
+                "secret_updated",
+                Some(&format!("Secret '{}' updated", secret.key)),
+            )
+            .await?;
+
+        Ok(DeploymentSecretResponse {
+            key: secret.key,
+            created_at: secret.created_at,
+            updated_at: secret.updated_at,
+        })
+    }
+
+    /// Delete a secret key, sync the Kubernetes Secret and restart pods
+    pub async fn delete_secret(
+        pool: &PgPool,
+        kubernetes: &Kubernetes,
+        events: &EventBus,
+        encryption_service: &EncryptionService,
+        deployment_id: Uuid,
+        user_id: Uuid,
+        key: &str,
+    ) -> Result<(), AppError> {
+        let deployment = DeploymentRepository::get_by_id(pool, deployment_id, user_id).await?;
+        let mut tx = pool.begin().await?;
+
+        if !DeploymentSecretRepository::delete_by_key(&mut tx, deployment.id, key).await? {
+            return Err(AppError::NotFoundError(format!(
+                "Secret '{}' not found",
+                key
+            )));
+        }
+
+        Self::apply_secrets(&mut tx, kubernetes, encryption_service, &deployment).await?;
+
+        tx.commit().await?;
+
+        events
+            .record(
+                pool,
+                &deployment,
+                "secret_deleted",
+                Some(&format!("Secret '{}' deleted", key)),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Push the secrets as seen inside `tx` to the cluster.
+    ///
+    /// Runs before the transaction commits so a failed sync leaves the
+    /// database untouched.
+    async fn apply_secrets(
+        tx: &mut Transaction<'_, Postgres>,
+        kubernetes: &Kubernetes,
+        encryption_service: &EncryptionService,
+        deployment: &Deployment,
+    ) -> Result<(), AppError> {
+        let data_key = encryption_service.data_key(tx, deployment.id).await?;
+        let secrets = DeploymentSecretRepository::get_all_by_deployment(&mut **tx, deployment.id)
+            .await?
+            .into_iter()
+            .map(|s| Ok((s.key, data_key.decrypt(&s.value)?)))
+            .collect::<Result<HashMap<String, String>, AppError>>()?;
+
+        Self::sync_secrets(kubernetes.backend(deployment)?, deployment, &secrets).await
+    }
+
+    /// Rebuild the `<name>-secrets` Secret, point the container env at its
+    /// keys and roll the pods so they pick up the new values
+    async fn sync_secrets(
+        cluster: &dyn ClusterBackend,
+        deployment: &Deployment,
+        secrets: &HashMap<String, String>,
+    ) -> Result<(), AppError> {
+        let namespace = &deployment.cluster_namespace;
+        let name = &deployment.cluster_deployment_name;
+        let secret_name = Self::secret_name(name);
+
+        // 1. Secret object
+        let existing = cluster
+            .get(ObjectKind::Secret, namespace, &secret_name)
+            .await?;
+
+        match existing {
+            Some(_) if secrets.is_empty() => {
+                cluster
+                    .delete(ObjectKind::Secret, namespace, &secret_name)
+                    .await?;
+            }
+            Some(existing) => {
+                let mut secret = Self::build_secret(deployment, secrets);
+                secret.metadata.resource_version = existing.metadata().resource_version.clone();
+                cluster.replace(&ClusterObject::Secret(secret)).await?;
+            }
+            None if secrets.is_empty() => {}
+            None => {
+                let secret = Self::build_secret(deployment, secrets);
+                cluster.create(&ClusterObject::Secret(secret)).await?;
+            }
+        }
+
+        // 2. Container env + restart
+        let mut k8s_deployment = cluster
+            .get(ObjectKind::Deployment, namespace, name)
+            .await?
+            .and_then(ClusterObject::into_deployment)
+            .ok_or_else(|| {
+                AppError::InternalError(format!("Failed to get k8s deployment: {} not found", name))
+            })?;
+
+        if let Some(spec) = k8s_deployment.spec.as_mut() {
+            if let Some(container) = spec
+                .template
+                .spec
+                .as_mut()
+                .and_then(|pod_spec| pod_spec.containers.iter_mut().find(|c| c.name == "app"))
+            {
+                let references_secret = |env: &EnvVar| {
+                    env.value_from
+                        .as_ref()
+                        .and_then(|source| source.secret_key_ref.as_ref())
+                        .is_some_and(|selector| selector.name == secret_name)
+                };
+
+                let mut env: Vec<EnvVar> = container
+                    .env
+                    .take()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|e| !references_secret(e))
+                    .collect();
+
+                let mut keys: Vec<&String> = secrets.keys().collect();
+                keys.sort();
+                env.extend(Self::secret_env_vars(name, keys));
+
+                container.env = if env.is_empty() { None } else { Some(env) };
+            }
+
+            spec.template
+                .metadata
+                .get_or_insert_with(Default::default)
+                .annotations
+                .get_or_insert_with(Default::default)
+                .insert(
+                    RESTARTED_AT_ANNOTATION.to_string(),
+                    chrono::Utc::now().to_rfc3339(),
+                );
+        }
+
+        cluster
+            .replace(&ClusterObject::Deployment(k8s_deployment))
+            .await
+    }
+}
+
+/// Replace the tag of an image reference, dropping any digest
+pub fn with_tag(image: &str, tag: &str) -> String {
+    let image = image.split('@').next().unwrap_or(image);
+    // A colon before the last slash belongs to the registry port
+    let name_start = image.rfind('/').map_or(0, |i| i + 1);
+    let repository = match image[name_start..].find(':') {
+        Some(colon) => &image[..name_start + colon],
+        None => image,
+    };
+    format!("{}:{}", repository, tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_tag() {
+        assert_eq!(with_tag("nginx", "pr-42"), "nginx:pr-42");
+        assert_eq!(
+            with_tag("ghcr.io/acme/api:1.4", "pr-42"),
+            "ghcr.io/acme/api:pr-42"
+        );
+        assert_eq!(
+            with_tag("registry:5000/acme/api@sha256:abc", "pr-42"),
+            "registry:5000/acme/api:pr-42"
+        );
+    }
 }
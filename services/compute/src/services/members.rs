@@ -0,0 +1,282 @@
+use chrono::{Duration, Utc};
+use serde_json::json;
+use shared::schemas::Pagination;
+use shared::utilities::errors::AppError;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::features::models::{
+    AuditLogEntry, Project, ProjectInvitation, ProjectMember, ProjectRole,
+};
+use crate::features::repository::{
+    AuditLogRepository, ProjectInvitationRepository, ProjectMemberRepository, ProjectRepository,
+    UserRepository,
+};
+use crate::features::schemas::{InviteMemberRequest, ProjectInvitationResponse};
+use crate::utilities::tokens;
+
+const TOKEN_PREFIX: &str = "inv_";
+const TOKEN_LEN: usize = 32;
+const INVITATION_TTL_DAYS: i64 = 7;
+
+/// Who works on a project. Members join by accepting an emailed
+/// invitation, and every change to the member list lands in the audit log.
+pub struct ProjectMemberService;
+
+impl ProjectMemberService {
+    pub async fn list(pool: &PgPool, project_id: Uuid) -> Result<Vec<ProjectMember>, AppError> {
+        Ok(ProjectMemberRepository::get_all_by_project(pool, project_id).await?)
+    }
+
+    /// Invite `req.email`, returns the invitation and the token for its link.
+    /// Inviting the same email again replaces the pending invitation.
+    pub async fn invite(
+        pool: &PgPool,
+        project_id: Uuid,
+        actor_id: Uuid,
+        req: InviteMemberRequest,
+    ) -> Result<(ProjectInvitationResponse, String), AppError> {
+        if req.role == ProjectRole::Owner {
+            return Err(Self::owner_role());
+        }
+        if ProjectMemberRepository::exists_by_email(pool, project_id, &req.email).await? {
+            return Err(AppError::ConflictError(format!(
+                "{} is already a member of this project",
+                req.email
+            )));
+        }
+
+        let token = tokens::generate(TOKEN_PREFIX, TOKEN_LEN);
+        let invitation = ProjectInvitationRepository::upsert(
+            pool,
+            project_id,
+            &req.email,
+            req.role,
+            &tokens::hash(&token),
+            actor_id,
+            Utc::now() + Duration::days(INVITATION_TTL_DAYS),
+        )
+        .await?;
+
+        AuditLogRepository::create(
+            pool,
+            Some(project_id),
+            Some(actor_id),
+            None,
+            "member_invited",
+            json!({ "email": invitation.email, "role": invitation.role }),
+        )
+        .await?;
+
+        Ok((Self::invitation_response(invitation), token))
+    }
+
+    pub fn invitation_link(frontend_endpoint: &str, token: &str) -> String {
+        format!(
+            "{}/invitations/accept?token={}",
+            frontend_endpoint.trim_end_matches('/'),
+            token
+        )
+    }
+
+    pub async fn invitations(
+        pool: &PgPool,
+        project_id: Uuid,
+    ) -> Result<Vec<ProjectInvitationResponse>, AppError> {
+        let invitations = ProjectInvitationRepository::get_pending_by_project(pool, project_id)
+            .await?
+            .into_iter()
+            .map(Self::invitation_response)
+            .collect();
+
+        Ok(invitations)
+    }
+
+    pub async fn revoke_invitation(
+        pool: &PgPool,
+        project_id: Uuid,
+        invitation_id: Uuid,
+        actor_id: Uuid,
+    ) -> Result<(), AppError> {
+        let invitation = ProjectInvitationRepository::delete(pool, invitation_id, project_id)
+            .await
+            .map_err(|e| match e {
+                sqlx::Error::RowNotFound => {
+                    AppError::NotFoundError("Invitation not found".to_string())
+                }
+                e => AppError::from(e),
+            })?;
+
+        AuditLogRepository::create(
+            pool,
+            Some(project_id),
+            Some(actor_id),
+            None,
+            "invitation_revoked",
+            json!({ "email": invitation.email, "role": invitation.role }),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Join the project an invitation is for. It has to be accepted from the
+    /// account the invitation was sent to.
+    pub async fn accept(pool: &PgPool, user_id: Uuid, token: &str) -> Result<Project, AppError> {
+        let email = UserRepository::get_email(pool, user_id).await?;
+
+        let mut tx = pool.begin().await?;
+
+        let invitation =
+            ProjectInvitationRepository::find_pending_by_token_hash(&mut tx, &tokens::hash(token))
+                .await?
+                .ok_or_else(|| {
+                    AppError::NotFoundError("Invitation not found or expired".to_string())
+                })?;
+
+        if !invitation.email.eq_ignore_ascii_case(&email) {
+            return Err(AppError::ForbiddenError(
+                "This invitation was sent to another email address".to_string(),
+            ));
+        }
+
+        ProjectMemberRepository::create(&mut *tx, invitation.project_id, user_id, invitation.role)
+            .await
+            .map_err(|e| match e {
+                sqlx::Error::Database(db) if db.is_unique_violation() => {
+                    AppError::ConflictError("You are already a member of this project".to_string())
+                }
+                e => AppError::from(e),
+            })?;
+        ProjectInvitationRepository::mark_accepted(&mut tx, invitation.id).await?;
+
+        AuditLogRepository::create(
+            &mut *tx,
+            Some(invitation.project_id),
+            Some(user_id),
+            Some(user_id),
+            "member_joined",
+            json!({ "role": invitation.role, "invitedBy": invitation.invited_by }),
+        )
+        .await?;
+
+        tx.commit().await?;
+
+        ProjectRepository::find_by_id(pool, invitation.project_id)
+            .await?
+            .ok_or_else(|| AppError::NotFoundError("Project not found".to_string()))
+    }
+
+    pub async fn update_role(
+        pool: &PgPool,
+        project_id: Uuid,
+        actor_id: Uuid,
+        user_id: Uuid,
+        role: ProjectRole,
+    ) -> Result<ProjectMember, AppError> {
+        if role == ProjectRole::Owner {
+            return Err(Self::owner_role());
+        }
+
+        let mut tx = pool.begin().await?;
+
+        let previous = Self::member_role(pool, project_id, user_id).await?;
+        let member = ProjectMemberRepository::update_role(&mut *tx, project_id, user_id, role)
+            .await?
+            .ok_or_else(Self::owner_role)?;
+
+        AuditLogRepository::create(
+            &mut *tx,
+            Some(project_id),
+            Some(actor_id),
+            Some(user_id),
+            "member_role_changed",
+            json!({ "from": previous, "to": role }),
+        )
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(member)
+    }
+
+    /// Remove a member, or leave the project when `user_id` is the actor
+    pub async fn remove(
+        pool: &PgPool,
+        project_id: Uuid,
+        actor_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<(), AppError> {
+        Self::member_role(pool, project_id, user_id).await?;
+
+        let mut tx = pool.begin().await?;
+
+        let role = ProjectMemberRepository::delete(&mut *tx, project_id, user_id)
+            .await?
+            .ok_or_else(|| {
+                AppError::ForbiddenError("The project owner can't be removed".to_string())
+            })?;
+
+        AuditLogRepository::create(
+            &mut *tx,
+            Some(project_id),
+            Some(actor_id),
+            Some(user_id),
+            if actor_id == user_id {
+                "member_left"
+            } else {
+                "member_removed"
+            },
+            json!({ "role": role }),
+        )
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    pub async fn project_audit_log(
+        pool: &PgPool,
+        project_id: Uuid,
+        pagination: Pagination,
+    ) -> Result<(Vec<AuditLogEntry>, i64), AppError> {
+        Ok(AuditLogRepository::get_many_by_project(pool, project_id, pagination).await?)
+    }
+
+    /// What the user did, and what was done to their memberships
+    pub async fn user_audit_log(
+        pool: &PgPool,
+        user_id: Uuid,
+        pagination: Pagination,
+    ) -> Result<(Vec<AuditLogEntry>, i64), AppError> {
+        Ok(AuditLogRepository::get_many_by_user(pool, user_id, pagination).await?)
+    }
+
+    async fn member_role(
+        pool: &PgPool,
+        project_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<ProjectRole, AppError> {
+        ProjectMemberRepository::get_role(pool, project_id, user_id)
+            .await?
+            .ok_or_else(|| AppError::NotFoundError("Member not found".to_string()))
+    }
+
+    fn owner_role() -> AppError {
+        AppError::ForbiddenError("The owner role can't be granted or changed".to_string())
+    }
+
+    fn invitation_response(invitation: ProjectInvitation) -> ProjectInvitationResponse {
+        ProjectInvitationResponse {
+            id: invitation.id,
+            project_id: invitation.project_id,
+            email: invitation.email,
+            role: invitation.role,
+            invited_by: invitation.invited_by,
+            expires_at: invitation.expires_at,
+            accepted_at: invitation.accepted_at,
+            created_at: invitation.created_at,
+        }
+    }
+}
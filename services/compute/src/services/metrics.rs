@@ -0,0 +1,267 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use kube::api::{ApiResource, DynamicObject, GroupVersionKind, ListParams};
+use kube::{Api, Client};
+use shared::utilities::errors::AppError;
+use sqlx::PgPool;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::features::models::{Deployment, DeploymentStatus, ResourceSpec};
+use crate::features::repository::{DeploymentMetricRepository, DeploymentRepository};
+use crate::features::schemas::{DeploymentMetricsResponse, MetricPointResponse, MetricsQuery};
+use crate::services::build_kubernetes::Kubernetes;
+
+/// metrics-server refreshes every 15-60s, sampling faster only repeats values
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(60);
+const RETENTION_DAYS: i64 = 30;
+
+const DEFAULT_RANGE_SECONDS: i64 = 60 * 60;
+const MIN_STEP_SECONDS: i64 = 60;
+const MAX_POINTS: i64 = 1440;
+
+/// Usage of one deployment summed over its pods
+#[derive(Debug, Default, PartialEq)]
+pub struct UsageSample {
+    pub cpu_millicores: f64,
+    pub memory_mb: f64,
+    pub pod_count: i32,
+}
+
+pub struct MetricsService;
+
+impl MetricsService {
+    /// Start the background sampler, it runs for the lifetime of the process
+    pub fn spawn_sampler(pool: PgPool, kubernetes: Kubernetes) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SAMPLE_INTERVAL);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+            loop {
+                interval.tick().await;
+                if let Err(e) = Self::sample_all(&pool, &kubernetes).await {
+                    warn!("Metrics sampling failed: {}", e);
+                }
+            }
+        });
+
+        info!("📈 Metrics sampler started");
+    }
+
+    async fn sample_all(pool: &PgPool, kubernetes: &Kubernetes) -> Result<(), AppError> {
+        let sampled_at = Utc::now();
+        let deployments =
+            DeploymentRepository::get_all_by_status(pool, DeploymentStatus::Running).await?;
+
+        for deployment in deployments {
+            let usage = async {
+                let cluster = kubernetes.cluster(&deployment)?;
+                Self::usage(&cluster.client, &deployment).await
+            };
+            match usage.await {
+                Ok(sample) => {
+                    DeploymentMetricRepository::create(
+                        pool,
+                        deployment.id,
+                        sampled_at,
+                        sample.cpu_millicores,
+                        sample.memory_mb,
+                        sample.pod_count,
+                    )
+                    .await?;
+                }
+                Err(e) => warn!(
+                    "Failed to read metrics of deployment {}: {}",
+                    deployment.id, e
+                ),
+            }
+        }
+
+        let cutoff = sampled_at - chrono::Duration::days(RETENTION_DAYS);
+        DeploymentMetricRepository::delete_older_than(pool, cutoff).await?;
+
+        Ok(())
+    }
+
+    /// Current usage of a deployment's pods from the `metrics.k8s.io` API
+    pub async fn usage(client: &Client, deployment: &Deployment) -> Result<UsageSample, AppError> {
+        let gvk = GroupVersionKind::gvk("metrics.k8s.io", "v1beta1", "PodMetrics");
+        let resource = ApiResource::from_gvk_with_plural(&gvk, "pods");
+        let api: Api<DynamicObject> =
+            Api::namespaced_with(client.clone(), &deployment.cluster_namespace, &resource);
+
+        let params = ListParams::default().labels(&format!("deployment-id={}", deployment.id));
+        let pods = api.list(&params).await?.items;
+
+        let mut sample = UsageSample {
+            pod_count: pods.len() as i32,
+            ..Default::default()
+        };
+
+        for pod in &pods {
+            let containers = pod.data["containers"].as_array().into_iter().flatten();
+            for container in containers {
+                let usage = &container["usage"];
+                sample.cpu_millicores += usage["cpu"]
+                    .as_str()
+                    .and_then(parse_cpu_millicores)
+                    .unwrap_or(0.0);
+                sample.memory_mb += usage["memory"]
+                    .as_str()
+                    .and_then(parse_memory_mb)
+                    .unwrap_or(0.0);
+            }
+        }
+
+        Ok(sample)
+    }
+
+    /// Rolled-up usage of a deployment next to the resources it pays for
+    pub async fn get_series(
+        pool: &PgPool,
+        deployment_id: Uuid,
+        user_id: Uuid,
+        query: MetricsQuery,
+    ) -> Result<DeploymentMetricsResponse, AppError> {
+        let (from, to, step) = Self::resolve_range(&query, Utc::now())?;
+
+        let deployment = DeploymentRepository::get_by_id(pool, deployment_id, user_id).await?;
+        let resources: ResourceSpec = serde_json::from_value(deployment.resources.clone())?;
+
+        let rollup =
+            DeploymentMetricRepository::get_rollup(pool, deployment.id, from, to, step).await?;
+
+        let points = rollup
+            .into_iter()
+            .map(|r| {
+                let pods = r.pod_count as i64;
+                MetricPointResponse {
+                    timestamp: r.bucket,
+                    pod_count: r.pod_count,
+                    cpu_millicores_avg: r.cpu_millicores_avg,
+                    cpu_millicores_max: r.cpu_millicores_max,
+                    cpu_request_millicores: resources.cpu_request_millicores as i64 * pods,
+                    cpu_limit_millicores: resources.cpu_limit_millicores as i64 * pods,
+                    memory_mb_avg: r.memory_mb_avg,
+                    memory_mb_max: r.memory_mb_max,
+                    memory_request_mb: resources.memory_request_mb as i64 * pods,
+                    memory_limit_mb: resources.memory_limit_mb as i64 * pods,
+                }
+            })
+            .collect();
+
+        Ok(DeploymentMetricsResponse {
+            from,
+            to,
+            step,
+            resources,
+            points,
+        })
+    }
+
+    fn resolve_range(
+        query: &MetricsQuery,
+        now: DateTime<Utc>,
+    ) -> Result<(DateTime<Utc>, DateTime<Utc>, i64), AppError> {
+        let to = query.to.unwrap_or(now);
+        let from = query
+            .from
+            .unwrap_or(to - chrono::Duration::seconds(DEFAULT_RANGE_SECONDS));
+        let step = query.step.unwrap_or(MIN_STEP_SECONDS);
+
+        if from >= to {
+            return Err(AppError::ValidationError(
+                "from must be before to".to_string(),
+            ));
+        }
+        if step < MIN_STEP_SECONDS {
+            return Err(AppError::ValidationError(format!(
+                "step must be at least {} seconds",
+                MIN_STEP_SECONDS
+            )));
+        }
+        if (to - from).num_seconds() / step > MAX_POINTS {
+            return Err(AppError::ValidationError(format!(
+                "Range would return more than {} points, increase step",
+                MAX_POINTS
+            )));
+        }
+
+        Ok((from, to, step))
+    }
+}
+
+/// Parse a CPU quantity ("250m", "1", "123456n") into millicores
+fn parse_cpu_millicores(quantity: &str) -> Option<f64> {
+    let (number, factor) = match quantity {
+        q if q.ends_with('n') => (&q[..q.len() - 1], 1e-6),
+        q if q.ends_with('u') => (&q[..q.len() - 1], 1e-3),
+        q if q.ends_with('m') => (&q[..q.len() - 1], 1.0),
+        q => (q, 1000.0),
+    };
+    number.parse::<f64>().ok().map(|n| n * factor)
+}
+
+/// Parse a memory quantity ("128974848", "129e6", "123Mi", "1Gi", "500M") into MiB
+fn parse_memory_mb(quantity: &str) -> Option<f64> {
+    const MIB: f64 = 1024.0 * 1024.0;
+    let suffixes: [(&str, f64); 10] = [
+        ("Ki", 1024.0),
+        ("Mi", MIB),
+        ("Gi", MIB * 1024.0),
+        ("Ti", MIB * 1024.0 * 1024.0),
+        ("k", 1e3),
+        ("K", 1e3),
+        ("M", 1e6),
+        ("G", 1e9),
+        ("T", 1e12),
+        ("", 1.0),
+    ];
+
+    suffixes.iter().find_map(|(suffix, factor)| {
+        quantity
+            .strip_suffix(suffix)
+            .and_then(|n| n.parse::<f64>().ok())
+            .map(|n| n * factor / MIB)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_quantities() {
+        assert_eq!(parse_cpu_millicores("250m"), Some(250.0));
+        assert_eq!(parse_cpu_millicores("2"), Some(2000.0));
+        assert_eq!(parse_cpu_millicores("1500000n"), Some(1.5));
+        assert_eq!(parse_cpu_millicores("abc"), None);
+
+        assert_eq!(parse_memory_mb("128Mi"), Some(128.0));
+        assert_eq!(parse_memory_mb("2048Ki"), Some(2.0));
+        assert_eq!(parse_memory_mb("1Gi"), Some(1024.0));
+        assert_eq!(parse_memory_mb("1048576"), Some(1.0));
+        assert_eq!(parse_memory_mb("1M"), Some(1e6 / (1024.0 * 1024.0)));
+    }
+
+    #[test]
+    fn test_resolve_range() {
+        let now = Utc::now();
+        let query = MetricsQuery {
+            from: None,
+            to: None,
+            step: None,
+        };
+        let (from, to, step) = MetricsService::resolve_range(&query, now).unwrap();
+        assert_eq!((to - from).num_seconds(), DEFAULT_RANGE_SECONDS);
+        assert_eq!(step, MIN_STEP_SECONDS);
+
+        let too_fine = MetricsQuery {
+            from: Some(now - chrono::Duration::days(30)),
+            to: Some(now),
+            step: Some(60),
+        };
+        assert!(MetricsService::resolve_range(&too_fine, now).is_err());
+    }
+}
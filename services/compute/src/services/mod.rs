@@ -1,2 +1,26 @@
+pub mod access;
+pub mod billing;
 pub mod build_kubernetes;
+pub mod cluster;
+pub mod compose;
+pub mod deploy_hooks;
+pub mod duplicate;
+pub mod estimate;
+pub mod event_bus;
+pub mod export;
+#[cfg(test)]
+pub mod fake_cluster;
+pub mod kms;
 pub mod kubernetes;
+pub mod members;
+pub mod metrics;
+pub mod outbound;
+pub mod preview;
+pub mod project_spec;
+pub mod quota;
+pub mod runtime_status;
+pub mod secret_rotation;
+pub mod status_watcher;
+pub mod timeline;
+pub mod transfers;
+pub mod webhooks;
@@ -0,0 +1,210 @@
+//! HTTP calls to URLs users configure: webhooks and preview callbacks.
+//!
+//! Only http(s) URLs are called, and only on public addresses. Hostnames are
+//! resolved by the client's own resolver as the request connects, so a name
+//! can't pass a check and then resolve to an internal address. Addresses
+//! written into the URL are never resolved and are checked up front instead.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::{RequestBuilder, Url};
+use shared::utilities::errors::AppError;
+
+#[derive(Clone)]
+pub struct OutboundClient {
+    http_client: reqwest::Client,
+    allow_private: bool,
+}
+
+impl OutboundClient {
+    pub fn new() -> Result<Self, reqwest::Error> {
+        let http_client = reqwest::ClientBuilder::new()
+            .redirect(reqwest::redirect::Policy::none())
+            .dns_resolver(Arc::new(PublicResolver))
+            .build()?;
+
+        Ok(Self {
+            http_client,
+            allow_private: false,
+        })
+    }
+
+    /// Reaches private addresses too, for receivers the tests run locally
+    #[cfg(test)]
+    pub fn permissive() -> Self {
+        Self {
+            http_client: reqwest::Client::new(),
+            allow_private: true,
+        }
+    }
+
+    pub fn post(&self, url: &str) -> Result<RequestBuilder, AppError> {
+        let url = Self::check(url)?;
+        if !self.allow_private
+            && let Some(ip) = Self::literal_ip(&url)
+            && !is_public(ip)
+        {
+            return Err(AppError::ValidationError(format!(
+                "{} is not a public address",
+                ip
+            )));
+        }
+
+        Ok(self.http_client.post(url))
+    }
+
+    /// The URL, when it is http(s) with a host. Run on URLs users submit,
+    /// addresses are checked when they're called.
+    pub fn check(url: &str) -> Result<Url, AppError> {
+        let url = Url::parse(url)
+            .map_err(|e| AppError::ValidationError(format!("Invalid URL {}: {}", url, e)))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(AppError::ValidationError(format!(
+                "Only http and https URLs can be called, not {}",
+                url.scheme()
+            )));
+        }
+        if url.host().is_none() {
+            return Err(AppError::ValidationError(format!(
+                "URL {} has no host",
+                url
+            )));
+        }
+
+        Ok(url)
+    }
+
+    /// IPv6 hosts come bracketed, `[::1]`
+    fn literal_ip(url: &Url) -> Option<IpAddr> {
+        url.host_str()?
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse()
+            .ok()
+    }
+}
+
+/// System resolver refusing names with any non-public address
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> =
+                tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            if let Some(addr) = addrs.iter().find(|addr| !is_public(addr.ip())) {
+                return Err(
+                    format!("{} resolves to {}, not a public address", host, addr.ip()).into(),
+                );
+            }
+
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Whether `ip` is reachable on the internet, rather than private, loopback,
+/// link-local or otherwise reserved
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => is_public_v6(ip),
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        // 0.0.0.0/8, 100.64.0.0/10 (carrier-grade NAT), 192.0.0.0/24,
+        // 198.18.0.0/15 (benchmarking), 240.0.0.0/4 (reserved)
+        || a == 0
+        || (a == 100 && (b & 0xc0) == 64)
+        || (a == 192 && b == 0 && c == 0)
+        || (a == 198 && (b & 0xfe) == 18)
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    // IPv4-mapped and NAT64 addresses reach the embedded IPv4 address
+    if let Some(v4) = ip.to_ipv4_mapped() {
+        return is_public_v4(v4);
+    }
+    let segments = ip.segments();
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        let [a, b] = segments[6].to_be_bytes();
+        let [c, d] = segments[7].to_be_bytes();
+        return is_public_v4(Ipv4Addr::new(a, b, c, d));
+    }
+
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // fc00::/7 (unique local), fe80::/10 (link-local), 2001:db8::/32
+        // (documentation)
+        || (segments[0] & 0xfe00) == 0xfc00
+        || (segments[0] & 0xffc0) == 0xfe80
+        || (segments[0] == 0x2001 && segments[1] == 0x0db8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_public() {
+        for ip in ["8.8.8.8", "1.1.1.1", "2606:4700:4700::1111"] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "10.0.0.1",
+            "172.16.5.4",
+            "192.168.1.1",
+            "127.0.0.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "fc00::1",
+            "fd12:3456::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:10.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_post() {
+        let client = OutboundClient::new().unwrap();
+        assert!(client.post("https://example.com/hook").is_ok());
+        for url in [
+            "ftp://example.com/hook",
+            "file:///etc/passwd",
+            "http://127.0.0.1:8080/hook",
+            "http://[::1]/hook",
+            "http://169.254.169.254/latest/meta-data",
+        ] {
+            assert!(
+                matches!(client.post(url), Err(AppError::ValidationError(_))),
+                "{}",
+                url
+            );
+        }
+
+        // Names are refused once they resolve to a private address
+        let result = client.post("http://localhost:9/hook").unwrap().send().await;
+        assert!(result.unwrap_err().is_connect());
+    }
+}
@@ -0,0 +1,284 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use serde_json::json;
+use shared::utilities::errors::AppError;
+use sqlx::PgPool;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::features::models::{Deployment, DeploymentPreview};
+use crate::features::repository::{DeploymentPreviewRepository, DeploymentRepository};
+use crate::features::schemas::{CreatePreviewRequest, PreviewResponse};
+use crate::services::billing::BillingClient;
+use crate::services::build_kubernetes::Kubernetes;
+use crate::services::duplicate::DuplicateService;
+use crate::services::event_bus::EventBus;
+use crate::services::export::ExportService;
+use crate::services::kubernetes::{DeploymentService, with_tag};
+use crate::services::outbound::OutboundClient;
+use crate::utilities::encryption::EncryptionService;
+use crate::utilities::naming;
+
+const DEFAULT_TTL_HOURS: i64 = 72;
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+/// How long a sweeper owns an expired preview before another may retry it
+const SWEEP_LEASE_SECONDS: i64 = 600;
+const SWEEP_BATCH: i64 = 50;
+const CALLBACK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Ephemeral copies of a deployment running another image, torn down on expiry
+pub struct PreviewService;
+
+impl PreviewService {
+    /// Create the preview, or roll an existing one onto the new image and
+    /// expiry. Returns whether it was created.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn upsert(
+        pool: &PgPool,
+        kubernetes: &Kubernetes,
+        billing: &BillingClient,
+        events: &EventBus,
+        encryption_service: &EncryptionService,
+        outbound: &OutboundClient,
+        base_domain: &str,
+        base_deployment_id: Uuid,
+        user_id: Uuid,
+        req: CreatePreviewRequest,
+    ) -> Result<(PreviewResponse, bool), AppError> {
+        let base = DeploymentRepository::get_by_id(pool, base_deployment_id, user_id).await?;
+        if let Some(callback_url) = &req.callback_url {
+            OutboundClient::check(callback_url)?;
+        }
+
+        let image = match (&req.image, &req.image_tag) {
+            (Some(image), _) => image.clone(),
+            (None, Some(tag)) => with_tag(&base.image, tag),
+            (None, None) => base.image.clone(),
+        };
+        let expires_at =
+            Utc::now() + chrono::Duration::hours(req.ttl_hours.unwrap_or(DEFAULT_TTL_HOURS));
+
+        let existing = DeploymentPreviewRepository::get_by_name(pool, base.id, &req.name).await?;
+        let (preview, created) = match existing {
+            Some(preview) => {
+                let deployment =
+                    DeploymentRepository::get_by_id(pool, preview.deployment_id, user_id).await?;
+                DeploymentService::update_image(
+                    pool,
+                    kubernetes,
+                    billing,
+                    events,
+                    &deployment,
+                    image,
+                )
+                .await?;
+
+                let preview = DeploymentPreviewRepository::extend(
+                    pool,
+                    deployment.id,
+                    req.callback_url.as_deref(),
+                    expires_at,
+                )
+                .await?;
+                (preview, false)
+            }
+            None => {
+                let preview = Self::create(
+                    pool,
+                    kubernetes,
+                    billing,
+                    events,
+                    encryption_service,
+                    base_domain,
+                    &base,
+                    &req,
+                    image,
+                    expires_at,
+                )
+                .await?;
+                (preview, true)
+            }
+        };
+
+        let event = if created {
+            "preview.ready"
+        } else {
+            "preview.updated"
+        };
+        Self::notify(outbound, &preview, event).await;
+
+        Ok((Self::preview_response(preview), created))
+    }
+
+    pub async fn list(
+        pool: &PgPool,
+        base_deployment_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<Vec<PreviewResponse>, AppError> {
+        let base = DeploymentRepository::get_by_id(pool, base_deployment_id, user_id).await?;
+        let previews = DeploymentPreviewRepository::get_all_by_base(pool, base.id).await?;
+
+        Ok(previews.into_iter().map(Self::preview_response).collect())
+    }
+
+    /// Start the expiry sweeper, it runs for the lifetime of the process
+    pub fn spawn_sweeper(
+        pool: PgPool,
+        kubernetes: Kubernetes,
+        events: EventBus,
+        outbound: OutboundClient,
+    ) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+            loop {
+                interval.tick().await;
+                if let Err(e) = Self::sweep(&pool, &kubernetes, &events, &outbound).await {
+                    warn!("Preview sweep failed: {}", e);
+                }
+            }
+        });
+
+        info!("🧹 Preview sweeper started");
+    }
+
+    pub(crate) async fn sweep(
+        pool: &PgPool,
+        kubernetes: &Kubernetes,
+        events: &EventBus,
+        outbound: &OutboundClient,
+    ) -> Result<(), AppError> {
+        let expired =
+            DeploymentPreviewRepository::claim_expired(pool, SWEEP_LEASE_SECONDS, SWEEP_BATCH)
+                .await?;
+
+        for preview in expired {
+            let Some(deployment) =
+                DeploymentRepository::find_by_id(pool, preview.deployment_id).await?
+            else {
+                continue;
+            };
+
+            // Deleting the deployment cascades to the preview row
+            match DeploymentService::delete_deployment(pool, kubernetes, events, deployment.clone())
+                .await
+            {
+                Ok(()) => {
+                    info!("Preview {} expired and was removed", deployment.name);
+                    Self::notify(outbound, &preview, "preview.expired").await;
+                }
+                Err(e) => warn!("Failed to remove expired preview {}: {}", deployment.id, e),
+            }
+        }
+
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn create(
+        pool: &PgPool,
+        kubernetes: &Kubernetes,
+        billing: &BillingClient,
+        events: &EventBus,
+        encryption_service: &EncryptionService,
+        base_domain: &str,
+        base: &Deployment,
+        req: &CreatePreviewRequest,
+        image: String,
+        expires_at: chrono::DateTime<Utc>,
+    ) -> Result<DeploymentPreview, AppError> {
+        // `pr-42-<app>`, where the app is the first label of the base's host
+        let app = ExportService::live_spec(kubernetes, base)
+            .await?
+            .and_then(|live| live.external_url)
+            .and_then(|host| host.split('.').next().map(str::to_string))
+            .unwrap_or_else(|| base.name.to_lowercase().replace('_', "-"));
+        let subdomain = naming::fit_label("subdomain", &format!("{}-{}", req.name, app))?;
+
+        let mut create = DuplicateService::create_request(
+            pool,
+            kubernetes,
+            encryption_service,
+            base,
+            format!("{}-{}", req.name, base.name),
+            subdomain,
+            None,
+        )
+        .await?;
+        create.image = image;
+        let url = create
+            .subdomain
+            .as_ref()
+            .map(|subdomain| format!("https://{}.{}", subdomain, base_domain));
+
+        let deployment = DeploymentService::create(
+            pool,
+            kubernetes,
+            billing,
+            events,
+            encryption_service,
+            base.user_id,
+            base.project_id,
+            base_domain,
+            create,
+        )
+        .await?;
+
+        Ok(DeploymentPreviewRepository::create(
+            pool,
+            deployment.id,
+            base.id,
+            &req.name,
+            url.as_deref(),
+            req.callback_url.as_deref(),
+            expires_at,
+        )
+        .await?)
+    }
+
+    /// Best effort, a slow or failing receiver never fails the request
+    async fn notify(outbound: &OutboundClient, preview: &DeploymentPreview, event: &str) {
+        let Some(callback_url) = &preview.callback_url else {
+            return;
+        };
+
+        let payload = json!({
+            "event": event,
+            "name": preview.name,
+            "deploymentId": preview.deployment_id,
+            "baseDeploymentId": preview.base_deployment_id,
+            "url": preview.url,
+            "expiresAt": preview.expires_at,
+        });
+
+        let request = match outbound.post(callback_url) {
+            Ok(request) => request,
+            Err(e) => {
+                warn!("Preview callback to {} refused: {}", callback_url, e);
+                return;
+            }
+        };
+        let result = request
+            .timeout(CALLBACK_TIMEOUT)
+            .json(&payload)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Err(e) = result {
+            warn!("Preview callback to {} failed: {}", callback_url, e);
+        }
+    }
+
+    fn preview_response(preview: DeploymentPreview) -> PreviewResponse {
+        PreviewResponse {
+            deployment_id: preview.deployment_id,
+            base_deployment_id: preview.base_deployment_id,
+            name: preview.name,
+            url: preview.url,
+            expires_at: preview.expires_at,
+            created_at: preview.created_at,
+        }
+    }
+}
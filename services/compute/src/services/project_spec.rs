@@ -0,0 +1,792 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use shared::utilities::errors::AppError;
+use sqlx::PgPool;
+use tracing::warn;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::features::models::{
+    BillingAccount, Deployment, DeploymentStatus, Project, ResourceSpec, ResourceUsage,
+};
+use crate::features::repository::{DeploymentRepository, ProjectRepository};
+use crate::features::schemas::{
+    ApplyState, CreateDeploymentRequest, DeploymentResponse, DeploymentSpec, DeploymentUpdate,
+    PlannedChange, ProjectSpec, ProjectUpdate, SpecAction, SpecApplyResponse, SpecPlanResponse,
+};
+use crate::services::billing::BillingClient;
+use crate::services::build_kubernetes::Kubernetes;
+use crate::services::event_bus::EventBus;
+use crate::services::export::ExportService;
+use crate::services::kubernetes::DeploymentService;
+use crate::services::quota::QuotaService;
+use crate::utilities::encryption::EncryptionService;
+
+enum Step {
+    Create(CreateDeploymentRequest),
+    Update {
+        deployment: Box<Deployment>,
+        previous: DeploymentUpdate,
+        next: DeploymentUpdate,
+        changes: Vec<String>,
+    },
+    Delete(Deployment),
+}
+
+impl Step {
+    fn action(&self) -> SpecAction {
+        match self {
+            Self::Create(_) => SpecAction::Create,
+            Self::Update { .. } => SpecAction::Update,
+            Self::Delete(_) => SpecAction::Delete,
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Self::Create(req) => &req.name,
+            Self::Update { deployment, .. } => &deployment.name,
+            Self::Delete(deployment) => &deployment.name,
+        }
+    }
+
+    fn planned_change(&self) -> PlannedChange {
+        let (deployment_id, changes) = match self {
+            Self::Create(_) => (None, vec![]),
+            Self::Update {
+                deployment,
+                changes,
+                ..
+            } => (Some(deployment.id), changes.clone()),
+            Self::Delete(deployment) => (Some(deployment.id), vec![]),
+        };
+
+        PlannedChange {
+            action: self.action(),
+            name: self.name().to_string(),
+            deployment_id,
+            changes,
+        }
+    }
+}
+
+/// Creates, then updates, then deletes
+struct Plan {
+    project: Project,
+    project_changes: Vec<String>,
+    steps: Vec<Step>,
+}
+
+impl Plan {
+    fn response(&self) -> SpecPlanResponse {
+        let changes = |action: SpecAction| {
+            self.steps
+                .iter()
+                .filter(|step| step.action() == action)
+                .map(Step::planned_change)
+                .collect()
+        };
+
+        SpecPlanResponse {
+            project: self.project_changes.clone(),
+            creates: changes(SpecAction::Create),
+            updates: changes(SpecAction::Update),
+            deletes: changes(SpecAction::Delete),
+        }
+    }
+}
+
+/// What a failed apply has to undo
+enum Completed {
+    Project(Project),
+    Created(DeploymentResponse),
+    Updated {
+        deployment: Box<Deployment>,
+        previous: DeploymentUpdate,
+        response: DeploymentResponse,
+    },
+    /// An update that failed part way, its row may already have changed
+    Attempted {
+        deployment: Box<Deployment>,
+        previous: DeploymentUpdate,
+    },
+}
+
+/// Declarative project management: diff a spec document against the stored
+/// project and apply the difference
+pub struct ProjectSpecService;
+
+impl ProjectSpecService {
+    /// Parse and validate a YAML or JSON spec
+    pub fn parse(document: &str) -> Result<ProjectSpec, AppError> {
+        let spec: ProjectSpec = serde_yaml::from_str(document)
+            .map_err(|e| AppError::ValidationError(format!("Invalid project spec: {}", e)))?;
+        spec.validate()?;
+
+        let mut names = HashSet::new();
+        for deployment in &spec.deployments {
+            if !names.insert(&deployment.name) {
+                return Err(AppError::ValidationError(format!(
+                    "Deployment {} is declared twice",
+                    deployment.name
+                )));
+            }
+            if let Some(group) = deployment
+                .env_groups
+                .iter()
+                .find(|group| !spec.env_groups.contains_key(*group))
+            {
+                return Err(AppError::ValidationError(format!(
+                    "Deployment {} uses unknown env group {}",
+                    deployment.name, group
+                )));
+            }
+        }
+
+        Ok(spec)
+    }
+
+    /// Creates, updates and deletes that `apply` would run
+    pub async fn plan(
+        pool: &PgPool,
+        kubernetes: &Kubernetes,
+        base_domain: &str,
+        project_id: Uuid,
+        user_id: Uuid,
+        document: &str,
+    ) -> Result<SpecPlanResponse, AppError> {
+        let spec = Self::parse(document)?;
+        let project = ProjectRepository::get_one_by_id(pool, project_id, user_id).await?;
+
+        let plan = Self::diff(pool, kubernetes, base_domain, project, user_id, &spec).await?;
+        Ok(plan.response())
+    }
+
+    /// Run the plan, publishing progress to the project stream. One apply
+    /// runs per project at a time, and the quota and balance are checked for
+    /// the whole plan before any step. A failing step rolls back the creates
+    /// and updates before it; deletes can't be undone, so they run last.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn apply(
+        pool: &PgPool,
+        kubernetes: &Kubernetes,
+        billing: &BillingClient,
+        events: &EventBus,
+        encryption_service: &EncryptionService,
+        base_domain: &str,
+        project_id: Uuid,
+        user_id: Uuid,
+        document: &str,
+    ) -> Result<SpecApplyResponse, AppError> {
+        let spec = Self::parse(document)?;
+        let project = ProjectRepository::get_one_by_id(pool, project_id, user_id).await?;
+
+        // Held on its own connection for the whole apply, so a crashed
+        // replica releases it with the connection
+        let mut connection = pool.acquire().await?;
+        if !ProjectRepository::try_lock_apply(&mut connection, project.id).await? {
+            return Err(AppError::ConflictError(
+                "Another apply is running for this project".to_string(),
+            ));
+        }
+
+        let result = async {
+            let plan = Self::diff(pool, kubernetes, base_domain, project, user_id, &spec).await?;
+            Self::check(pool, billing, &plan).await?;
+            Self::execute(
+                pool,
+                kubernetes,
+                billing,
+                events,
+                encryption_service,
+                base_domain,
+                user_id,
+                &spec,
+                plan,
+            )
+            .await
+        }
+        .await;
+
+        if let Err(e) = ProjectRepository::unlock_apply(&mut connection, project_id).await {
+            warn!(
+                "Failed to release apply lock of project {}: {}",
+                project_id, e
+            );
+            if let Err(e) = connection.close().await {
+                warn!("Failed to close connection holding an apply lock: {}", e);
+            }
+        }
+
+        result
+    }
+
+    /// Quota and balance for everything the plan runs, so a plan that can't
+    /// fit fails before it changes anything
+    async fn check(pool: &PgPool, billing: &BillingClient, plan: &Plan) -> Result<(), AppError> {
+        let mut replaced = vec![];
+        let mut demand = vec![];
+        for step in &plan.steps {
+            match step {
+                Step::Create(req) => demand.push(QuotaService::footprint(
+                    req.replicas,
+                    req.resources.as_ref().unwrap_or(&ResourceSpec::default()),
+                    false,
+                )),
+                Step::Update {
+                    deployment, next, ..
+                } => {
+                    replaced.push(deployment.as_ref());
+                    demand.push(QuotaService::footprint(
+                        next.replicas,
+                        &next.resources,
+                        deployment.status == DeploymentStatus::Paused,
+                    ));
+                }
+                Step::Delete(deployment) => replaced.push(deployment),
+            }
+        }
+        let demand: ResourceUsage = demand.into_iter().sum();
+
+        let account = BillingAccount::of(&plan.project);
+        billing
+            .check_replacing(pool, account, &replaced, demand)
+            .await?;
+
+        let mut tx = pool.begin().await?;
+        QuotaService::check_replacing(&mut tx, account, &replaced, demand).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn diff(
+        pool: &PgPool,
+        kubernetes: &Kubernetes,
+        base_domain: &str,
+        project: Project,
+        user_id: Uuid,
+        spec: &ProjectSpec,
+    ) -> Result<Plan, AppError> {
+        let mut project_changes = vec![];
+        if let Some(desired) = &spec.project {
+            if let Some(name) = desired.name.as_ref().filter(|n| **n != project.name) {
+                project_changes.push(format!("name: {} -> {}", project.name, name));
+            }
+            if let Some(description) = desired
+                .description
+                .as_ref()
+                .filter(|d| Some(*d) != project.description.as_ref())
+            {
+                project_changes.push(format!(
+                    "description: {} -> {}",
+                    project.description.as_deref().unwrap_or("(none)"),
+                    description
+                ));
+            }
+        }
+
+        let mut existing: HashMap<String, Deployment> =
+            DeploymentRepository::get_all_by_project(pool, project.id, user_id)
+                .await?
+                .into_iter()
+                .map(|d| (d.name.clone(), d))
+                .collect();
+
+        let mut creates = vec![];
+        let mut updates = vec![];
+        for desired in &spec.deployments {
+            let env_vars = Self::env_vars(spec, desired);
+            let resources = QuotaService::resources(desired.size, desired.resources.clone())
+                .map_err(|e| {
+                    AppError::ValidationError(format!("Deployment {}: {}", desired.name, e))
+                })?;
+
+            let Some(deployment) = existing.remove(&desired.name) else {
+                let req = CreateDeploymentRequest {
+                    name: desired.name.clone(),
+                    image: desired.image.clone(),
+                    replicas: desired.replicas,
+                    port: desired.port,
+                    env_vars: (!env_vars.is_empty()).then_some(env_vars),
+                    secrets: None,
+                    resources: Some(resources),
+                    size: None,
+                    labels: None,
+                    subdomain: desired.subdomain.clone(),
+                    region: desired.region.clone(),
+                    node_pool: desired.node_pool.clone(),
+                };
+                req.validate().map_err(|e| {
+                    AppError::ValidationError(format!("Deployment {}: {}", desired.name, e))
+                })?;
+                kubernetes.placement(req.region.as_deref(), req.node_pool.as_deref())?;
+                creates.push(Step::Create(req));
+                continue;
+            };
+
+            let moved = |desired: &Option<String>, current: &Option<String>| {
+                desired.is_some() && desired != current
+            };
+            if moved(&desired.region, &deployment.region)
+                || moved(&desired.node_pool, &deployment.node_pool)
+            {
+                return Err(AppError::ValidationError(format!(
+                    "Deployment {} can't change region or node pool, recreate it instead",
+                    desired.name
+                )));
+            }
+
+            let live = ExportService::live_spec(kubernetes, &deployment)
+                .await?
+                .ok_or_else(|| {
+                    AppError::ConflictError(format!(
+                        "Deployment {} has no Kubernetes objects, delete it before applying",
+                        deployment.name
+                    ))
+                })?;
+
+            let previous = DeploymentUpdate {
+                image: deployment.image.clone(),
+                replicas: deployment.replicas,
+                port: live.container_port,
+                env_vars: serde_json::from_value(deployment.env_vars.clone())?,
+                resources: serde_json::from_value(deployment.resources.clone())?,
+                external_url: live.external_url.clone(),
+            };
+            let next = DeploymentUpdate {
+                image: desired.image.clone(),
+                replicas: desired.replicas,
+                port: desired.port,
+                env_vars,
+                resources,
+                external_url: desired
+                    .subdomain
+                    .as_ref()
+                    .map(|subdomain| format!("{}.{}", subdomain, base_domain))
+                    .or(live.external_url),
+            };
+
+            let changes = Self::changes(&previous, &next);
+            if !changes.is_empty() {
+                updates.push(Step::Update {
+                    deployment: Box::new(deployment),
+                    previous,
+                    next,
+                    changes,
+                });
+            }
+        }
+
+        let mut deletes: Vec<Step> = existing.into_values().map(Step::Delete).collect();
+        deletes.sort_by(|a, b| a.name().cmp(b.name()));
+
+        let mut steps = creates;
+        steps.extend(updates);
+        steps.extend(deletes);
+
+        Ok(Plan {
+            project,
+            project_changes,
+            steps,
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn execute(
+        pool: &PgPool,
+        kubernetes: &Kubernetes,
+        billing: &BillingClient,
+        events: &EventBus,
+        encryption_service: &EncryptionService,
+        base_domain: &str,
+        user_id: Uuid,
+        spec: &ProjectSpec,
+        plan: Plan,
+    ) -> Result<SpecApplyResponse, AppError> {
+        let response = plan.response();
+        let project = plan.project;
+        let project_step = usize::from(!plan.project_changes.is_empty());
+        let total = plan.steps.len() + project_step;
+
+        let mut completed = vec![];
+        let mut deleted = vec![];
+
+        if project_step == 1
+            && let Some(desired) = &spec.project
+        {
+            Self::progress(
+                events,
+                project.id,
+                total,
+                1,
+                SpecAction::Update,
+                &project.name,
+                ApplyState::Started,
+            )
+            .await;
+            ProjectRepository::update(
+                pool,
+                project.id,
+                user_id,
+                desired.name.as_deref(),
+                desired.description.as_deref(),
+            )
+            .await?;
+            Self::progress(
+                events,
+                project.id,
+                total,
+                1,
+                SpecAction::Update,
+                &project.name,
+                ApplyState::Done,
+            )
+            .await;
+            completed.push(Completed::Project(project.clone()));
+        }
+
+        for (index, step) in plan.steps.into_iter().enumerate() {
+            let number = index + 1 + project_step;
+            let action = step.action();
+            let name = step.name().to_string();
+            Self::progress(
+                events,
+                project.id,
+                total,
+                number,
+                action,
+                &name,
+                ApplyState::Started,
+            )
+            .await;
+
+            let result = match step {
+                Step::Create(req) => DeploymentService::create(
+                    pool,
+                    kubernetes,
+                    billing,
+                    events,
+                    encryption_service,
+                    user_id,
+                    project.id,
+                    base_domain,
+                    req,
+                )
+                .await
+                .map(|created| completed.push(Completed::Created(created))),
+                Step::Update {
+                    deployment,
+                    previous,
+                    next,
+                    ..
+                } => {
+                    match DeploymentService::update(
+                        pool,
+                        kubernetes,
+                        billing,
+                        events,
+                        &deployment,
+                        &next,
+                    )
+                    .await
+                    {
+                        Ok(response) => {
+                            completed.push(Completed::Updated {
+                                deployment,
+                                previous,
+                                response,
+                            });
+                            Ok(())
+                        }
+                        Err(e) => {
+                            completed.push(Completed::Attempted {
+                                deployment,
+                                previous,
+                            });
+                            Err(e)
+                        }
+                    }
+                }
+                Step::Delete(deployment) => {
+                    DeploymentService::delete(pool, kubernetes, events, deployment.id, user_id)
+                        .await
+                        .map(|_| deleted.push(deployment.id))
+                }
+            };
+
+            if let Err(e) = result {
+                Self::progress(
+                    events,
+                    project.id,
+                    total,
+                    number,
+                    action,
+                    &name,
+                    ApplyState::Failed,
+                )
+                .await;
+                Self::rollback(pool, kubernetes, billing, events, user_id, completed).await;
+                if !deleted.is_empty() {
+                    warn!(
+                        "Apply of project {} failed after deleting {} deployments, which stay deleted",
+                        project.id,
+                        deleted.len()
+                    );
+                }
+                Self::progress(
+                    events,
+                    project.id,
+                    total,
+                    number,
+                    action,
+                    &name,
+                    ApplyState::RolledBack,
+                )
+                .await;
+                return Err(e);
+            }
+
+            Self::progress(
+                events,
+                project.id,
+                total,
+                number,
+                action,
+                &name,
+                ApplyState::Done,
+            )
+            .await;
+        }
+
+        let mut created = vec![];
+        let mut updated = vec![];
+        for step in completed {
+            match step {
+                Completed::Created(deployment) => created.push(deployment),
+                Completed::Updated { response, .. } => updated.push(response),
+                Completed::Project(_) | Completed::Attempted { .. } => {}
+            }
+        }
+
+        Ok(SpecApplyResponse {
+            plan: response,
+            created,
+            updated,
+            deleted,
+        })
+    }
+
+    /// Undo completed steps newest first. Best effort, every failure is logged.
+    async fn rollback(
+        pool: &PgPool,
+        kubernetes: &Kubernetes,
+        billing: &BillingClient,
+        events: &EventBus,
+        user_id: Uuid,
+        completed: Vec<Completed>,
+    ) {
+        for step in completed.into_iter().rev() {
+            let result = match step {
+                Completed::Created(created) => {
+                    DeploymentService::delete(pool, kubernetes, events, created.id, user_id).await
+                }
+                Completed::Updated {
+                    deployment,
+                    previous,
+                    ..
+                }
+                | Completed::Attempted {
+                    deployment,
+                    previous,
+                } => DeploymentService::update(
+                    pool,
+                    kubernetes,
+                    billing,
+                    events,
+                    &deployment,
+                    &previous,
+                )
+                .await
+                .map(|_| ()),
+                Completed::Project(project) => ProjectRepository::update(
+                    pool,
+                    project.id,
+                    user_id,
+                    Some(&project.name),
+                    project.description.as_deref(),
+                )
+                .await
+                .map(|_| ())
+                .map_err(AppError::from),
+            };
+
+            if let Err(e) = result {
+                warn!("Failed to roll back project spec step: {}", e);
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn progress(
+        events: &EventBus,
+        project_id: Uuid,
+        total: usize,
+        step: usize,
+        action: SpecAction,
+        name: &str,
+        state: ApplyState,
+    ) {
+        events
+            .publish(&ProjectUpdate::Apply {
+                project_id,
+                step,
+                total,
+                action,
+                name: name.to_string(),
+                state,
+            })
+            .await;
+    }
+
+    /// Env groups in order, then the deployment's own env
+    fn env_vars(spec: &ProjectSpec, deployment: &DeploymentSpec) -> HashMap<String, String> {
+        let mut env_vars = HashMap::new();
+        for group in &deployment.env_groups {
+            if let Some(vars) = spec.env_groups.get(group) {
+                env_vars.extend(vars.clone());
+            }
+        }
+        env_vars.extend(deployment.env.clone());
+        env_vars
+    }
+
+    fn changes(previous: &DeploymentUpdate, next: &DeploymentUpdate) -> Vec<String> {
+        let mut changes = vec![];
+
+        if previous.image != next.image {
+            changes.push(format!("image: {} -> {}", previous.image, next.image));
+        }
+        if previous.replicas != next.replicas {
+            changes.push(format!(
+                "replicas: {} -> {}",
+                previous.replicas, next.replicas
+            ));
+        }
+        if previous.port != next.port {
+            changes.push(format!("port: {} -> {}", previous.port, next.port));
+        }
+
+        // Keys only, values may be sensitive even outside secrets
+        let keys: BTreeSet<&String> = previous
+            .env_vars
+            .keys()
+            .chain(next.env_vars.keys())
+            .collect();
+        let env: Vec<String> = keys
+            .into_iter()
+            .filter_map(
+                |key| match (previous.env_vars.get(key), next.env_vars.get(key)) {
+                    (None, Some(_)) => Some(format!("+{}", key)),
+                    (Some(_), None) => Some(format!("-{}", key)),
+                    (Some(a), Some(b)) if a != b => Some(format!("~{}", key)),
+                    _ => None,
+                },
+            )
+            .collect();
+        if !env.is_empty() {
+            changes.push(format!("env: {}", env.join(", ")));
+        }
+
+        if previous.resources != next.resources {
+            changes.push(format!(
+                "resources: {} -> {}",
+                Self::describe_resources(&previous.resources),
+                Self::describe_resources(&next.resources)
+            ));
+        }
+        if previous.external_url != next.external_url {
+            changes.push(format!(
+                "domain: {} -> {}",
+                previous.external_url.as_deref().unwrap_or("(none)"),
+                next.external_url.as_deref().unwrap_or("(none)")
+            ));
+        }
+
+        changes
+    }
+
+    fn describe_resources(resources: &ResourceSpec) -> String {
+        format!(
+            "cpu {}m/{}m, memory {}Mi/{}Mi",
+            resources.cpu_request_millicores,
+            resources.cpu_limit_millicores,
+            resources.memory_request_mb,
+            resources.memory_limit_mb
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPEC: &str = r#"
+project:
+  name: shop
+envGroups:
+  common:
+    LOG_LEVEL: info
+    REGION: eu
+deployments:
+  - name: api
+    image: ghcr.io/acme/api:2.0
+    port: 8000
+    replicas: 3
+    envGroups: [common]
+    env:
+      LOG_LEVEL: debug
+"#;
+
+    #[test]
+    fn test_parse_and_merge_env_groups() {
+        let spec = ProjectSpecService::parse(SPEC).unwrap();
+        let env_vars = ProjectSpecService::env_vars(&spec, &spec.deployments[0]);
+
+        assert_eq!(env_vars["LOG_LEVEL"], "debug");
+        assert_eq!(env_vars["REGION"], "eu");
+
+        let json = r#"{"deployments": [{"name": "api", "image": "api", "port": 80, "envGroups": ["missing"]}]}"#;
+        assert!(ProjectSpecService::parse(json).is_err());
+        assert!(ProjectSpecService::parse("deployments: []\nunknown: 1").is_err());
+    }
+
+    #[test]
+    fn test_changes() {
+        let previous = DeploymentUpdate {
+            image: "api:1".to_string(),
+            replicas: 1,
+            port: 8000,
+            env_vars: HashMap::from([
+                ("A".to_string(), "1".to_string()),
+                ("B".to_string(), "2".to_string()),
+            ]),
+            resources: ResourceSpec::default(),
+            external_url: Some("api.example.com".to_string()),
+        };
+        let next = DeploymentUpdate {
+            image: "api:2".to_string(),
+            env_vars: HashMap::from([
+                ("A".to_string(), "changed".to_string()),
+                ("C".to_string(), "3".to_string()),
+            ]),
+            ..previous.clone()
+        };
+
+        assert_eq!(
+            ProjectSpecService::changes(&previous, &next),
+            ["image: api:1 -> api:2", "env: ~A, -B, +C"]
+        );
+        assert!(ProjectSpecService::changes(&previous, &previous).is_empty());
+    }
+}
@@ -0,0 +1,219 @@
+use shared::utilities::errors::AppError;
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::features::models::{
+    BillingAccount, Deployment, DeploymentStatus, Plan, ResourcePreset, ResourceSpec, ResourceUsage,
+};
+use crate::features::repository::{DeploymentRepository, PlanRepository};
+use crate::features::schemas::QuotaResponse;
+
+pub struct QuotaService;
+
+impl QuotaService {
+    /// Resources of a request that may name a preset instead, defaults when
+    /// it has neither
+    pub fn resources(
+        size: Option<ResourcePreset>,
+        resources: Option<ResourceSpec>,
+    ) -> Result<ResourceSpec, AppError> {
+        let resources = match (size, resources) {
+            (Some(_), Some(_)) => {
+                return Err(AppError::ValidationError(
+                    "Set either size or resources, not both".to_string(),
+                ));
+            }
+            (Some(size), None) => size.resources(),
+            (None, resources) => resources.unwrap_or_default(),
+        };
+
+        Self::validate(&resources)?;
+        Ok(resources)
+    }
+
+    pub fn validate(resources: &ResourceSpec) -> Result<(), AppError> {
+        if resources.cpu_request_millicores <= 0 || resources.memory_request_mb <= 0 {
+            return Err(AppError::ValidationError(
+                "CPU and memory requests must be positive".to_string(),
+            ));
+        }
+        if resources.cpu_request_millicores > resources.cpu_limit_millicores {
+            return Err(AppError::ValidationError(format!(
+                "CPU request of {}m exceeds its limit of {}m",
+                resources.cpu_request_millicores, resources.cpu_limit_millicores
+            )));
+        }
+        if resources.memory_request_mb > resources.memory_limit_mb {
+            return Err(AppError::ValidationError(format!(
+                "Memory request of {}Mi exceeds its limit of {}Mi",
+                resources.memory_request_mb, resources.memory_limit_mb
+            )));
+        }
+        Ok(())
+    }
+
+    /// What one deployment takes out of its owner's plan
+    pub fn footprint(replicas: i32, resources: &ResourceSpec, paused: bool) -> ResourceUsage {
+        if paused {
+            return ResourceUsage {
+                deployments: 1,
+                ..Default::default()
+            };
+        }
+
+        let replicas = replicas as i64;
+        ResourceUsage {
+            deployments: 1,
+            replicas,
+            cpu_millicores: replicas * resources.cpu_limit_millicores as i64,
+            memory_mb: replicas * resources.memory_limit_mb as i64,
+        }
+    }
+
+    /// What an existing deployment takes out of its owner's plan now
+    pub fn footprint_of(deployment: &Deployment) -> Result<ResourceUsage, AppError> {
+        let resources: ResourceSpec = serde_json::from_value(deployment.resources.clone())?;
+        Ok(Self::footprint(
+            deployment.replicas,
+            &resources,
+            deployment.status == DeploymentStatus::Paused,
+        ))
+    }
+
+    /// Make sure the account's plan fits `demand`, as a new deployment or in
+    /// place of what `previous` uses now. Holds a per-account lock until `tx`
+    /// ends, so the deployment row must be written in the same transaction.
+    ///
+    /// Only what grows is checked, an account over a lowered cap can still
+    /// shrink.
+    pub async fn check(
+        tx: &mut Transaction<'_, Postgres>,
+        account: BillingAccount,
+        previous: Option<&Deployment>,
+        demand: ResourceUsage,
+    ) -> Result<(), AppError> {
+        Self::check_replacing(tx, account, previous.as_slice(), demand).await
+    }
+
+    /// `check` for a change that replaces several deployments at once, with
+    /// `demand` what all of their replacements use together
+    pub async fn check_replacing(
+        tx: &mut Transaction<'_, Postgres>,
+        account: BillingAccount,
+        replaced: &[&Deployment],
+        demand: ResourceUsage,
+    ) -> Result<(), AppError> {
+        PlanRepository::lock_account(tx, account).await?;
+        let plan = Self::plan(&mut **tx, account).await?;
+        let excluding: Vec<Uuid> = replaced.iter().map(|d| d.id).collect();
+        let others = DeploymentRepository::usage_by_account(&mut **tx, account, &excluding).await?;
+
+        let current = replaced
+            .iter()
+            .map(|d| Self::footprint_of(d))
+            .sum::<Result<ResourceUsage, AppError>>()?;
+
+        let limits = Self::limits(&plan);
+        let checks = [
+            (
+                "deployments",
+                others.deployments,
+                current.deployments,
+                demand.deployments,
+                limits.deployments,
+            ),
+            (
+                "replicas",
+                others.replicas,
+                current.replicas,
+                demand.replicas,
+                limits.replicas,
+            ),
+            (
+                "millicores of CPU",
+                others.cpu_millicores,
+                current.cpu_millicores,
+                demand.cpu_millicores,
+                limits.cpu_millicores,
+            ),
+            (
+                "MiB of memory",
+                others.memory_mb,
+                current.memory_mb,
+                demand.memory_mb,
+                limits.memory_mb,
+            ),
+        ];
+
+        for (what, others, current, demand, limit) in checks {
+            let total = others + demand;
+            if demand > current && total > limit {
+                return Err(AppError::QuotaExceededError(format!(
+                    "The {} plan allows {} {}, this would use {}",
+                    plan.name, limit, what, total
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn usage(pool: &PgPool, account: BillingAccount) -> Result<QuotaResponse, AppError> {
+        let plan = Self::plan(pool, account).await?;
+        let usage = DeploymentRepository::usage_by_account(pool, account, &[]).await?;
+
+        Ok(QuotaResponse {
+            limits: Self::limits(&plan),
+            plan: plan.name,
+            usage,
+        })
+    }
+
+    async fn plan<'e>(
+        executor: impl PgExecutor<'e>,
+        account: BillingAccount,
+    ) -> Result<Plan, AppError> {
+        PlanRepository::get_for_account(executor, account)
+            .await?
+            .ok_or_else(|| AppError::InternalError("No default plan is configured".to_string()))
+    }
+
+    fn limits(plan: &Plan) -> ResourceUsage {
+        ResourceUsage {
+            deployments: plan.max_deployments as i64,
+            replicas: plan.max_replicas as i64,
+            cpu_millicores: plan.max_cpu_millicores as i64,
+            memory_mb: plan.max_memory_mb as i64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resources() {
+        assert_eq!(
+            QuotaService::resources(Some(ResourcePreset::Nano), None).unwrap(),
+            ResourcePreset::Nano.resources()
+        );
+        assert_eq!(
+            QuotaService::resources(None, None).unwrap(),
+            ResourceSpec::default()
+        );
+        assert!(matches!(
+            QuotaService::resources(Some(ResourcePreset::Small), Some(ResourceSpec::default())),
+            Err(AppError::ValidationError(_))
+        ));
+
+        let inverted = ResourceSpec {
+            cpu_request_millicores: 1000,
+            ..Default::default()
+        };
+        assert!(matches!(
+            QuotaService::resources(None, Some(inverted)),
+            Err(AppError::ValidationError(message)) if message.starts_with("CPU request")
+        ));
+    }
+}
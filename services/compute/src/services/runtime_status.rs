@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use k8s_openapi::api::core::v1::{ContainerStatus, Pod};
+use redis::AsyncCommands;
+use shared::services::redis::Redis;
+use shared::utilities::errors::AppError;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::features::models::Deployment;
+use crate::features::schemas::{DeploymentRuntimeStatus, PodStatusResponse};
+use crate::services::cluster::{ClusterBackend, ClusterObject, ObjectKind};
+
+/// Short enough to feel live, long enough that a polling dashboard
+/// doesn't turn into one API server round trip per request
+pub(crate) const CACHE_TTL_SECONDS: u64 = 5;
+
+type LocalCache = HashMap<Uuid, (Instant, DeploymentRuntimeStatus)>;
+
+/// Live replica and pod state of deployments, read from the cluster and
+/// cached briefly in Redis, shared by every compute replica
+#[derive(Clone)]
+pub struct RuntimeStatusService {
+    /// `None` keeps the cache on this replica
+    redis: Option<Redis>,
+    local: Arc<Mutex<LocalCache>>,
+}
+
+impl RuntimeStatusService {
+    pub fn new(redis: Redis) -> Self {
+        Self {
+            redis: Some(redis),
+            local: Default::default(),
+        }
+    }
+
+    /// Cache without Redis, kept in memory
+    #[cfg(test)]
+    pub fn local() -> Self {
+        Self {
+            redis: None,
+            local: Default::default(),
+        }
+    }
+
+    /// Runtime status from the cache, falling back to the cluster
+    pub async fn get(
+        &self,
+        cluster: &dyn ClusterBackend,
+        deployment: &Deployment,
+    ) -> Result<DeploymentRuntimeStatus, AppError> {
+        if let Some(status) = self.cached(deployment.id).await {
+            return Ok(status);
+        }
+
+        let status = Self::fetch(cluster, deployment).await?;
+        self.store(deployment.id, &status).await;
+
+        Ok(status)
+    }
+
+    /// A cache outage should only cost a cluster lookup
+    async fn cached(&self, deployment_id: Uuid) -> Option<DeploymentRuntimeStatus> {
+        let Some(redis) = &self.redis else {
+            let local = self.local.lock().unwrap();
+            return local
+                .get(&deployment_id)
+                .filter(|(stored_at, _)| {
+                    stored_at.elapsed() < Duration::from_secs(CACHE_TTL_SECONDS)
+                })
+                .map(|(_, status)| status.clone());
+        };
+
+        let key = Self::cache_key(deployment_id);
+        let mut connection = redis.connection.clone();
+        match connection.get::<_, Option<String>>(&key).await {
+            Ok(Some(cached)) => match serde_json::from_str(&cached) {
+                Ok(status) => Some(status),
+                Err(e) => {
+                    warn!("Discarding cached runtime status {}: {}", key, e);
+                    None
+                }
+            },
+            Ok(None) => None,
+            Err(e) => {
+                warn!("Failed to read runtime status cache: {}", e);
+                None
+            }
+        }
+    }
+
+    async fn store(&self, deployment_id: Uuid, status: &DeploymentRuntimeStatus) {
+        let Some(redis) = &self.redis else {
+            let mut local = self.local.lock().unwrap();
+            local.insert(deployment_id, (Instant::now(), status.clone()));
+            return;
+        };
+
+        let payload = match serde_json::to_string(status) {
+            Ok(payload) => payload,
+            Err(e) => {
+                warn!("Failed to serialize runtime status: {}", e);
+                return;
+            }
+        };
+        let mut connection = redis.connection.clone();
+        if let Err(e) = connection
+            .set_ex::<_, _, ()>(Self::cache_key(deployment_id), payload, CACHE_TTL_SECONDS)
+            .await
+        {
+            warn!("Failed to cache runtime status: {}", e);
+        }
+    }
+
+    fn cache_key(deployment_id: Uuid) -> String {
+        format!("compute:deployment:{}:runtime", deployment_id)
+    }
+
+    async fn fetch(
+        cluster: &dyn ClusterBackend,
+        deployment: &Deployment,
+    ) -> Result<DeploymentRuntimeStatus, AppError> {
+        let namespace = &deployment.cluster_namespace;
+        let name = &deployment.cluster_deployment_name;
+
+        let k8s_status = cluster
+            .get(ObjectKind::Deployment, namespace, name)
+            .await?
+            .and_then(ClusterObject::into_deployment)
+            .and_then(|d| d.status)
+            .unwrap_or_default();
+
+        // The Ingress always terminates TLS, see `build_ingress`
+        let external_url = cluster
+            .get(ObjectKind::Ingress, namespace, name)
+            .await?
+            .and_then(ClusterObject::into_ingress)
+            .and_then(|i| i.spec)
+            .and_then(|spec| spec.rules)
+            .and_then(|rules| rules.into_iter().find_map(|rule| rule.host))
+            .map(|host| format!("https://{}", host));
+
+        let labels = format!("deployment-id={}", deployment.id);
+        let mut pods: Vec<PodStatusResponse> = cluster
+            .pods(namespace, &labels)
+            .await?
+            .into_iter()
+            .map(Self::pod_status)
+            .collect();
+        pods.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(DeploymentRuntimeStatus {
+            ready_replicas: k8s_status.ready_replicas.unwrap_or(0),
+            available_replicas: k8s_status.available_replicas.unwrap_or(0),
+            updated_replicas: k8s_status.updated_replicas.unwrap_or(0),
+            external_url,
+            pods,
+        })
+    }
+
+    fn pod_status(pod: Pod) -> PodStatusResponse {
+        let status = pod.status.unwrap_or_default();
+        let containers: Vec<ContainerStatus> = status.container_statuses.unwrap_or_default();
+
+        PodStatusResponse {
+            name: pod.metadata.name.unwrap_or_default(),
+            phase: status.phase,
+            ready: !containers.is_empty() && containers.iter().all(|c| c.ready),
+            restart_count: containers.iter().map(|c| c.restart_count).sum(),
+            waiting_reason: containers.iter().find_map(|c| {
+                c.state
+                    .as_ref()
+                    .and_then(|s| s.waiting.as_ref())
+                    .and_then(|w| w.reason.clone())
+            }),
+            last_termination_reason: containers.iter().find_map(|c| {
+                c.last_state
+                    .as_ref()
+                    .and_then(|s| s.terminated.as_ref())
+                    .and_then(|t| t.reason.clone())
+            }),
+            node_name: pod.spec.and_then(|spec| spec.node_name),
+            started_at: status.start_time.map(|t| t.0),
+        }
+    }
+}
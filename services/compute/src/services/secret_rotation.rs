@@ -0,0 +1,170 @@
+use std::collections::{HashMap, hash_map::Entry};
+
+use shared::utilities::errors::AppError;
+use sqlx::PgPool;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::features::repository::{DeploymentDataKeyRepository, DeploymentSecretRepository};
+use crate::utilities::encryption::{DataKey, EncryptionService};
+
+#[derive(Debug, Default)]
+pub struct ReencryptionReport {
+    pub total: i64,
+    pub scanned: i64,
+    pub reencrypted: i64,
+    pub skipped: i64,
+    pub failed: i64,
+    pub rewrapped_keys: i64,
+}
+
+/// Rewraps data keys with the current KMS key and moves
+/// `deployment_secrets.value` onto per-deployment data keys
+pub struct SecretReencryptionJob;
+
+impl SecretReencryptionJob {
+    pub async fn run(
+        pool: &PgPool,
+        encryption_service: &EncryptionService,
+        batch_size: i64,
+    ) -> Result<ReencryptionReport, AppError> {
+        let mut report = ReencryptionReport {
+            total: DeploymentSecretRepository::count_all(pool).await?,
+            ..Default::default()
+        };
+
+        Self::rewrap_data_keys(pool, encryption_service, &mut report).await?;
+
+        info!(
+            "🔑 Re-encrypting {} secrets with per-deployment data keys",
+            report.total
+        );
+
+        let mut data_keys: HashMap<Uuid, DataKey> = HashMap::new();
+        let mut after_id = None;
+        loop {
+            let batch =
+                DeploymentSecretRepository::get_batch_after(pool, after_id, batch_size).await?;
+            let Some(last) = batch.last() else {
+                break;
+            };
+            after_id = Some(last.id);
+
+            for secret in batch {
+                report.scanned += 1;
+
+                let data_key = match data_keys.entry(secret.deployment_id) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => {
+                        let mut conn = pool.acquire().await?;
+                        match encryption_service
+                            .data_key(&mut conn, secret.deployment_id)
+                            .await
+                        {
+                            Ok(data_key) => entry.insert(data_key),
+                            Err(e) => {
+                                warn!(
+                                    "Failed to load data key of deployment {}: {}",
+                                    secret.deployment_id, e
+                                );
+                                report.failed += 1;
+                                continue;
+                            }
+                        }
+                    }
+                };
+
+                if !data_key.needs_reencryption(&secret.value) {
+                    report.skipped += 1;
+                    continue;
+                }
+
+                let reencrypted = data_key
+                    .decrypt(&secret.value)
+                    .and_then(|plaintext| data_key.encrypt(&plaintext));
+
+                match reencrypted {
+                    Ok(value) => {
+                        let swapped = DeploymentSecretRepository::swap_value(
+                            pool,
+                            secret.id,
+                            &secret.value,
+                            value,
+                        )
+                        .await?;
+
+                        if swapped {
+                            report.reencrypted += 1;
+                        } else {
+                            // Changed concurrently, so already written with the data key
+                            report.skipped += 1;
+                        }
+                    }
+                    Err(e) => {
+                        warn!("Failed to re-encrypt secret {}: {}", secret.id, e);
+                        report.failed += 1;
+                    }
+                }
+            }
+
+            info!(
+                "Re-encryption progress: {}/{} scanned, {} re-encrypted, {} failed",
+                report.scanned, report.total, report.reencrypted, report.failed
+            );
+        }
+
+        Ok(report)
+    }
+
+    /// Data keys are small, so rewrapping them is all a master key rotation needs
+    async fn rewrap_data_keys(
+        pool: &PgPool,
+        encryption_service: &EncryptionService,
+        report: &mut ReencryptionReport,
+    ) -> Result<(), AppError> {
+        let kms = encryption_service.kms();
+        let stale = DeploymentDataKeyRepository::get_stale(pool, kms.name(), kms.key_id()).await?;
+
+        info!(
+            "🔑 Rewrapping {} data keys with {} key '{}'",
+            stale.len(),
+            kms.name(),
+            kms.key_id()
+        );
+
+        for record in stale {
+            // Only the current provider can unwrap, keys from another provider
+            // need the old provider configured to be migrated
+            let rewrapped = match kms.unwrap_key(&record.wrapped_key).await {
+                Ok(key) => kms.wrap_key(&key).await,
+                Err(e) => Err(e),
+            };
+
+            match rewrapped {
+                Ok(wrapped_key) => {
+                    if DeploymentDataKeyRepository::swap_wrapped_key(
+                        pool,
+                        record.deployment_id,
+                        &record.wrapped_key,
+                        wrapped_key,
+                        kms.name(),
+                        kms.key_id(),
+                    )
+                    .await?
+                    {
+                        report.rewrapped_keys += 1;
+                    }
+                }
+                Err(e) => {
+                    warn!(
+                        "Failed to rewrap data key of deployment {}: {}",
+                        record.deployment_id, e
+                    );
+                    report.failed += 1;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,215 @@
+use std::time::Duration;
+
+use futures::{StreamExt, TryStreamExt};
+use k8s_openapi::api::apps::v1::Deployment as K8sDeployment;
+use kube::runtime::{WatchStreamExt, watcher};
+use kube::{Api, Client};
+use shared::utilities::errors::AppError;
+use sqlx::PgPool;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::features::repository::DeploymentRepository;
+use crate::features::schemas::ProjectUpdate;
+use crate::services::event_bus::EventBus;
+
+/// Watches the Kubernetes Deployments we manage and pushes their replica
+/// counts to the project streams
+pub struct StatusWatcher;
+
+impl StatusWatcher {
+    pub fn spawn(pool: PgPool, client: Client, events: EventBus) {
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = Self::watch(&pool, &client, &events).await {
+                    warn!("Deployment status watcher stopped: {}", e);
+                }
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+        });
+    }
+
+    async fn watch(pool: &PgPool, client: &Client, events: &EventBus) -> Result<(), AppError> {
+        let api: Api<K8sDeployment> = Api::all(client.clone());
+        let config = watcher::Config::default().labels("deployment-id");
+        let mut stream = watcher(api, config).applied_objects().boxed();
+
+        info!("👀 Watching deployment status");
+        while let Some(k8s_deployment) = stream
+            .try_next()
+            .await
+            .map_err(|e| AppError::InternalError(format!("Watch failed: {}", e)))?
+        {
+            if let Err(e) = Self::publish(pool, events, &k8s_deployment).await {
+                warn!("Failed to publish deployment status: {}", e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Push the replica counts of a watched Deployment, and record how its
+    /// rollout ended once it has
+    pub(crate) async fn publish(
+        pool: &PgPool,
+        events: &EventBus,
+        k8s_deployment: &K8sDeployment,
+    ) -> Result<(), AppError> {
+        let Some(deployment_id) = k8s_deployment
+            .metadata
+            .labels
+            .as_ref()
+            .and_then(|labels| labels.get("deployment-id"))
+            .and_then(|id| Uuid::parse_str(id).ok())
+        else {
+            return Ok(());
+        };
+
+        let Some(deployment) = DeploymentRepository::find_by_id(pool, deployment_id).await? else {
+            return Ok(());
+        };
+
+        let status = k8s_deployment.status.clone().unwrap_or_default();
+        let update = ProjectUpdate::Status {
+            project_id: deployment.project_id,
+            deployment_id: deployment.id,
+            status: deployment.status,
+            replicas: deployment.replicas,
+            ready_replicas: Some(status.ready_replicas.unwrap_or(0)),
+            available_replicas: Some(status.available_replicas.unwrap_or(0)),
+            updated_replicas: Some(status.updated_replicas.unwrap_or(0)),
+        };
+
+        // Every replica sees the same resource version, only one publishes it
+        let resource_version = k8s_deployment
+            .metadata
+            .resource_version
+            .as_deref()
+            .unwrap_or_default();
+        events
+            .publish_once(
+                &format!("deployment:{}:{}", deployment.id, resource_version),
+                &update,
+            )
+            .await;
+
+        // Recorded once per rollout, however many replicas watch it
+        if let Some((event_type, message)) = Self::rollout_outcome(k8s_deployment) {
+            let generation = k8s_deployment.metadata.generation.unwrap_or_default();
+            events
+                .record_once(
+                    pool,
+                    &deployment,
+                    event_type,
+                    Some(&message),
+                    &format!("{}:{}", event_type, generation),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether the current rollout has become ready or failed, `None` while it
+    /// is in progress
+    fn rollout_outcome(k8s_deployment: &K8sDeployment) -> Option<(&'static str, String)> {
+        let status = k8s_deployment.status.as_ref()?;
+        let generation = k8s_deployment.metadata.generation.unwrap_or_default();
+        if status.observed_generation.unwrap_or_default() < generation {
+            return None;
+        }
+
+        for condition in status.conditions.iter().flatten() {
+            let failed = match condition.type_.as_str() {
+                "Progressing" => {
+                    condition.status == "False"
+                        && condition.reason.as_deref() == Some("ProgressDeadlineExceeded")
+                }
+                "ReplicaFailure" => condition.status == "True",
+                _ => false,
+            };
+            if failed {
+                let message = condition
+                    .message
+                    .clone()
+                    .unwrap_or_else(|| format!("Rollout failed: {}", condition.type_));
+                return Some(("deployment_failed", message));
+            }
+        }
+
+        let replicas = k8s_deployment
+            .spec
+            .as_ref()
+            .and_then(|spec| spec.replicas)
+            .unwrap_or(1);
+        let ready = status.ready_replicas.unwrap_or(0);
+        if replicas > 0
+            && status.updated_replicas.unwrap_or(0) == replicas
+            && status.available_replicas.unwrap_or(0) == replicas
+            && ready == replicas
+        {
+            return Some((
+                "deployment_ready",
+                format!("{} of {} replicas ready", ready, replicas),
+            ));
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::apps::v1::{DeploymentCondition, DeploymentSpec, DeploymentStatus};
+    use kube::api::ObjectMeta;
+
+    use super::*;
+
+    #[test]
+    fn test_rollout_outcome() {
+        let k8s_deployment = |observed: i64, ready: i32, conditions| K8sDeployment {
+            metadata: ObjectMeta {
+                generation: Some(2),
+                ..Default::default()
+            },
+            spec: Some(DeploymentSpec {
+                replicas: Some(2),
+                ..Default::default()
+            }),
+            status: Some(DeploymentStatus {
+                observed_generation: Some(observed),
+                ready_replicas: Some(ready),
+                available_replicas: Some(ready),
+                updated_replicas: Some(ready),
+                conditions,
+                ..Default::default()
+            }),
+        };
+
+        assert_eq!(
+            StatusWatcher::rollout_outcome(&k8s_deployment(2, 2, None)),
+            Some(("deployment_ready", "2 of 2 replicas ready".to_string()))
+        );
+        // An older generation's status says nothing about the current rollout
+        assert_eq!(
+            StatusWatcher::rollout_outcome(&k8s_deployment(1, 2, None)),
+            None
+        );
+        assert_eq!(
+            StatusWatcher::rollout_outcome(&k8s_deployment(2, 1, None)),
+            None
+        );
+
+        let deadline_exceeded = DeploymentCondition {
+            type_: "Progressing".to_string(),
+            status: "False".to_string(),
+            reason: Some("ProgressDeadlineExceeded".to_string()),
+            message: Some("ReplicaSet \"web-5d4\" has timed out progressing.".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            StatusWatcher::rollout_outcome(&k8s_deployment(2, 1, Some(vec![deadline_exceeded]))),
+            Some(("deployment_failed", message)) if message.contains("timed out")
+        ));
+    }
+}
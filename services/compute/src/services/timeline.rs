@@ -0,0 +1,342 @@
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::response::sse::Event as SseEvent;
+use chrono::Utc;
+use futures::{Stream, StreamExt, future, stream};
+use k8s_openapi::api::core::v1::Event as K8sEvent;
+use shared::schemas::{ListResponse, Pagination};
+use shared::utilities::errors::AppError;
+use sqlx::PgPool;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::features::models::{Deployment, DeploymentEvent};
+use crate::features::repository::{DeploymentEventRepository, DeploymentRepository};
+use crate::features::schemas::{
+    DeploymentEventResponse, EventSource, ProjectUpdate, TimelineQuery,
+};
+use crate::services::build_kubernetes::Kubernetes;
+use crate::services::cluster::ClusterBackend;
+use crate::services::event_bus::EventBus;
+
+const CLUSTER_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// Events listed per object, Kubernetes keeps about an hour of them
+const CLUSTER_EVENT_LIMIT: u32 = 100;
+
+/// Merged timeline of our own `deployment_events` and native Kubernetes Events
+pub struct TimelineService;
+
+impl TimelineService {
+    /// One page of the timeline, newest first
+    pub async fn list(
+        pool: &PgPool,
+        kubernetes: &Kubernetes,
+        deployment_id: Uuid,
+        user_id: Uuid,
+        pagination: Pagination,
+        query: TimelineQuery,
+    ) -> Result<ListResponse<DeploymentEventResponse>, AppError> {
+        pagination.validate()?;
+        let deployment = DeploymentRepository::get_by_id(pool, deployment_id, user_id).await?;
+
+        // Both sources are sorted, so the first `offset + limit` of each is enough to merge
+        let window = pagination.offset + pagination.limit;
+        let (mut events, platform_total) =
+            Self::platform_events(pool, &deployment, &query, Some(window)).await?;
+        let cluster = kubernetes.backend(&deployment)?;
+        let cluster_events = Self::cluster_events(cluster, &deployment, &query).await;
+
+        let total = platform_total + cluster_events.len() as i64;
+        events.extend(cluster_events);
+        events.sort_by_key(|e| std::cmp::Reverse(e.created_at));
+
+        let data = events
+            .into_iter()
+            .skip(pagination.offset as usize)
+            .take(pagination.limit as usize)
+            .collect();
+
+        Ok(ListResponse { data, total })
+    }
+
+    /// Server-Sent Events of timeline entries as they appear, oldest first.
+    /// Our own events arrive through the event bus, Kubernetes Events are
+    /// polled and sent again whenever their count goes up.
+    pub async fn stream(
+        pool: PgPool,
+        kubernetes: Kubernetes,
+        events: EventBus,
+        deployment_id: Uuid,
+        user_id: Uuid,
+        query: TimelineQuery,
+    ) -> Result<impl Stream<Item = Result<SseEvent, Infallible>>, AppError> {
+        let deployment = DeploymentRepository::get_by_id(&pool, deployment_id, user_id).await?;
+        let backend = kubernetes.cluster(&deployment)?.backend.clone();
+
+        // Subscribed before reading what's stored, so nothing recorded in
+        // between is lost. Repeats of stored events are dropped.
+        let updates = events.updates(deployment.project_id);
+        let mut backlog_query = query.clone();
+        backlog_query.from = Some(query.from.unwrap_or_else(Utc::now));
+        let (mut backlog, _) =
+            Self::platform_events(&pool, &deployment, &backlog_query, None).await?;
+        let stored: HashSet<String> = backlog.iter().map(|e| e.id.clone()).collect();
+
+        let mut cluster = ClusterPoll {
+            backend,
+            deployment: deployment.clone(),
+            query: backlog_query,
+            seen: HashSet::new(),
+        };
+        backlog.extend(cluster.poll().await);
+        backlog.sort_by_key(|e| e.created_at);
+
+        let platform = match query.source {
+            Some(EventSource::Kubernetes) => stream::empty().boxed(),
+            _ => updates
+                .filter_map(move |update| {
+                    let event = match update {
+                        ProjectUpdate::Event {
+                            deployment_id,
+                            event,
+                            ..
+                        } if deployment_id == deployment.id
+                            && !stored.contains(&event.id)
+                            && Self::matches(&query, &event) =>
+                        {
+                            Some(event)
+                        }
+                        _ => None,
+                    };
+                    std::future::ready(event)
+                })
+                .boxed(),
+        };
+        let cluster = match cluster.query.source {
+            Some(EventSource::Platform) => stream::empty().boxed(),
+            _ => stream::unfold(cluster, |mut cluster| async move {
+                tokio::time::sleep(CLUSTER_POLL_INTERVAL).await;
+                let batch = cluster.poll().await;
+                Some((stream::iter(batch), cluster))
+            })
+            .flatten()
+            .boxed(),
+        };
+
+        Ok(stream::iter(backlog)
+            .chain(stream::select(platform, cluster))
+            .filter_map(|event| {
+                std::future::ready(
+                    SseEvent::default()
+                        .event("timeline")
+                        .json_data(&event)
+                        .ok()
+                        .map(Ok),
+                )
+            }))
+    }
+
+    async fn platform_events(
+        pool: &PgPool,
+        deployment: &Deployment,
+        query: &TimelineQuery,
+        limit: Option<i64>,
+    ) -> Result<(Vec<DeploymentEventResponse>, i64), AppError> {
+        if query.source == Some(EventSource::Kubernetes) {
+            return Ok((vec![], 0));
+        }
+
+        let event_types = query.event_types();
+        let total = DeploymentEventRepository::count_filtered(
+            pool,
+            deployment.id,
+            event_types.as_deref(),
+            query.from,
+            query.to,
+        )
+        .await?;
+
+        let events = DeploymentEventRepository::get_filtered(
+            pool,
+            deployment.id,
+            event_types.as_deref(),
+            query.from,
+            query.to,
+            limit.unwrap_or(total),
+        )
+        .await?;
+
+        Ok((
+            events.into_iter().map(Self::platform_event).collect(),
+            total,
+        ))
+    }
+
+    /// Events of the deployment's objects, empty when the cluster can't be reached
+    async fn cluster_events(
+        cluster: &dyn ClusterBackend,
+        deployment: &Deployment,
+        query: &TimelineQuery,
+    ) -> Vec<DeploymentEventResponse> {
+        if query.source == Some(EventSource::Platform) {
+            return vec![];
+        }
+
+        let names = match Self::object_names(cluster, deployment).await {
+            Ok(names) => names,
+            Err(e) => {
+                warn!(
+                    "Failed to list Kubernetes objects of deployment {}: {}",
+                    deployment.id, e
+                );
+                return vec![];
+            }
+        };
+
+        let lists =
+            future::join_all(names.iter().map(|name| {
+                cluster.events(&deployment.cluster_namespace, name, CLUSTER_EVENT_LIMIT)
+            }))
+            .await;
+
+        let mut events = vec![];
+        for list in lists {
+            match list {
+                Ok(list) => events.extend(list),
+                Err(e) => warn!(
+                    "Failed to list Kubernetes events of deployment {}: {}",
+                    deployment.id, e
+                ),
+            }
+        }
+
+        events
+            .into_iter()
+            .filter(|e| Self::belongs_to(e, &deployment.cluster_deployment_name))
+            .filter_map(Self::cluster_event)
+            .filter(|e| Self::matches(query, e))
+            .collect()
+    }
+
+    /// The Deployment and its current ReplicaSets and pods, whose events are
+    /// listed by name. Events of pods that are gone drop out with them.
+    async fn object_names(
+        cluster: &dyn ClusterBackend,
+        deployment: &Deployment,
+    ) -> Result<Vec<String>, AppError> {
+        let owned = cluster
+            .owned_objects(
+                &deployment.cluster_namespace,
+                &format!("deployment-id={}", deployment.id),
+            )
+            .await?;
+
+        let mut names = vec![deployment.cluster_deployment_name.clone()];
+        names.extend(owned);
+        Ok(names)
+    }
+
+    /// Whether an event passes the query's type and time filters
+    fn matches(query: &TimelineQuery, event: &DeploymentEventResponse) -> bool {
+        query
+            .event_types()
+            .is_none_or(|types| types.contains(&event.event_type))
+            && query.from.is_none_or(|from| event.created_at >= from)
+            && query.to.is_none_or(|to| event.created_at <= to)
+    }
+
+    /// Whether the event is about the Deployment, one of its ReplicaSets
+    /// (`<name>-<hash>`) or one of their pods (`<name>-<hash>-<suffix>`)
+    fn belongs_to(event: &K8sEvent, cluster_deployment_name: &str) -> bool {
+        let object = &event.involved_object;
+        let Some(name) = object.name.as_deref() else {
+            return false;
+        };
+
+        let segments = match name.strip_prefix(cluster_deployment_name) {
+            Some("") => 0,
+            Some(rest) => match rest.strip_prefix('-') {
+                Some(rest) => rest.split('-').count(),
+                None => return false,
+            },
+            None => return false,
+        };
+
+        matches!(
+            (object.kind.as_deref(), segments),
+            (Some("Deployment"), 0) | (Some("ReplicaSet"), 1) | (Some("Pod"), 2)
+        )
+    }
+
+    pub fn platform_event(event: DeploymentEvent) -> DeploymentEventResponse {
+        DeploymentEventResponse {
+            id: event.id.to_string(),
+            source: EventSource::Platform,
+            event_type: event.event_type,
+            severity: None,
+            object: None,
+            message: event.message,
+            count: 1,
+            created_at: event.created_at,
+        }
+    }
+
+    fn cluster_event(event: K8sEvent) -> Option<DeploymentEventResponse> {
+        let created_at = event
+            .last_timestamp
+            .map(|t| t.0)
+            .or(event.event_time.map(|t| t.0))
+            .or(event.first_timestamp.map(|t| t.0))
+            .or(event.metadata.creation_timestamp.map(|t| t.0))?;
+
+        let object = match (event.involved_object.kind, event.involved_object.name) {
+            (Some(kind), Some(name)) => Some(format!("{}/{}", kind, name)),
+            _ => None,
+        };
+
+        Some(DeploymentEventResponse {
+            id: event.metadata.uid.unwrap_or_default(),
+            source: EventSource::Kubernetes,
+            event_type: event.reason.unwrap_or_default(),
+            severity: event.type_,
+            object,
+            message: event.message,
+            count: event.count.unwrap_or(1),
+            created_at,
+        })
+    }
+}
+
+/// Kubernetes Events of a streamed timeline, sent when new or when their
+/// count went up
+struct ClusterPoll {
+    backend: Arc<dyn ClusterBackend>,
+    deployment: Deployment,
+    query: TimelineQuery,
+    seen: HashSet<(String, i32)>,
+}
+
+impl ClusterPoll {
+    async fn poll(&mut self) -> Vec<DeploymentEventResponse> {
+        let events =
+            TimelineService::cluster_events(self.backend.as_ref(), &self.deployment, &self.query)
+                .await;
+
+        let mut seen = HashSet::new();
+        let mut batch = vec![];
+        for event in events {
+            let key = (event.id.clone(), event.count);
+            if !self.seen.contains(&key) {
+                batch.push(event);
+            }
+            seen.insert(key);
+        }
+
+        self.seen = seen;
+        batch.sort_by_key(|e| e.created_at);
+        batch
+    }
+}
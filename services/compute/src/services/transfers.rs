@@ -0,0 +1,205 @@
+use chrono::{Duration, Utc};
+use serde_json::json;
+use shared::utilities::errors::AppError;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::features::models::{OrganizationRole, Project, ProjectTransfer};
+use crate::features::repository::{
+    AuditLogRepository, ProjectMemberRepository, ProjectRepository, ProjectTransferRepository,
+    UserRepository,
+};
+use crate::features::schemas::{ProjectTransferResponse, TransferProjectRequest};
+use crate::services::access::AccessService;
+use crate::utilities::tokens;
+
+const TOKEN_PREFIX: &str = "xfer_";
+const TOKEN_LEN: usize = 32;
+const TRANSFER_TTL_DAYS: i64 = 7;
+
+/// Handing a project to another account. The owner starts a transfer to an
+/// email, its recipient accepts it from the emailed link into their own
+/// account or an organization they administer.
+pub struct ProjectTransferService;
+
+impl ProjectTransferService {
+    /// Start a transfer, returns it and the token for its link. Starting
+    /// another replaces the pending one.
+    pub async fn initiate(
+        pool: &PgPool,
+        project_id: Uuid,
+        actor_id: Uuid,
+        req: TransferProjectRequest,
+    ) -> Result<(ProjectTransferResponse, String), AppError> {
+        let token = tokens::generate(TOKEN_PREFIX, TOKEN_LEN);
+        let transfer = ProjectTransferRepository::upsert(
+            pool,
+            project_id,
+            &req.email,
+            &tokens::hash(&token),
+            actor_id,
+            Utc::now() + Duration::days(TRANSFER_TTL_DAYS),
+        )
+        .await?;
+
+        AuditLogRepository::create(
+            pool,
+            Some(project_id),
+            Some(actor_id),
+            None,
+            "project_transfer_initiated",
+            json!({ "email": transfer.email }),
+        )
+        .await?;
+
+        Ok((Self::transfer_response(transfer), token))
+    }
+
+    pub fn transfer_link(frontend_endpoint: &str, token: &str) -> String {
+        format!(
+            "{}/transfers/accept?token={}",
+            frontend_endpoint.trim_end_matches('/'),
+            token
+        )
+    }
+
+    pub async fn pending(
+        pool: &PgPool,
+        project_id: Uuid,
+    ) -> Result<Option<ProjectTransferResponse>, AppError> {
+        Ok(
+            ProjectTransferRepository::get_pending_by_project(pool, project_id)
+                .await?
+                .map(Self::transfer_response),
+        )
+    }
+
+    pub async fn cancel(
+        pool: &PgPool,
+        project_id: Uuid,
+        transfer_id: Uuid,
+        actor_id: Uuid,
+    ) -> Result<(), AppError> {
+        let transfer = ProjectTransferRepository::delete(pool, transfer_id, project_id)
+            .await
+            .map_err(|e| match e {
+                sqlx::Error::RowNotFound => {
+                    AppError::NotFoundError("Transfer not found".to_string())
+                }
+                e => AppError::from(e),
+            })?;
+
+        AuditLogRepository::create(
+            pool,
+            Some(project_id),
+            Some(actor_id),
+            None,
+            "project_transfer_cancelled",
+            json!({ "email": transfer.email }),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Take over the project a transfer is for, into `organization_id` or
+    /// the user's own account. The project and its deployments change hands
+    /// at once, their cluster objects stay as they are and billing follows
+    /// the project from then on. Members keep their roles, the previous
+    /// owner keeps none.
+    pub async fn accept(
+        pool: &PgPool,
+        user_id: Uuid,
+        token: &str,
+        organization_id: Option<Uuid>,
+    ) -> Result<Project, AppError> {
+        let email = UserRepository::get_email(pool, user_id).await?;
+        if let Some(organization_id) = organization_id {
+            AccessService::organization(pool, organization_id, user_id, OrganizationRole::Admin)
+                .await?;
+        }
+
+        let mut tx = pool.begin().await?;
+
+        let transfer =
+            ProjectTransferRepository::find_pending_by_token_hash(&mut tx, &tokens::hash(token))
+                .await?
+                .ok_or_else(|| {
+                    AppError::NotFoundError("Transfer not found or expired".to_string())
+                })?;
+
+        if !transfer.email.eq_ignore_ascii_case(&email) {
+            return Err(AppError::ForbiddenError(
+                "This transfer was sent to another email address".to_string(),
+            ));
+        }
+
+        let previous = ProjectRepository::find_by_id(pool, transfer.project_id)
+            .await?
+            .ok_or_else(|| AppError::NotFoundError("Project not found".to_string()))?;
+        let unchanged = match organization_id {
+            Some(organization_id) => previous.organization_id == Some(organization_id),
+            None => previous.organization_id.is_none() && previous.owner_id == user_id,
+        };
+        if unchanged {
+            return Err(AppError::ConflictError(
+                "The project already belongs to this account".to_string(),
+            ));
+        }
+
+        let project =
+            ProjectRepository::transfer(&mut tx, transfer.project_id, user_id, organization_id)
+                .await
+                .map_err(|e| match e {
+                    sqlx::Error::Database(db) if db.is_unique_violation() => {
+                        AppError::ConflictError(format!(
+                            "A project named '{}' already exists there, rename one first",
+                            previous.name
+                        ))
+                    }
+                    e => AppError::from(e),
+                })?;
+        // Organization projects are owned through the organization
+        ProjectMemberRepository::replace_owner(
+            &mut tx,
+            project.id,
+            organization_id.is_none().then_some(user_id),
+        )
+        .await?;
+        ProjectTransferRepository::mark_accepted(&mut tx, transfer.id, user_id, organization_id)
+            .await?;
+
+        // Lands in the audit log of both the new and the previous owner
+        AuditLogRepository::create(
+            &mut *tx,
+            Some(project.id),
+            Some(user_id),
+            Some(previous.owner_id),
+            "project_transferred",
+            json!({
+                "from": { "userId": previous.owner_id, "organizationId": previous.organization_id },
+                "to": { "userId": user_id, "organizationId": organization_id },
+                "initiatedBy": transfer.initiated_by,
+            }),
+        )
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(project)
+    }
+
+    fn transfer_response(transfer: ProjectTransfer) -> ProjectTransferResponse {
+        ProjectTransferResponse {
+            id: transfer.id,
+            project_id: transfer.project_id,
+            email: transfer.email,
+            initiated_by: transfer.initiated_by,
+            expires_at: transfer.expires_at,
+            accepted_at: transfer.accepted_at,
+            accepted_by: transfer.accepted_by,
+            organization_id: transfer.organization_id,
+            created_at: transfer.created_at,
+        }
+    }
+}
@@ -0,0 +1,392 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde_json::json;
+use sha2::Sha256;
+use shared::schemas::Pagination;
+use shared::utilities::errors::AppError;
+use sqlx::PgPool;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::features::models::{
+    Deployment, DeploymentEvent, Webhook, WebhookDelivery, WebhookEvent,
+};
+use crate::features::repository::{
+    ProjectRepository, WebhookDeliveryRepository, WebhookRepository,
+};
+use crate::features::schemas::{CreateWebhookRequest, UpdateWebhookRequest, WebhookResponse};
+use crate::services::outbound::OutboundClient;
+use crate::utilities::encryption::EncryptionService;
+use crate::utilities::tokens;
+
+const DISPATCH_INTERVAL: Duration = Duration::from_secs(5);
+const DISPATCH_BATCH: i64 = 50;
+/// Long enough for a batch to time out on every receiver
+const DISPATCH_LEASE_SECONDS: i64 = 600;
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+/// Attempts before a delivery is given up on, spread over about 15 hours
+const MAX_ATTEMPTS: i32 = 12;
+const BASE_BACKOFF_SECONDS: i64 = 30;
+const MAX_BACKOFF_SECONDS: i64 = 6 * 60 * 60;
+const SECRET_PREFIX: &str = "whsec_";
+const SECRET_LEN: usize = 24;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Project webhooks notified of deployment lifecycle events.
+///
+/// Recording a deployment event queues a delivery per subscribed webhook, a
+/// background dispatcher sends them signed and retries failures with
+/// exponential backoff.
+pub struct WebhookService;
+
+impl WebhookService {
+    pub async fn list(
+        pool: &PgPool,
+        project_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<Vec<WebhookResponse>, AppError> {
+        ProjectRepository::get_one_by_id(pool, project_id, user_id).await?;
+        let webhooks = WebhookRepository::get_many_by_project(pool, project_id).await?;
+
+        Ok(webhooks
+            .into_iter()
+            .map(|webhook| Self::webhook_response(webhook, None))
+            .collect())
+    }
+
+    /// A generated secret is returned once, it can't be read back later
+    pub async fn create(
+        pool: &PgPool,
+        encryption: &EncryptionService,
+        project_id: Uuid,
+        user_id: Uuid,
+        req: CreateWebhookRequest,
+    ) -> Result<WebhookResponse, AppError> {
+        ProjectRepository::get_one_by_id(pool, project_id, user_id).await?;
+        OutboundClient::check(&req.url)?;
+
+        let (secret, generated) = match req.secret {
+            Some(secret) => (secret, false),
+            None => (tokens::generate(SECRET_PREFIX, SECRET_LEN), true),
+        };
+        let wrapped_secret = encryption.kms().wrap_key(secret.as_bytes()).await?;
+
+        let webhook = WebhookRepository::create(
+            pool,
+            project_id,
+            &req.url,
+            wrapped_secret,
+            encryption.kms().name(),
+            &Self::event_types(&req.event_types),
+        )
+        .await?;
+
+        Ok(Self::webhook_response(webhook, generated.then_some(secret)))
+    }
+
+    pub async fn update(
+        pool: &PgPool,
+        encryption: &EncryptionService,
+        project_id: Uuid,
+        webhook_id: Uuid,
+        user_id: Uuid,
+        req: UpdateWebhookRequest,
+    ) -> Result<WebhookResponse, AppError> {
+        ProjectRepository::get_one_by_id(pool, project_id, user_id).await?;
+        if let Some(url) = &req.url {
+            OutboundClient::check(url)?;
+        }
+
+        let wrapped_secret = match &req.secret {
+            Some(secret) => Some(encryption.kms().wrap_key(secret.as_bytes()).await?),
+            None => None,
+        };
+        let event_types = req.event_types.as_deref().map(Self::event_types);
+
+        let webhook = WebhookRepository::update(
+            pool,
+            webhook_id,
+            project_id,
+            req.url.as_deref(),
+            wrapped_secret,
+            req.secret.is_some().then(|| encryption.kms().name()),
+            event_types.as_deref(),
+            req.is_active,
+        )
+        .await?;
+
+        Ok(Self::webhook_response(webhook, None))
+    }
+
+    /// Pending deliveries of the webhook are dropped with it
+    pub async fn delete(
+        pool: &PgPool,
+        project_id: Uuid,
+        webhook_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<(), AppError> {
+        ProjectRepository::get_one_by_id(pool, project_id, user_id).await?;
+        WebhookRepository::delete(pool, webhook_id, project_id).await?;
+
+        Ok(())
+    }
+
+    pub async fn deliveries(
+        pool: &PgPool,
+        project_id: Uuid,
+        webhook_id: Uuid,
+        user_id: Uuid,
+        pagination: Pagination,
+    ) -> Result<(Vec<WebhookDelivery>, i64), AppError> {
+        ProjectRepository::get_one_by_id(pool, project_id, user_id).await?;
+        let webhook = WebhookRepository::get_by_id(pool, webhook_id, project_id).await?;
+
+        Ok(WebhookDeliveryRepository::get_many_by_webhook(pool, webhook.id, pagination).await?)
+    }
+
+    /// Queue a delivery's payload again as a new delivery, the original keeps
+    /// its outcome in the log
+    pub async fn redeliver(
+        pool: &PgPool,
+        project_id: Uuid,
+        webhook_id: Uuid,
+        delivery_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<WebhookDelivery, AppError> {
+        ProjectRepository::get_one_by_id(pool, project_id, user_id).await?;
+        let webhook = WebhookRepository::get_by_id(pool, webhook_id, project_id).await?;
+        let delivery = WebhookDeliveryRepository::get_by_id(pool, delivery_id, webhook.id).await?;
+
+        Ok(WebhookDeliveryRepository::create(
+            pool,
+            webhook.id,
+            delivery.deployment_id,
+            &delivery.event_type,
+            &delivery.payload,
+        )
+        .await?)
+    }
+
+    /// Queue deliveries of a recorded event. Best effort, a webhook must never
+    /// fail the request that caused the event.
+    pub async fn enqueue(pool: &PgPool, deployment: &Deployment, event: &DeploymentEvent) {
+        let Some(webhook_event) = WebhookEvent::from_event_type(&event.event_type) else {
+            return;
+        };
+
+        let payload = json!({
+            "id": event.id,
+            "type": webhook_event,
+            "createdAt": event.created_at,
+            "data": {
+                "projectId": deployment.project_id,
+                "deploymentId": deployment.id,
+                "name": deployment.name,
+                "image": deployment.image,
+                "status": deployment.status,
+                "replicas": deployment.replicas,
+                "message": event.message,
+            },
+        });
+
+        if let Err(e) = WebhookDeliveryRepository::enqueue(
+            pool,
+            deployment.project_id,
+            deployment.id,
+            webhook_event.as_str(),
+            &payload,
+        )
+        .await
+        {
+            warn!(
+                "Failed to queue webhooks of deployment {}: {}",
+                deployment.id, e
+            );
+        }
+    }
+
+    // ==============================================
+    // DISPATCHER
+    // ==============================================
+
+    pub fn spawn_dispatcher(pool: PgPool, encryption: EncryptionService, outbound: OutboundClient) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(DISPATCH_INTERVAL);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+            loop {
+                interval.tick().await;
+                if let Err(e) = Self::dispatch(&pool, &encryption, &outbound).await {
+                    warn!("Webhook dispatch failed: {}", e);
+                }
+            }
+        });
+
+        info!("📮 Webhook dispatcher started");
+    }
+
+    /// Send every due delivery once, returning how many were attempted
+    pub async fn dispatch(
+        pool: &PgPool,
+        encryption: &EncryptionService,
+        outbound: &OutboundClient,
+    ) -> Result<usize, AppError> {
+        let deliveries =
+            WebhookDeliveryRepository::claim_due(pool, DISPATCH_LEASE_SECONDS, DISPATCH_BATCH)
+                .await?;
+
+        for delivery in &deliveries {
+            let Some(webhook) = WebhookRepository::find_by_id(pool, delivery.webhook_id).await?
+            else {
+                continue;
+            };
+
+            let attempt = delivery.attempts + 1;
+            let retry_in_seconds = Self::backoff_seconds(attempt);
+            match Self::send(encryption, outbound, &webhook, delivery).await {
+                Ok(status) if (200..300).contains(&status) => {
+                    WebhookDeliveryRepository::mark_succeeded(pool, delivery.id, status).await?;
+                }
+                Ok(status) => {
+                    WebhookDeliveryRepository::mark_attempt_failed(
+                        pool,
+                        delivery.id,
+                        Some(status),
+                        &format!("Receiver responded with HTTP {}", status),
+                        retry_in_seconds,
+                    )
+                    .await?;
+                }
+                Err(e) => {
+                    WebhookDeliveryRepository::mark_attempt_failed(
+                        pool,
+                        delivery.id,
+                        None,
+                        &e.to_string(),
+                        retry_in_seconds,
+                    )
+                    .await?;
+                }
+            }
+        }
+
+        Ok(deliveries.len())
+    }
+
+    /// POST the payload, returning the receiver's status code
+    async fn send(
+        encryption: &EncryptionService,
+        outbound: &OutboundClient,
+        webhook: &Webhook,
+        delivery: &WebhookDelivery,
+    ) -> Result<i32, AppError> {
+        let secret = Self::unwrap_secret(encryption, webhook).await?;
+        let body = serde_json::to_string(&delivery.payload)?;
+        let timestamp = Utc::now().timestamp();
+
+        let response = outbound
+            .post(&webhook.url)?
+            .timeout(DELIVERY_TIMEOUT)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header("X-Webhook-Event", &delivery.event_type)
+            .header("X-Webhook-Delivery", delivery.id.to_string())
+            .header("X-Webhook-Timestamp", timestamp.to_string())
+            .header(
+                "X-Webhook-Signature",
+                format!("sha256={}", Self::signature(&secret, timestamp, &body)?),
+            )
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| AppError::InternalError(format!("Webhook request failed: {}", e)))?;
+
+        Ok(response.status().as_u16() as i32)
+    }
+
+    /// Hex HMAC-SHA256 of `{timestamp}.{body}`, receivers recompute it with
+    /// the webhook's secret and should reject stale timestamps
+    pub fn signature(secret: &[u8], timestamp: i64, body: &str) -> Result<String, AppError> {
+        let mut mac =
+            HmacSha256::new_from_slice(secret).map_err(|e| AppError::InvalidKey(e.to_string()))?;
+        mac.update(format!("{}.{}", timestamp, body).as_bytes());
+
+        Ok(mac
+            .finalize()
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect())
+    }
+
+    /// Delay before retrying after `attempt` failed, `None` once it was the last
+    fn backoff_seconds(attempt: i32) -> Option<i64> {
+        if attempt >= MAX_ATTEMPTS {
+            return None;
+        }
+
+        let factor = 1_i64 << (attempt - 1).clamp(0, 20);
+        Some((BASE_BACKOFF_SECONDS * factor).min(MAX_BACKOFF_SECONDS))
+    }
+
+    async fn unwrap_secret(
+        encryption: &EncryptionService,
+        webhook: &Webhook,
+    ) -> Result<Vec<u8>, AppError> {
+        if webhook.kms_provider != encryption.kms().name() {
+            return Err(AppError::DecryptionError(format!(
+                "Secret of webhook {} is wrapped by '{}', but '{}' is configured",
+                webhook.id,
+                webhook.kms_provider,
+                encryption.kms().name()
+            )));
+        }
+
+        encryption.kms().unwrap_key(&webhook.wrapped_secret).await
+    }
+
+    fn event_types(events: &[WebhookEvent]) -> Vec<String> {
+        let mut event_types: Vec<String> = events.iter().map(|e| e.as_str().to_string()).collect();
+        event_types.sort();
+        event_types.dedup();
+        event_types
+    }
+
+    fn webhook_response(webhook: Webhook, secret: Option<String>) -> WebhookResponse {
+        WebhookResponse {
+            id: webhook.id,
+            project_id: webhook.project_id,
+            url: webhook.url,
+            event_types: webhook.event_types,
+            is_active: webhook.is_active,
+            secret,
+            created_at: webhook.created_at,
+            updated_at: webhook.updated_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature_and_backoff() {
+        // Same as Python's hmac.new(secret, f"{timestamp}.{body}", sha256)
+        assert_eq!(
+            WebhookService::signature(b"whsec_test", 1700000000, r#"{"type":"deployment.ready"}"#)
+                .unwrap(),
+            "82395582d4719fae30696645320be37c31e22179e62ffe28936e66c8c5c5419f"
+        );
+
+        assert_eq!(WebhookService::backoff_seconds(1), Some(30));
+        assert_eq!(WebhookService::backoff_seconds(2), Some(60));
+        assert_eq!(
+            WebhookService::backoff_seconds(11),
+            Some(MAX_BACKOFF_SECONDS)
+        );
+        assert_eq!(WebhookService::backoff_seconds(MAX_ATTEMPTS), None);
+    }
+}
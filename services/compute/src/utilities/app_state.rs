@@ -1,4 +1,10 @@
-use crate::services::build_kubernetes::Kubernetes;
+use crate::{
+    services::{
+        billing::BillingClient, build_kubernetes::Kubernetes, event_bus::EventBus,
+        outbound::OutboundClient, runtime_status::RuntimeStatusService,
+    },
+    utilities::encryption::EncryptionService,
+};
 use axum::extract::FromRef;
 use reqwest::Client;
 use rustls::ClientConfig;
@@ -11,12 +17,17 @@ use shared::{
 pub struct AppState {
     pub rustls_config: Option<ClientConfig>,
     pub kubernetes: Kubernetes,
+    pub billing: BillingClient,
+    pub encryption: EncryptionService,
+    pub events: EventBus,
     pub database: Database,
     pub redis: Redis,
     pub amqp: Amqp,
     pub kafka: Kafka,
     pub config: Config,
     pub http_client: Client,
+    pub outbound: OutboundClient,
+    pub runtime_status: RuntimeStatusService,
 }
 
 impl FromRef<AppState> for Kubernetes {
@@ -25,6 +36,24 @@ impl FromRef<AppState> for Kubernetes {
     }
 }
 
+impl FromRef<AppState> for BillingClient {
+    fn from_ref(state: &AppState) -> Self {
+        state.billing.clone()
+    }
+}
+
+impl FromRef<AppState> for EncryptionService {
+    fn from_ref(state: &AppState) -> Self {
+        state.encryption.clone()
+    }
+}
+
+impl FromRef<AppState> for EventBus {
+    fn from_ref(state: &AppState) -> Self {
+        state.events.clone()
+    }
+}
+
 impl FromRef<AppState> for Database {
     fn from_ref(state: &AppState) -> Self {
         state.database.clone()
@@ -60,3 +89,15 @@ impl FromRef<AppState> for Client {
         state.http_client.clone()
     }
 }
+
+impl FromRef<AppState> for OutboundClient {
+    fn from_ref(state: &AppState) -> Self {
+        state.outbound.clone()
+    }
+}
+
+impl FromRef<AppState> for RuntimeStatusService {
+    fn from_ref(state: &AppState) -> Self {
+        state.runtime_status.clone()
+    }
+}
@@ -1,17 +1,125 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
 use aes_gcm::{
     Aes256Gcm, Nonce,
     aead::{Aead, KeyInit, OsRng, rand_core::RngCore},
 };
 use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
-use shared::utilities::errors::AppError;
+use shared::utilities::{config::Config, errors::AppError};
+use sqlx::PgConnection;
+use uuid::Uuid;
 
-pub struct EncryptionService {
-    cipher: Aes256Gcm,
+use crate::features::models::DeploymentDataKey;
+use crate::features::repository::DeploymentDataKeyRepository;
+use crate::services::kms::KmsProvider;
+
+/// Version byte written in front of every ciphertext produced by `encrypt`
+const FORMAT_VERSION: u8 = 1;
+const NONCE_LEN: usize = 12;
+
+/// Key id used when only the legacy `K8S_ENCRYPTION_KEY` is configured
+const DEFAULT_KEY_ID: &str = "default";
+
+/// Set of AES-256-GCM master keys addressed by id.
+///
+/// Ciphertext layout: `version (1) | key id length (1) | key id | nonce (12) | ciphertext`.
+/// Values written before key ids existed (`nonce | ciphertext`) are still
+/// readable through the legacy key.
+#[derive(Clone)]
+pub struct Keyring {
+    keys: HashMap<String, Aes256Gcm>,
+    active_key_id: String,
+    legacy: Option<Aes256Gcm>,
 }
 
-impl EncryptionService {
-    /// Create new encryption service from a base64-encoded 32-byte key
+impl Keyring {
+    /// Create a single-key keyring from a base64-encoded 32-byte key
     pub fn new(key_base64: &str) -> Result<Self, AppError> {
+        let cipher = Self::cipher(key_base64)?;
+
+        Ok(Self {
+            keys: HashMap::from([(DEFAULT_KEY_ID.to_string(), cipher.clone())]),
+            active_key_id: DEFAULT_KEY_ID.to_string(),
+            legacy: Some(cipher),
+        })
+    }
+
+    /// Build the keyring from `K8S_ENCRYPTION_KEYS` (`id:base64,id:base64`),
+    /// `K8S_ENCRYPTION_ACTIVE_KEY_ID` and the legacy `K8S_ENCRYPTION_KEY`.
+    /// Returns `None` when none of them is set.
+    pub fn from_config(config: &Config) -> Result<Option<Self>, AppError> {
+        match (&config.k8s_encryption_keys, &config.k8s_encryption_key) {
+            (Some(entries), legacy) => Self::parse(
+                entries,
+                config.k8s_encryption_active_key_id.as_deref(),
+                legacy.as_deref(),
+            )
+            .map(Some),
+            (None, Some(legacy)) => Self::new(legacy).map(Some),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Parse `id:base64` entries separated by commas or newlines. The active
+    /// key defaults to the first entry.
+    pub fn parse(
+        entries: &str,
+        active_key_id: Option<&str>,
+        legacy_key: Option<&str>,
+    ) -> Result<Self, AppError> {
+        let legacy = legacy_key.map(Self::cipher).transpose()?;
+
+        let mut keys = HashMap::new();
+        let mut first_key_id = None;
+        for entry in entries
+            .split([',', '\n'])
+            .map(str::trim)
+            .filter(|e| !e.is_empty())
+        {
+            let (key_id, key_base64) = entry.split_once(':').ok_or_else(|| {
+                AppError::InvalidKey(format!("Keyring entry '{}' must be id:key", entry))
+            })?;
+            let key_id = key_id.trim();
+
+            if key_id.is_empty() || key_id.len() > u8::MAX as usize {
+                return Err(AppError::InvalidKey(format!(
+                    "Key id '{}' must be 1-255 bytes",
+                    key_id
+                )));
+            }
+            if keys
+                .insert(key_id.to_string(), Self::cipher(key_base64.trim())?)
+                .is_some()
+            {
+                return Err(AppError::InvalidKey(format!(
+                    "Duplicate key id '{}'",
+                    key_id
+                )));
+            }
+            first_key_id.get_or_insert_with(|| key_id.to_string());
+        }
+
+        let active_key_id = active_key_id
+            .map(str::to_string)
+            .or(first_key_id)
+            .ok_or_else(|| AppError::InvalidKey("Keyring is empty".to_string()))?;
+
+        if !keys.contains_key(&active_key_id) {
+            return Err(AppError::InvalidKey(format!(
+                "Active key id '{}' is not in the keyring",
+                active_key_id
+            )));
+        }
+
+        Ok(Self {
+            keys,
+            active_key_id,
+            legacy,
+        })
+    }
+
+    fn cipher(key_base64: &str) -> Result<Aes256Gcm, AppError> {
         let key_bytes = BASE64
             .decode(key_base64)
             .map_err(|e| AppError::InvalidKey(e.to_string()))?;
@@ -20,10 +128,7 @@ impl EncryptionService {
             return Err(AppError::InvalidKey("Key must be 32 bytes".to_string()));
         }
 
-        let cipher = Aes256Gcm::new_from_slice(&key_bytes)
-            .map_err(|e| AppError::InvalidKey(e.to_string()))?;
-
-        Ok(Self { cipher })
+        Aes256Gcm::new_from_slice(&key_bytes).map_err(|e| AppError::InvalidKey(e.to_string()))
     }
 
     /// Generate a new random encryption key (base64-encoded)
@@ -33,57 +138,320 @@ impl EncryptionService {
         BASE64.encode(key)
     }
 
-    /// Encrypt data and return bytes (nonce + ciphertext)
-    pub fn encrypt(&self, plaintext: &str) -> Result<Vec<u8>, AppError> {
-        let mut nonce_bytes = [0u8; 12];
-        OsRng.fill_bytes(&mut nonce_bytes);
-        #[allow(deprecated)]
-        let nonce = Nonce::from_slice(&nonce_bytes);
+    /// Id of the key new ciphertexts are written with
+    pub fn active_key_id(&self) -> &str {
+        &self.active_key_id
+    }
 
-        let ciphertext = self
-            .cipher
-            .encrypt(nonce, plaintext.as_bytes())
-            .map_err(|e| AppError::EncryptionError(e.to_string()))?;
+    /// Encrypt data with the active key and return versioned bytes
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, AppError> {
+        let sealed = seal(&self.keys[&self.active_key_id], plaintext)?;
 
-        // Store nonce + ciphertext together
-        let mut result = nonce_bytes.to_vec();
-        result.extend_from_slice(&ciphertext);
+        let key_id = self.active_key_id.as_bytes();
+        let mut result = Vec::with_capacity(2 + key_id.len() + sealed.len());
+        result.push(FORMAT_VERSION);
+        result.push(key_id.len() as u8);
+        result.extend_from_slice(key_id);
+        result.extend_from_slice(&sealed);
 
         Ok(result)
     }
 
-    /// Decrypt data from bytes (nonce + ciphertext)
+    /// Decrypt versioned bytes with the key they name, falling back to the
+    /// legacy `nonce + ciphertext` layout
+    pub fn decrypt(&self, encrypted_data: &[u8]) -> Result<Vec<u8>, AppError> {
+        if let Some((key_id, payload)) = Self::split_header(encrypted_data) {
+            match self.keys.get(key_id) {
+                Some(cipher) => {
+                    if let Ok(plaintext) = open(cipher, payload) {
+                        return Ok(plaintext);
+                    }
+                }
+                None if self.legacy.is_none() => {
+                    return Err(AppError::DecryptionError(format!(
+                        "Unknown encryption key id '{}'",
+                        key_id
+                    )));
+                }
+                None => {}
+            }
+        }
+
+        // The header check can't tell a legacy value whose nonce happens to
+        // start like a header apart from a real one, so try the legacy key too
+        let legacy = self
+            .legacy
+            .as_ref()
+            .ok_or_else(|| AppError::DecryptionError("No key can decrypt this value".into()))?;
+
+        open(legacy, encrypted_data)
+    }
+
+    /// Key id recorded in the ciphertext header, `None` for legacy values
+    pub fn key_id<'a>(&self, encrypted_data: &'a [u8]) -> Option<&'a str> {
+        Self::split_header(encrypted_data)
+            .map(|(key_id, _)| key_id)
+            .filter(|key_id| self.keys.contains_key(*key_id))
+    }
+
+    /// Whether the value was written with anything other than the active key
+    pub fn needs_reencryption(&self, encrypted_data: &[u8]) -> bool {
+        self.key_id(encrypted_data) != Some(self.active_key_id.as_str())
+    }
+
+    fn split_header(encrypted_data: &[u8]) -> Option<(&str, &[u8])> {
+        let (&version, rest) = encrypted_data.split_first()?;
+        if version != FORMAT_VERSION {
+            return None;
+        }
+
+        let (&key_id_len, rest) = rest.split_first()?;
+        let key_id_len = key_id_len as usize;
+        if key_id_len == 0 || rest.len() < key_id_len + NONCE_LEN {
+            return None;
+        }
+
+        let (key_id, payload) = rest.split_at(key_id_len);
+        Some((std::str::from_utf8(key_id).ok()?, payload))
+    }
+}
+
+/// Version byte of values encrypted with a deployment data key
+const ENVELOPE_VERSION: u8 = 2;
+const DATA_KEY_LEN: usize = 32;
+
+/// Envelope encryption for deployment secrets.
+///
+/// Every deployment gets its own random data key which encrypts its secrets.
+/// The data key is wrapped by the configured KMS and stored in
+/// `deployment_data_keys`; the master key never leaves the KMS.
+#[derive(Clone)]
+pub struct EncryptionService {
+    kms: Arc<dyn KmsProvider>,
+    keyring: Option<Keyring>,
+}
+
+impl EncryptionService {
+    /// `keyring` is only used to read values written before envelope encryption
+    pub fn new(kms: Arc<dyn KmsProvider>, keyring: Option<Keyring>) -> Self {
+        Self { kms, keyring }
+    }
+
+    pub fn kms(&self) -> &dyn KmsProvider {
+        self.kms.as_ref()
+    }
+
+    /// Load the deployment's data key, generating and wrapping one on first use
+    pub async fn data_key(
+        &self,
+        conn: &mut PgConnection,
+        deployment_id: Uuid,
+    ) -> Result<DataKey, AppError> {
+        if let Some(record) = DeploymentDataKeyRepository::get(&mut *conn, deployment_id).await? {
+            return self.unwrap(&record).await;
+        }
+
+        let mut key = [0u8; DATA_KEY_LEN];
+        OsRng.fill_bytes(&mut key);
+        let wrapped_key = self.kms.wrap_key(&key).await?;
+
+        let created = DeploymentDataKeyRepository::create_if_absent(
+            &mut *conn,
+            deployment_id,
+            wrapped_key,
+            self.kms.name(),
+            self.kms.key_id(),
+        )
+        .await?;
+
+        match created {
+            Some(_) => self.data_key_from_bytes(&key),
+            // Lost the race against a concurrent request, use its key
+            None => {
+                let record = DeploymentDataKeyRepository::get(&mut *conn, deployment_id)
+                    .await?
+                    .ok_or(AppError::OptionError)?;
+                self.unwrap(&record).await
+            }
+        }
+    }
+
+    /// Unwrap a stored data key through the KMS
+    pub async fn unwrap(&self, record: &DeploymentDataKey) -> Result<DataKey, AppError> {
+        if record.kms_provider != self.kms.name() {
+            return Err(AppError::DecryptionError(format!(
+                "Data key of deployment {} is wrapped by '{}', but '{}' is configured",
+                record.deployment_id,
+                record.kms_provider,
+                self.kms.name()
+            )));
+        }
+
+        let key = self.kms.unwrap_key(&record.wrapped_key).await?;
+        self.data_key_from_bytes(&key)
+    }
+
+    fn data_key_from_bytes(&self, key: &[u8]) -> Result<DataKey, AppError> {
+        if key.len() != DATA_KEY_LEN {
+            return Err(AppError::InvalidKey(
+                "Data key must be 32 bytes".to_string(),
+            ));
+        }
+
+        Ok(DataKey {
+            cipher: Aes256Gcm::new_from_slice(key)
+                .map_err(|e| AppError::InvalidKey(e.to_string()))?,
+            keyring: self.keyring.clone(),
+        })
+    }
+}
+
+/// Plaintext data key of a single deployment
+pub struct DataKey {
+    cipher: Aes256Gcm,
+    keyring: Option<Keyring>,
+}
+
+impl DataKey {
+    /// Encrypt and return bytes (version + nonce + ciphertext)
+    pub fn encrypt(&self, plaintext: &str) -> Result<Vec<u8>, AppError> {
+        let mut result = vec![ENVELOPE_VERSION];
+        result.extend(seal(&self.cipher, plaintext.as_bytes())?);
+        Ok(result)
+    }
+
+    /// Decrypt a value written by `encrypt`, or by the keyring before
+    /// envelope encryption was introduced
     pub fn decrypt(&self, encrypted_data: &[u8]) -> Result<String, AppError> {
-        if encrypted_data.len() < 12 {
-            return Err(AppError::InvalidFormat);
+        let plaintext = match self.open(encrypted_data) {
+            Some(plaintext) => plaintext,
+            None => self
+                .keyring
+                .as_ref()
+                .ok_or_else(|| {
+                    AppError::DecryptionError("Value was not encrypted with this data key".into())
+                })?
+                .decrypt(encrypted_data)?,
+        };
+
+        String::from_utf8(plaintext).map_err(AppError::FromUtf8Error)
+    }
+
+    /// Whether the value still has to be moved onto this data key
+    pub fn needs_reencryption(&self, encrypted_data: &[u8]) -> bool {
+        self.open(encrypted_data).is_none()
+    }
+
+    fn open(&self, encrypted_data: &[u8]) -> Option<Vec<u8>> {
+        match encrypted_data.split_first() {
+            Some((&ENVELOPE_VERSION, payload)) => open(&self.cipher, payload).ok(),
+            _ => None,
         }
+    }
+}
 
-        let (nonce_bytes, ciphertext) = encrypted_data.split_at(12);
-        #[allow(deprecated)]
-        let nonce = Nonce::from_slice(nonce_bytes);
+/// Encrypt and return bytes (nonce + ciphertext)
+fn seal(cipher: &Aes256Gcm, plaintext: &[u8]) -> Result<Vec<u8>, AppError> {
+    let mut nonce_bytes = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce_bytes);
+    #[allow(deprecated)]
+    let nonce = Nonce::from_slice(&nonce_bytes);
+
+    let ciphertext = cipher
+        .encrypt(nonce, plaintext)
+        .map_err(|e| AppError::EncryptionError(e.to_string()))?;
 
-        let plaintext = self
-            .cipher
-            .decrypt(nonce, ciphertext)
-            .map_err(|e| AppError::DecryptionError(e.to_string()))?;
+    let mut result = nonce_bytes.to_vec();
+    result.extend_from_slice(&ciphertext);
+    Ok(result)
+}
 
-        String::from_utf8(plaintext).map_err(|e| AppError::FromUtf8Error(e))
+/// Decrypt bytes (nonce + ciphertext)
+fn open(cipher: &Aes256Gcm, payload: &[u8]) -> Result<Vec<u8>, AppError> {
+    if payload.len() < NONCE_LEN {
+        return Err(AppError::InvalidFormat);
     }
+
+    let (nonce_bytes, ciphertext) = payload.split_at(NONCE_LEN);
+    #[allow(deprecated)]
+    let nonce = Nonce::from_slice(nonce_bytes);
+
+    cipher
+        .decrypt(nonce, ciphertext)
+        .map_err(|e| AppError::DecryptionError(e.to_string()))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn keyring(keys: &[(&str, &str)], active: &str) -> Keyring {
+        let entries: Vec<String> = keys.iter().map(|(id, key)| format!("{id}:{key}")).collect();
+        Keyring::parse(&entries.join(","), Some(active), None).unwrap()
+    }
+
     #[test]
     fn test_encryption_decryption() {
-        let key = EncryptionService::generate_key();
-        let service = EncryptionService::new(&key).unwrap();
+        let key = Keyring::generate_key();
+        let keyring = Keyring::new(&key).unwrap();
+
+        let original = b"my-secret-password";
+        let encrypted = keyring.encrypt(original).unwrap();
+        let decrypted = keyring.decrypt(&encrypted).unwrap();
+
+        assert_eq!(original.to_vec(), decrypted);
+    }
+
+    #[test]
+    fn test_rotation_keeps_old_keys_readable() {
+        let old_key = Keyring::generate_key();
+        let new_key = Keyring::generate_key();
+
+        let before = keyring(&[("k1", &old_key)], "k1");
+        let encrypted = before.encrypt(b"value").unwrap();
+
+        let after = keyring(&[("k1", &old_key), ("k2", &new_key)], "k2");
+        assert_eq!(after.key_id(&encrypted), Some("k1"));
+        assert!(after.needs_reencryption(&encrypted));
+        assert_eq!(after.decrypt(&encrypted).unwrap(), b"value");
+
+        let reencrypted = after.encrypt(b"value").unwrap();
+        assert!(!after.needs_reencryption(&reencrypted));
+    }
+
+    #[test]
+    fn test_legacy_ciphertext_is_readable() {
+        let key = Keyring::generate_key();
+        let cipher = Keyring::cipher(&key).unwrap();
+
+        let nonce_bytes = [1u8; NONCE_LEN];
+        #[allow(deprecated)]
+        let nonce = Nonce::from_slice(&nonce_bytes);
+        let mut legacy = nonce_bytes.to_vec();
+        legacy.extend_from_slice(&cipher.encrypt(nonce, b"old".as_ref()).unwrap());
+
+        let keyring = Keyring::new(&key).unwrap();
+        assert!(keyring.needs_reencryption(&legacy));
+        assert_eq!(keyring.decrypt(&legacy).unwrap(), b"old");
+    }
+
+    #[test]
+    fn test_data_key_reads_keyring_values() {
+        let keyring = Keyring::new(&Keyring::generate_key()).unwrap();
+        let before_envelope = keyring.encrypt(b"value").unwrap();
+
+        let mut key = [0u8; DATA_KEY_LEN];
+        OsRng.fill_bytes(&mut key);
+        let data_key = DataKey {
+            cipher: Aes256Gcm::new_from_slice(&key).unwrap(),
+            keyring: Some(keyring),
+        };
 
-        let original = "my-secret-password";
-        let encrypted = service.encrypt(original).unwrap();
-        let decrypted = service.decrypt(&encrypted).unwrap();
+        assert!(data_key.needs_reencryption(&before_envelope));
+        assert_eq!(data_key.decrypt(&before_envelope).unwrap(), "value");
 
-        assert_eq!(original, decrypted);
+        let encrypted = data_key.encrypt("value").unwrap();
+        assert!(!data_key.needs_reencryption(&encrypted));
+        assert_eq!(data_key.decrypt(&encrypted).unwrap(), "value");
     }
 }
@@ -1,2 +1,4 @@
 pub mod app_state;
 pub mod encryption;
+pub mod naming;
+pub mod tokens;
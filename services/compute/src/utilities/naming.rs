@@ -0,0 +1,184 @@
+//! Names for Kubernetes objects and hostnames derived from user input.
+//!
+//! Every generated name is a DNS-1035 label: lowercase alphanumerics and `-`,
+//! starting with a letter and ending with an alphanumeric. That is the
+//! strictest rule among the objects we create (Services), so one name fits
+//! the Deployment, Service, Ingress and Secret alike. Names are a readable
+//! slug plus a short hash of the exact input, which keeps them stable and
+//! keeps inputs that slugify the same (`My.App`, `my_app`) apart.
+
+use sha2::{Digest, Sha256};
+use shared::utilities::errors::AppError;
+use uuid::Uuid;
+
+/// DNS label limit, applies to Services and to every hostname label
+pub const MAX_LABEL_LEN: usize = 63;
+
+/// Kubernetes appends `-<10 char hash>-<5 char suffix>` to a Deployment's
+/// name for its pods, whose hostnames are labels too
+pub const MAX_DEPLOYMENT_NAME_LEN: usize = MAX_LABEL_LEN - 16;
+
+const HASH_LEN: usize = 8;
+
+/// Used when a slug would otherwise start with a digit
+const LETTER_PREFIX: &str = "app-";
+
+/// `cluster_deployment_name` of a new deployment, unique per project and name
+pub fn cluster_deployment_name(project_id: Uuid, name: &str) -> Result<String, AppError> {
+    hashed_label(
+        "name",
+        &project_id.to_string(),
+        name,
+        MAX_DEPLOYMENT_NAME_LEN,
+    )
+}
+
+/// Hostname label generated for a deployment, `seed` scopes it (e.g. a user
+/// or project id) so equal names of different owners don't collide
+pub fn generated_subdomain(seed: &str, name: &str) -> Result<String, AppError> {
+    hashed_label("subdomain", seed, name, MAX_LABEL_LEN)
+}
+
+/// `value` as a label, readable as is when it already fits. Only values that
+/// are too long get truncated and hashed.
+pub fn fit_label(field: &str, value: &str) -> Result<String, AppError> {
+    let slug = slugify(value);
+    if slug.is_empty() {
+        return Err(empty_error(field));
+    }
+
+    let slug = with_letter_start(slug);
+    if slug.len() <= MAX_LABEL_LEN {
+        return Ok(slug);
+    }
+    hashed_label(field, "", value, MAX_LABEL_LEN)
+}
+
+/// `<slug>-<hash>` no longer than `max_len`
+fn hashed_label(field: &str, seed: &str, value: &str, max_len: usize) -> Result<String, AppError> {
+    let slug = slugify(value);
+    if slug.is_empty() {
+        return Err(empty_error(field));
+    }
+
+    let slug = with_letter_start(slug);
+    let hash = short_hash(seed, value);
+    let budget = max_len - HASH_LEN - 1;
+    let slug = slug[..slug.len().min(budget)].trim_end_matches('-');
+
+    Ok(format!("{}-{}", slug, hash))
+}
+
+/// Lowercase, every run of other characters becomes one `-`, no `-` at the ends
+pub fn slugify(value: &str) -> String {
+    let mut slug = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+fn with_letter_start(slug: String) -> String {
+    if slug.starts_with(|c: char| c.is_ascii_lowercase()) {
+        slug
+    } else {
+        format!("{}{}", LETTER_PREFIX, slug)
+    }
+}
+
+fn short_hash(seed: &str, value: &str) -> String {
+    let digest = Sha256::new()
+        .chain_update(seed.as_bytes())
+        .chain_update([0])
+        .chain_update(value.as_bytes())
+        .finalize();
+    digest
+        .iter()
+        .take(HASH_LEN / 2)
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn empty_error(field: &str) -> AppError {
+    AppError::ValidationError(format!(
+        "{} must contain at least one ASCII letter or digit",
+        field
+    ))
+}
+
+/// Whether `value` is a DNS-1035 label no longer than `max_len`
+pub fn is_dns_label(value: &str, max_len: usize) -> bool {
+    !value.is_empty()
+        && value.len() <= max_len
+        && value.starts_with(|c: char| c.is_ascii_lowercase())
+        && value.ends_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && value
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_cluster_deployment_name() {
+        let project_id = Uuid::nil();
+        let name = cluster_deployment_name(project_id, "My.App").unwrap();
+        assert!(name.starts_with("my-app-"));
+        assert_ne!(name, cluster_deployment_name(project_id, "my_app").unwrap());
+        assert!(
+            cluster_deployment_name(project_id, "42")
+                .unwrap()
+                .starts_with("app-42-")
+        );
+
+        assert!(matches!(
+            cluster_deployment_name(project_id, "___"),
+            Err(AppError::ValidationError(message)) if message.starts_with("name ")
+        ));
+    }
+
+    #[test]
+    fn test_fit_label_keeps_short_values() {
+        assert_eq!(fit_label("subdomain", "pr-42-api").unwrap(), "pr-42-api");
+        assert_eq!(
+            fit_label("subdomain", &"a".repeat(80)).unwrap().len(),
+            MAX_LABEL_LEN
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn prop_names_are_dns_labels(name in ".{0,200}", seed in any::<u128>()) {
+            let project_id = Uuid::from_u128(seed);
+            if let Ok(generated) = cluster_deployment_name(project_id, &name) {
+                prop_assert!(is_dns_label(&generated, MAX_DEPLOYMENT_NAME_LEN), "{}", generated);
+                let secret_name = format!("{}-secrets", generated);
+                prop_assert!(is_dns_label(&secret_name, MAX_LABEL_LEN));
+                prop_assert_eq!(&generated, &cluster_deployment_name(project_id, &name).unwrap());
+            } else {
+                prop_assert!(slugify(&name).is_empty());
+            }
+
+            if let Ok(label) = fit_label("subdomain", &name) {
+                prop_assert!(is_dns_label(&label, MAX_LABEL_LEN), "{}", label);
+            }
+        }
+
+        #[test]
+        fn prop_distinct_names_get_distinct_results(a in "[a-zA-Z0-9._ -]{1,80}", b in "[a-zA-Z0-9._ -]{1,80}") {
+            prop_assume!(a != b);
+            let project_id = Uuid::nil();
+            if let (Ok(x), Ok(y)) = (cluster_deployment_name(project_id, &a), cluster_deployment_name(project_id, &b)) {
+                prop_assert_ne!(x, y);
+            }
+        }
+    }
+}
@@ -0,0 +1,22 @@
+//! Random bearer tokens and the digests they are looked up by. Only the
+//! digest is stored, the token itself is shown once.
+
+use aes_gcm::aead::{OsRng, rand_core::RngCore};
+use sha2::{Digest, Sha256};
+
+/// `prefix` followed by `len` random bytes, hex encoded
+pub fn generate(prefix: &str, len: usize) -> String {
+    let mut bytes = vec![0u8; len];
+    OsRng.fill_bytes(&mut bytes);
+
+    format!("{}{}", prefix, hex(&bytes))
+}
+
+/// SHA-256 of `token`, hex encoded
+pub fn hash(token: &str) -> String {
+    hex(&Sha256::digest(token.as_bytes()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
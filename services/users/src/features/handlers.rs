@@ -1,10 +1,15 @@
 use crate::{
     features::{
-        models::{OAuthUser, Provider, User, UserRole, UserStatus},
-        repository::create_session,
+        models::{
+            OAuthUser, Organization, OrganizationMember, OrganizationRole, Provider, User,
+            UserRole, UserStatus,
+        },
+        repository::{create_session, get_active_organization, require_organization_role},
         schemas::{
-            AuthIn, AuthOut, GithubOAuthUser, GoogleOAuthUser, OAuthCallback, RedirectResponse,
-            Tokens, UserIn, VerifyQuery,
+            AddOrganizationMemberIn, AuthIn, AuthOut, CreateOrganizationIn, GithubOAuthUser,
+            GoogleOAuthUser, OAuthCallback, OrganizationOut, RedirectResponse,
+            SwitchOrganizationIn, Tokens, UpdateOrganizationIn, UpdateOrganizationMemberIn, UserIn,
+            VerifyQuery,
         },
     },
     services::build_oauth::{GithubOAuthClient, GoogleOAuthClient},
@@ -12,18 +17,19 @@ use crate::{
 use bcrypt::{DEFAULT_COST, hash};
 use serde_json::{Value, json};
 use shared::{
+    schemas::ListResponse,
     services::{database::Database, zepto::ZeptoMail},
     utilities::{
         config::Config,
         errors::AppError,
-        jwt::{Claims, TokenType, create_token, verify_token},
+        jwt::{Claims, TokenType, create_token, create_token_for_organization, verify_token},
     },
 };
 use std::net::SocketAddr;
 
 use axum::{
     Json,
-    extract::{ConnectInfo, Multipart, Query, State},
+    extract::{ConnectInfo, Multipart, Path, Query, State},
     http::StatusCode,
     response::{IntoResponse, Redirect, Response},
 };
@@ -41,6 +47,7 @@ use object_store::{ObjectStore, gcp::GoogleCloudStorage, path::Path as ObjectSto
 use reqwest::Client;
 use tracing::debug;
 use uuid::Uuid;
+use validator::Validate;
 
 // -- =====================
 // -- GOOGLE OAUTH
@@ -262,7 +269,9 @@ pub async fn continue_with_email_handler(
     debug!("maybe_user is {:#?}", maybe_user);
 
     if let Some(user) = maybe_user {
-        let new_access = create_token(&config, user.id, TokenType::Access)?;
+        let organization_id = get_active_organization(&database.pool, &user.id).await?;
+        let new_access =
+            create_token_for_organization(&config, user.id, TokenType::Access, organization_id)?;
         let new_refresh = create_token(&config, user.id, TokenType::Refresh)?;
 
         let max_age_days = config.refresh_token_expire_in_days;
@@ -431,7 +440,7 @@ pub async fn verify_handler(
             };
 
             let response = Json(RedirectResponse { redirect_to });
-            return Ok((jar, response).into_response());
+            Ok((jar, response).into_response())
         }
     }
 }
@@ -544,6 +553,7 @@ pub async fn delete_user_handler(
 // -- =====================
 pub async fn refresh_handler(
     State(config): State<Config>,
+    State(database): State<Database>,
     jar: PrivateCookieJar,
     auth_header: Option<TypedHeader<Authorization<Bearer>>>,
 ) -> Result<impl IntoResponse, AppError> {
@@ -584,7 +594,9 @@ pub async fn refresh_handler(
         jar
     };
 
-    let new_access = create_token(&config, claims.sub, TokenType::Access)?;
+    let organization_id = get_active_organization(&database.pool, &claims.sub).await?;
+    let new_access =
+        create_token_for_organization(&config, claims.sub, TokenType::Access, organization_id)?;
 
     let response = Json(Tokens {
         access_token: new_access,
@@ -597,11 +609,13 @@ pub async fn refresh_handler(
 // -- =====================
 // -- LOGOUT
 // -- =====================
+type CookieParts = (String, String, bool, Option<String>, Option<SameSite>, bool);
+
 pub async fn logout_handler(jar: PrivateCookieJar) -> impl IntoResponse {
     let mut jar = jar;
 
     // collect cookies into owned values first
-    let cookies: Vec<(String, String, bool, Option<String>, Option<SameSite>, bool)> = jar
+    let cookies: Vec<CookieParts> = jar
         .iter()
         .map(|c| {
             (
@@ -644,3 +658,519 @@ pub async fn logout_handler(jar: PrivateCookieJar) -> impl IntoResponse {
         })),
     )
 }
+
+// -- =====================
+// -- ORGANIZATIONS
+// -- =====================
+pub async fn get_organizations_handler(
+    claims: Claims,
+    State(database): State<Database>,
+) -> Result<impl IntoResponse, AppError> {
+    let rows = sqlx::query!(
+        r#"
+            SELECT
+                o.id,
+                o.name,
+                o.slug,
+                o.plan_name,
+                o.created_at,
+                o.updated_at,
+                m.role AS "role: OrganizationRole"
+            FROM organizations o
+            INNER JOIN organization_members m ON m.organization_id = o.id
+            WHERE m.user_id = $1
+            ORDER BY o.name
+        "#,
+        claims.sub
+    )
+    .fetch_all(&database.pool)
+    .await?;
+
+    let organizations: Vec<OrganizationOut> = rows
+        .into_iter()
+        .map(|row| OrganizationOut {
+            organization: Organization {
+                id: row.id,
+                name: row.name,
+                slug: row.slug,
+                plan_name: row.plan_name,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+            },
+            role: row.role,
+        })
+        .collect();
+
+    Ok(Json(ListResponse {
+        total: i64::try_from(organizations.len()).unwrap_or(0),
+        data: organizations,
+    }))
+}
+
+pub async fn create_organization_handler(
+    claims: Claims,
+    State(database): State<Database>,
+    Json(organization_in): Json<CreateOrganizationIn>,
+) -> Result<impl IntoResponse, AppError> {
+    organization_in.validate()?;
+
+    let mut tx = database.pool.begin().await?;
+
+    let organization = sqlx::query_as!(
+        Organization,
+        r#"
+            INSERT INTO organizations (name, slug)
+            VALUES ($1, $2)
+            RETURNING id, name, slug, plan_name, created_at, updated_at
+        "#,
+        organization_in.name,
+        organization_in.slug
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| slug_conflict(e, &organization_in.slug))?;
+
+    sqlx::query!(
+        r#"
+            INSERT INTO organization_members (organization_id, user_id, role)
+            VALUES ($1, $2, 'owner')
+        "#,
+        organization.id,
+        claims.sub
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(OrganizationOut {
+            organization,
+            role: OrganizationRole::Owner,
+        }),
+    ))
+}
+
+pub async fn get_organization_handler(
+    claims: Claims,
+    Path(organization_id): Path<Uuid>,
+    State(database): State<Database>,
+) -> Result<impl IntoResponse, AppError> {
+    let role = require_organization_role(
+        &database.pool,
+        &organization_id,
+        &claims.sub,
+        OrganizationRole::Member,
+    )
+    .await?;
+
+    let organization = sqlx::query_as!(
+        Organization,
+        r#"
+            SELECT id, name, slug, plan_name, created_at, updated_at
+            FROM organizations WHERE id = $1
+        "#,
+        organization_id
+    )
+    .fetch_one(&database.pool)
+    .await?;
+
+    Ok(Json(OrganizationOut { organization, role }))
+}
+
+pub async fn update_organization_handler(
+    claims: Claims,
+    Path(organization_id): Path<Uuid>,
+    State(database): State<Database>,
+    Json(organization_in): Json<UpdateOrganizationIn>,
+) -> Result<impl IntoResponse, AppError> {
+    organization_in.validate()?;
+
+    let role = require_organization_role(
+        &database.pool,
+        &organization_id,
+        &claims.sub,
+        OrganizationRole::Admin,
+    )
+    .await?;
+
+    let organization = sqlx::query_as!(
+        Organization,
+        r#"
+            UPDATE organizations
+            SET name = COALESCE($2, name), slug = COALESCE($3, slug)
+            WHERE id = $1
+            RETURNING id, name, slug, plan_name, created_at, updated_at
+        "#,
+        organization_id,
+        organization_in.name,
+        organization_in.slug
+    )
+    .fetch_one(&database.pool)
+    .await
+    .map_err(|e| slug_conflict(e, organization_in.slug.as_deref().unwrap_or_default()))?;
+
+    Ok(Json(OrganizationOut { organization, role }))
+}
+
+/// Only an organization without projects can be deleted, their deployments
+/// have to be deleted or transferred first
+pub async fn delete_organization_handler(
+    claims: Claims,
+    Path(organization_id): Path<Uuid>,
+    State(database): State<Database>,
+) -> Result<impl IntoResponse, AppError> {
+    require_organization_role(
+        &database.pool,
+        &organization_id,
+        &claims.sub,
+        OrganizationRole::Owner,
+    )
+    .await?;
+
+    let has_projects = sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM projects WHERE organization_id = $1) AS "exists!""#,
+        organization_id
+    )
+    .fetch_one(&database.pool)
+    .await?;
+    if has_projects {
+        return Err(AppError::ConflictError(
+            "Delete or transfer the organization's projects first".to_string(),
+        ));
+    }
+
+    sqlx::query!("DELETE FROM organizations WHERE id = $1", organization_id)
+        .execute(&database.pool)
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "message": "Organization deleted successfully" })),
+    ))
+}
+
+pub async fn get_organization_members_handler(
+    claims: Claims,
+    Path(organization_id): Path<Uuid>,
+    State(database): State<Database>,
+) -> Result<impl IntoResponse, AppError> {
+    require_organization_role(
+        &database.pool,
+        &organization_id,
+        &claims.sub,
+        OrganizationRole::Member,
+    )
+    .await?;
+
+    let members = sqlx::query_as!(
+        OrganizationMember,
+        r#"
+            SELECT
+                m.organization_id,
+                m.user_id,
+                u.username,
+                u.email,
+                m.role AS "role: OrganizationRole",
+                m.created_at,
+                m.updated_at
+            FROM organization_members m
+            INNER JOIN users u ON u.id = m.user_id
+            WHERE m.organization_id = $1
+            ORDER BY m.created_at
+        "#,
+        organization_id
+    )
+    .fetch_all(&database.pool)
+    .await?;
+
+    Ok(Json(ListResponse {
+        total: i64::try_from(members.len()).unwrap_or(0),
+        data: members,
+    }))
+}
+
+/// Admins add existing users by email, only owners can add other owners
+pub async fn add_organization_member_handler(
+    claims: Claims,
+    Path(organization_id): Path<Uuid>,
+    State(database): State<Database>,
+    Json(member_in): Json<AddOrganizationMemberIn>,
+) -> Result<impl IntoResponse, AppError> {
+    member_in.validate()?;
+
+    let role = require_organization_role(
+        &database.pool,
+        &organization_id,
+        &claims.sub,
+        OrganizationRole::Admin,
+    )
+    .await?;
+    require_grantable(role, member_in.role)?;
+
+    let user_id = sqlx::query_scalar!(
+        "SELECT id FROM users WHERE lower(email) = lower($1)",
+        member_in.email
+    )
+    .fetch_optional(&database.pool)
+    .await?
+    .ok_or_else(|| AppError::NotFoundError(format!("No user with email {}", member_in.email)))?;
+
+    let member = sqlx::query_as!(
+        OrganizationMember,
+        r#"
+            WITH inserted AS (
+                INSERT INTO organization_members (organization_id, user_id, role)
+                VALUES ($1, $2, $3)
+                RETURNING *
+            )
+            SELECT
+                i.organization_id,
+                i.user_id,
+                u.username,
+                u.email,
+                i.role AS "role: OrganizationRole",
+                i.created_at,
+                i.updated_at
+            FROM inserted i
+            INNER JOIN users u ON u.id = i.user_id
+        "#,
+        organization_id,
+        user_id,
+        member_in.role as OrganizationRole
+    )
+    .fetch_one(&database.pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(db) if db.is_unique_violation() => AppError::ConflictError(format!(
+            "{} is already a member of this organization",
+            member_in.email
+        )),
+        e => AppError::from(e),
+    })?;
+
+    Ok((StatusCode::CREATED, Json(member)))
+}
+
+pub async fn update_organization_member_handler(
+    claims: Claims,
+    Path((organization_id, user_id)): Path<(Uuid, Uuid)>,
+    State(database): State<Database>,
+    Json(member_in): Json<UpdateOrganizationMemberIn>,
+) -> Result<impl IntoResponse, AppError> {
+    let role = require_organization_role(
+        &database.pool,
+        &organization_id,
+        &claims.sub,
+        OrganizationRole::Admin,
+    )
+    .await?;
+
+    let mut tx = database.pool.begin().await?;
+
+    let previous = lock_member(&mut tx, &organization_id, &user_id).await?;
+    require_grantable(role, previous)?;
+    require_grantable(role, member_in.role)?;
+    if previous == OrganizationRole::Owner && member_in.role != OrganizationRole::Owner {
+        require_another_owner(&mut tx, &organization_id).await?;
+    }
+
+    let member = sqlx::query_as!(
+        OrganizationMember,
+        r#"
+            WITH updated AS (
+                UPDATE organization_members
+                SET role = $3
+                WHERE organization_id = $1 AND user_id = $2
+                RETURNING *
+            )
+            SELECT
+                m.organization_id,
+                m.user_id,
+                u.username,
+                u.email,
+                m.role AS "role: OrganizationRole",
+                m.created_at,
+                m.updated_at
+            FROM updated m
+            INNER JOIN users u ON u.id = m.user_id
+        "#,
+        organization_id,
+        user_id,
+        member_in.role as OrganizationRole
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(member))
+}
+
+/// Admins remove members, and every member can leave by removing themselves.
+/// The last owner can do neither.
+pub async fn delete_organization_member_handler(
+    claims: Claims,
+    Path((organization_id, user_id)): Path<(Uuid, Uuid)>,
+    State(database): State<Database>,
+) -> Result<impl IntoResponse, AppError> {
+    let leaving = claims.sub == user_id;
+    let role = require_organization_role(
+        &database.pool,
+        &organization_id,
+        &claims.sub,
+        if leaving {
+            OrganizationRole::Member
+        } else {
+            OrganizationRole::Admin
+        },
+    )
+    .await?;
+
+    let mut tx = database.pool.begin().await?;
+
+    let previous = lock_member(&mut tx, &organization_id, &user_id).await?;
+    if !leaving {
+        require_grantable(role, previous)?;
+    }
+    if previous == OrganizationRole::Owner {
+        require_another_owner(&mut tx, &organization_id).await?;
+    }
+
+    sqlx::query!(
+        "DELETE FROM organization_members WHERE organization_id = $1 AND user_id = $2",
+        organization_id,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        r#"
+            UPDATE users SET active_organization_id = NULL
+            WHERE id = $1 AND active_organization_id = $2
+        "#,
+        user_id,
+        organization_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "message": "Member removed successfully" })),
+    ))
+}
+
+/// Work in an organization, or back in the user's own account. Returns an
+/// access token for it, later logins and refreshes keep using it.
+pub async fn switch_organization_handler(
+    claims: Claims,
+    State(config): State<Config>,
+    State(database): State<Database>,
+    Json(switch_in): Json<SwitchOrganizationIn>,
+) -> Result<impl IntoResponse, AppError> {
+    if let Some(organization_id) = switch_in.organization_id {
+        require_organization_role(
+            &database.pool,
+            &organization_id,
+            &claims.sub,
+            OrganizationRole::Member,
+        )
+        .await?;
+    }
+
+    sqlx::query!(
+        "UPDATE users SET active_organization_id = $2 WHERE id = $1",
+        claims.sub,
+        switch_in.organization_id
+    )
+    .execute(&database.pool)
+    .await?;
+
+    let access_token = create_token_for_organization(
+        &config,
+        claims.sub,
+        TokenType::Access,
+        switch_in.organization_id,
+    )?;
+
+    Ok(Json(Tokens {
+        access_token,
+        refresh_token: None,
+    }))
+}
+
+fn slug_conflict(e: sqlx::Error, slug: &str) -> AppError {
+    match e {
+        sqlx::Error::Database(db) if db.is_unique_violation() => {
+            AppError::ConflictError(format!("Organization slug '{}' is taken", slug))
+        }
+        e => AppError::from(e),
+    }
+}
+
+/// Admins manage members and admins, owners manage everyone
+fn require_grantable(role: OrganizationRole, target: OrganizationRole) -> Result<(), AppError> {
+    if target == OrganizationRole::Owner && role != OrganizationRole::Owner {
+        return Err(AppError::ForbiddenError(
+            "Only owners can grant, change or remove the owner role".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// The member's role, holding the organization's row so owner counts stay
+/// accurate until the transaction ends
+async fn lock_member(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    organization_id: &Uuid,
+    user_id: &Uuid,
+) -> Result<OrganizationRole, AppError> {
+    sqlx::query!(
+        "SELECT id FROM organizations WHERE id = $1 FOR UPDATE",
+        organization_id
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+
+    sqlx::query_scalar!(
+        r#"
+            SELECT role AS "role: OrganizationRole"
+            FROM organization_members
+            WHERE organization_id = $1 AND user_id = $2
+        "#,
+        organization_id,
+        user_id
+    )
+    .fetch_optional(&mut **tx)
+    .await?
+    .ok_or_else(|| AppError::NotFoundError("Member not found".to_string()))
+}
+
+async fn require_another_owner(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    organization_id: &Uuid,
+) -> Result<(), AppError> {
+    let owners = sqlx::query_scalar!(
+        r#"
+            SELECT COUNT(*) AS "count!"
+            FROM organization_members
+            WHERE organization_id = $1 AND role = 'owner'
+        "#,
+        organization_id
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+
+    if owners < 2 {
+        return Err(AppError::ForbiddenError(
+            "An organization needs at least one owner, add another first".to_string(),
+        ));
+    }
+
+    Ok(())
+}
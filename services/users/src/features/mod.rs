@@ -8,7 +8,7 @@ use crate::utilities::app_state::AppState;
 
 use axum::{
     Router,
-    routing::{get, post},
+    routing::{get, patch, post},
 };
 
 pub fn routes() -> Router<AppState> {
@@ -36,4 +36,28 @@ pub fn routes() -> Router<AppState> {
             "/api/v1/auth/github/callback",
             get(handlers::github_oauth_callback_handler),
         )
+        .route(
+            "/api/v1/organizations",
+            get(handlers::get_organizations_handler).post(handlers::create_organization_handler),
+        )
+        .route(
+            "/api/v1/organizations/switch",
+            post(handlers::switch_organization_handler),
+        )
+        .route(
+            "/api/v1/organizations/{organization_id}",
+            get(handlers::get_organization_handler)
+                .patch(handlers::update_organization_handler)
+                .delete(handlers::delete_organization_handler),
+        )
+        .route(
+            "/api/v1/organizations/{organization_id}/members",
+            get(handlers::get_organization_members_handler)
+                .post(handlers::add_organization_member_handler),
+        )
+        .route(
+            "/api/v1/organizations/{organization_id}/members/{user_id}",
+            patch(handlers::update_organization_member_handler)
+                .delete(handlers::delete_organization_member_handler),
+        )
 }
@@ -2,6 +2,8 @@ use shared::utilities::errors::AppError;
 use sqlx::PgPool;
 use uuid::Uuid;
 
+use crate::features::models::OrganizationRole;
+
 pub async fn create_session(
     pool: &PgPool,
     user_id: &Uuid,
@@ -24,3 +26,53 @@ pub async fn create_session(
 
     Ok(())
 }
+
+/// The organization the user is working in, `None` when they left it since
+pub async fn get_active_organization(
+    pool: &PgPool,
+    user_id: &Uuid,
+) -> Result<Option<Uuid>, AppError> {
+    let organization_id = sqlx::query_scalar!(
+        r#"
+            SELECT m.organization_id
+            FROM users u
+            INNER JOIN organization_members m
+                ON m.organization_id = u.active_organization_id AND m.user_id = u.id
+            WHERE u.id = $1
+        "#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(organization_id)
+}
+
+/// The user's role in the organization, an error when it's below `required`
+pub async fn require_organization_role(
+    pool: &PgPool,
+    organization_id: &Uuid,
+    user_id: &Uuid,
+    required: OrganizationRole,
+) -> Result<OrganizationRole, AppError> {
+    let role = sqlx::query_scalar!(
+        r#"
+            SELECT role AS "role: OrganizationRole"
+            FROM organization_members
+            WHERE organization_id = $1 AND user_id = $2
+        "#,
+        organization_id,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::NotFoundError("Organization not found".to_string()))?;
+
+    if role < required {
+        return Err(AppError::ForbiddenError(
+            "Your role in the organization doesn't allow this".to_string(),
+        ));
+    }
+
+    Ok(role)
+}
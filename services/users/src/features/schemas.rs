@@ -1,9 +1,11 @@
 use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use validator::Validate;
 
-use crate::features::models::{User, UserRole, UserStatus};
+use crate::features::models::{Organization, OrganizationRole, User, UserRole, UserStatus};
 
 #[derive(Deserialize, Debug)]
 pub struct VerifyQuery {
@@ -99,3 +101,56 @@ pub struct UserOut {
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
+
+// -- =====================
+// -- ORGANIZATIONS
+// -- =====================
+static SLUG: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[a-z0-9]([-a-z0-9]*[a-z0-9])?$").unwrap());
+
+#[derive(Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateOrganizationIn {
+    #[validate(length(min = 1, max = 64))]
+    pub name: String,
+    #[validate(length(min = 3, max = 64), regex(path = *SLUG))]
+    pub slug: String,
+}
+
+#[derive(Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateOrganizationIn {
+    #[validate(length(min = 1, max = 64))]
+    pub name: Option<String>,
+    #[validate(length(min = 3, max = 64), regex(path = *SLUG))]
+    pub slug: Option<String>,
+}
+
+#[derive(Deserialize, Validate, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AddOrganizationMemberIn {
+    #[validate(email(message = "Invalid email address"))]
+    pub email: String,
+    pub role: OrganizationRole,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateOrganizationMemberIn {
+    pub role: OrganizationRole,
+}
+
+/// `None` switches back to the user's own account
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SwitchOrganizationIn {
+    pub organization_id: Option<Uuid>,
+}
+
+/// An organization the user belongs to, and as what
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct OrganizationOut {
+    #[serde(flatten)]
+    pub organization: Organization,
+    pub role: OrganizationRole,
+}
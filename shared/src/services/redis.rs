@@ -7,6 +7,7 @@ use tracing::info;
 
 #[derive(Clone)]
 pub struct Redis {
+    pub client: Client,
     pub connection: MultiplexedConnection,
 }
 
@@ -49,7 +50,7 @@ impl Redis {
 
             let connection = client.get_multiplexed_tokio_connection().await?;
 
-            return Ok(Self { connection });
+            return Ok(Self { client, connection });
         }
         let client = Client::open(redis_url)?;
 
@@ -58,7 +59,7 @@ impl Redis {
 
         let connection = client.get_multiplexed_tokio_connection().await?;
 
-        Ok(Self { connection })
+        Ok(Self { client, connection })
     }
 
     // pub async fn set_user(&self) {
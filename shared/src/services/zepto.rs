@@ -115,6 +115,72 @@ impl ZeptoMail {
 
         debug!("Sending email to '{}' with email '{}'", name, to_email);
 
+        self.send(payload, config).await
+    }
+
+    pub async fn send_project_invitation(
+        &self,
+        to_email: String,
+        project_name: String,
+        role: String,
+        link: String,
+        config: &Config,
+    ) -> Result<(), AppError> {
+        let payload = Payload {
+            template_alias: "poddle-project-invitation-key-alias".to_string(),
+            from: EmailAddress {
+                name: "Poddle".to_string(),
+                address: "verification@kronk.uz".to_string(),
+            },
+            to: vec![Recipient {
+                email_address: EmailAddress {
+                    address: to_email.clone(),
+                    name: to_email.clone(),
+                },
+            }],
+            merge_info: serde_json::json!({
+                "project": project_name,
+                "role": role,
+                "link": link
+            }),
+        };
+
+        debug!("Sending project invitation to '{}'", to_email);
+
+        self.send(payload, config).await
+    }
+
+    pub async fn send_project_transfer(
+        &self,
+        to_email: String,
+        project_name: String,
+        link: String,
+        config: &Config,
+    ) -> Result<(), AppError> {
+        let payload = Payload {
+            template_alias: "poddle-project-transfer-key-alias".to_string(),
+            from: EmailAddress {
+                name: "Poddle".to_string(),
+                address: "verification@kronk.uz".to_string(),
+            },
+            to: vec![Recipient {
+                email_address: EmailAddress {
+                    address: to_email.clone(),
+                    name: to_email.clone(),
+                },
+            }],
+            merge_info: serde_json::json!({
+                "project": project_name,
+                "link": link
+            }),
+        };
+
+        debug!("Sending project transfer to '{}'", to_email);
+
+        self.send(payload, config).await
+    }
+
+    async fn send(&self, payload: Payload, config: &Config) -> Result<(), AppError> {
         let api_key = config.email_service_api_key.clone();
 
         let res = self
@@ -16,11 +16,32 @@ pub struct Config {
     pub frontend_endpoint: String,
 
     pub base_domain: String,
+    /// Where the API is reachable from outside, used in links we hand out
+    pub public_api_url: String,
 
     // KUBERNETES
     pub k8s_in_cluster: bool,
     pub k8s_config_path: Option<String>,
-    pub k8s_encryption_key: String,
+    pub k8s_clusters_path: Option<String>,
+
+    // BILLING
+    pub billing_service_url: Option<String>,
+    pub internal_api_token: Option<String>,
+    pub min_balance_hours: i64,
+
+    // ENCRYPTION
+    pub k8s_encryption_key: Option<String>,
+    pub k8s_encryption_keys: Option<String>,
+    pub k8s_encryption_active_key_id: Option<String>,
+
+    // KMS
+    pub kms_provider: String,
+    pub kms_local_keyring: Option<String>,
+    pub kms_local_active_key_id: Option<String>,
+    pub vault_addr: Option<String>,
+    pub vault_token: Option<String>,
+    pub vault_transit_mount: String,
+    pub vault_transit_key: String,
 
     pub base_dir: PathBuf,
     pub tracing_level: Level,
@@ -86,16 +107,90 @@ pub struct Config {
 
 impl Config {
     pub async fn init() -> Result<Self, AppError> {
-        let k8s_encryption_key = std::env::var("K8S_ENCRYPTION_KEY")
-            .expect("K8S_ENCRYPTION_KEY must be set - generate with: openssl rand -base64 32");
+        // Legacy single key, still used to read values written before key ids existed
+        let k8s_encryption_key =
+            get_optional_config_value("K8S_ENCRYPTION_KEY", Some("K8S_ENCRYPTION_KEY"), None)
+                .await?;
+        // Keyring as `id:base64,id:base64`, generate keys with: openssl rand -base64 32
+        let k8s_encryption_keys =
+            get_optional_config_value("K8S_ENCRYPTION_KEYS", Some("K8S_ENCRYPTION_KEYS"), None)
+                .await?;
+        let k8s_encryption_active_key_id = get_optional_config_value(
+            "K8S_ENCRYPTION_ACTIVE_KEY_ID",
+            Some("K8S_ENCRYPTION_ACTIVE_KEY_ID"),
+            None,
+        )
+        .await?;
+
+        let base_dir = find_project_root().unwrap_or_else(|| PathBuf::from("."));
+
+        // Wraps per-deployment data keys: `local` (keyring file) or `vault` (Transit)
+        let kms_provider = get_config_value(
+            "KMS_PROVIDER",
+            Some("KMS_PROVIDER"),
+            None,
+            Some("local".to_string()),
+        )
+        .await?;
+        let kms_local_keyring_path = base_dir.join("certs/kms/keyring");
+        let kms_local_keyring = get_optional_config_value(
+            "kms_keyring",
+            Some("KMS_LOCAL_KEYRING"),
+            Some(&kms_local_keyring_path),
+        )
+        .await?;
+        let kms_local_active_key_id = get_optional_config_value(
+            "KMS_LOCAL_ACTIVE_KEY_ID",
+            Some("KMS_LOCAL_ACTIVE_KEY_ID"),
+            None,
+        )
+        .await?;
+        let vault_addr = get_optional_config_value("VAULT_ADDR", Some("VAULT_ADDR"), None).await?;
+        let vault_token =
+            get_optional_config_value("VAULT_TOKEN", Some("VAULT_TOKEN"), None).await?;
+        let vault_transit_mount = get_config_value(
+            "VAULT_TRANSIT_MOUNT",
+            Some("VAULT_TRANSIT_MOUNT"),
+            None,
+            Some("transit".to_string()),
+        )
+        .await?;
+        let vault_transit_key = get_config_value(
+            "VAULT_TRANSIT_KEY",
+            Some("VAULT_TRANSIT_KEY"),
+            None,
+            Some("compute-secrets".to_string()),
+        )
+        .await?;
 
         let k8s_config_path =
             get_optional_config_value("K8S_KUBECONFIG", Some("K8S_KUBECONFIG"), None).await?;
         let k8s_in_cluster =
             get_config_value("K8S_IN_CLUSTER", Some("K8S_IN_CLUSTER"), None, Some(false)).await?;
+        let k8s_clusters_path =
+            get_optional_config_value("K8S_CLUSTERS", Some("K8S_CLUSTERS"), None).await?;
+
+        // Compute asks billing before deployments grow, checks are off when unset
+        let billing_service_url =
+            get_optional_config_value("BILLING_SERVICE_URL", Some("BILLING_SERVICE_URL"), None)
+                .await?;
+        // Shared by services calling each other's `/internal` routes
+        let internal_api_token =
+            get_optional_config_value("INTERNAL_API_TOKEN", Some("INTERNAL_API_TOKEN"), None)
+                .await?;
+        // Hours of projected spend a balance must cover
+        let min_balance_hours = get_config_value(
+            "MIN_BALANCE_HOURS",
+            Some("MIN_BALANCE_HOURS"),
+            None,
+            Some(24),
+        )
+        .await?;
 
         let base_domain =
             std::env::var("BASE_DOMAIN").unwrap_or_else(|_| "app.pinespot.uz".to_string());
+        let public_api_url = std::env::var("PUBLIC_API_URL")
+            .unwrap_or_else(|_| "https://api.pinespot.uz".to_string());
 
         let server_addres = get_config_value(
             "SERVER_ADDRES",
@@ -113,8 +208,6 @@ impl Config {
         )
         .await?;
 
-        let base_dir = find_project_root().unwrap_or_else(|| PathBuf::from("."));
-
         let debug = get_config_value("DEBUG", Some("DEBUG"), None, Some(false)).await?;
 
         let tracing_level = get_config_value(
@@ -295,8 +388,22 @@ impl Config {
         let config = Config {
             k8s_in_cluster,
             k8s_config_path,
+            k8s_clusters_path,
+            billing_service_url,
+            internal_api_token,
+            min_balance_hours,
             k8s_encryption_key,
+            k8s_encryption_keys,
+            k8s_encryption_active_key_id,
+            kms_provider,
+            kms_local_keyring,
+            kms_local_active_key_id,
+            vault_addr,
+            vault_token,
+            vault_transit_mount,
+            vault_transit_key,
             base_domain,
+            public_api_url,
             server_addres,
             frontend_endpoint,
             base_dir,
@@ -366,32 +473,28 @@ where
 {
     // Docker secret
     let docker_secret = Path::new("/run/secrets").join(secret_name);
-    if docker_secret.exists() {
-        if let Ok(content) = fs::read_to_string(&docker_secret).await {
-            if let Ok(parsed) = T::from_str(content.trim()) {
-                return Ok(Some(parsed));
-            }
-        }
+    if docker_secret.exists()
+        && let Ok(content) = fs::read_to_string(&docker_secret).await
+        && let Ok(parsed) = T::from_str(content.trim())
+    {
+        return Ok(Some(parsed));
     }
 
     // Env var
     if let Some(env_key) = env_name
         && let Ok(val) = std::env::var(env_key)
+        && let Ok(parsed) = T::from_str(val.trim())
     {
-        if let Ok(parsed) = T::from_str(val.trim()) {
-            return Ok(Some(parsed));
-        }
+        return Ok(Some(parsed));
     }
 
     // Fallback path
     if let Some(path) = fallback_path
         && path.exists()
+        && let Ok(content) = fs::read_to_string(path).await
+        && let Ok(parsed) = T::from_str(content.trim())
     {
-        if let Ok(content) = fs::read_to_string(path).await {
-            if let Ok(parsed) = T::from_str(content.trim()) {
-                return Ok(Some(parsed));
-            }
-        }
+        return Ok(Some(parsed));
     }
 
     Ok(None)
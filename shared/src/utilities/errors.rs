@@ -139,6 +139,16 @@ pub enum AppError {
     ),
     #[error("{0}")]
     NotFoundError(String),
+    #[error("{0}")]
+    ConflictError(String),
+    #[error("{0}")]
+    ForbiddenError(String),
+    #[error("{0}")]
+    QuotaExceededError(String),
+    #[error("{0}")]
+    InsufficientBalanceError(String),
+    #[error("{0}")]
+    RateLimitedError(String),
     #[error("IO error, {0}")]
     IoError(#[from] std::io::Error),
     #[error("Invalid ca cert error")]
@@ -173,6 +183,8 @@ pub enum AppError {
     EncryptionError(String),
     #[error("Decryption error, {0}")]
     DecryptionError(String),
+    #[error("KMS error, {0}")]
+    KmsError(String),
     #[error("FromUtf8Error, {0}")]
     FromUtf8Error(#[from] std::string::FromUtf8Error),
     #[error("Invalid format error")]
@@ -187,6 +199,13 @@ pub enum AppError {
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
+        // Both are 402, the code tells the UI whether to upgrade or add funds
+        let code = match &self {
+            Self::QuotaExceededError(_) => Some("quota_exceeded"),
+            Self::InsufficientBalanceError(_) => Some("insufficient_balance"),
+            _ => None,
+        };
+
         let (status, error_message) = match self {
             Self::FileReadError(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
             Self::IoError(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
@@ -405,12 +424,18 @@ impl IntoResponse for AppError {
             Self::ValidatorValidationErrors(e) => (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()),
             Self::RequestTokenError(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
             Self::NotFoundError(e) => (StatusCode::NOT_FOUND, e),
+            Self::ConflictError(e) => (StatusCode::CONFLICT, e),
+            Self::ForbiddenError(e) => (StatusCode::FORBIDDEN, e),
+            Self::QuotaExceededError(e) => (StatusCode::PAYMENT_REQUIRED, e),
+            Self::InsufficientBalanceError(e) => (StatusCode::PAYMENT_REQUIRED, e),
+            Self::RateLimitedError(e) => (StatusCode::TOO_MANY_REQUESTS, e),
             Self::InvalidImageFormatError(e) => (StatusCode::UNPROCESSABLE_ENTITY, e),
             Self::KubeError(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
             Self::KafkaError(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
             Self::LapinError(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
             Self::InvalidKey(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
             Self::EncryptionError(e) => (StatusCode::INTERNAL_SERVER_ERROR, e),
+            Self::KmsError(e) => (StatusCode::INTERNAL_SERVER_ERROR, e),
             Self::DecryptionError(e) => (StatusCode::INTERNAL_SERVER_ERROR, e),
             Self::FromUtf8Error(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
             Self::InvalidFormat => (
@@ -422,7 +447,10 @@ impl IntoResponse for AppError {
             Self::InferConfigError(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
         };
 
-        let body = Json(json!({"error": error_message}));
+        let body = match code {
+            Some(code) => Json(json!({"error": error_message, "code": code})),
+            None => Json(json!({"error": error_message})),
+        };
 
         (status, body).into_response()
     }
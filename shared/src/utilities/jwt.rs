@@ -29,9 +29,21 @@ pub struct Claims {
     pub typ: TokenType,
     pub exp: i64,
     pub iat: i64,
+    /// Organization the user switched to, none while in their own account
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub org: Option<Uuid>,
 }
 
 pub fn create_token(config: &Config, user_id: Uuid, typ: TokenType) -> Result<String, AppError> {
+    create_token_for_organization(config, user_id, typ, None)
+}
+
+pub fn create_token_for_organization(
+    config: &Config,
+    user_id: Uuid,
+    typ: TokenType,
+    organization_id: Option<Uuid>,
+) -> Result<String, AppError> {
     let now = Utc::now();
 
     let exp = now
@@ -48,6 +60,7 @@ pub fn create_token(config: &Config, user_id: Uuid, typ: TokenType) -> Result<St
         typ,
         iat: now.timestamp(),
         exp: exp.timestamp(),
+        org: organization_id,
     };
 
     let encoding_key = EncodingKey::from_secret(config.jwt_secret_key.as_bytes());